@@ -0,0 +1,298 @@
+#[cfg(not(feature = "fjall"))]
+fn main() {
+    eprintln!(
+        "`ebloom_backend_benchmarks` requires the `fjall` feature. Run with `cargo bench --bench ebloom_backend_benchmarks --features fjall`."
+    );
+}
+
+#[cfg(feature = "fjall")]
+mod ebloom_backend_bench {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+
+    use criterion::{
+        BatchSize, BenchmarkId, Criterion, Throughput, black_box, criterion_group,
+    };
+    use probabilistic_rs::ebloom::{
+        config::{
+            ExpiringFilterConfig, ExpiringFilterConfigBuilder,
+            ExpiringPersistenceConfigBuilder,
+        },
+        filter::ExpiringBloomFilter,
+        traits::{BulkExpiringBloomFilterOps, ExpiringBloomFilterOps},
+    };
+    use tokio::runtime::Runtime;
+
+    const CAPACITIES: [usize; 2] = [10_000, 100_000];
+    const FPRS: [f64; 2] = [0.01, 0.001];
+    const NUM_LEVELS: usize = 3;
+    const BULK_SIZE: usize = 1_000;
+    /// Batch size and capacity of `bench_contains_large_batch`
+    const LARGE_BATCH_SIZE: usize = 10_000;
+    const LARGE_CAPACITY: usize = 10_000_000;
+
+    static DB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    #[derive(Clone, Copy)]
+    enum Backend {
+        InMemory,
+        Fjall,
+    }
+
+    impl Backend {
+        const ALL: [Backend; 2] = [Backend::InMemory, Backend::Fjall];
+
+        fn label(self) -> &'static str {
+            match self {
+                Backend::InMemory => "inmemory",
+                Backend::Fjall => "fjall",
+            }
+        }
+    }
+
+    /// Filter plus the directory to remove once the benchmark is done
+    struct BenchFilter {
+        filter: ExpiringBloomFilter,
+        db_path: Option<PathBuf>,
+    }
+
+    impl Drop for BenchFilter {
+        fn drop(&mut self) {
+            if let Some(ref db_path) = self.db_path
+                && let Err(err) = fs::remove_dir_all(db_path)
+            {
+                eprintln!("cleanup warning ({}): {}", db_path.display(), err);
+            }
+        }
+    }
+
+    fn build_config(
+        capacity: usize,
+        fpr: f64,
+        db_path: Option<PathBuf>,
+    ) -> ExpiringFilterConfig {
+        let persistence = db_path.map(|db_path| {
+            ExpiringPersistenceConfigBuilder::default()
+                .db_path(db_path)
+                .chunk_size_bytes(4096_usize)
+                .build()
+                .expect("failed to build persistence config")
+        });
+
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(capacity)
+            .target_fpr(fpr)
+            .num_levels(NUM_LEVELS)
+            .level_duration(Duration::from_secs(3600))
+            .persistence(persistence)
+            .build()
+            .expect("failed to build filter config")
+    }
+
+    fn create_filter(
+        runtime: &Runtime,
+        backend: Backend,
+        capacity: usize,
+        fpr: f64,
+    ) -> BenchFilter {
+        let db_path = match backend {
+            Backend::InMemory => None,
+            Backend::Fjall => Some(std::env::temp_dir().join(format!(
+                "ebloom_bench_{}_{}.fjall",
+                std::process::id(),
+                DB_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ))),
+        };
+        let config = build_config(capacity, fpr, db_path.clone());
+        let filter = runtime
+            .block_on(ExpiringBloomFilter::create(config))
+            .expect("failed to create filter");
+        BenchFilter { filter, db_path }
+    }
+
+    fn generate_items(count: usize, prefix: &str) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| format!("{prefix}_{i:08}").into_bytes())
+            .collect()
+    }
+
+    /// Every backend, capacity and FPR combination with a readable id
+    fn scenarios() -> impl Iterator<Item = (Backend, usize, f64, String)> {
+        Backend::ALL.into_iter().flat_map(|backend| {
+            CAPACITIES.into_iter().flat_map(move |capacity| {
+                FPRS.into_iter().map(move |fpr| {
+                    (
+                        backend,
+                        capacity,
+                        fpr,
+                        format!("{}/cap_{capacity}/fpr_{fpr}", backend.label()),
+                    )
+                })
+            })
+        })
+    }
+
+    pub fn bench_insert(c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to create Tokio runtime");
+        let mut group = c.benchmark_group("ebloom_insert");
+        let items = generate_items(BULK_SIZE, "insert");
+        group.throughput(Throughput::Elements(items.len() as u64));
+
+        for (backend, capacity, fpr, id) in scenarios() {
+            let bench = create_filter(&runtime, backend, capacity, fpr);
+            group.bench_function(BenchmarkId::new("single", &id), |b| {
+                b.iter(|| {
+                    for item in &items {
+                        bench.filter.insert(black_box(item)).unwrap();
+                    }
+                })
+            });
+
+            let refs: Vec<&[u8]> = items.iter().map(Vec::as_slice).collect();
+            group.bench_function(BenchmarkId::new("bulk", &id), |b| {
+                b.iter(|| bench.filter.insert_bulk(black_box(&refs)).unwrap())
+            });
+        }
+        group.finish();
+    }
+
+    pub fn bench_contains(c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to create Tokio runtime");
+        let mut group = c.benchmark_group("ebloom_contains");
+        let present = generate_items(BULK_SIZE, "present");
+        let absent = generate_items(BULK_SIZE, "absent");
+        group.throughput(Throughput::Elements(BULK_SIZE as u64 * 2));
+
+        for (backend, capacity, fpr, id) in scenarios() {
+            let bench = create_filter(&runtime, backend, capacity, fpr);
+            for item in &present {
+                bench.filter.insert(item).unwrap();
+            }
+
+            group.bench_function(BenchmarkId::new("single", &id), |b| {
+                b.iter(|| {
+                    for item in present.iter().chain(&absent) {
+                        black_box(bench.filter.contains(item).unwrap());
+                    }
+                })
+            });
+
+            let refs: Vec<&[u8]> =
+                present.iter().chain(&absent).map(Vec::as_slice).collect();
+            group.bench_function(BenchmarkId::new("bulk", &id), |b| {
+                b.iter(|| bench.filter.contains_bulk(black_box(&refs)).unwrap())
+            });
+        }
+        group.finish();
+    }
+
+    /// Batched `contains_bulk` against one probe at a time, on a filter
+    /// large enough that probes miss the cache
+    pub fn bench_contains_large_batch(c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to create Tokio runtime");
+        let mut group = c.benchmark_group("ebloom_contains_large_batch");
+        let present = generate_items(LARGE_BATCH_SIZE / 2, "present");
+        let absent = generate_items(LARGE_BATCH_SIZE / 2, "absent");
+        group.throughput(Throughput::Elements(LARGE_BATCH_SIZE as u64));
+
+        let bench =
+            create_filter(&runtime, Backend::InMemory, LARGE_CAPACITY, 0.01);
+        for item in generate_items(LARGE_CAPACITY / 2, "fill")
+            .iter()
+            .chain(&present)
+        {
+            bench.filter.insert(item).unwrap();
+        }
+        let refs: Vec<&[u8]> =
+            present.iter().chain(&absent).map(Vec::as_slice).collect();
+
+        group.bench_function("single", |b| {
+            b.iter(|| {
+                for item in &refs {
+                    black_box(bench.filter.contains(item).unwrap());
+                }
+            })
+        });
+        group.bench_function("bulk", |b| {
+            b.iter(|| bench.filter.contains_bulk(black_box(&refs)).unwrap())
+        });
+        group.finish();
+    }
+
+    pub fn bench_rotation(c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to create Tokio runtime");
+        let mut group = c.benchmark_group("ebloom_rotation");
+        group.sample_size(20);
+
+        for (backend, capacity, fpr, id) in scenarios() {
+            let bench = create_filter(&runtime, backend, capacity, fpr);
+            let items = generate_items(capacity / 2, "rotation");
+
+            // Each rotation clears the next level and, with Fjall, writes a
+            // full snapshot of the level being rotated out
+            group.bench_function(BenchmarkId::from_parameter(&id), |b| {
+                b.iter_batched(
+                    || {
+                        for item in &items {
+                            bench.filter.insert(item).unwrap();
+                        }
+                    },
+                    |()| runtime.block_on(bench.filter.rotate_levels()).unwrap(),
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+        group.finish();
+    }
+
+    pub fn bench_snapshot(c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to create Tokio runtime");
+        let mut group = c.benchmark_group("ebloom_snapshot");
+        group.sample_size(20);
+
+        // Snapshots are a no-op without persistence, so only Fjall is measured
+        for (backend, capacity, fpr, id) in
+            scenarios().filter(|(backend, ..)| matches!(backend, Backend::Fjall))
+        {
+            let bench = create_filter(&runtime, backend, capacity, fpr);
+            let items = generate_items(capacity / 10, "snapshot");
+            let mut round = 0;
+
+            group.bench_function(BenchmarkId::new("incremental", &id), |b| {
+                b.iter_batched(
+                    || {
+                        // Touch a fresh 10% of the capacity every round
+                        round += 1;
+                        for item in &items {
+                            let mut item = item.clone();
+                            item.extend_from_slice(&u64::to_le_bytes(round));
+                            bench.filter.insert(&item).unwrap();
+                        }
+                    },
+                    |()| runtime.block_on(bench.filter.save_snapshot()).unwrap(),
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+        group.finish();
+    }
+
+    criterion_group!(
+        ebloom_backend_bench_group,
+        bench_insert,
+        bench_contains,
+        bench_contains_large_batch,
+        bench_rotation,
+        bench_snapshot
+    );
+}
+
+#[cfg(feature = "fjall")]
+use ebloom_backend_bench::ebloom_backend_bench_group;
+
+#[cfg(feature = "fjall")]
+criterion::criterion_main!(ebloom_backend_bench_group);
@@ -1,8 +1,8 @@
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
-use probabilistic_rs::{FilterStorage, InMemoryStorage};
+use probabilistic_rs::ebloom::bits::AtomicBitVec;
 use rand::{Rng, distr::Uniform};
 use std::collections::HashSet;
-use std::sync::{Arc, Barrier, RwLock};
+use std::sync::{Arc, Barrier};
 use std::thread;
 
 // Helper function to generate a set of random indices within capacity
@@ -19,6 +19,12 @@ fn generate_random_indices(count: usize, capacity: usize) -> Vec<usize> {
     indices_set.into_iter().collect()
 }
 
+fn new_levels(capacity: usize, num_levels: usize) -> Vec<AtomicBitVec> {
+    (0..num_levels)
+        .map(|_| AtomicBitVec::new(capacity))
+        .collect()
+}
+
 fn bench_set_bits(c: &mut Criterion) {
     let mut group = c.benchmark_group("storage_set_bits");
 
@@ -33,13 +39,15 @@ fn bench_set_bits(c: &mut Criterion) {
                     b.iter_batched(
                         || {
                             // Setup: Create storage and random indices
-                            let storage = InMemoryStorage::new(cap, 1).unwrap();
+                            let storage = AtomicBitVec::new(cap);
                             let indices = generate_random_indices(bits, cap);
                             (storage, indices)
                         },
-                        |(mut storage, indices)| {
+                        |(storage, indices)| {
                             // Benchmark: Set bits operation
-                            storage.set_bits(0, &indices).unwrap();
+                            for &idx in &indices {
+                                storage.set(idx);
+                            }
                         },
                         criterion::BatchSize::SmallInput,
                     )
@@ -61,15 +69,15 @@ fn bench_get_bits(c: &mut Criterion) {
                 &(capacity, bit_count),
                 |b, &(cap, bits)| {
                     // Setup: Create storage, set some bits, then measure get performance
-                    let mut storage = InMemoryStorage::new(cap, 1).unwrap();
+                    let storage = AtomicBitVec::new(cap);
                     let indices = generate_random_indices(bits, cap);
 
                     // Set the bits first
-                    storage.set_bits(0, &indices).unwrap();
+                    for &idx in &indices {
+                        storage.set(idx);
+                    }
 
-                    b.iter(|| {
-                        storage.get_bits(0, &indices).unwrap();
-                    });
+                    b.iter(|| indices.iter().all(|&idx| storage.get(idx)));
                 },
             );
         }
@@ -78,13 +86,80 @@ fn bench_get_bits(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_snapshot_bytes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage_snapshot_bytes");
+
+    // Packed words are copied out and back as Lsb0 bytes, 8 bits per byte
+    for &capacity in &[1_000_000, 10_000_000] {
+        let storage = AtomicBitVec::new(capacity);
+        for idx in generate_random_indices(capacity / 10, capacity) {
+            storage.set(idx);
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("read_bytes", capacity),
+            &capacity,
+            |b, &cap| b.iter(|| storage.read_bytes(0, cap)),
+        );
+
+        let bytes = storage.read_bytes(0, capacity);
+        let restored = AtomicBitVec::new(capacity);
+        group.bench_with_input(
+            BenchmarkId::new("write_bytes", capacity),
+            &bytes,
+            |b, bytes| b.iter(|| restored.write_bytes(0, bytes)),
+        );
+    }
+
+    group.finish();
+}
+
+// Thread body for the concurrent benchmark; the role depends on `id`
+fn run_worker(id: usize, storage: &[AtomicBitVec], cap: usize) {
+    const OPERATIONS_PER_THREAD: usize = 1000;
+
+    let mut rng = rand::rng();
+    match id % 3 {
+        0 => {
+            // Writer threads - write to current level
+            for _ in 0..OPERATIONS_PER_THREAD {
+                for idx in generate_random_indices(10, cap) {
+                    storage[0].set(idx);
+                }
+            }
+        }
+        1 => {
+            // Reader threads - read from random levels
+            for _ in 0..OPERATIONS_PER_THREAD {
+                let level = &storage[rng.random_range(0..storage.len())];
+                let indices = generate_random_indices(10, cap);
+                let _ = indices.iter().all(|&idx| level.get(idx));
+            }
+        }
+        _ => {
+            // Mixed operation threads - both read and clear
+            for op_num in 0..OPERATIONS_PER_THREAD {
+                if op_num % 10 == 0 {
+                    // Occasionally clear a random old level
+                    storage[rng.random_range(1..storage.len())].clear();
+                } else {
+                    // Mostly read operations
+                    let indices = generate_random_indices(5, cap);
+                    for level in storage {
+                        let _ = indices.iter().all(|&idx| level.get(idx));
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn bench_concurrent_access(c: &mut Criterion) {
     let mut group = c.benchmark_group("storage_concurrent_access");
     group.sample_size(10); // Reduce sample size for complex threading benchmarks
 
     // Test parameters
     const NUM_THREADS: usize = 8;
-    const OPERATIONS_PER_THREAD: usize = 1000;
     const NUM_LEVELS: usize = 5;
 
     // Test different storage capacities
@@ -95,19 +170,15 @@ fn bench_concurrent_access(c: &mut Criterion) {
             |b, &cap| {
                 b.iter_batched(
                     || {
-                        // Setup: Create storage wrapped in Arc+RwLock for thread sharing
-                        let storage = Arc::new(RwLock::new(
-                            InMemoryStorage::new(cap, NUM_LEVELS).unwrap(),
-                        ));
+                        // Setup: Levels are lock-free, shared through an Arc
+                        let storage = Arc::new(new_levels(cap, NUM_LEVELS));
 
                         // Pre-populate with some data
                         let indices = generate_random_indices(cap / 10, cap);
-                        for level in 0..NUM_LEVELS {
-                            storage
-                                .write()
-                                .unwrap()
-                                .set_bits(level, &indices)
-                                .unwrap();
+                        for level in storage.iter() {
+                            for &idx in &indices {
+                                level.set(idx);
+                            }
                         }
 
                         storage
@@ -125,70 +196,7 @@ fn bench_concurrent_access(c: &mut Criterion) {
                                 thread::spawn(move || {
                                     // Wait for all threads to be ready
                                     barrier.wait();
-
-                                    // Determine thread role
-                                    match id % 3 {
-                                        0 => {
-                                            // Writer threads - write to current level
-                                            for _ in 0..OPERATIONS_PER_THREAD {
-                                                let indices =
-                                                    generate_random_indices(
-                                                        10, cap,
-                                                    );
-                                                let _ = storage
-                                                    .write()
-                                                    .unwrap()
-                                                    .set_bits(0, &indices);
-                                            }
-                                        }
-                                        1 => {
-                                            // Reader threads - read from random levels
-                                            let mut rng = rand::rng();
-                                            for _ in 0..OPERATIONS_PER_THREAD {
-                                                let level = rng
-                                                    .random_range(0..NUM_LEVELS);
-                                                let indices =
-                                                    generate_random_indices(
-                                                        10, cap,
-                                                    );
-                                                let _ = storage
-                                                    .read()
-                                                    .unwrap()
-                                                    .get_bits(level, &indices);
-                                            }
-                                        }
-                                        _ => {
-                                            // Mixed operation threads - both read and clear
-                                            let mut rng = rand::rng();
-                                            for op_num in 0..OPERATIONS_PER_THREAD
-                                            {
-                                                if op_num % 10 == 0 {
-                                                    // Occasionally clear a random old level
-                                                    let level = rng.random_range(
-                                                        1..NUM_LEVELS,
-                                                    );
-                                                    let _ = storage
-                                                        .write()
-                                                        .unwrap()
-                                                        .clear_level(level);
-                                                } else {
-                                                    // Mostly read operations
-                                                    let indices =
-                                                        generate_random_indices(
-                                                            5, cap,
-                                                        );
-                                                    for level in 0..NUM_LEVELS {
-                                                        let _ = storage
-                                                            .read()
-                                                            .unwrap()
-                                                            .get_bits(
-                                                                level, &indices,
-                                                            );
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
+                                    run_worker(id, &storage, cap);
                                 })
                             })
                             .collect();
@@ -214,6 +222,7 @@ criterion_group!(
     benches,
     bench_set_bits,
     bench_get_bits,
+    bench_snapshot_bytes,
     bench_concurrent_access
 );
 criterion_main!(benches);
@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // gRPC stubs need `protoc` on the PATH (or PROTOC set)
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/ebloom.proto")?;
+    Ok(())
+}
@@ -92,6 +92,7 @@ async fn basic_workflow_example() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     println!("  Inserted elements inserted: {}", filter.insert_count());
+    println!("  Fill ratio: {:.4}", filter.fill_ratio());
 
     Ok(())
 }
@@ -0,0 +1,44 @@
+//! Shared expiring filter served over gRPC
+//!
+//! Run with `cargo run --example grpc_server --features grpc`, then call it
+//! with any client generated from `proto/ebloom.proto`, e.g.
+//! `grpcurl -plaintext -import-path proto -proto ebloom.proto \
+//!  -d '{"item": "aGVsbG8="}' 127.0.0.1:50051 ebloom.v1.ExpiringBloom/Insert`.
+use std::sync::Arc;
+use std::time::Duration;
+
+use probabilistic_rs::ebloom::{
+    config::ExpiringFilterConfigBuilder, filter::ExpiringBloomFilter,
+};
+use probabilistic_rs::grpc::ExpiringBloomService;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ExpiringFilterConfigBuilder::default()
+        .capacity_per_level(1_000_000_usize)
+        .target_fpr(0.01)
+        .num_levels(4_usize)
+        .level_duration(Duration::from_secs(60))
+        .build()?;
+    let filter = Arc::new(ExpiringBloomFilter::new(config)?);
+
+    // Rotate expired windows in the background
+    let cleanup = Arc::clone(&filter);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            if let Err(e) = cleanup.cleanup_expired_levels().await {
+                eprintln!("cleanup failed: {e}");
+            }
+        }
+    });
+
+    let addr = "127.0.0.1:50051".parse()?;
+    println!("Serving ebloom.v1.ExpiringBloom on {addr}");
+    tonic::transport::Server::builder()
+        .add_service(ExpiringBloomService::new(filter).into_server())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
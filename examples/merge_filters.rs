@@ -0,0 +1,34 @@
+//! Merge persisted expiring filters into one database
+//!
+//! Run with `cargo run --example merge_filters -- <output> <input>...`, e.g.
+//! to consolidate per-worker filters once a batch job is done:
+//! `cargo run --example merge_filters -- merged.fjall worker_*.fjall`.
+//! Every input must have the same config and be on the same level.
+use std::path::PathBuf;
+
+use probabilistic_rs::ebloom::{
+    filter::ExpiringBloomFilter, traits::ExpiringBloomFilterStats,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1).map(PathBuf::from);
+    let Some(output) = args.next() else {
+        return Err("usage: merge_filters <output> <input>...".into());
+    };
+    let inputs: Vec<PathBuf> = args.collect();
+    if inputs.is_empty() {
+        return Err("usage: merge_filters <output> <input>...".into());
+    }
+
+    let merged =
+        ExpiringBloomFilter::merge_persisted(&inputs, output.clone()).await?;
+    println!(
+        "Merged {} filters into {:?}: {} inserts, current level {}",
+        inputs.len(),
+        output,
+        merged.total_insert_count(),
+        merged.get_active_level(),
+    );
+    Ok(())
+}
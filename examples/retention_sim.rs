@@ -0,0 +1,51 @@
+//! Measure how long keys actually stay in an expiring filter
+//!
+//! Run with `cargo run --example retention_sim --features test_support`.
+//! The filter runs on a mock clock, so simulating hours takes a moment.
+use std::time::Duration;
+
+use probabilistic_rs::ebloom::config::ExpiringFilterConfigBuilder;
+use probabilistic_rs::ebloom::test_support::retention::{
+    RetentionWorkload, simulate_retention,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ExpiringFilterConfigBuilder::default()
+        .capacity_per_level(10_000_usize)
+        .target_fpr(0.001)
+        .num_levels(4_usize)
+        .level_duration(Duration::from_secs(15 * 60))
+        .build()?;
+
+    // One insert a minute over a day, probed every 10 seconds
+    let workload = RetentionWorkload {
+        inserts: 24 * 60,
+        insert_every: Duration::from_secs(60),
+        step: Duration::from_secs(10),
+        seed: 42,
+    };
+    let report = simulate_retention(config, workload).await?;
+
+    let minutes = |ms: u64| ms as f64 / 60_000.0;
+    println!(
+        "Configured window: {:.1} min",
+        minutes(report.configured_ms)
+    );
+    println!(
+        "Observed retention: {:.1} - {:.1} min, mean {:.1} min (±{:.2} min)",
+        minutes(report.min_ms.unwrap_or(0)),
+        minutes(report.max_ms.unwrap_or(0)),
+        report.mean_ms.unwrap_or(0.0) / 60_000.0,
+        minutes(report.resolution_ms),
+    );
+    println!(
+        "Expired early: {} (up to {:.1} min), late: {} (up to {:.1} min)",
+        report.expired_early,
+        minutes(report.early_by_max_ms()),
+        report.expired_late,
+        minutes(report.late_by_max_ms()),
+    );
+    println!("Never expired: {}", report.censored);
+    Ok(())
+}
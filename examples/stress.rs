@@ -0,0 +1,299 @@
+//! Configurable stress test for `ExpiringBloomFilter` backends
+//!
+//! Runs a mixed insert/query workload against each backend and prints
+//! throughput, latency percentiles and the measured false positive rate:
+//!
+//! ```bash
+//! cargo run --release --example stress -- --keys 1000000 --read-ratio 0.9
+//! cargo run --release --example stress -- --backend fjall --rotate-every 100000
+//! ```
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
+use comfy_table::{Table, presets::UTF8_FULL};
+use probabilistic_rs::ebloom::{
+    config::{
+        ExpiringFilterConfig, ExpiringFilterConfigBuilder,
+        ExpiringPersistenceConfigBuilder, LevelBacking,
+    },
+    filter::ExpiringBloomFilter,
+    traits::ExpiringBloomFilterOps,
+};
+use probabilistic_rs::{FprReport, measure_fpr};
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// In-memory levels on the heap
+    Memory,
+    /// Anonymous memory-mapped levels (`mmap` feature)
+    Mmap,
+    /// Fjall database, dirty chunks saved on snapshot
+    Fjall,
+    /// Fjall database fed by the write-behind queue
+    WriteBehind,
+    /// Every backend above, one after another
+    All,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Stress test ExpiringBloomFilter backends")]
+struct Args {
+    /// Operations in the mixed workload, and negative keys for the FPR run
+    #[arg(long, default_value_t = 200_000)]
+    keys: usize,
+    /// Key length in bytes; the first 9 bytes keep keys unique
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(9..))]
+    key_size: u16,
+    /// Fraction of operations that are queries of an already inserted key
+    #[arg(long, default_value_t = 0.5)]
+    read_ratio: f64,
+    /// Rotate levels after this many operations; 0 never rotates
+    #[arg(long, default_value_t = 0)]
+    rotate_every: usize,
+    /// Items per level the filter is sized for
+    #[arg(long, default_value_t = 1_000_000)]
+    capacity: usize,
+    #[arg(long, default_value_t = 0.01)]
+    fpr: f64,
+    #[arg(long, default_value_t = 3)]
+    levels: usize,
+    #[arg(long, value_enum, default_value_t = Backend::All)]
+    backend: Backend,
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+}
+
+/// Measurements of one backend run
+struct RunReport {
+    backend: Backend,
+    elapsed: Duration,
+    ops: usize,
+    inserts: Vec<Duration>,
+    queries: Vec<Duration>,
+    rotations: Vec<Duration>,
+    snapshot: Option<Duration>,
+    fpr: FprReport,
+}
+
+/// Key `index` of the inserted (`tag` 0) or negative (`tag` 1) set
+fn make_key(rng: &mut StdRng, index: usize, tag: u8, size: usize) -> Vec<u8> {
+    let mut key = vec![0; size];
+    rng.fill_bytes(&mut key[9..]);
+    key[..8].copy_from_slice(&(index as u64).to_le_bytes());
+    key[8] = tag;
+    key
+}
+
+fn percentile(samples: &mut [Duration], p: f64) -> Duration {
+    if samples.is_empty() {
+        return Duration::ZERO;
+    }
+    samples.sort_unstable();
+    let rank = ((samples.len() - 1) as f64 * p).round() as usize;
+    samples[rank]
+}
+
+fn db_path(backend: Backend) -> PathBuf {
+    std::env::temp_dir().join(format!("ebloom_stress_{backend:?}.fjall"))
+}
+
+fn config_for(
+    args: &Args,
+    backend: Backend,
+) -> Result<ExpiringFilterConfig, Box<dyn std::error::Error>> {
+    let mut builder = ExpiringFilterConfigBuilder::default();
+    builder
+        .capacity_per_level(args.capacity)
+        .target_fpr(args.fpr)
+        .num_levels(args.levels)
+        .level_duration(Duration::from_secs(3600));
+    match backend {
+        Backend::Memory | Backend::All => {}
+        Backend::Mmap => {
+            builder.level_backing(LevelBacking::AnonymousMmap);
+        }
+        Backend::Fjall | Backend::WriteBehind => {
+            let write_behind =
+                (backend == Backend::WriteBehind).then_some(64 * 1024);
+            builder.persistence(Some(
+                ExpiringPersistenceConfigBuilder::default()
+                    .db_path(db_path(backend))
+                    .write_behind_capacity(write_behind)
+                    .build()?,
+            ));
+        }
+    }
+    Ok(builder.build()?)
+}
+
+async fn run(
+    args: &Args,
+    backend: Backend,
+) -> Result<RunReport, Box<dyn std::error::Error>> {
+    let config = config_for(args, backend)?;
+    let persistent = config.persistence.is_some();
+    let filter = ExpiringBloomFilter::create(config).await?;
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let key_size = args.key_size as usize;
+    let keys: Vec<Vec<u8>> = (0..args.keys)
+        .map(|i| make_key(&mut rng, i, 0, key_size))
+        .collect();
+
+    let mut inserts = Vec::new();
+    let mut queries = Vec::new();
+    let mut rotations = Vec::new();
+    let mut inserted = 0;
+    let start = Instant::now();
+    for op in 0..args.keys {
+        if args.rotate_every > 0 && op > 0 && op % args.rotate_every == 0 {
+            let started = Instant::now();
+            filter.rotate_levels().await?;
+            rotations.push(started.elapsed());
+        }
+        if inserted > 0 && rng.random::<f64>() < args.read_ratio {
+            let key = &keys[rng.random_range(0..inserted)];
+            let started = Instant::now();
+            filter.contains(key)?;
+            queries.push(started.elapsed());
+        } else {
+            let started = Instant::now();
+            filter.insert(&keys[inserted])?;
+            inserts.push(started.elapsed());
+            inserted += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let snapshot = if persistent {
+        let started = Instant::now();
+        filter.save_snapshot().await?;
+        filter.flush().await?;
+        Some(started.elapsed())
+    } else {
+        None
+    };
+
+    let negatives = (0..args.keys).map(|i| make_key(&mut rng, i, 1, key_size));
+    let fpr = measure_fpr(&filter, negatives)?;
+
+    drop(filter);
+    if persistent {
+        let _ = std::fs::remove_dir_all(db_path(backend));
+    }
+
+    Ok(RunReport {
+        backend,
+        elapsed,
+        ops: args.keys,
+        inserts,
+        queries,
+        rotations,
+        snapshot,
+        fpr,
+    })
+}
+
+fn print_reports(args: &Args, reports: &mut [RunReport]) {
+    println!(
+        "{} ops, {}-byte keys, {:.0}% reads, rotate every {}, {} levels of {} at FPR {}",
+        args.keys,
+        args.key_size,
+        args.read_ratio * 100.0,
+        if args.rotate_every == 0 {
+            "never".to_string()
+        } else {
+            format!("{} ops", args.rotate_every)
+        },
+        args.levels,
+        args.capacity,
+        args.fpr,
+    );
+
+    let mut throughput = Table::new();
+    throughput.load_preset(UTF8_FULL).set_header(vec![
+        "Backend",
+        "Ops/s",
+        "Insert p50",
+        "Insert p99",
+        "Query p50",
+        "Query p99",
+        "Rotation p99",
+        "Snapshot",
+    ]);
+    for report in reports.iter_mut() {
+        let ops_per_sec = report.ops as f64 / report.elapsed.as_secs_f64();
+        throughput.add_row(vec![
+            format!("{:?}", report.backend),
+            format!("{ops_per_sec:.0}"),
+            format!("{:?}", percentile(&mut report.inserts, 0.5)),
+            format!("{:?}", percentile(&mut report.inserts, 0.99)),
+            format!("{:?}", percentile(&mut report.queries, 0.5)),
+            format!("{:?}", percentile(&mut report.queries, 0.99)),
+            format!("{:?}", percentile(&mut report.rotations, 0.99)),
+            report
+                .snapshot
+                .map_or_else(|| "-".to_string(), |d| format!("{d:?}")),
+        ]);
+    }
+    println!("{throughput}");
+
+    let mut fpr = Table::new();
+    fpr.load_preset(UTF8_FULL).set_header(vec![
+        "Backend",
+        "Queries",
+        "False positives",
+        "FPR",
+        "95% CI",
+        "Target",
+    ]);
+    for report in reports.iter() {
+        fpr.add_row(vec![
+            format!("{:?}", report.backend),
+            report.fpr.queries.to_string(),
+            report.fpr.false_positives.to_string(),
+            format!("{:.4}%", report.fpr.rate * 100.0),
+            format!(
+                "{:.4}% - {:.4}%",
+                report.fpr.ci_low * 100.0,
+                report.fpr.ci_high * 100.0
+            ),
+            if report.fpr.exceeds(args.fpr) {
+                "exceeded".to_string()
+            } else {
+                "ok".to_string()
+            },
+        ]);
+    }
+    println!("{fpr}");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    if !(0.0..=1.0).contains(&args.read_ratio) {
+        return Err("--read-ratio must be between 0 and 1".into());
+    }
+
+    let backends = match args.backend {
+        Backend::All if cfg!(feature = "mmap") => vec![
+            Backend::Memory,
+            Backend::Mmap,
+            Backend::Fjall,
+            Backend::WriteBehind,
+        ],
+        Backend::All => {
+            vec![Backend::Memory, Backend::Fjall, Backend::WriteBehind]
+        }
+        backend => vec![backend],
+    };
+
+    let mut reports = Vec::new();
+    for backend in backends {
+        reports.push(run(&args, backend).await?);
+    }
+    print_reports(&args, &mut reports);
+    Ok(())
+}
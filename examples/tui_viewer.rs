@@ -0,0 +1,52 @@
+//! Live view of an expiring filter while items stream in
+//!
+//! Run with `cargo run --example tui_viewer --features tui`.
+use std::time::Duration;
+
+use probabilistic_rs::ebloom::{
+    config::ExpiringFilterConfigBuilder, filter::ExpiringBloomFilter,
+    traits::ExpiringBloomFilterOps,
+};
+use probabilistic_rs::tui::{App, ui};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ExpiringFilterConfigBuilder::default()
+        .capacity_per_level(50_000_usize)
+        .target_fpr(0.01)
+        .num_levels(4_usize)
+        .level_duration(Duration::from_secs(10))
+        .build()?;
+    let filter = ExpiringBloomFilter::new(config)?;
+
+    let mut terminal = ratatui::init();
+    let mut app = App::new();
+    let mut next_item = 0u64;
+    let result = loop {
+        // Feed a steady stream so the levels visibly fill and rotate
+        for _ in 0..500 {
+            filter.insert(&next_item.to_le_bytes())?;
+            next_item += 1;
+        }
+        filter.cleanup_expired_levels().await?;
+        app.refresh(&filter)?;
+
+        if let Err(e) = terminal.draw(|frame| ui(frame, &app)) {
+            break Err(e);
+        }
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+                KeyCode::Right => app.select_next_level(),
+                KeyCode::Left => app.select_previous_level(),
+                _ => {}
+            }
+        }
+    };
+    ratatui::restore();
+    Ok(result?)
+}
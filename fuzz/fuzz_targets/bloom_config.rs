@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use probabilistic_rs::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzz::bloom_config(data);
+});
@@ -0,0 +1,22 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use probabilistic_rs::fuzz;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    // Small sizes keep each run fast; chunk ids stay arbitrary
+    bit_len: u16,
+    chunk_size_bytes: u8,
+    chunks: Vec<(usize, Vec<u8>)>,
+}
+
+fuzz_target!(|input: Input| {
+    // Config validation rejects a zero chunk size before load gets here
+    let chunk_size_bytes = input.chunk_size_bytes.max(1) as usize;
+    let bit_len = input.bit_len as usize;
+
+    let _ = fuzz::bloom_chunks(bit_len, chunk_size_bytes, &input.chunks);
+    let _ = fuzz::level_chunks(bit_len, chunk_size_bytes, &input.chunks);
+});
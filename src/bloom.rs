@@ -1,17 +1,37 @@
 //! Standard Bloom Filter implementation
+pub mod admission;
+pub mod auto;
 pub mod config;
+pub mod denylist;
 pub mod error;
 pub mod filter;
+pub mod gobloom;
+#[cfg(feature = "pybloom")]
+pub mod pybloom;
+pub mod redisbloom;
+pub mod stats;
 #[cfg(feature = "fjall")]
 pub mod storage;
 pub mod traits;
 
+#[cfg(feature = "moka")]
+pub use admission::AdmittingCache;
+pub use admission::{TinyLfu, TinyLfuConfig, TinyLfuConfigBuilder};
+pub use auto::{
+    AutoBloomConfig, AutoBloomConfigBuilder, AutoBloomFilter, GrowthTrigger,
+};
 pub use config::{
     BloomFilterConfig, BloomFilterConfigBuilder, PersistenceConfig,
     PersistenceConfigBuilder,
 };
+pub use denylist::{DenylistFormat, StaticDenylist};
 pub use error::{BloomError, BloomResult};
 pub use filter::BloomFilter;
+pub use gobloom::GoBloomFilter;
+#[cfg(feature = "pybloom")]
+pub use pybloom::{PyBloomFilter, PyHashScheme, PyScalableBloomFilter};
+pub use redisbloom::RedisBloomFilter;
+pub use stats::{AutoBloomStats, BloomStats};
 pub use traits::{
     BloomFilterOps, BloomFilterStats, BulkBloomFilterOps, PersistentBloomFilter,
     StorageBackend,
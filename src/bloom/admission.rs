@@ -0,0 +1,313 @@
+//! Frequency-based cache admission
+//!
+//! A cache that admits every miss lets one-hit wonders push out keys that
+//! are requested again and again. [`TinyLfu`] keeps a short memory of how
+//! often each key was asked for: a [`BloomFilter`] doorkeeper absorbs the
+//! first access of a key, and a count-min sketch of 4-bit counters counts
+//! the later ones. Every `sample_size` accesses the counts are halved and
+//! the doorkeeper cleared, so the estimate follows a changing workload.
+//!
+//! `admit(key)` is the predicate: a key is admitted once it was requested
+//! `min_frequency` times in the current sample. With the `moka` feature,
+//! [`TinyLfu::wrap`] puts it in front of a `moka::sync::Cache`:
+//!
+//! ```ignore
+//! let cache = TinyLfu::new(TinyLfuConfigBuilder::default().build()?)?
+//!     .wrap(moka::sync::Cache::new(10_000));
+//! let value = cache.get_with(key, || load(&key));
+//! ```
+
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BloomError, BloomFilter, BloomFilterConfigBuilder, BloomFilterOps,
+    BloomResult,
+};
+use crate::hash::hash_murmur64;
+
+/// Rows of the frequency sketch
+const SKETCH_DEPTH: usize = 4;
+
+/// Largest count a sketch counter holds
+const MAX_COUNT: u8 = 15;
+
+/// Sample size per expected item when `sample_size` is left at 0
+const SAMPLE_PER_ITEM: usize = 10;
+
+#[derive(Clone, Debug, Builder, Serialize, Deserialize)]
+#[builder(pattern = "owned")]
+pub struct TinyLfuConfig {
+    /// Distinct keys expected per sample; sizes the doorkeeper and sketch
+    #[builder(default = "100_000")]
+    pub expected_items: usize,
+
+    /// Accesses in the current sample a key needs to be admitted
+    #[builder(default = "2")]
+    pub min_frequency: u8,
+
+    /// Accesses after which counts are halved and the doorkeeper cleared;
+    /// 0 means `10 * expected_items`
+    #[builder(default = "0")]
+    pub sample_size: usize,
+}
+
+impl TinyLfuConfig {
+    pub fn validate(&self) -> BloomResult<()> {
+        if self.expected_items == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        // The doorkeeper adds one to the largest sketch count
+        if self.min_frequency == 0 || self.min_frequency > MAX_COUNT + 1 {
+            return Err(BloomError::InvalidConfig(format!(
+                "Min frequency must be between 1 and {}",
+                MAX_COUNT + 1
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Count-min sketch of saturating 4-bit counters, one byte each
+struct FrequencySketch {
+    /// `SKETCH_DEPTH` rows of `width` counters
+    counters: Vec<AtomicU8>,
+    width: usize,
+}
+
+impl FrequencySketch {
+    fn new(expected_items: usize) -> Self {
+        let width = expected_items.next_power_of_two().max(16);
+        Self {
+            counters: (0..SKETCH_DEPTH * width)
+                .map(|_| AtomicU8::new(0))
+                .collect(),
+            width,
+        }
+    }
+
+    /// Counter of `hash` in each row, by double hashing
+    fn slots(&self, hash: u64) -> [usize; SKETCH_DEPTH] {
+        let h1 = hash as usize;
+        let h2 = (hash >> 32) as usize | 1;
+        std::array::from_fn(|row| {
+            row * self.width
+                + (h1.wrapping_add(row.wrapping_mul(h2)) & (self.width - 1))
+        })
+    }
+
+    fn estimate(&self, hash: u64) -> u8 {
+        self.slots(hash)
+            .iter()
+            .map(|&slot| self.counters[slot].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Conservative update: only the counters at the minimum are raised,
+    /// which keeps collisions from inflating the estimate
+    fn increment(&self, hash: u64) {
+        let slots = self.slots(hash);
+        let min = slots
+            .iter()
+            .map(|&slot| self.counters[slot].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0);
+        if min >= MAX_COUNT {
+            return;
+        }
+        for slot in slots {
+            let _ = self.counters[slot].compare_exchange(
+                min,
+                min + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    fn halve(&self) {
+        for counter in &self.counters {
+            let _ =
+                counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                    Some(c / 2)
+                });
+        }
+    }
+}
+
+/// Admission policy: admit keys requested often enough recently
+pub struct TinyLfu {
+    config: TinyLfuConfig,
+    doorkeeper: BloomFilter,
+    sketch: FrequencySketch,
+    /// Accesses recorded since the last aging
+    accesses: AtomicUsize,
+    sample_size: usize,
+}
+
+impl TinyLfu {
+    pub fn new(config: TinyLfuConfig) -> BloomResult<Self> {
+        config.validate()?;
+        let doorkeeper = BloomFilter::new(
+            BloomFilterConfigBuilder::default()
+                .capacity(config.expected_items)
+                .false_positive_rate(0.01)
+                .build()
+                .map_err(|e| BloomError::InvalidConfig(e.to_string()))?,
+        )?;
+        let sample_size = match config.sample_size {
+            0 => config.expected_items.saturating_mul(SAMPLE_PER_ITEM),
+            size => size,
+        };
+        Ok(Self {
+            sketch: FrequencySketch::new(config.expected_items),
+            config,
+            doorkeeper,
+            accesses: AtomicUsize::new(0),
+            sample_size,
+        })
+    }
+
+    pub fn config(&self) -> &TinyLfuConfig {
+        &self.config
+    }
+
+    /// Count one access of `key`
+    pub fn record(&self, key: &[u8]) -> BloomResult<()> {
+        if self.doorkeeper.contains(key)? {
+            self.sketch.increment(hash_murmur64(key));
+        } else {
+            self.doorkeeper.insert(key)?;
+        }
+        let accesses = self.accesses.fetch_add(1, Ordering::Relaxed) + 1;
+        // Only the access that wins the reset ages the counts
+        if accesses >= self.sample_size
+            && self
+                .accesses
+                .compare_exchange(
+                    accesses,
+                    0,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            self.age()?;
+        }
+        Ok(())
+    }
+
+    /// Estimated accesses of `key` in the current sample, at most 16
+    pub fn frequency(&self, key: &[u8]) -> BloomResult<u8> {
+        let seen = self.doorkeeper.contains(key)?;
+        Ok(self.sketch.estimate(hash_murmur64(key)) + seen as u8)
+    }
+
+    /// Whether `key` was requested at least `min_frequency` times
+    pub fn admit(&self, key: &[u8]) -> BloomResult<bool> {
+        Ok(self.frequency(key)? >= self.config.min_frequency)
+    }
+
+    /// Whether `candidate` should replace `victim` in a full cache
+    pub fn admit_over(
+        &self,
+        candidate: &[u8],
+        victim: &[u8],
+    ) -> BloomResult<bool> {
+        Ok(self.frequency(candidate)? > self.frequency(victim)?)
+    }
+
+    /// Halve every count and clear the doorkeeper
+    fn age(&self) -> BloomResult<()> {
+        self.sketch.halve();
+        self.doorkeeper.clear()
+    }
+
+    /// Put this policy in front of `cache`
+    #[cfg(feature = "moka")]
+    pub fn wrap<K, V>(
+        self,
+        cache: moka::sync::Cache<K, V>,
+    ) -> AdmittingCache<K, V>
+    where
+        K: std::hash::Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        AdmittingCache::new(cache, self)
+    }
+}
+
+/// `moka::sync::Cache` that only stores values of frequently requested keys
+///
+/// `get` and `get_with` count an access of the key; `insert` stores the
+/// value only if the key is admitted or already cached. Keys are hashed
+/// with a per-cache `RandomState` before they reach the policy.
+#[cfg(feature = "moka")]
+pub struct AdmittingCache<K, V> {
+    cache: moka::sync::Cache<K, V>,
+    admission: TinyLfu,
+    hasher: std::hash::RandomState,
+}
+
+#[cfg(feature = "moka")]
+impl<K, V> AdmittingCache<K, V>
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(cache: moka::sync::Cache<K, V>, admission: TinyLfu) -> Self {
+        Self {
+            cache,
+            admission,
+            hasher: std::hash::RandomState::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &moka::sync::Cache<K, V> {
+        &self.cache
+    }
+
+    pub fn admission(&self) -> &TinyLfu {
+        &self.admission
+    }
+
+    /// Look `key` up, counting the access towards its admission
+    pub fn get(&self, key: &K) -> BloomResult<Option<V>> {
+        self.admission.record(&self.key_bytes(key))?;
+        Ok(self.cache.get(key))
+    }
+
+    /// Store `value` if `key` is admitted or already cached; returns
+    /// whether it was stored
+    pub fn insert(&self, key: K, value: V) -> BloomResult<bool> {
+        let admitted = self.cache.contains_key(&key)
+            || self.admission.admit(&self.key_bytes(&key))?;
+        if admitted {
+            self.cache.insert(key, value);
+        }
+        Ok(admitted)
+    }
+
+    /// `get`, computing the value with `init` on a miss and storing it if
+    /// the key is admitted
+    pub fn get_with(&self, key: K, init: impl FnOnce() -> V) -> BloomResult<V> {
+        if let Some(value) = self.get(&key)? {
+            return Ok(value);
+        }
+        let value = init();
+        self.insert(key, value.clone())?;
+        Ok(value)
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        self.cache.invalidate(key);
+    }
+
+    fn key_bytes(&self, key: &K) -> [u8; 8] {
+        use std::hash::BuildHasher;
+        self.hasher.hash_one(key).to_le_bytes()
+    }
+}
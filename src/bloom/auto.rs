@@ -0,0 +1,313 @@
+//! Bloom filter that grows with its input
+//!
+//! [`AutoBloomFilter`] starts with a single [`BloomFilter`] slice and chains
+//! a larger one whenever the newest slice is full, so an ingest volume that
+//! is hard to predict no longer pushes the false positive rate past its
+//! target. Queries check every slice.
+//!
+//! Slice `i` holds `initial_capacity * growth_factor^i` items at
+//! `false_positive_rate * (1 - tightening_ratio) * tightening_ratio^i`.
+//! The slice rates add up to at most the configured rate however many
+//! slices are chained, at the cost of a few more bits per item than a
+//! single filter sized up front.
+//!
+//! ```ignore
+//! let filter = AutoBloomFilter::new(AutoBloomConfigBuilder::default()
+//!     .initial_capacity(10_000)
+//!     .build()?)?;
+//! for key in unbounded_stream {
+//!     filter.insert(key)?;
+//! }
+//! println!("{} slices", filter.num_slices());
+//! ```
+
+use std::sync::{Arc, RwLock};
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BloomError, BloomFilter, BloomFilterConfigBuilder, BloomFilterOps,
+    BloomFilterStats, BloomResult, BulkBloomFilterOps, stats::AutoBloomStats,
+};
+
+/// Inserts between two fill ratio checks of the newest slice; the check
+/// counts every bit, so it is not done on each insert
+pub const FILL_CHECK_INTERVAL: usize = 1024;
+
+/// When the newest slice is considered full
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GrowthTrigger {
+    /// Once it holds `capacity` distinct items
+    Capacity,
+    /// Once this fraction of its bits is set, or it holds `capacity`
+    /// items, whichever comes first. Checked every `FILL_CHECK_INTERVAL`
+    /// inserts.
+    FillRatio(f64),
+}
+
+#[derive(Clone, Debug, Builder, Serialize, Deserialize)]
+#[builder(pattern = "owned")]
+pub struct AutoBloomConfig {
+    /// Items the first slice is sized for
+    #[builder(default = "100_000")]
+    pub initial_capacity: usize,
+
+    /// Target false positive rate across all slices
+    #[builder(default = "0.01")]
+    pub false_positive_rate: f64,
+
+    /// Capacity of each new slice relative to the previous one
+    #[builder(default = "2")]
+    pub growth_factor: usize,
+
+    /// False positive rate of each new slice relative to the previous one
+    #[builder(default = "0.9")]
+    pub tightening_ratio: f64,
+
+    #[builder(default = "GrowthTrigger::Capacity")]
+    pub growth_trigger: GrowthTrigger,
+}
+
+impl AutoBloomConfig {
+    pub fn validate(&self) -> BloomResult<()> {
+        if self.initial_capacity == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        // Written so that NaN fails too
+        if !(self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0) {
+            return Err(BloomError::InvalidFalsePositiveRate {
+                rate: self.false_positive_rate,
+            });
+        }
+        if self.growth_factor == 0 {
+            return Err(BloomError::InvalidConfig(
+                "Growth factor must be > 0".into(),
+            ));
+        }
+        if !(self.tightening_ratio > 0.0 && self.tightening_ratio < 1.0) {
+            return Err(BloomError::InvalidConfig(
+                "Tightening ratio must be between 0 and 1".into(),
+            ));
+        }
+        if let GrowthTrigger::FillRatio(ratio) = self.growth_trigger
+            && !(ratio > 0.0 && ratio <= 1.0)
+        {
+            return Err(BloomError::InvalidConfig(
+                "Growth fill ratio must be in (0, 1]".into(),
+            ));
+        }
+        self.slice(0).map(|_| ())
+    }
+
+    /// Filter for slice `index`
+    fn slice(&self, index: usize) -> BloomResult<BloomFilter> {
+        let too_large = || {
+            BloomError::InvalidConfig(format!(
+                "Slice {index} is too large to allocate"
+            ))
+        };
+        let exponent = u32::try_from(index).map_err(|_| too_large())?;
+        let capacity = self
+            .growth_factor
+            .checked_pow(exponent)
+            .and_then(|factor| factor.checked_mul(self.initial_capacity))
+            .ok_or_else(too_large)?;
+        let false_positive_rate = self.false_positive_rate
+            * (1.0 - self.tightening_ratio)
+            * self.tightening_ratio.powi(exponent as i32);
+        let config = BloomFilterConfigBuilder::default()
+            .capacity(capacity)
+            .false_positive_rate(false_positive_rate)
+            .build()
+            .map_err(|e| BloomError::InvalidConfig(e.to_string()))?;
+        BloomFilter::new(config)
+    }
+}
+
+/// In-memory bloom filter that chains slices as it fills up
+pub struct AutoBloomFilter {
+    config: AutoBloomConfig,
+    /// Oldest first; only the last one takes inserts
+    slices: RwLock<Vec<Arc<BloomFilter>>>,
+}
+
+impl AutoBloomFilter {
+    pub fn new(config: AutoBloomConfig) -> BloomResult<Self> {
+        config.validate()?;
+        let first = config.slice(0)?;
+        Ok(Self {
+            config,
+            slices: RwLock::new(vec![Arc::new(first)]),
+        })
+    }
+
+    pub fn config(&self) -> &AutoBloomConfig {
+        &self.config
+    }
+
+    pub fn num_slices(&self) -> usize {
+        self.read_slices().len()
+    }
+
+    /// Add `item` unless it is (probably) present already; `true` if it
+    /// was added
+    ///
+    /// Items already reported present are not inserted again, so repeats
+    /// do not use up slice capacity.
+    pub fn insert_new(&self, item: &[u8]) -> BloomResult<bool> {
+        let newest = {
+            let slices = self.read_slices();
+            for slice in slices.iter() {
+                if slice.contains(item)? {
+                    return Ok(false);
+                }
+            }
+            Arc::clone(slices.last().expect("at least one slice"))
+        };
+        let newest = if self.is_full(&newest) {
+            self.grow(&newest)?
+        } else {
+            newest
+        };
+        newest.insert(item)?;
+        Ok(true)
+    }
+
+    /// Per-slice and aggregate statistics
+    pub fn stats(&self) -> AutoBloomStats {
+        let slices: Vec<_> = self
+            .read_slices()
+            .iter()
+            .map(|slice| slice.stats())
+            .collect();
+        let never_false = slices
+            .iter()
+            .map(|s| 1.0 - s.estimated_fpr)
+            .product::<f64>();
+        AutoBloomStats {
+            target_fpr: self.config.false_positive_rate,
+            capacity: slices.iter().map(|s| s.capacity).sum(),
+            insert_count: slices.iter().map(|s| s.insert_count).sum(),
+            estimated_fpr: 1.0 - never_false,
+            memory_bytes: slices.iter().map(|s| s.memory_bytes).sum(),
+            slices,
+        }
+    }
+
+    fn read_slices(
+        &self,
+    ) -> std::sync::RwLockReadGuard<'_, Vec<Arc<BloomFilter>>> {
+        self.slices.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_full(&self, slice: &BloomFilter) -> bool {
+        let inserted = slice.insert_count();
+        if inserted >= slice.capacity() {
+            return true;
+        }
+        match self.config.growth_trigger {
+            GrowthTrigger::Capacity => false,
+            GrowthTrigger::FillRatio(ratio) => {
+                inserted > 0
+                    && inserted.is_multiple_of(FILL_CHECK_INTERVAL)
+                    && slice.fill_ratio() >= ratio
+            }
+        }
+    }
+
+    /// Chain a new slice after `full`, unless another insert already did
+    fn grow(&self, full: &Arc<BloomFilter>) -> BloomResult<Arc<BloomFilter>> {
+        let mut slices = self.slices.write().unwrap_or_else(|e| e.into_inner());
+        let newest = slices.last().expect("at least one slice");
+        if !Arc::ptr_eq(newest, full) {
+            return Ok(Arc::clone(newest));
+        }
+        let slice = Arc::new(self.config.slice(slices.len())?);
+        slices.push(Arc::clone(&slice));
+        Ok(slice)
+    }
+}
+
+impl BloomFilterOps for AutoBloomFilter {
+    fn insert(&self, item: &[u8]) -> BloomResult<()> {
+        self.insert_new(item).map(|_| ())
+    }
+
+    fn contains(&self, item: &[u8]) -> BloomResult<bool> {
+        for slice in self.read_slices().iter() {
+            if slice.contains(item)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Drop every slice and start over with the first one
+    fn clear(&self) -> BloomResult<()> {
+        let first = self.config.slice(0)?;
+        *self.slices.write().unwrap_or_else(|e| e.into_inner()) =
+            vec![Arc::new(first)];
+        Ok(())
+    }
+}
+
+impl BloomFilterStats for AutoBloomFilter {
+    /// Combined capacity of the slices so far
+    fn capacity(&self) -> usize {
+        self.read_slices()
+            .iter()
+            .map(|slice| slice.capacity())
+            .sum()
+    }
+
+    fn false_positive_rate(&self) -> f64 {
+        self.config.false_positive_rate
+    }
+
+    fn insert_count(&self) -> usize {
+        self.read_slices()
+            .iter()
+            .map(|slice| slice.insert_count())
+            .sum()
+    }
+
+    /// Combined bits of the slices so far
+    fn bit_vector_size(&self) -> usize {
+        self.read_slices()
+            .iter()
+            .map(|slice| slice.bit_vector_size)
+            .sum()
+    }
+
+    /// Probes of the newest slice, the one taking inserts; older slices
+    /// use fewer
+    fn num_hashes(&self) -> usize {
+        self.read_slices()
+            .last()
+            .map_or(0, |slice| slice.num_hashes)
+    }
+
+    fn bit_count(&self) -> usize {
+        self.read_slices()
+            .iter()
+            .map(|slice| slice.bit_count())
+            .sum()
+    }
+}
+
+impl BulkBloomFilterOps for AutoBloomFilter {
+    fn insert_bulk(&self, items: &[&[u8]]) -> BloomResult<()> {
+        items.iter().try_for_each(|item| self.insert(item))
+    }
+
+    fn contains_bulk(&self, items: &[&[u8]]) -> BloomResult<Vec<bool>> {
+        let slices = self.read_slices();
+        let mut found = vec![false; items.len()];
+        for slice in slices.iter() {
+            let hits = slice.contains_bulk(items)?;
+            found.iter_mut().zip(hits).for_each(|(f, hit)| *f |= hit);
+        }
+        Ok(found)
+    }
+}
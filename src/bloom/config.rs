@@ -1,4 +1,5 @@
 use super::{BloomError, BloomResult};
+use crate::hash::checked_filter_params;
 use bincode::{Decode, Encode};
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
@@ -35,11 +36,24 @@ impl BloomFilterConfig {
                 "Capacity must be > 0".into(),
             ));
         }
-        if self.false_positive_rate <= 0.0 || self.false_positive_rate >= 1.0 {
+        // Written so that NaN fails too
+        if !(self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0) {
             return Err(super::BloomError::InvalidConfig(
                 "FPR must be between 0 and 1".into(),
             ));
         }
+        if let Err(reason) =
+            checked_filter_params(self.capacity, self.false_positive_rate)
+        {
+            return Err(super::BloomError::InvalidConfig(reason));
+        }
+        if let Some(ref persistence) = self.persistence
+            && persistence.chunk_size_bytes == 0
+        {
+            return Err(super::BloomError::InvalidConfig(
+                "Chunk size must be > 0".into(),
+            ));
+        }
         Ok(())
     }
 
@@ -49,7 +63,7 @@ impl BloomFilterConfig {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> BloomResult<Self> {
-        bincode::decode_from_slice(bytes, bincode::config::standard())
+        bincode::decode_from_slice(bytes, crate::common::decode_config())
             .map(|(config, _)| config)
             .map_err(|e| BloomError::SerializationError(e.to_string()))
     }
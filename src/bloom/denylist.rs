@@ -0,0 +1,160 @@
+//! Read-only denylists loaded at startup
+//!
+//! Breached-password or malicious-domain lists are built offline, shipped
+//! as a filter file and only queried afterwards. [`StaticDenylist`] loads
+//! such a file once and answers [`StaticDenylist::maybe_listed`]: `false`
+//! means definitely not listed, `true` means listed or a false positive.
+//!
+//! Files use one of the interchange encodings of this module's siblings:
+//! Go `bits-and-blooms/bloom` (streamed, so large lists are never held in
+//! memory twice) or, with the `pybloom` feature, pybloom's `tofile`.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::bloom::error::{BloomError, BloomResult};
+use crate::bloom::gobloom::GoBloomFilter;
+#[cfg(feature = "pybloom")]
+use crate::bloom::pybloom::{PyBloomFilter, PyHashScheme, PyScalableBloomFilter};
+
+/// Encoding of a denylist file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenylistFormat {
+    /// Go `bits-and-blooms/bloom` `WriteTo` output
+    GoBloom,
+    /// pybloom `BloomFilter.tofile` output
+    #[cfg(feature = "pybloom")]
+    Pybloom(PyHashScheme),
+    /// pybloom `ScalableBloomFilter.tofile` output
+    #[cfg(feature = "pybloom")]
+    PybloomScalable(PyHashScheme),
+}
+
+enum Filter {
+    Go(GoBloomFilter),
+    #[cfg(feature = "pybloom")]
+    Py(PyBloomFilter),
+    #[cfg(feature = "pybloom")]
+    PyScalable(PyScalableBloomFilter),
+}
+
+/// Immutable membership filter over a prebuilt list
+pub struct StaticDenylist {
+    filter: Filter,
+}
+
+impl StaticDenylist {
+    /// Build a list from its entries, e.g. in the job that ships it; save
+    /// it with [`Self::save`] and load it with `DenylistFormat::GoBloom`
+    pub fn from_entries<I, T>(
+        entries: I,
+        expected: u64,
+        fpr: f64,
+    ) -> BloomResult<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut filter = GoBloomFilter::with_estimates(expected, fpr)?;
+        for entry in entries {
+            filter.insert(entry.as_ref());
+        }
+        Ok(Self {
+            filter: Filter::Go(filter),
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8], format: DenylistFormat) -> BloomResult<Self> {
+        let filter = match format {
+            DenylistFormat::GoBloom => {
+                Filter::Go(GoBloomFilter::from_bytes(bytes)?)
+            }
+            #[cfg(feature = "pybloom")]
+            DenylistFormat::Pybloom(scheme) => {
+                Filter::Py(PyBloomFilter::from_bytes(bytes, scheme)?)
+            }
+            #[cfg(feature = "pybloom")]
+            DenylistFormat::PybloomScalable(scheme) => Filter::PyScalable(
+                PyScalableBloomFilter::from_bytes(bytes, scheme)?,
+            ),
+        };
+        Ok(Self { filter })
+    }
+
+    /// Load a list from `reader`; Go-encoded lists are decoded as they
+    /// stream in
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        format: DenylistFormat,
+    ) -> BloomResult<Self> {
+        if format == DenylistFormat::GoBloom {
+            return Ok(Self {
+                filter: Filter::Go(GoBloomFilter::read_from(reader)?),
+            });
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| {
+            BloomError::StorageError(format!("Failed to read denylist: {e}"))
+        })?;
+        Self::from_bytes(&bytes, format)
+    }
+
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        format: DenylistFormat,
+    ) -> BloomResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            BloomError::StorageError(format!(
+                "Failed to open denylist {}: {e}",
+                path.display()
+            ))
+        })?;
+        Self::from_reader(BufReader::new(file), format)
+    }
+
+    /// Write a list built by [`Self::from_entries`] (or loaded from a Go
+    /// encoding) in the Go encoding
+    pub fn save(&self, path: impl AsRef<Path>) -> BloomResult<()> {
+        // Only irrefutable without `pybloom`
+        #[allow(clippy::infallible_destructuring_match)]
+        let filter = match &self.filter {
+            Filter::Go(filter) => filter,
+            #[cfg(feature = "pybloom")]
+            _ => {
+                return Err(BloomError::InvalidConfig(
+                    "Only Go-encoded denylists can be saved".to_string(),
+                ));
+            }
+        };
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            BloomError::StorageError(format!(
+                "Failed to create denylist {}: {e}",
+                path.display()
+            ))
+        })?;
+        let mut writer = BufWriter::new(file);
+        filter.write_to(&mut writer)?;
+        writer.flush().map_err(|e| {
+            BloomError::StorageError(format!("Failed to write denylist: {e}"))
+        })
+    }
+
+    /// Whether `item` may be on the list; `false` is definite
+    pub fn maybe_listed(&self, item: &[u8]) -> bool {
+        match &self.filter {
+            Filter::Go(filter) => filter.contains(item),
+            #[cfg(feature = "pybloom")]
+            Filter::Py(filter) => filter.contains(item),
+            #[cfg(feature = "pybloom")]
+            Filter::PyScalable(filter) => filter.contains(item),
+        }
+    }
+
+    /// [`Self::maybe_listed`] for each item
+    pub fn maybe_listed_bulk(&self, items: &[&[u8]]) -> Vec<bool> {
+        items.iter().map(|item| self.maybe_listed(item)).collect()
+    }
+}
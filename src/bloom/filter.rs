@@ -3,8 +3,15 @@ use super::{
     storage::FjallBackend,
 };
 use crate::{
-    bloom::traits::{BloomFilterStats, BulkBloomFilterOps},
-    hash::{default_hash_function, optimal_bit_vector_size, optimal_num_hashes},
+    bloom::{
+        stats::BloomStats,
+        traits::{BloomFilterStats, BulkBloomFilterOps},
+    },
+    common::Bitset,
+    hash::{
+        default_hash_function, default_hash_into, optimal_bit_vector_size,
+        optimal_num_hashes,
+    },
 };
 use bitvec::{bitvec, order::Lsb0, vec::BitVec};
 use tracing::{debug, info, warn};
@@ -17,6 +24,9 @@ use std::{
     },
 };
 
+/// Bytes per raw `BitVec` storage word
+const WORD_BYTES: usize = size_of::<usize>();
+
 pub struct BloomFilter {
     config: BloomFilterConfig,
     pub bit_vector_size: usize,
@@ -34,6 +44,17 @@ pub struct BloomFilter {
 }
 
 impl BloomFilter {
+    /// Creates an in-memory bloom filter; use `create` for persistence
+    pub fn new(config: BloomFilterConfig) -> BloomResult<Self> {
+        config.validate()?;
+        if config.persistence.is_some() {
+            return Err(BloomError::InvalidConfig(
+                "Persistent filters are built with `create`".into(),
+            ));
+        }
+        Self::build_filter(config, None)
+    }
+
     /// Creates a new bloom filter, optionally with persistence
     /// If persistence is enabled and DB exists, it will be overwritten
     pub async fn create(config: BloomFilterConfig) -> BloomResult<Self> {
@@ -81,7 +102,7 @@ impl BloomFilter {
             None
         };
 
-        Self::build_filter(config, storage).await
+        Self::build_filter(config, storage)
     }
 
     /// Loads an existing bloom filter from database
@@ -107,8 +128,10 @@ impl BloomFilter {
             loaded_config.false_positive_rate * 100.0
         );
 
+        loaded_config.validate()?;
+
         // Build filter with loaded config
-        let mut filter = Self::build_filter(loaded_config, Some(backend)).await?;
+        let mut filter = Self::build_filter(loaded_config, Some(backend))?;
 
         // Load snapshot data from DB
 
@@ -147,7 +170,7 @@ impl BloomFilter {
     }
 
     /// Internal helper to build the actual BloomFilter struct
-    async fn build_filter(
+    fn build_filter(
         config: BloomFilterConfig,
         storage: Option<FjallBackend>,
     ) -> BloomResult<Self> {
@@ -157,18 +180,18 @@ impl BloomFilter {
         let bits = Arc::new(RwLock::new(bitvec![0; bit_vector_size]));
 
         // Setup chunking if persistence enabled
-        let (chunk_size_bytes, dirty_chunks) = if config.persistence.is_some() {
-            let chunk_size =
-                config.persistence.as_ref().unwrap().chunk_size_bytes;
-            let chunk_count =
-                (bit_vector_size + chunk_size * 8 - 1).div_ceil(chunk_size * 8);
-            (
-                chunk_size,
-                Some(Arc::new(RwLock::new(bitvec![0; chunk_count]))),
-            )
-        } else {
-            (0, None)
-        };
+        let (chunk_size_bytes, dirty_chunks) =
+            if let Some(ref persistence) = config.persistence {
+                let chunk_size = persistence.chunk_size_bytes;
+                let chunk_count = (bit_vector_size + chunk_size * 8 - 1)
+                    .div_ceil(chunk_size * 8);
+                (
+                    chunk_size,
+                    Some(Arc::new(RwLock::new(bitvec![0; chunk_count]))),
+                )
+            } else {
+                (0, None)
+            };
 
         Ok(Self {
             config,
@@ -256,17 +279,25 @@ impl BloomFilter {
         }
 
         let end_bit = std::cmp::min(start_bit + chunk_size_bits, bits.len());
-        let chunk_bits = &bits[start_bit..end_bit];
-        let mut bytes = Vec::new();
-
-        for byte_chunk in chunk_bits.chunks(8) {
-            let mut byte = 0u8;
-            for (bit_pos, bit) in byte_chunk.iter().enumerate() {
-                if *bit {
-                    byte |= 1 << bit_pos;
-                }
-            }
-            bytes.push(byte);
+
+        // With `Lsb0` ordering the little-endian bytes of the raw words are
+        // exactly the canonical chunk layout, so whole words are copied out
+        let start_byte = start_bit / 8;
+        let end_byte = end_bit.div_ceil(8);
+        let words = &bits.as_raw_slice()
+            [start_byte / WORD_BYTES..end_byte.div_ceil(WORD_BYTES)];
+        let mut bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .skip(start_byte % WORD_BYTES)
+            .take(end_byte - start_byte)
+            .collect();
+
+        // Never leak bits past the end of the filter
+        if !end_bit.is_multiple_of(8)
+            && let Some(last) = bytes.last_mut()
+        {
+            *last &= (1u8 << (end_bit % 8)) - 1;
         }
 
         bytes
@@ -276,25 +307,9 @@ impl BloomFilter {
         &mut self,
         chunks: &[(usize, Vec<u8>)],
     ) -> BloomResult<()> {
-        let chunk_size_bits = self.chunk_size_bytes * 8;
-
         // Get write lock for the entire reconstruction
         let mut bits = self.bits.write().unwrap();
-
-        for (chunk_id, chunk_bytes) in chunks {
-            let start_bit = chunk_id * chunk_size_bits;
-
-            for (byte_idx, &byte) in chunk_bytes.iter().enumerate() {
-                for bit_pos in 0..8 {
-                    let bit_idx = start_bit + byte_idx * 8 + bit_pos;
-                    if bit_idx < bits.len() {
-                        // Use bits instead of self.bits
-                        let bit_value = (byte & (1 << bit_pos)) != 0;
-                        bits.set(bit_idx, bit_value); // Use bits instead of self.bits
-                    }
-                }
-            }
-        }
+        reconstruct_bits_from_chunks(&mut bits, chunks, self.chunk_size_bytes)?;
 
         debug!("Reconstructed filter from {} chunks", chunks.len());
         Ok(())
@@ -314,6 +329,71 @@ impl BloomFilter {
     pub fn bits_per_item(&self) -> f64 {
         self.approx_memory_bits() as f64 / self.config.capacity as f64
     }
+
+    /// Fraction of bits set, using word-level popcount
+    pub fn fill_ratio(&self) -> f64 {
+        let bits = self.bits.read().unwrap();
+        if bits.is_empty() {
+            return 0.0;
+        }
+        bits.count_ones() as f64 / bits.len() as f64
+    }
+
+    /// Snapshot of configuration, density and memory use
+    pub fn stats(&self) -> BloomStats {
+        let fill_ratio = self.fill_ratio();
+        BloomStats {
+            capacity: self.config.capacity,
+            target_fpr: self.config.false_positive_rate,
+            estimated_fpr: fill_ratio.powi(self.num_hashes as i32),
+            bit_vector_size: self.bit_vector_size,
+            num_hashes: self.num_hashes,
+            insert_count: self.insert_count.load(Ordering::Relaxed),
+            fill_ratio,
+            memory_bytes: self.approx_memory_bits(),
+        }
+    }
+}
+
+/// Helper: write snapshot chunks back into the bit vector
+pub(crate) fn reconstruct_bits_from_chunks(
+    bits: &mut BitVec<usize, Lsb0>,
+    chunks: &[(usize, Vec<u8>)],
+    chunk_size_bytes: usize,
+) -> BloomResult<()> {
+    let chunk_size_bits = chunk_size_bytes * 8;
+
+    let len = bits.len();
+    // Same count the dirty tracker uses, which can include one trailing
+    // empty chunk
+    let num_chunks = (len + chunk_size_bits - 1).div_ceil(chunk_size_bits);
+    let words = bits.as_raw_mut_slice();
+    for (chunk_id, chunk_bytes) in chunks {
+        // Chunk ids come from storage keys; a corrupt one must not
+        // overflow the bit offset
+        if *chunk_id >= num_chunks {
+            return Err(BloomError::StorageError(format!(
+                "Chunk {chunk_id} out of range, filter has {num_chunks} chunks"
+            )));
+        }
+        let start_bit = chunk_id * chunk_size_bits;
+
+        // Chunks start on a byte boundary, so a byte never straddles two
+        // words and can be written with a single mask
+        for (byte_idx, &byte) in chunk_bytes.iter().enumerate() {
+            let bit_idx = start_bit + byte_idx * 8;
+            if bit_idx >= len {
+                break;
+            }
+            let valid_bits = (len - bit_idx).min(8);
+            let mask = ((1u16 << valid_bits) - 1) as usize;
+            let shift = bit_idx % usize::BITS as usize;
+            let word = &mut words[bit_idx / usize::BITS as usize];
+            *word =
+                (*word & !(mask << shift)) | ((byte as usize & mask) << shift);
+        }
+    }
+    Ok(())
 }
 
 impl BloomFilterStats for BloomFilter {
@@ -328,6 +408,18 @@ impl BloomFilterStats for BloomFilter {
     fn false_positive_rate(&self) -> f64 {
         self.config.false_positive_rate
     }
+
+    fn bit_vector_size(&self) -> usize {
+        self.bit_vector_size
+    }
+
+    fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    fn bit_count(&self) -> usize {
+        self.bits.read().unwrap().count_ones()
+    }
 }
 
 impl BloomFilterOps for BloomFilter {
@@ -339,7 +431,6 @@ impl BloomFilterOps for BloomFilter {
         let mut bits = self.bits.write().unwrap();
 
         for idx in indices {
-            let idx = idx as usize;
             if idx >= self.bit_vector_size {
                 return Err(BloomError::IndexOutOfBounds {
                     index: idx,
@@ -369,7 +460,6 @@ impl BloomFilterOps for BloomFilter {
         let bits = self.bits.read().unwrap();
 
         for idx in indices {
-            let idx = idx as usize;
             if idx >= self.bit_vector_size {
                 return Err(BloomError::IndexOutOfBounds {
                     index: idx,
@@ -398,7 +488,7 @@ impl BulkBloomFilterOps for BloomFilter {
         }
 
         // Pre-compute all hash indices for all items before acquiring locks
-        let all_indices: Vec<Vec<u32>> = items
+        let all_indices: Vec<Vec<usize>> = items
             .iter()
             .map(|item| {
                 default_hash_function(item, self.num_hashes, self.bit_vector_size)
@@ -411,7 +501,6 @@ impl BulkBloomFilterOps for BloomFilter {
         // Process all items in single lock session
         for indices in &all_indices {
             for &idx in indices {
-                let idx = idx as usize;
                 if idx >= self.bit_vector_size {
                     return Err(BloomError::IndexOutOfBounds {
                         index: idx,
@@ -443,7 +532,7 @@ impl BulkBloomFilterOps for BloomFilter {
         }
 
         // Pre-compute all hash indices for all items
-        let all_indices: Vec<Vec<u32>> = items
+        let all_indices: Vec<Vec<usize>> = items
             .iter()
             .map(|item| {
                 default_hash_function(item, self.num_hashes, self.bit_vector_size)
@@ -458,7 +547,6 @@ impl BulkBloomFilterOps for BloomFilter {
         for indices in &all_indices {
             let mut exists = true;
             for &idx in indices {
-                let idx = idx as usize;
                 if idx >= self.bit_vector_size {
                     return Err(BloomError::IndexOutOfBounds {
                         index: idx,
@@ -475,4 +563,29 @@ impl BulkBloomFilterOps for BloomFilter {
 
         Ok(results)
     }
+
+    fn contains_bulk_bitset(&self, items: &[&[u8]]) -> BloomResult<Bitset> {
+        let bits = self.bits.read().unwrap();
+        let mut indices = Vec::with_capacity(self.num_hashes);
+        let mut found = Bitset::with_capacity(items.len());
+        for item in items {
+            indices.clear();
+            default_hash_into(
+                item,
+                self.num_hashes,
+                self.bit_vector_size,
+                &mut indices,
+            );
+            if let Some(&idx) =
+                indices.iter().find(|&&idx| idx >= self.bit_vector_size)
+            {
+                return Err(BloomError::IndexOutOfBounds {
+                    index: idx,
+                    capacity: self.bit_vector_size,
+                });
+            }
+            found.push(indices.iter().all(|&idx| bits[idx]));
+        }
+        Ok(found)
+    }
 }
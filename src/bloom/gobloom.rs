@@ -0,0 +1,294 @@
+//! Go `bits-and-blooms/bloom` binary encoding interop
+//!
+//! [`GoBloomFilter`] mirrors the Go filter: `m` bits, `k` locations per
+//! item derived from two 128-bit MurmurHash3 digests, and the big-endian
+//! encoding written by `BloomFilter.WriteTo` (`m`, `k`, then the bitset's
+//! length and words). A filter built by a Go service can be loaded with
+//! [`GoBloomFilter::from_bytes`] and queried here with the same answers,
+//! and [`GoBloomFilter::to_bytes`] produces input for `ReadFrom`.
+//!
+//! The encoding does not say how items were hashed, so a filter decodes
+//! fine even when the producer hashed differently. Check a few items the
+//! producer is known to have added with [`GoBloomFilter::verify_members`]
+//! before trusting the answers.
+
+use std::io::{ErrorKind, Read, Write};
+
+use crate::bloom::error::{BloomError, BloomResult};
+
+/// `m`, `k` and the bitset length, each a big-endian `u64`
+const HEADER_LEN: usize = 8 * 3;
+/// Words encoded or decoded per read or write call
+const IO_WORDS: usize = 8192;
+const LN2: f64 = std::f64::consts::LN_2;
+
+/// Bloom filter with the bit layout and hashing of `bits-and-blooms/bloom`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoBloomFilter {
+    m: u64,
+    k: u64,
+    words: Vec<u64>,
+}
+
+impl GoBloomFilter {
+    /// Same as `bloom.New(m, k)`; both are raised to at least 1
+    pub fn new(m: u64, k: u64) -> Self {
+        let m = m.max(1);
+        Self {
+            m,
+            k: k.max(1),
+            words: vec![0; m.div_ceil(64) as usize],
+        }
+    }
+
+    /// Same as `bloom.NewWithEstimates(n, fp)`
+    pub fn with_estimates(n: u64, fp: f64) -> BloomResult<Self> {
+        if n == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        if !(fp > 0.0 && fp < 1.0) {
+            return Err(BloomError::InvalidFalsePositiveRate { rate: fp });
+        }
+        let (m, k) = estimate_parameters(n, fp);
+        Ok(Self::new(m, k))
+    }
+
+    /// Number of bits
+    pub fn m(&self) -> u64 {
+        self.m
+    }
+
+    /// Number of locations per item
+    pub fn k(&self) -> u64 {
+        self.k
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let hashes = base_hashes(item);
+        for i in 0..self.k {
+            let bit = location(&hashes, i) % self.m;
+            self.words[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hashes = base_hashes(item);
+        (0..self.k).all(|i| {
+            let bit = location(&hashes, i) % self.m;
+            self.words[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Same as `TestAndAdd`: whether the item was present, then add it
+    pub fn test_and_insert(&mut self, item: &[u8]) -> bool {
+        let present = self.contains(item);
+        self.insert(item);
+        present
+    }
+
+    /// Number of set bits
+    pub fn count_ones(&self) -> u64 {
+        self.words.iter().map(|w| w.count_ones() as u64).sum()
+    }
+
+    /// Confirm that items the producer added are all present
+    ///
+    /// A miss cannot happen with matching hashing, so it means the bytes
+    /// came from an incompatible implementation or a different item
+    /// encoding.
+    pub fn verify_members<I, T>(&self, items: I) -> BloomResult<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        match items
+            .into_iter()
+            .position(|item| !self.contains(item.as_ref()))
+        {
+            None => Ok(()),
+            Some(index) => Err(BloomError::SerializationError(format!(
+                "Go bloom filter is missing known member #{index}; \
+                 it was not hashed the way bits-and-blooms/bloom hashes"
+            ))),
+        }
+    }
+
+    /// The encoding written by Go's `BloomFilter.WriteTo`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.words.len() * 8);
+        self.write_to(&mut out)
+            .expect("writing to a Vec cannot fail");
+        out
+    }
+
+    /// Stream the encoding of Go's `BloomFilter.WriteTo` to `writer`
+    pub fn write_to<W: Write>(&self, mut writer: W) -> BloomResult<()> {
+        let mut header = [0u8; HEADER_LEN];
+        header[..8].copy_from_slice(&self.m.to_be_bytes());
+        header[8..16].copy_from_slice(&self.k.to_be_bytes());
+        header[16..].copy_from_slice(&self.m.to_be_bytes());
+        writer.write_all(&header).map_err(write_error)?;
+        for words in self.words.chunks(IO_WORDS) {
+            let bytes: Vec<u8> =
+                words.iter().flat_map(|word| word.to_be_bytes()).collect();
+            writer.write_all(&bytes).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Decode the output of Go's `BloomFilter.WriteTo`
+    pub fn from_bytes(bytes: &[u8]) -> BloomResult<Self> {
+        let mut reader = bytes;
+        let filter = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(decode_error("bitset words do not match its length"));
+        }
+        Ok(filter)
+    }
+
+    /// Decode one filter from `reader`, as Go's `BloomFilter.ReadFrom`
+    ///
+    /// Words are read in batches, so a large filter never sits in memory
+    /// twice. Bytes after the filter are left unread.
+    pub fn read_from<R: Read>(mut reader: R) -> BloomResult<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        read_exact(&mut reader, &mut header, "truncated header")?;
+        let field = |i: usize| {
+            u64::from_be_bytes(header[i * 8..i * 8 + 8].try_into().unwrap())
+        };
+        let (m, k, length) = (field(0), field(1), field(2));
+        if m == 0 || k == 0 {
+            return Err(decode_error("m and k must be at least 1"));
+        }
+        if length != m {
+            return Err(decode_error("bitset length does not match m"));
+        }
+
+        let num_words = usize::try_from(m.div_ceil(64))
+            .map_err(|_| decode_error("bitset too large"))?;
+        // Grow as words arrive rather than trusting a corrupt header
+        let mut words = Vec::with_capacity(num_words.min(IO_WORDS));
+        let mut buf = vec![0u8; IO_WORDS.min(num_words) * 8];
+        while words.len() < num_words {
+            let batch = (num_words - words.len()).min(IO_WORDS);
+            let bytes = &mut buf[..batch * 8];
+            read_exact(
+                &mut reader,
+                bytes,
+                "bitset words do not match its length",
+            )?;
+            words.extend(
+                bytes
+                    .chunks_exact(8)
+                    .map(|w| u64::from_be_bytes(w.try_into().unwrap())),
+            );
+        }
+        let tail_bits = m % 64;
+        if tail_bits != 0 && words[words.len() - 1] >> tail_bits != 0 {
+            return Err(decode_error("bits set past the end of the bitset"));
+        }
+        Ok(Self { m, k, words })
+    }
+}
+
+/// Same as `bloom.EstimateParameters(n, p)`
+pub fn estimate_parameters(n: u64, p: f64) -> (u64, u64) {
+    let m = (-(n as f64) * p.ln() / (LN2 * LN2)).ceil() as u64;
+    let k = (LN2 * m as f64 / n as f64).ceil() as u64;
+    (m, k)
+}
+
+/// Same as `bloom.Locations(data, k)`: locations before reduction mod `m`
+pub fn locations(item: &[u8], k: u64) -> Vec<u64> {
+    let hashes = base_hashes(item);
+    (0..k).map(|i| location(&hashes, i)).collect()
+}
+
+fn location(h: &[u64; 4], i: u64) -> u64 {
+    h[(i % 2) as usize]
+        .wrapping_add(i.wrapping_mul(h[2 + (((i + (i % 2)) % 4) / 2) as usize]))
+}
+
+/// MurmurHash3 x64 128 of the item, then of the item followed by a `1`
+fn base_hashes(item: &[u8]) -> [u64; 4] {
+    let (h1, h2) = murmur3_x64_128(item);
+    let mut extended = Vec::with_capacity(item.len() + 1);
+    extended.extend_from_slice(item);
+    extended.push(1);
+    let (h3, h4) = murmur3_x64_128(&extended);
+    [h1, h2, h3, h4]
+}
+
+/// MurmurHash3 x64 128 with seed 0
+fn murmur3_x64_128(data: &[u8]) -> (u64, u64) {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+
+    let mix_k1 = |k: u64| k.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k: u64| k.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+
+    let (mut h1, mut h2) = (0u64, 0u64);
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+
+        h1 ^= mix_k1(k1);
+        h1 = h1.rotate_left(27).wrapping_add(h2);
+        h1 = h1.wrapping_mul(5).wrapping_add(0x52dce729);
+
+        h2 ^= mix_k2(k2);
+        h2 = h2.rotate_left(31).wrapping_add(h1);
+        h2 = h2.wrapping_mul(5).wrapping_add(0x38495ab5);
+    }
+
+    let tail = blocks.remainder();
+    let mut padded = [0u8; 16];
+    padded[..tail.len()].copy_from_slice(tail);
+    if tail.len() > 8 {
+        h2 ^= mix_k2(u64::from_le_bytes(padded[8..].try_into().unwrap()));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(u64::from_le_bytes(padded[..8].try_into().unwrap()));
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^= k >> 33;
+    k
+}
+
+fn read_exact<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    truncated: &str,
+) -> BloomResult<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => decode_error(truncated),
+        _ => BloomError::StorageError(format!(
+            "Failed to read Go bloom filter: {e}"
+        )),
+    })
+}
+
+fn write_error(e: std::io::Error) -> BloomError {
+    BloomError::StorageError(format!("Failed to write Go bloom filter: {e}"))
+}
+
+fn decode_error(msg: &str) -> BloomError {
+    BloomError::SerializationError(format!("Invalid Go bloom filter: {msg}"))
+}
@@ -0,0 +1,464 @@
+//! Python `pybloom` / `pybloom-live` filter files (`pybloom` feature)
+//!
+//! [`PyBloomFilter`] and [`PyScalableBloomFilter`] read and write what
+//! `BloomFilter.tofile` and `ScalableBloomFilter.tofile` produce, and hash
+//! keys the way those classes do: one slice of bits per hash, offsets cut
+//! from salted digests whose algorithm depends on the filter size. Legacy
+//! filters built by Python pipelines can then be served from Rust.
+//!
+//! Python hashes a `str` key as its UTF-8 bytes, and any other key as the
+//! UTF-8 of `str(key)`, so a `bytes` key `b"k"` was hashed as `"b'k'"`.
+//! Pass the bytes Python actually hashed.
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::bloom::error::{BloomError, BloomResult};
+
+/// `<dQQQQ`: error rate, slices, bits per slice, capacity, count
+const HEADER_LEN: usize = 8 * 5;
+/// `<idQd`: scale, ratio, initial capacity, error rate
+const SCALABLE_HEADER_LEN: usize = 4 + 8 + 8 + 8;
+const LN2_SQUARED: f64 = 0.480453013918201;
+
+/// Digest used by filters small enough to need at most 128 hash bits
+///
+/// Bigger filters use SHA-1 or SHA-2 in every release; only this case
+/// changed between packages, so it has to match the producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PyHashScheme {
+    /// MD5, as in `pybloom` and earlier `pybloom-live` releases
+    #[default]
+    Md5,
+    /// XXH3 128, as in `pybloom-live` releases built on `xxhash`
+    Xxh128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashFn {
+    Md5,
+    Xxh128,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashFn {
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Md5 => Md5::digest(data).to_vec(),
+            Self::Xxh128 => {
+                xxhash_rust::xxh3::xxh3_128(data).to_be_bytes().to_vec()
+            }
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha384 => Sha384::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    fn digest_size(self) -> usize {
+        match self {
+            Self::Md5 | Self::Xxh128 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha384 => 48,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+/// Salted digests cut into per-slice offsets, as `make_hashfuncs` does
+#[derive(Debug, Clone, PartialEq)]
+struct Hasher {
+    hash_fn: HashFn,
+    chunk_size: usize,
+    num_slices: usize,
+    bits_per_slice: u64,
+    /// `H(pack('I', i))` for each salt, prepended to the key
+    salts: Vec<Vec<u8>>,
+}
+
+impl Hasher {
+    fn new(num_slices: usize, bits_per_slice: u64, scheme: PyHashScheme) -> Self {
+        let chunk_size = if bits_per_slice >= 1 << 31 {
+            8
+        } else if bits_per_slice >= 1 << 15 {
+            4
+        } else {
+            2
+        };
+        let hash_fn = match 8 * num_slices * chunk_size {
+            bits if bits > 384 => HashFn::Sha512,
+            bits if bits > 256 => HashFn::Sha384,
+            bits if bits > 160 => HashFn::Sha256,
+            bits if bits > 128 => HashFn::Sha1,
+            _ => match scheme {
+                PyHashScheme::Md5 => HashFn::Md5,
+                PyHashScheme::Xxh128 => HashFn::Xxh128,
+            },
+        };
+        let per_digest = hash_fn.digest_size() / chunk_size;
+        let salts = (0..num_slices.div_ceil(per_digest) as u32)
+            .map(|i| hash_fn.digest(&i.to_le_bytes()))
+            .collect();
+        Self {
+            hash_fn,
+            chunk_size,
+            num_slices,
+            bits_per_slice,
+            salts,
+        }
+    }
+
+    /// Offset of the key within each slice
+    fn offsets(&self, key: &[u8]) -> Vec<u64> {
+        let mut offsets = Vec::with_capacity(self.num_slices);
+        for salt in &self.salts {
+            let mut input = Vec::with_capacity(salt.len() + key.len());
+            input.extend_from_slice(salt);
+            input.extend_from_slice(key);
+            for chunk in self.hash_fn.digest(&input).chunks_exact(self.chunk_size)
+            {
+                let mut word = [0u8; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                offsets.push(u64::from_le_bytes(word) % self.bits_per_slice);
+                if offsets.len() == self.num_slices {
+                    return offsets;
+                }
+            }
+        }
+        offsets
+    }
+}
+
+/// Fixed-size filter compatible with pybloom's `BloomFilter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyBloomFilter {
+    error_rate: f64,
+    capacity: u64,
+    count: u64,
+    hasher: Hasher,
+    /// Bit `i` is `bits[i / 8] >> (i % 8)`
+    bits: Vec<u8>,
+}
+
+impl PyBloomFilter {
+    /// Same as `BloomFilter(capacity, error_rate)`
+    pub fn new(
+        capacity: u64,
+        error_rate: f64,
+        scheme: PyHashScheme,
+    ) -> BloomResult<Self> {
+        if capacity == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(BloomError::InvalidFalsePositiveRate {
+                rate: error_rate,
+            });
+        }
+        let num_slices = (1.0 / error_rate).log2().ceil() as u64;
+        let bits_per_slice = ((capacity as f64 * error_rate.ln().abs())
+            / (num_slices as f64 * LN2_SQUARED))
+            .ceil() as u64;
+        Self::with_layout(
+            error_rate,
+            num_slices,
+            bits_per_slice,
+            capacity,
+            0,
+            scheme,
+        )
+    }
+
+    fn with_layout(
+        error_rate: f64,
+        num_slices: u64,
+        bits_per_slice: u64,
+        capacity: u64,
+        count: u64,
+        scheme: PyHashScheme,
+    ) -> BloomResult<Self> {
+        if num_slices == 0 || bits_per_slice == 0 {
+            return Err(file_error("filter has no bits"));
+        }
+        let num_bits = num_slices
+            .checked_mul(bits_per_slice)
+            .ok_or_else(|| file_error("filter size overflows"))?;
+        Ok(Self {
+            error_rate,
+            capacity,
+            count,
+            hasher: Hasher::new(num_slices as usize, bits_per_slice, scheme),
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+        })
+    }
+
+    /// Add a key; `false` if it was (probably) present already
+    ///
+    /// Like Python, a filter past its capacity refuses new keys.
+    pub fn insert(&mut self, key: &[u8]) -> BloomResult<bool> {
+        if self.count > self.capacity {
+            return Err(BloomError::InvalidConfig(
+                "pybloom filter is at capacity".to_string(),
+            ));
+        }
+        let mut found_all = true;
+        for bit in self.bit_indices(key) {
+            let (byte, mask) = ((bit / 8) as usize, 1 << (bit % 8));
+            found_all &= self.bits[byte] & mask != 0;
+            self.bits[byte] |= mask;
+        }
+        if found_all {
+            return Ok(false);
+        }
+        self.count += 1;
+        Ok(true)
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.bit_indices(key)
+            .all(|bit| self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Keys added, as `len()` reports in Python
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        self.error_rate
+    }
+
+    pub fn num_slices(&self) -> u64 {
+        self.hasher.num_slices as u64
+    }
+
+    pub fn bits_per_slice(&self) -> u64 {
+        self.hasher.bits_per_slice
+    }
+
+    /// The bytes `BloomFilter.tofile` writes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.bits.len());
+        out.extend_from_slice(&self.error_rate.to_le_bytes());
+        out.extend_from_slice(&self.num_slices().to_le_bytes());
+        out.extend_from_slice(&self.bits_per_slice().to_le_bytes());
+        out.extend_from_slice(&self.capacity.to_le_bytes());
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    /// Read a file written by `BloomFilter.tofile`
+    pub fn from_bytes(bytes: &[u8], scheme: PyHashScheme) -> BloomResult<Self> {
+        let (header, bits) = bytes
+            .split_first_chunk::<HEADER_LEN>()
+            .ok_or_else(|| file_error("truncated header"))?;
+        let field = |i: usize| header[i * 8..i * 8 + 8].try_into().unwrap();
+        let mut filter = Self::with_layout(
+            f64::from_le_bytes(field(0)),
+            u64::from_le_bytes(field(1)),
+            u64::from_le_bytes(field(2)),
+            u64::from_le_bytes(field(3)),
+            u64::from_le_bytes(field(4)),
+            scheme,
+        )?;
+        if bits.len() != filter.bits.len() {
+            return Err(file_error("bit length mismatch"));
+        }
+        filter.bits.copy_from_slice(bits);
+        Ok(filter)
+    }
+
+    fn bit_indices(&self, key: &[u8]) -> impl Iterator<Item = u64> + use<> {
+        let bits_per_slice = self.hasher.bits_per_slice;
+        self.hasher
+            .offsets(key)
+            .into_iter()
+            .enumerate()
+            .map(move |(slice, offset)| slice as u64 * bits_per_slice + offset)
+    }
+}
+
+/// Growing filter compatible with pybloom's `ScalableBloomFilter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyScalableBloomFilter {
+    scale: i32,
+    ratio: f64,
+    initial_capacity: u64,
+    error_rate: f64,
+    scheme: PyHashScheme,
+    filters: Vec<PyBloomFilter>,
+}
+
+impl PyScalableBloomFilter {
+    /// `SMALL_SET_GROWTH`: each new filter is twice as large
+    pub const SMALL_SET_GROWTH: i32 = 2;
+    /// `LARGE_SET_GROWTH`: each new filter is four times as large
+    pub const LARGE_SET_GROWTH: i32 = 4;
+    /// Error rate of each new filter relative to the previous one
+    pub const DEFAULT_RATIO: f64 = 0.9;
+
+    /// Same as `ScalableBloomFilter(initial_capacity, error_rate, mode)`
+    pub fn new(
+        initial_capacity: u64,
+        error_rate: f64,
+        mode: i32,
+        scheme: PyHashScheme,
+    ) -> BloomResult<Self> {
+        if initial_capacity == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(BloomError::InvalidFalsePositiveRate {
+                rate: error_rate,
+            });
+        }
+        if mode < 1 {
+            return Err(BloomError::InvalidConfig(
+                "Scale must be at least 1".to_string(),
+            ));
+        }
+        Ok(Self {
+            scale: mode,
+            ratio: Self::DEFAULT_RATIO,
+            initial_capacity,
+            error_rate,
+            scheme,
+            filters: Vec::new(),
+        })
+    }
+
+    /// Add a key; `false` if it was (probably) present already
+    pub fn insert(&mut self, key: &[u8]) -> BloomResult<bool> {
+        if self.contains(key) {
+            return Ok(false);
+        }
+        let grow = match self.filters.last() {
+            None => Some((
+                self.initial_capacity,
+                self.error_rate * (1.0 - self.ratio),
+            )),
+            Some(last) if last.count >= last.capacity => Some((
+                last.capacity.saturating_mul(self.scale as u64),
+                last.error_rate * self.ratio,
+            )),
+            Some(_) => None,
+        };
+        if let Some((capacity, error_rate)) = grow {
+            self.filters.push(PyBloomFilter::new(
+                capacity,
+                error_rate,
+                self.scheme,
+            )?);
+        }
+        self.filters
+            .last_mut()
+            .expect("a filter was just ensured")
+            .insert(key)?;
+        Ok(true)
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.filters.iter().rev().any(|filter| filter.contains(key))
+    }
+
+    /// Keys added across every filter
+    pub fn len(&self) -> u64 {
+        self.filters.iter().map(|filter| filter.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Summed capacity of every filter
+    pub fn capacity(&self) -> u64 {
+        self.filters.iter().map(|filter| filter.capacity).sum()
+    }
+
+    pub fn filters(&self) -> &[PyBloomFilter] {
+        &self.filters
+    }
+
+    /// The bytes `ScalableBloomFilter.tofile` writes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let encoded: Vec<Vec<u8>> =
+            self.filters.iter().map(PyBloomFilter::to_bytes).collect();
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.scale.to_le_bytes());
+        out.extend_from_slice(&self.ratio.to_le_bytes());
+        out.extend_from_slice(&self.initial_capacity.to_le_bytes());
+        out.extend_from_slice(&self.error_rate.to_le_bytes());
+        out.extend_from_slice(&(encoded.len() as i32).to_le_bytes());
+        for filter in &encoded {
+            out.extend_from_slice(&(filter.len() as u64).to_le_bytes());
+        }
+        for filter in &encoded {
+            out.extend_from_slice(filter);
+        }
+        out
+    }
+
+    /// Read a file written by `ScalableBloomFilter.tofile`
+    pub fn from_bytes(bytes: &[u8], scheme: PyHashScheme) -> BloomResult<Self> {
+        let (header, rest) = bytes
+            .split_first_chunk::<SCALABLE_HEADER_LEN>()
+            .ok_or_else(|| file_error("truncated header"))?;
+        let scale = i32::from_le_bytes(header[0..4].try_into().unwrap());
+        let ratio = f64::from_le_bytes(header[4..12].try_into().unwrap());
+        let initial_capacity =
+            u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let error_rate = f64::from_le_bytes(header[20..28].try_into().unwrap());
+
+        let (count, mut rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| file_error("truncated filter count"))?;
+        let count = usize::try_from(i32::from_le_bytes(*count))
+            .map_err(|_| file_error("negative filter count"))?;
+        let mut sizes = Vec::with_capacity(count.min(64));
+        for _ in 0..count {
+            let (size, tail) = rest
+                .split_first_chunk::<8>()
+                .ok_or_else(|| file_error("truncated filter sizes"))?;
+            sizes.push(u64::from_le_bytes(*size) as usize);
+            rest = tail;
+        }
+        let mut filters = Vec::with_capacity(count.min(64));
+        for size in sizes {
+            if rest.len() < size {
+                return Err(file_error("truncated filter"));
+            }
+            let (filter, tail) = rest.split_at(size);
+            filters.push(PyBloomFilter::from_bytes(filter, scheme)?);
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            return Err(file_error("trailing bytes after the last filter"));
+        }
+        Ok(Self {
+            scale,
+            ratio,
+            initial_capacity,
+            error_rate,
+            scheme,
+            filters,
+        })
+    }
+}
+
+fn file_error(msg: &str) -> BloomError {
+    BloomError::SerializationError(format!("Invalid pybloom file: {msg}"))
+}
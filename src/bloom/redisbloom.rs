@@ -0,0 +1,413 @@
+//! RedisBloom `BF.SCANDUMP` / `BF.LOADCHUNK` interop
+//!
+//! [`RedisBloomFilter`] keeps RedisBloom's layout: a chain of links, each
+//! growing by the expansion factor, hashed with 64-bit MurmurHash64A. Bits
+//! imported from a dump are therefore queried exactly as Redis would, and
+//! exported chunks load back into Redis with `BF.LOADCHUNK`, so a filter
+//! moves between the two without replaying its keys.
+//!
+//! Only the 64-bit hashing used by RedisBloom 2.x (`FORCE64`) is
+//! supported; dumps of filters created by 1.x are rejected.
+
+use crate::bloom::error::{BloomError, BloomResult};
+
+/// Link sizes are used as given instead of rounded to a power of two
+pub const OPT_NOROUND: u32 = 1;
+/// Link capacities are given in bits rather than entries
+pub const OPT_ENTS_IS_BITS: u32 = 2;
+/// 64-bit hashing
+pub const OPT_FORCE64: u32 = 4;
+/// Full filters reject inserts instead of adding a link
+pub const OPT_NO_SCALING: u32 = 8;
+
+/// Largest data chunk produced by `scan_dump`, as in RedisBloom
+pub const MAX_SCANDUMP_CHUNK: usize = 16 * 1024 * 1024;
+/// Expansion of `BF.RESERVE` without `EXPANSION`
+pub const DEFAULT_EXPANSION: u32 = 2;
+
+/// Error rate of each new link relative to the previous one
+const TIGHTENING_RATIO: f64 = 0.5;
+/// Seed of the first hash; the second is seeded with the first
+const HASH_SEED: u64 = 0xc6a4a7935bd1e995;
+/// `ln(2)^2`
+const LN2_SQUARED: f64 = 0.480453013918201;
+const LN2: f64 = std::f64::consts::LN_2;
+
+/// Packed sizes of the dump header structs
+const HEADER_LEN: usize = 8 + 4 + 4 + 4;
+const LINK_LEN: usize = 8 + 8 + 8 + 8 + 8 + 4 + 8 + 1;
+
+/// One fixed-size filter of the chain
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    /// Capacity
+    entries: u64,
+    error: f64,
+    bpe: f64,
+    hashes: u32,
+    bits: u64,
+    /// Bits are `1 << n2` when non-zero
+    n2: u8,
+    /// Items added
+    size: u64,
+    data: Vec<u8>,
+}
+
+impl Link {
+    fn new(entries: u64, error: f64, options: u32) -> BloomResult<Self> {
+        if entries == 0 {
+            return Err(BloomError::ZeroCapacity);
+        }
+        if !(error > 0.0 && error < 1.0) {
+            return Err(BloomError::InvalidFalsePositiveRate { rate: error });
+        }
+        let bpe = -(error.ln() / LN2_SQUARED);
+        let mut bits = if options & OPT_ENTS_IS_BITS != 0 {
+            entries
+        } else {
+            (entries as f64 * bpe) as u64
+        };
+        let mut n2 = 0;
+        if options & OPT_NOROUND == 0 {
+            n2 = bits.max(2).next_power_of_two().trailing_zeros() as u8;
+            bits = 1 << n2;
+        }
+        // Whole 64-bit words
+        let bytes = bits.div_ceil(64) * 8;
+        if n2 == 0 {
+            bits = bytes * 8;
+        }
+        Ok(Self {
+            entries,
+            error,
+            bpe,
+            hashes: (LN2 * bpe).ceil() as u32,
+            bits,
+            n2,
+            size: 0,
+            data: vec![0; bytes as usize],
+        })
+    }
+
+    /// Bit positions of an item: `(a + i * b) mod bits`, LSB first
+    fn indices(&self, (a, b): (u64, u64)) -> impl Iterator<Item = u64> + use<> {
+        let modulus = modulus(self.n2, self.bits);
+        (0..self.hashes as u64)
+            .map(move |i| a.wrapping_add(i.wrapping_mul(b)) % modulus)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.indices(hash)
+            .all(|x| self.data[(x >> 3) as usize] & (1 << (x & 7)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        for x in self.indices(hash) {
+            self.data[(x >> 3) as usize] |= 1 << (x & 7);
+        }
+        self.size += 1;
+    }
+}
+
+/// Scalable bloom filter with RedisBloom's bit layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisBloomFilter {
+    links: Vec<Link>,
+    options: u32,
+    growth: u32,
+    size: u64,
+}
+
+impl RedisBloomFilter {
+    /// Same as `BF.RESERVE key error_rate capacity [EXPANSION e] [NONSCALING]`
+    pub fn new(
+        capacity: u64,
+        error_rate: f64,
+        expansion: u32,
+        nonscaling: bool,
+    ) -> BloomResult<Self> {
+        if expansion == 0 {
+            return Err(BloomError::InvalidConfig(
+                "Expansion must be greater than 0".to_string(),
+            ));
+        }
+        let mut options = OPT_NOROUND | OPT_FORCE64;
+        if nonscaling {
+            options |= OPT_NO_SCALING;
+        }
+        Ok(Self {
+            links: vec![Link::new(capacity, error_rate, options)?],
+            options,
+            growth: expansion,
+            size: 0,
+        })
+    }
+
+    /// Add an item; `false` if it was (probably) present already
+    ///
+    /// A full scaling filter grows by a link of `expansion` times the
+    /// previous capacity; a full non-scaling filter returns an error.
+    pub fn insert(&mut self, item: &[u8]) -> BloomResult<bool> {
+        let hash = hash_item(item);
+        if self.links.iter().any(|link| link.contains(hash)) {
+            return Ok(false);
+        }
+        let last = self.links.last().expect("a chain always has a link");
+        if last.size >= last.entries {
+            if self.options & OPT_NO_SCALING != 0 {
+                return Err(BloomError::InvalidConfig(
+                    "Non-scaling filter is full".to_string(),
+                ));
+            }
+            let link = Link::new(
+                last.entries.saturating_mul(self.growth as u64),
+                last.error * TIGHTENING_RATIO,
+                self.options,
+            )?;
+            self.links.push(link);
+        }
+        self.links
+            .last_mut()
+            .expect("a chain always has a link")
+            .insert(hash);
+        self.size += 1;
+        Ok(true)
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash_item(item);
+        self.links.iter().any(|link| link.contains(hash))
+    }
+
+    /// Items added, as reported by `BF.INFO`
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Summed capacity of every link
+    pub fn capacity(&self) -> u64 {
+        self.links.iter().map(|link| link.entries).sum()
+    }
+
+    pub fn num_links(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn expansion(&self) -> u32 {
+        self.growth
+    }
+
+    /// Bytes of bit data across every link
+    pub fn memory_bytes(&self) -> usize {
+        self.links.iter().map(|link| link.data.len()).sum()
+    }
+
+    /// Every `(iterator, data)` pair that successive `BF.SCANDUMP` calls
+    /// return, header first and without the terminating `(0, "")`
+    ///
+    /// Feed them in order to `BF.LOADCHUNK` (or [`Self::load_chunks`]).
+    pub fn scan_dump(&self) -> Vec<(i64, Vec<u8>)> {
+        let mut chunks = vec![(1, self.encode_header())];
+        let mut iter = 1;
+        for link in &self.links {
+            for chunk in link.data.chunks(MAX_SCANDUMP_CHUNK) {
+                iter += chunk.len() as i64;
+                chunks.push((iter, chunk.to_vec()));
+            }
+        }
+        chunks
+    }
+
+    /// Rebuild a filter from `BF.SCANDUMP` output, header first
+    pub fn load_chunks<I>(chunks: I) -> BloomResult<Self>
+    where
+        I: IntoIterator<Item = (i64, Vec<u8>)>,
+    {
+        let mut chunks = chunks.into_iter();
+        let (iter, header) = chunks
+            .next()
+            .ok_or_else(|| dump_error("missing header chunk"))?;
+        if iter != 1 {
+            return Err(dump_error("first chunk must be the header"));
+        }
+        let mut filter = Self::decode_header(&header)?;
+        for (iter, data) in chunks {
+            if iter == 0 && data.is_empty() {
+                break;
+            }
+            filter.load_chunk(iter, &data)?;
+        }
+        Ok(filter)
+    }
+
+    /// Copy one data chunk in place, as `BF.LOADCHUNK` does
+    pub fn load_chunk(&mut self, iter: i64, data: &[u8]) -> BloomResult<()> {
+        let offset = (iter - 1)
+            .checked_sub(data.len() as i64)
+            .filter(|&offset| offset >= 0)
+            .ok_or_else(|| dump_error("chunk iterator out of range"))?
+            as usize;
+        let mut link_start = 0;
+        for link in &mut self.links {
+            let link_end = link_start + link.data.len();
+            if offset < link_end {
+                let seek = offset - link_start;
+                let target = link
+                    .data
+                    .get_mut(seek..seek + data.len())
+                    .ok_or_else(|| dump_error("chunk crosses a link boundary"))?;
+                target.copy_from_slice(data);
+                return Ok(());
+            }
+            link_start = link_end;
+        }
+        Err(dump_error("chunk iterator past the end of the filter"))
+    }
+
+    fn encode_header(&self) -> Vec<u8> {
+        let mut out =
+            Vec::with_capacity(HEADER_LEN + LINK_LEN * self.links.len());
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&(self.links.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.options.to_le_bytes());
+        out.extend_from_slice(&self.growth.to_le_bytes());
+        for link in &self.links {
+            out.extend_from_slice(&(link.data.len() as u64).to_le_bytes());
+            out.extend_from_slice(&link.bits.to_le_bytes());
+            out.extend_from_slice(&link.size.to_le_bytes());
+            out.extend_from_slice(&link.error.to_le_bytes());
+            out.extend_from_slice(&link.bpe.to_le_bytes());
+            out.extend_from_slice(&link.hashes.to_le_bytes());
+            out.extend_from_slice(&link.entries.to_le_bytes());
+            out.push(link.n2);
+        }
+        out
+    }
+
+    fn decode_header(bytes: &[u8]) -> BloomResult<Self> {
+        let mut reader = Reader(bytes);
+        let size = reader.u64()?;
+        let num_links = reader.u32()? as usize;
+        let options = reader.u32()?;
+        let growth = reader.u32()?;
+        if bytes.len() != HEADER_LEN + num_links * LINK_LEN {
+            return Err(dump_error("header length does not match link count"));
+        }
+        if options & OPT_FORCE64 == 0 {
+            return Err(dump_error(
+                "32-bit hashed filters (RedisBloom 1.x) are not supported",
+            ));
+        }
+        if num_links == 0 {
+            return Err(dump_error("filter has no links"));
+        }
+
+        let links = (0..num_links)
+            .map(|_| {
+                let bytes = reader.u64()?;
+                let bits = reader.u64()?;
+                let size = reader.u64()?;
+                let error = reader.f64()?;
+                let bpe = reader.f64()?;
+                let hashes = reader.u32()?;
+                let entries = reader.u64()?;
+                let n2 = reader.u8()?;
+                let modulus = modulus(n2, bits);
+                if modulus == 0 || modulus > bytes * 8 {
+                    return Err(dump_error("link has more bits than bytes"));
+                }
+                Ok(Link {
+                    entries,
+                    error,
+                    bpe,
+                    hashes,
+                    bits,
+                    n2,
+                    size,
+                    data: vec![0; bytes as usize],
+                })
+            })
+            .collect::<BloomResult<_>>()?;
+        Ok(Self {
+            links,
+            options,
+            growth,
+            size,
+        })
+    }
+}
+
+/// Little-endian reader over the packed header
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> BloomResult<[u8; N]> {
+        let (head, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or_else(|| dump_error("truncated header"))?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> BloomResult<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> BloomResult<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> BloomResult<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> BloomResult<f64> {
+        self.take().map(f64::from_le_bytes)
+    }
+}
+
+fn modulus(n2: u8, bits: u64) -> u64 {
+    if n2 > 0 { 1 << n2 } else { bits }
+}
+
+fn dump_error(msg: &str) -> BloomError {
+    BloomError::SerializationError(format!("Invalid RedisBloom dump: {msg}"))
+}
+
+fn hash_item(item: &[u8]) -> (u64, u64) {
+    let a = murmur_hash64a(item, HASH_SEED);
+    (a, murmur_hash64a(item, a))
+}
+
+/// MurmurHash64A, the 64-bit hash RedisBloom uses
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut words = key.chunks_exact(8);
+    for word in &mut words {
+        let mut k = u64::from_le_bytes(word.try_into().expect("8-byte chunk"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = words.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
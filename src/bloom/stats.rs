@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// Point-in-time view of a bloom filter, see `BloomFilter::stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BloomStats {
+    pub capacity: usize,
+    pub target_fpr: f64,
+    /// False positive rate of a query right now, `fill_ratio^num_hashes`
+    pub estimated_fpr: f64,
+    pub bit_vector_size: usize,
+    pub num_hashes: usize,
+    pub insert_count: usize,
+    /// Fraction of bits set
+    pub fill_ratio: f64,
+    pub memory_bytes: usize,
+}
+
+/// Point-in-time view of an `AutoBloomFilter`, see `AutoBloomFilter::stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoBloomStats {
+    pub target_fpr: f64,
+    /// Combined capacity of the slices so far
+    pub capacity: usize,
+    pub insert_count: usize,
+    /// False positive rate of a query right now, across every slice
+    pub estimated_fpr: f64,
+    pub memory_bytes: usize,
+    /// Oldest first
+    pub slices: Vec<BloomStats>,
+}
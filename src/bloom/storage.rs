@@ -51,14 +51,19 @@ impl StorageBackend for FjallBackend {
         &self,
         chunks: &[(usize, Vec<u8>)],
     ) -> BloomResult<()> {
+        // One write batch covers every chunk of the snapshot
+        let mut batch = self.keyspace.batch();
         for (chunk_id, chunk_data) in chunks {
             let key = format!("chunk_{chunk_id}");
-            self.chunks_partition
-                .insert(&key, chunk_data)
-                .map_err(|e| {
-                    BloomError::StorageError(format!("Failed to save chunk: {e}"))
-                })?;
+            batch.insert(
+                &self.chunks_partition,
+                key.as_bytes(),
+                chunk_data.as_slice(),
+            );
         }
+        batch.commit().map_err(|e| {
+            BloomError::StorageError(format!("Failed to save chunks: {e}"))
+        })?;
 
         // Persist to disk
         self.keyspace
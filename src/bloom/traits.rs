@@ -1,21 +1,62 @@
 use super::{BloomFilterConfig, BloomResult};
+use crate::common::{BITSET_BATCH_LEN, Bitset, Membership};
 use async_trait::async_trait;
 
 pub trait BloomFilterOps {
     fn insert(&self, item: &[u8]) -> BloomResult<()>;
     fn contains(&self, item: &[u8]) -> BloomResult<bool>;
     fn clear(&self) -> BloomResult<()>;
+
+    /// `contains` as a `Membership`, which can't be mistaken for certainty
+    fn contains_checked(&self, item: &[u8]) -> BloomResult<Membership> {
+        self.contains(item).map(Membership::from)
+    }
 }
 
 pub trait BloomFilterStats {
     fn capacity(&self) -> usize;
     fn false_positive_rate(&self) -> f64;
     fn insert_count(&self) -> usize;
+    /// Bits in the filter
+    fn bit_vector_size(&self) -> usize;
+    /// Hash probes per item
+    fn num_hashes(&self) -> usize;
+    /// Bits currently set
+    fn bit_count(&self) -> usize;
 }
 
 pub trait BulkBloomFilterOps {
     fn insert_bulk(&self, items: &[&[u8]]) -> BloomResult<()>;
     fn contains_bulk(&self, items: &[&[u8]]) -> BloomResult<Vec<bool>>;
+
+    /// `contains_bulk` packed one bit per item
+    ///
+    /// A bitset takes an eighth of the memory of `Vec<bool>`, which adds up
+    /// when checking hundreds of millions of keys in a batch job.
+    fn contains_bulk_bitset(&self, items: &[&[u8]]) -> BloomResult<Bitset> {
+        self.contains_bulk(items)
+            .map(|found| found.into_iter().collect())
+    }
+
+    /// `contains_bulk_bitset` over any iterator of keys, queried in batches
+    /// of `BITSET_BATCH_LEN`, so the keys never have to be held at once
+    fn contains_iter_bitset<I, K>(&self, items: I) -> BloomResult<Bitset>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut found = Bitset::new();
+        let mut batch = Vec::with_capacity(BITSET_BATCH_LEN);
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            batch.extend(items.by_ref().take(BITSET_BATCH_LEN));
+            let refs: Vec<&[u8]> = batch.iter().map(AsRef::as_ref).collect();
+            found.extend_from_bitslice(&self.contains_bulk_bitset(&refs)?);
+            batch.clear();
+        }
+        Ok(found)
+    }
 }
 
 #[async_trait]
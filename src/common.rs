@@ -1,5 +1,59 @@
 #![allow(clippy::uninlined_format_args)]
 
+/// Most bytes a decode of persisted data may claim for its containers
+pub(crate) const MAX_DECODE_BYTES: usize = 16 << 20;
+
+/// bincode config for decoding persisted data
+///
+/// Same wire format as `bincode::config::standard()`, but a corrupt length
+/// prefix fails the decode instead of allocating whatever it claims.
+pub(crate) fn decode_config() -> impl bincode::config::Config {
+    bincode::config::standard().with_limit::<MAX_DECODE_BYTES>()
+}
+
+/// One bit per item, as returned by the `contains_bulk_bitset` family;
+/// `iter_ones` yields the indices of items present
+pub type Bitset = bitvec::vec::BitVec<usize, bitvec::order::Lsb0>;
+
+/// Items queried per batch by `contains_iter_bitset`
+pub const BITSET_BATCH_LEN: usize = 4096;
+
+/// Result of a membership query that keeps its uncertainty in the type
+///
+/// A Bloom filter never misses an inserted item but may report one that
+/// was never inserted; `contains_checked` returns this instead of a `bool`
+/// so a "yes" can't be mistaken for certainty.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Membership {
+    /// Never inserted, or expired
+    DefinitelyAbsent,
+    /// Inserted, or a false positive
+    ProbablyPresent,
+}
+
+impl Membership {
+    pub fn is_definitely_absent(self) -> bool {
+        self == Self::DefinitelyAbsent
+    }
+
+    pub fn is_probably_present(self) -> bool {
+        self == Self::ProbablyPresent
+    }
+}
+
+impl From<bool> for Membership {
+    /// Interpret a `contains` result
+    fn from(found: bool) -> Self {
+        if found {
+            Self::ProbablyPresent
+        } else {
+            Self::DefinitelyAbsent
+        }
+    }
+}
+
 // Helper method to format bytes in human-readable form
 pub fn bytes2hr(bytes: usize) -> String {
     if bytes < 1024 {
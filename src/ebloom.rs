@@ -1,5 +1,43 @@
+pub mod batch;
+pub mod bits;
+pub mod bulk;
+pub mod clock;
 pub mod config;
+pub mod crdt;
+pub mod drift;
 pub mod error;
+pub mod events;
 pub mod filter;
+pub mod frozen;
+#[cfg(feature = "gossip")]
+pub mod gossip;
+pub mod greylist;
+#[cfg(feature = "fjall")]
+pub mod keyspace;
+#[cfg(feature = "latency")]
+mod latency;
+pub mod log_dedup;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod oplog;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod rate_limit;
+pub mod reshape;
+pub mod ring;
+pub mod sharded;
+pub mod shared;
+pub mod shipping;
+mod simd;
+pub mod stats;
+mod statsd;
 pub mod storage;
+mod sync;
+#[cfg(feature = "test_support")]
+pub mod test_support;
 pub mod traits;
+pub mod union;
+#[cfg(feature = "url")]
+pub mod url_dedup;
+#[cfg(feature = "fjall")]
+mod write_behind;
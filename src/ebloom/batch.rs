@@ -0,0 +1,88 @@
+//! Inserts committed together with a snapshot
+//!
+//! A stream processor wants to acknowledge consumed messages only once
+//! they are durable. [`Batch`] buffers inserts on the caller's side;
+//! [`Batch::commit`] inserts them, saves an incremental snapshot and
+//! flushes, so once it returns every item of the batch survives a crash
+//! and the consumer offset can be committed:
+//!
+//! ```ignore
+//! loop {
+//!     let messages = consumer.poll(500).await?;
+//!     let mut batch = filter.batch();
+//!     for message in &messages {
+//!         batch.insert(message.key());
+//!     }
+//!     batch.commit().await?;
+//!     consumer.commit(&messages).await?; // at-least-once
+//! }
+//! ```
+//!
+//! Buffered items are invisible to queries until the commit. A commit
+//! that fails may have inserted the items in memory without making them
+//! durable; re-consuming the batch inserts them again, which is harmless.
+//! Dropping a batch discards it.
+
+use crate::ebloom::error::Result;
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::BulkExpiringBloomFilterOps;
+
+/// Inserts buffered for one commit, from `ExpiringBloomFilter::batch`
+#[must_use = "a batch is discarded unless committed"]
+pub struct Batch<'a> {
+    filter: &'a ExpiringBloomFilter,
+    /// Item bytes back to back, so buffering allocates per batch rather
+    /// than per item
+    bytes: Vec<u8>,
+    /// End offset of each item in `bytes`
+    ends: Vec<usize>,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(filter: &'a ExpiringBloomFilter) -> Self {
+        Self {
+            filter,
+            bytes: Vec::new(),
+            ends: Vec::new(),
+        }
+    }
+
+    /// Buffer `item` for the next commit
+    pub fn insert(&mut self, item: &[u8]) {
+        self.bytes.extend_from_slice(item);
+        self.ends.push(self.bytes.len());
+    }
+
+    /// Items buffered so far
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Insert the buffered items, save an incremental snapshot and make it
+    /// durable; returns how many items were committed
+    ///
+    /// The items are inserted as one `insert_bulk`, so the FPR guardrail
+    /// refuses the batch as a whole. Without persistence this only inserts.
+    pub async fn commit(self) -> Result<usize> {
+        let mut start = 0;
+        let items: Vec<&[u8]> = self
+            .ends
+            .iter()
+            .map(|&end| {
+                let item = &self.bytes[start..end];
+                start = end;
+                item
+            })
+            .collect();
+        if !items.is_empty() {
+            self.filter.insert_bulk(&items)?;
+        }
+        self.filter.save_snapshot().await?;
+        self.filter.flush().await?;
+        Ok(items.len())
+    }
+}
@@ -0,0 +1,412 @@
+use crate::ebloom::simd;
+use crate::ebloom::sync::{AtomicU64, Ordering};
+
+#[cfg(all(feature = "mmap", unix))]
+use memmap2::Advice;
+#[cfg(feature = "mmap")]
+use memmap2::{MmapMut, MmapOptions};
+
+const WORD_BITS: usize = 64;
+/// Words per 4 KiB page, the smallest page size pre-faulting has to hit
+const PAGE_WORDS: usize = 4096 / size_of::<AtomicU64>();
+
+/// Fixed-size bit vector backed by `AtomicU64` words
+///
+/// Setting and reading bits are single relaxed `fetch_or`/`load` operations,
+/// so concurrent writers and readers never block each other. Bit `i` lives
+/// in word `i / 64` at position `i % 64` (least significant bit first), which
+/// is the same `Lsb0` byte layout used for persisted chunks.
+///
+/// With the `mmap` feature the words can live in a memory mapping instead of
+/// the heap, letting the OS page very large levels in and out.
+#[derive(Debug)]
+pub struct AtomicBitVec {
+    words: Words,
+    len: usize,
+}
+
+#[derive(Debug)]
+enum Words {
+    Heap(Box<[AtomicU64]>),
+    /// The pointer is taken from the mapping once, with write provenance
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: MmapMut,
+        ptr: std::ptr::NonNull<AtomicU64>,
+    },
+}
+
+// SAFETY: `ptr` points into `map`, which lives as long as `Words`, and the
+// memory is only accessed through atomics.
+#[cfg(feature = "mmap")]
+unsafe impl Send for Words {}
+#[cfg(feature = "mmap")]
+unsafe impl Sync for Words {}
+
+#[cfg(feature = "mmap")]
+impl Words {
+    fn mapped(mut map: MmapMut) -> Self {
+        let ptr = std::ptr::NonNull::new(map.as_mut_ptr().cast::<AtomicU64>())
+            .expect("mmap never returns a null pointer");
+        Words::Mapped { map, ptr }
+    }
+}
+
+impl AtomicBitVec {
+    /// Create a vector of `len` cleared bits
+    pub fn new(len: usize) -> Self {
+        let words = (0..len.div_ceil(WORD_BITS))
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        Self {
+            words: Words::Heap(words),
+            len,
+        }
+    }
+
+    /// Create a vector of `len` cleared bits in an anonymous mapping
+    #[cfg(feature = "mmap")]
+    pub fn new_mapped_anon(len: usize) -> std::io::Result<Self> {
+        let map = MmapOptions::new().len(Self::mapped_bytes(len)).map_anon()?;
+        Ok(Self {
+            words: Words::mapped(map),
+            len,
+        })
+    }
+
+    /// Create a vector of `len` cleared bits backed by the file at `path`
+    ///
+    /// The file only serves as swap space for the mapping; it is truncated
+    /// and cleared, and is not a persistence format.
+    #[cfg(feature = "mmap")]
+    pub fn new_mapped_file(
+        path: &std::path::Path,
+        len: usize,
+    ) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(Self::mapped_bytes(len) as u64)?;
+        // SAFETY: the file was just truncated and is owned by this vector;
+        // nothing else is expected to modify it while mapped.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(Self {
+            words: Words::mapped(map),
+            len,
+        })
+    }
+
+    /// Mapping size for `len` bits; never zero, which mmap rejects
+    #[cfg(feature = "mmap")]
+    fn mapped_bytes(len: usize) -> usize {
+        len.div_ceil(WORD_BITS).max(1) * size_of::<AtomicU64>()
+    }
+
+    /// Whether the words live in a memory mapping
+    pub fn is_mapped(&self) -> bool {
+        match self.words {
+            Words::Heap(_) => false,
+            #[cfg(feature = "mmap")]
+            Words::Mapped { .. } => true,
+        }
+    }
+
+    /// Hint the OS that the vector is about to be used heavily (hot) or
+    /// only probed at random (cold). No-op for heap storage.
+    pub fn advise_hot(&self, hot: bool) -> std::io::Result<()> {
+        #[cfg(all(feature = "mmap", unix))]
+        if let Words::Mapped { ref map, .. } = self.words {
+            return map.advise(if hot {
+                Advice::WillNeed
+            } else {
+                Advice::Random
+            });
+        }
+        let _ = hot;
+        Ok(())
+    }
+
+    /// Ask the kernel to back the mapping with transparent huge pages.
+    /// Only mapped storage on Linux is affected; elsewhere this is a no-op.
+    pub fn advise_huge_pages(&self) -> std::io::Result<()> {
+        #[cfg(all(feature = "mmap", target_os = "linux"))]
+        if let Words::Mapped { ref map, .. } = self.words {
+            return map.advise(Advice::HugePage);
+        }
+        Ok(())
+    }
+
+    /// Touch every page so it is faulted in now rather than on the first
+    /// insert that lands on it. Bits are left unchanged.
+    pub fn prefault(&self) {
+        for word in self.words().iter().step_by(PAGE_WORDS) {
+            word.fetch_or(0, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    fn words(&self) -> &[AtomicU64] {
+        match self.words {
+            Words::Heap(ref words) => words,
+            #[cfg(feature = "mmap")]
+            Words::Mapped { ptr, .. } => {
+                // SAFETY: the mapping is page aligned, sized to a whole
+                // number of words, zero-initialized and owned by `self`. It
+                // is only ever accessed through this shared atomic view.
+                unsafe {
+                    std::slice::from_raw_parts(
+                        ptr.as_ptr(),
+                        self.len.div_ceil(WORD_BITS),
+                    )
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes occupied by the backing words
+    pub fn memory_bytes(&self) -> usize {
+        size_of_val(self.words())
+    }
+
+    /// Read a bit; panics if `idx >= len`
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "bit index {idx} out of range {}", self.len);
+        let word = self.words()[idx / WORD_BITS].load(Ordering::Relaxed);
+        word & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    /// Set a bit; panics if `idx >= len`
+    #[inline]
+    pub fn set(&self, idx: usize) {
+        assert!(idx < self.len, "bit index {idx} out of range {}", self.len);
+        self.words()[idx / WORD_BITS]
+            .fetch_or(1 << (idx % WORD_BITS), Ordering::Relaxed);
+    }
+
+    /// Set a bit, returning whether it was already set; panics if
+    /// `idx >= len`
+    #[inline]
+    pub fn test_and_set(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "bit index {idx} out of range {}", self.len);
+        let mask = 1 << (idx % WORD_BITS);
+        self.words()[idx / WORD_BITS].fetch_or(mask, Ordering::Relaxed) & mask
+            != 0
+    }
+
+    /// Whether every bit in `indices` is set
+    ///
+    /// Evaluates all probes without short-circuiting, so the word loads are
+    /// independent and their cache misses can overlap.
+    #[inline]
+    pub fn all_set(&self, indices: &[usize]) -> bool {
+        indices.iter().fold(true, |acc, &idx| {
+            let word = self.words()[idx / WORD_BITS].load(Ordering::Relaxed);
+            acc & ((word >> (idx % WORD_BITS)) & 1 == 1)
+        })
+    }
+
+    /// For every item of `num_hashes` probes in `indices`, set its entry
+    /// in `found` if all of its bits are set; items already found are
+    /// skipped
+    ///
+    /// Uses AVX2 or NEON where the CPU has them, see `ebloom::simd`.
+    /// Panics if a probe is `>= len`.
+    pub fn all_set_each(
+        &self,
+        indices: &[usize],
+        num_hashes: usize,
+        found: &mut [bool],
+    ) {
+        assert!(
+            indices.iter().all(|&idx| idx < self.len),
+            "bit index out of range {}",
+            self.len
+        );
+        simd::all_set_each(self.words(), indices, num_hashes.max(1), found);
+    }
+
+    /// Clear a bit; panics if `idx >= len`
+    #[inline]
+    pub fn reset(&self, idx: usize) {
+        assert!(idx < self.len, "bit index {idx} out of range {}", self.len);
+        self.words()[idx / WORD_BITS]
+            .fetch_and(!(1 << (idx % WORD_BITS)), Ordering::Relaxed);
+    }
+
+    /// Clear every bit
+    pub fn clear(&self) {
+        for word in self.words().iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Number of set bits
+    pub fn count_ones(&self) -> usize {
+        self.words()
+            .iter()
+            .map(|w| w.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+
+    /// Fraction of bits set, counted a word at a time
+    pub fn fill_ratio(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        self.count_ones() as f64 / self.len as f64
+    }
+
+    /// Fill ratio of `buckets` equal slices of the vector, in order
+    ///
+    /// Slices are whole words, so they can differ in size by one word; at
+    /// most one bucket per word is returned.
+    pub fn density_histogram(&self, buckets: usize) -> Vec<f64> {
+        let words = self.words();
+        if buckets == 0 || words.is_empty() {
+            return Vec::new();
+        }
+        // Spread words as evenly as possible so exactly `buckets` slices
+        // come back whenever there are that many words
+        let buckets = buckets.min(words.len());
+        (0..buckets)
+            .map(|bucket| {
+                let start = bucket * words.len() / buckets;
+                let end = (bucket + 1) * words.len() / buckets;
+                let start_bit = start * WORD_BITS;
+                let bits = ((end - start) * WORD_BITS).min(self.len - start_bit);
+                let ones: u32 = words[start..end]
+                    .iter()
+                    .map(|w| w.load(Ordering::Relaxed).count_ones())
+                    .sum();
+                ones as f64 / bits as f64
+            })
+            .collect()
+    }
+
+    /// Whether any bit is set
+    pub fn any(&self) -> bool {
+        self.words().iter().any(|w| w.load(Ordering::Relaxed) != 0)
+    }
+
+    /// Indices of set bits, in ascending order
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words()
+            .iter()
+            .enumerate()
+            .flat_map(|(word_idx, word)| {
+                ones_in_word(word_idx, word.load(Ordering::Relaxed))
+            })
+    }
+
+    /// Atomically clear every bit, returning the indices that were set
+    pub fn take_ones(&self) -> Vec<usize> {
+        let mut taken = Vec::new();
+        for (word_idx, word) in self.words().iter().enumerate() {
+            let bits = word.swap(0, Ordering::Relaxed);
+            taken.extend(ones_in_word(word_idx, bits));
+        }
+        taken
+    }
+
+    /// Copy bits `[start_bit, end_bit)` out as `Lsb0` bytes
+    ///
+    /// `start_bit` must be a multiple of 8. Bits past `len` are never set,
+    /// so a trailing partial byte needs no masking.
+    pub fn read_bytes(&self, start_bit: usize, end_bit: usize) -> Vec<u8> {
+        let end_bit = end_bit.min(self.len);
+        if start_bit >= end_bit {
+            return Vec::new();
+        }
+
+        // Little-endian word bytes are the `Lsb0` byte layout, so each word
+        // is loaded once and copied out whole
+        let start_byte = start_bit / 8;
+        let end_byte = end_bit.div_ceil(8);
+        self.words()[start_byte / 8..end_byte.div_ceil(8)]
+            .iter()
+            .flat_map(|word| word.load(Ordering::Relaxed).to_le_bytes())
+            .skip(start_byte % 8)
+            .take(end_byte - start_byte)
+            .collect()
+    }
+
+    /// Overwrite bits starting at `start_bit` with `Lsb0` bytes
+    ///
+    /// `start_bit` must be a multiple of 8. Bits that would land past `len`
+    /// are dropped.
+    pub fn write_bytes(&self, start_bit: usize, bytes: &[u8]) {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            let bit_idx = start_bit + offset * 8;
+            if bit_idx >= self.len {
+                break;
+            }
+            let valid_bits = (self.len - bit_idx).min(8);
+            let byte_mask = (1u64 << valid_bits) - 1;
+            let shift = (bit_idx % WORD_BITS) as u32;
+            let word = &self.words()[bit_idx / WORD_BITS];
+            word.fetch_and(!(byte_mask << shift), Ordering::Relaxed);
+            word.fetch_or((byte as u64 & byte_mask) << shift, Ordering::Relaxed);
+        }
+    }
+
+    /// Set every bit that is set in `Lsb0` bytes starting at `start_bit`,
+    /// keeping bits already set
+    ///
+    /// `start_bit` must be a multiple of 8. Bits that would land past `len`
+    /// are dropped.
+    pub fn or_bytes(&self, start_bit: usize, bytes: &[u8]) {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            let bit_idx = start_bit + offset * 8;
+            if bit_idx >= self.len {
+                break;
+            }
+            if byte == 0 {
+                continue;
+            }
+            let valid_bits = (self.len - bit_idx).min(8);
+            let byte_mask = (1u64 << valid_bits) - 1;
+            let shift = (bit_idx % WORD_BITS) as u32;
+            self.words()[bit_idx / WORD_BITS]
+                .fetch_or((byte as u64 & byte_mask) << shift, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Clone for AtomicBitVec {
+    /// Point-in-time copy on the heap; concurrent writers may or may not be
+    /// observed
+    fn clone(&self) -> Self {
+        let words = self
+            .words()
+            .iter()
+            .map(|w| AtomicU64::new(w.load(Ordering::Relaxed)))
+            .collect();
+        Self {
+            words: Words::Heap(words),
+            len: self.len,
+        }
+    }
+}
+
+fn ones_in_word(word_idx: usize, mut bits: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let bit = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        Some(word_idx * WORD_BITS + bit)
+    })
+}
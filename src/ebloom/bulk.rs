@@ -0,0 +1,139 @@
+use std::cell::Cell;
+
+use crate::ebloom::bits::AtomicBitVec;
+use crate::hash::HashIntoFunction;
+
+/// Items each worker hashes between two write phases of a partitioned import
+pub(crate) const IMPORT_BATCH_PER_WORKER: usize = 1 << 16;
+
+/// Largest buffer kept in the per-thread scratch between calls, in elements
+const MAX_RETAINED_LEN: usize = 1 << 20;
+
+/// Scratch buffers reused across bulk operations
+///
+/// Holding one context per worker and passing it to `insert_bulk_with` /
+/// `contains_bulk_with` avoids allocating hash-index and result vectors for
+/// every batch. The plain `insert_bulk` / `contains_bulk` calls use a
+/// per-thread context internally.
+#[derive(Debug, Default)]
+pub struct BulkContext {
+    pub(crate) indices: Vec<usize>,
+    pub(crate) results: Vec<bool>,
+}
+
+impl BulkContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Context sized for batches of `items` items with `num_hashes` probes
+    pub fn with_capacity(items: usize, num_hashes: usize) -> Self {
+        Self {
+            indices: Vec::with_capacity(items * num_hashes),
+            results: Vec::with_capacity(items),
+        }
+    }
+
+    /// Results of the last `contains_bulk_with` call
+    pub fn results(&self) -> &[bool] {
+        &self.results
+    }
+}
+
+thread_local! {
+    static SCRATCH: Cell<BulkContext> = Cell::new(BulkContext::default());
+}
+
+/// Run `f` with this thread's scratch context
+///
+/// The context is taken out for the duration of the call, so a nested call
+/// simply starts from an empty one.
+pub(crate) fn with_scratch<R>(f: impl FnOnce(&mut BulkContext) -> R) -> R {
+    let mut ctx = SCRATCH.take();
+    let result = f(&mut ctx);
+    // Don't pin memory from one unusually large batch
+    if ctx.indices.capacity() <= MAX_RETAINED_LEN
+        && ctx.results.capacity() <= MAX_RETAINED_LEN
+    {
+        SCRATCH.set(ctx);
+    }
+    result
+}
+
+/// Set the bits of every item in `level` using `parallelism` threads
+///
+/// Items are taken in batches. Workers first hash their share of a batch
+/// into one bucket per region of the level, regions being disjoint ranges
+/// of 64-bit words; then every region is filled by one thread from all
+/// buckets, so no two threads touch the same word or cache line run.
+/// Returns the number of items imported.
+pub(crate) fn partitioned_import<T: AsRef<[u8]> + Sync>(
+    level: &AtomicBitVec,
+    items: impl IntoIterator<Item = T>,
+    hash_into: HashIntoFunction,
+    num_hashes: usize,
+    parallelism: usize,
+) -> u64 {
+    let parallelism = parallelism.max(1);
+    let bit_vector_size = level.len();
+    let region_bits = bit_vector_size.div_ceil(64).div_ceil(parallelism) * 64;
+    let batch_len = parallelism * IMPORT_BATCH_PER_WORKER;
+
+    let mut items = items.into_iter();
+    let mut batch: Vec<T> = Vec::with_capacity(batch_len);
+    let mut imported = 0u64;
+    loop {
+        batch.clear();
+        batch.extend(items.by_ref().take(batch_len));
+        if batch.is_empty() {
+            return imported;
+        }
+
+        let buckets = std::thread::scope(|scope| {
+            let workers: Vec<_> = batch
+                .chunks(IMPORT_BATCH_PER_WORKER)
+                .map(|share| {
+                    scope.spawn(move || {
+                        let mut buckets = vec![Vec::new(); parallelism];
+                        let mut indices = Vec::with_capacity(num_hashes);
+                        for item in share {
+                            indices.clear();
+                            hash_into(
+                                item.as_ref(),
+                                num_hashes,
+                                bit_vector_size,
+                                &mut indices,
+                            );
+                            for &idx in &indices {
+                                buckets[idx / region_bits].push(idx);
+                            }
+                        }
+                        buckets
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Vec<Vec<Vec<usize>>>>()
+        });
+
+        std::thread::scope(|scope| {
+            for region in 0..parallelism {
+                let buckets = &buckets;
+                scope.spawn(move || {
+                    for worker_buckets in buckets {
+                        worker_buckets[region]
+                            .iter()
+                            .for_each(|&idx| level.set(idx));
+                    }
+                });
+            }
+        });
+        imported += batch.len() as u64;
+    }
+}
@@ -0,0 +1,137 @@
+use crate::ebloom::error::{EbloomError, Result};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of time for expiring filters
+///
+/// All timestamps are milliseconds since the Unix epoch, matching
+/// `LevelMetadata::created_at`.
+pub trait Clock: Send + Sync {
+    /// Current time in milliseconds
+    fn now_ms(&self) -> Result<u64>;
+}
+
+/// Default clock backed by `SystemTime`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> Result<u64> {
+        Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| EbloomError::TimeError(e.to_string()))?
+            .as_millis() as u64)
+    }
+}
+
+/// Manually driven clock for deterministic tests
+///
+/// Share it with the filter through an `Arc` and call `advance` instead of
+/// sleeping.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_ms: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start_ms: u64) -> Self {
+        Self {
+            now_ms: AtomicU64::new(start_ms),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.now_ms
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Set the clock to an absolute time in milliseconds
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> Result<u64> {
+        Ok(self.now_ms.load(Ordering::SeqCst))
+    }
+}
+
+/// Clock driven by `Instant`, immune to wall-clock jumps
+///
+/// The wall-clock time is read once as an epoch; after that time only moves
+/// forward by the monotonic elapsed duration. When reopening a persisted
+/// filter the epoch is taken as the later of the wall clock and the newest
+/// stored timestamp, so time never runs backwards across restarts.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    epoch_ms: u64,
+    started: Instant,
+}
+
+impl MonotonicClock {
+    /// Start from the current wall-clock time
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_epoch(SystemClock.now_ms()?))
+    }
+
+    /// Start from an explicit epoch in milliseconds
+    pub fn with_epoch(epoch_ms: u64) -> Self {
+        Self {
+            epoch_ms,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn epoch_ms(&self) -> u64 {
+        self.epoch_ms
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_ms(&self) -> Result<u64> {
+        Ok(self.epoch_ms + self.started.elapsed().as_millis() as u64)
+    }
+}
+
+/// Time source selected by `ExpiringFilterConfig`
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Decode,
+    Encode,
+)]
+pub enum ClockMode {
+    /// Wall-clock time from `SystemTime`
+    #[default]
+    System,
+    /// Monotonic time anchored to a wall-clock epoch
+    Monotonic,
+}
+
+impl ClockMode {
+    /// Build a clock for this mode, never going below `min_epoch_ms`
+    pub fn build_clock(&self, min_epoch_ms: u64) -> Result<Arc<dyn Clock>> {
+        match self {
+            ClockMode::System => Ok(Arc::new(SystemClock)),
+            ClockMode::Monotonic => {
+                let now_ms = SystemClock.now_ms()?;
+                Ok(Arc::new(MonotonicClock::with_epoch(
+                    now_ms.max(min_epoch_ms),
+                )))
+            }
+        }
+    }
+}
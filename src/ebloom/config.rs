@@ -4,13 +4,48 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::time::Duration;
 
+use crate::ebloom::clock::ClockMode;
 use crate::ebloom::error::{EbloomError, Result};
+use crate::hash::{
+    CACHE_LINE_BITS, HashFunction, HashIntoFunction, blocked_hash_function,
+    blocked_hash_into, checked_filter_params, default_hash_function,
+    default_hash_into, optimal_bit_vector_size,
+};
 
 #[derive(Debug, Clone, Builder, Serialize, Deserialize, Decode, Encode)]
 pub struct ExpiringPersistenceConfig {
     pub db_path: PathBuf,
     #[builder(default = "4096")]
     pub chunk_size_bytes: usize,
+    /// Coalesce syncs requested within this window into one; deferred
+    /// writes are durable after the next sync, an explicit flush, or at the
+    /// latest when the window ends
+    #[builder(default = "None")]
+    pub group_commit_window: Option<Duration>,
+    /// Persist touched chunks from a background writer fed through a queue
+    /// of this many chunk ids; inserts block while the queue is full
+    #[builder(default = "None")]
+    pub write_behind_capacity: Option<usize>,
+    /// Keep the rotation log in the database so `rotation_history` covers
+    /// rotations from before a restart
+    #[builder(default = "false")]
+    pub persist_rotation_log: bool,
+}
+
+/// Where and how often to send StatsD metrics, see `ebloom::statsd`
+#[derive(Debug, Clone, Builder, Serialize, Deserialize, Decode, Encode)]
+#[builder(setter(into))]
+pub struct StatsdConfig {
+    /// `host:port` of the StatsD daemon
+    #[builder(default = "\"127.0.0.1:8125\".to_string()")]
+    pub host: String,
+    /// Prepended to every metric name, followed by a dot
+    #[builder(default = "\"ebloom\".to_string()")]
+    pub prefix: String,
+    /// Send counters and gauges at most this often, during
+    /// `cleanup_expired_levels`
+    #[builder(default = "Duration::from_secs(10)")]
+    pub flush_interval: Duration,
 }
 
 #[derive(Debug, Clone, Builder, Serialize, Deserialize, Decode, Encode)]
@@ -22,10 +57,67 @@ pub struct ExpiringFilterConfig {
     pub target_fpr: f64,
     #[builder(default = "Duration::from_secs(60 * 60)")] // 1 hour
     pub level_duration: Duration,
+    /// Optional window lengths by age, newest first: the current level takes
+    /// inserts for `level_durations[0]`, and the level `a` rotations old is
+    /// dropped once its window started more than `level_durations[0..=a]`
+    /// summed ago, even before the ring comes round to it. Empty means every
+    /// age uses `level_duration`.
+    #[builder(default = "Vec::new()")]
+    pub level_durations: Vec<Duration>,
     #[builder(default = "3")]
     pub num_levels: usize,
     #[builder(default = "None")]
     pub persistence: Option<ExpiringPersistenceConfig>,
+    #[builder(default = "ClockMode::System")]
+    pub clock_mode: ClockMode,
+    /// Align window starts to multiples of the level duration since the Unix
+    /// epoch, so a 1h window starts on the hour and a 24h window spans a
+    /// calendar day (UTC) instead of being anchored to process start
+    #[builder(default = "false")]
+    pub align_windows: bool,
+    #[builder(default = "InsertMode::Current")]
+    pub insert_mode: InsertMode,
+    #[builder(default = "RotationPolicy::Time")]
+    pub rotation_policy: RotationPolicy,
+    /// Items inserted within this span of the current level's scheduled
+    /// expiry are also carried into the next level when it becomes current
+    #[builder(default = "None")]
+    pub grace_overlap: Option<Duration>,
+    /// Rotate early once the current level's bit density exceeds this ratio
+    #[builder(default = "None")]
+    pub max_fill_ratio: Option<f64>,
+    /// Warn once per window (log, observers, metrics) when the current
+    /// level's bit density crosses this ratio
+    #[builder(default = "None")]
+    pub saturation_warning: Option<f64>,
+    /// Sample the false positive rate trend at most this often, during
+    /// `cleanup_expired_levels`. Each sample takes a pass over every level.
+    #[builder(default = "None")]
+    pub fpr_sample_interval: Option<Duration>,
+    /// Alert once the estimated false positive rate exceeds
+    /// `target_fpr * fpr_drift_tolerance`
+    #[builder(default = "2.0")]
+    pub fpr_drift_tolerance: f64,
+    /// Send insert, duplicate, rotation and density metrics to StatsD
+    #[builder(default = "None")]
+    pub statsd: Option<StatsdConfig>,
+    /// Keep all probes of an item within one cache line of a level. Levels
+    /// are rounded up to whole blocks; FPR is slightly higher and the
+    /// persisted bit layout differs from the default one.
+    #[builder(default = "false")]
+    pub blocked_layout: bool,
+    /// Where level bits are allocated
+    #[builder(default = "LevelBacking::Heap")]
+    pub level_backing: LevelBacking,
+    /// Fault in every page of the levels when the filter is built, so the
+    /// first inserts don't pay for page faults
+    #[builder(default = "false")]
+    pub prefault_levels: bool,
+    /// Request transparent huge pages for level bits (Linux, `mmap`
+    /// feature). Heap levels are allocated as anonymous mappings instead so
+    /// the advice can apply; fewer TLB misses pay off for multi-GB levels.
+    #[builder(default = "false")]
+    pub huge_pages: bool,
 }
 
 impl ExpiringFilterConfig {
@@ -35,11 +127,17 @@ impl ExpiringFilterConfig {
                 "Capacity per level must be greater than 0".to_string(),
             ));
         }
-        if self.target_fpr <= 0.0 || self.target_fpr >= 1.0 {
+        // Written so that NaN fails too
+        if !(self.target_fpr > 0.0 && self.target_fpr < 1.0) {
             return Err(EbloomError::InvalidConfig(
                 "Target false positive rate must be between 0 and 1".to_string(),
             ));
         }
+        if let Err(reason) =
+            checked_filter_params(self.capacity_per_level, self.target_fpr)
+        {
+            return Err(EbloomError::InvalidConfig(reason));
+        }
         if self.level_duration.as_millis() == 0 {
             return Err(EbloomError::InvalidConfig(
                 "Level duration must be greater than 0".to_string(),
@@ -55,16 +153,156 @@ impl ExpiringFilterConfig {
                 "Number of levels must be <= 255".to_string(),
             ));
         }
+        if self.rotation_policy.max_inserts() == Some(0) {
+            return Err(EbloomError::InvalidConfig(
+                "Rotation insert count must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(ratio) = self.max_fill_ratio
+            && (ratio <= 0.0 || ratio > 1.0)
+        {
+            return Err(EbloomError::InvalidConfig(
+                "Max fill ratio must be in (0, 1]".to_string(),
+            ));
+        }
+        if self.fpr_drift_tolerance < 1.0 {
+            return Err(EbloomError::InvalidConfig(
+                "FPR drift tolerance must be at least 1".to_string(),
+            ));
+        }
+        if let Some(ratio) = self.saturation_warning
+            && (ratio <= 0.0 || ratio > 1.0)
+        {
+            return Err(EbloomError::InvalidConfig(
+                "Saturation warning ratio must be in (0, 1]".to_string(),
+            ));
+        }
+        if let Some(ref statsd) = self.statsd
+            && statsd.prefix.is_empty()
+        {
+            return Err(EbloomError::InvalidConfig(
+                "StatsD prefix must not be empty".to_string(),
+            ));
+        }
+        if let Some(ref pers) = self.persistence
+            && pers.chunk_size_bytes == 0
+        {
+            return Err(EbloomError::InvalidConfig(
+                "Chunk size must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(ref pers) = self.persistence
+            && pers.write_behind_capacity == Some(0)
+        {
+            return Err(EbloomError::InvalidConfig(
+                "Write-behind queue capacity must be greater than 0".to_string(),
+            ));
+        }
+        if self.level_backing != LevelBacking::Heap && !cfg!(feature = "mmap") {
+            return Err(EbloomError::InvalidConfig(
+                "Memory-mapped levels require the `mmap` feature".to_string(),
+            ));
+        }
+        if self.huge_pages && !cfg!(feature = "mmap") {
+            return Err(EbloomError::InvalidConfig(
+                "Huge pages require the `mmap` feature".to_string(),
+            ));
+        }
+        if !self.level_durations.is_empty() {
+            if self.level_durations.len() != self.num_levels {
+                return Err(EbloomError::InvalidConfig(format!(
+                    "Expected {} level durations, got {}",
+                    self.num_levels,
+                    self.level_durations.len()
+                )));
+            }
+            if self.level_durations.iter().any(|d| d.as_millis() == 0) {
+                return Err(EbloomError::InvalidConfig(
+                    "Level durations must be greater than 0".to_string(),
+                ));
+            }
+        }
         Ok(())
     }
 
+    /// Start of the current window containing `now_ms`, honoring
+    /// `align_windows`
+    pub fn window_start(&self, now_ms: u64) -> u64 {
+        if !self.align_windows {
+            return now_ms;
+        }
+        let duration_ms = self.duration_for_age(0).as_millis() as u64;
+        now_ms - now_ms % duration_ms
+    }
+
+    /// Number of bits in each level
+    pub fn level_bit_size(&self) -> usize {
+        let bits =
+            optimal_bit_vector_size(self.capacity_per_level, self.target_fpr);
+        if self.blocked_layout {
+            bits.next_multiple_of(CACHE_LINE_BITS)
+        } else {
+            bits
+        }
+    }
+
+    /// Hash function matching the configured bit layout
+    pub fn hash_function(&self) -> HashFunction {
+        if self.blocked_layout {
+            blocked_hash_function
+        } else {
+            default_hash_function
+        }
+    }
+
+    /// Buffer-appending form of `hash_function`
+    pub fn hash_into_function(&self) -> HashIntoFunction {
+        if self.blocked_layout {
+            blocked_hash_into
+        } else {
+            default_hash_into
+        }
+    }
+
+    /// Window length of the level `age` rotations old (0 is current)
+    pub fn duration_for_age(&self, age: usize) -> Duration {
+        self.level_durations
+            .get(age)
+            .copied()
+            .unwrap_or(self.level_duration)
+    }
+
+    /// How long after its window started the level `age` rotations old is
+    /// dropped
+    pub fn retention_for_age(&self, age: usize) -> Duration {
+        (0..=age).map(|a| self.duration_for_age(a)).sum()
+    }
+
+    /// How long after its window started a level is dropped, assuming it
+    /// rotates every `duration_for_age(0)`
+    ///
+    /// The level is dropped at the first age whose retention ends before
+    /// the next rotation, and at the latest when the ring comes round to it
+    /// again, so durations past the ring length do not extend it.
+    pub fn level_lifetime(&self) -> Duration {
+        let window = self.duration_for_age(0);
+        for age in 1..self.num_levels {
+            let entered = window * age as u32;
+            let retention = self.retention_for_age(age);
+            if retention < entered + window {
+                return retention.max(entered);
+            }
+        }
+        window * self.num_levels as u32
+    }
+
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         bincode::encode_to_vec(self, bincode::config::standard())
             .map_err(|e| EbloomError::SerializationError(e.to_string()))
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        bincode::decode_from_slice(bytes, bincode::config::standard())
+        bincode::decode_from_slice(bytes, crate::common::decode_config())
             .map(|(config, _)| config)
             .map_err(|e| EbloomError::SerializationError(e.to_string()))
     }
@@ -75,4 +313,134 @@ pub struct LevelMetadata {
     pub created_at: u64,
     pub insert_count: u64,
     pub last_snapshot_at: u64,
+    /// Why this level became the current level
+    pub rotation_reason: RotationReason,
+}
+
+/// Cause of a level becoming current
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Decode,
+    Encode,
+)]
+pub enum RotationReason {
+    /// Level initialized on filter creation or clear
+    #[default]
+    Created,
+    /// Explicit `rotate_levels` call
+    Manual,
+    /// Previous level's time window elapsed
+    Time,
+    /// Previous level exceeded `max_fill_ratio`
+    Saturation,
+    /// Previous level reached the insert count of the rotation policy
+    InsertCount,
+    /// Level cleared by `clear_by_rotation`
+    Clear,
+}
+
+impl RotationReason {
+    /// Label used by the metrics exporters
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RotationReason::Created => "created",
+            RotationReason::Manual => "manual",
+            RotationReason::Time => "time",
+            RotationReason::Saturation => "saturation",
+            RotationReason::InsertCount => "insert_count",
+            RotationReason::Clear => "clear",
+        }
+    }
+}
+
+/// Memory backing for level bit vectors
+///
+/// Mapped variants need the `mmap` feature and suit levels too large to keep
+/// resident comfortably: the OS pages them in and out, and the current level
+/// is hinted as hot. Mapped memory is scratch space, not persistence.
+#[derive(
+    Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Decode, Encode,
+)]
+pub enum LevelBacking {
+    /// Regular heap allocation
+    #[default]
+    Heap,
+    /// Anonymous memory mapping
+    AnonymousMmap,
+    /// File-backed mapping, one `level_<n>.bits` file per level in the
+    /// given directory
+    FileMmap(PathBuf),
+}
+
+/// Which levels an insert writes to
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Decode,
+    Encode,
+)]
+pub enum InsertMode {
+    /// Write to the current level only
+    #[default]
+    Current,
+    /// Smooth decay: also carry every item into the next window when it
+    /// starts, so each item is kept one window longer than the current
+    /// level alone would keep it
+    #[serde(alias = "CurrentAndPrevious")]
+    CurrentAndNext,
+}
+
+/// When the current level rolls over
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Decode,
+    Encode,
+)]
+pub enum RotationPolicy {
+    /// Rotate when the level duration elapses
+    #[default]
+    Time,
+    /// Rotate after the given number of inserts, ignoring time. The insert
+    /// reaching the count fires `FilterObserver::on_rotation_due`, so the
+    /// rotation need not wait for the next cleanup tick
+    InsertCount(u64),
+    /// Rotate on whichever comes first: duration or insert count
+    Either(u64),
+}
+
+impl RotationPolicy {
+    /// Whether elapsed time triggers rotation
+    pub fn uses_time(&self) -> bool {
+        matches!(self, RotationPolicy::Time | RotationPolicy::Either(_))
+    }
+
+    /// Insert count that triggers rotation, if any
+    pub fn max_inserts(&self) -> Option<u64> {
+        match self {
+            RotationPolicy::Time => None,
+            RotationPolicy::InsertCount(n) | RotationPolicy::Either(n) => {
+                Some(*n)
+            }
+        }
+    }
 }
@@ -0,0 +1,115 @@
+//! Filter state as a state-based CRDT
+//!
+//! [`BloomCrdt`] holds the level bits of a filter keyed by the start of
+//! their window. [`BloomCrdt::merge`] ORs the bits of windows both sides
+//! know and keeps the newest `num_levels` windows, which makes it a join:
+//! commutative, associative and idempotent. Rotation is the only way bits
+//! disappear, and it is expressed as a newer window pushing the oldest
+//! one out, so replicas converge on the same recent set regardless of
+//! delivery order or duplication.
+//!
+//! Replicas should set `align_windows` so that their windows start at the
+//! same instants and get merged bit for bit; otherwise each replica's
+//! windows are kept side by side until they age out. A filter exports its
+//! state with `ExpiringBloomFilter::crdt_state` and folds a merged state
+//! back in with `merge_crdt`.
+
+use std::collections::BTreeMap;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::error::{EbloomError, Result};
+
+/// Join-semilattice over the recent windows of a filter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Decode, Encode)]
+pub struct BloomCrdt {
+    layout_fingerprint: u64,
+    num_levels: usize,
+    /// Window start (ms) to level bits as `Lsb0` bytes
+    windows: BTreeMap<u64, Vec<u8>>,
+}
+
+impl BloomCrdt {
+    pub(crate) fn new(
+        layout_fingerprint: u64,
+        num_levels: usize,
+        windows: BTreeMap<u64, Vec<u8>>,
+    ) -> Self {
+        let mut state = Self {
+            layout_fingerprint,
+            num_levels,
+            windows,
+        };
+        state.truncate();
+        state
+    }
+
+    /// Join `other` into this state
+    ///
+    /// Fails, leaving this state untouched, if the states come from
+    /// filters with a different bit layout or number of levels.
+    pub fn merge(&mut self, other: &BloomCrdt) -> Result<()> {
+        if other.layout_fingerprint != self.layout_fingerprint
+            || other.num_levels != self.num_levels
+        {
+            return Err(EbloomError::InvalidConfig(
+                "CRDT states come from filters with different layouts"
+                    .to_string(),
+            ));
+        }
+        for (&window_start, bits) in &other.windows {
+            let merged = self
+                .windows
+                .entry(window_start)
+                .or_insert_with(|| vec![0; bits.len()]);
+            if merged.len() < bits.len() {
+                merged.resize(bits.len(), 0);
+            }
+            merged.iter_mut().zip(bits).for_each(|(byte, b)| *byte |= b);
+        }
+        self.truncate();
+        Ok(())
+    }
+
+    /// Join of two states, see [`Self::merge`]
+    pub fn join(&self, other: &BloomCrdt) -> Result<BloomCrdt> {
+        let mut joined = self.clone();
+        joined.merge(other)?;
+        Ok(joined)
+    }
+
+    /// Window starts (ms), oldest first
+    pub fn windows(&self) -> impl Iterator<Item = u64> + '_ {
+        self.windows.keys().copied()
+    }
+
+    pub(crate) fn layout_fingerprint(&self) -> u64 {
+        self.layout_fingerprint
+    }
+
+    /// `(window start, bits)`, oldest first
+    pub(crate) fn levels(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.windows
+            .iter()
+            .map(|(&start, bits)| (start, bits.as_slice()))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(state, _)| state)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    /// Keep only the newest `num_levels` windows
+    fn truncate(&mut self) {
+        while self.windows.len() > self.num_levels {
+            self.windows.pop_first();
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of samples kept by `ExpiringBloomFilter::fpr_drift`
+pub const FPR_HISTORY_LEN: usize = 128;
+
+/// One point of the false positive rate trend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FprSample {
+    /// Sample time in milliseconds
+    pub at_ms: u64,
+    /// Inserts since the previous sample
+    pub inserts: u64,
+    /// Inserts since the previous sample whose bits were all set already
+    pub probable_duplicates: u64,
+    /// `probable_duplicates / inserts`. Counts true duplicates as well as
+    /// false positives, so it bounds the real-world FPR from above.
+    pub duplicate_rate: f64,
+    /// Chance that a new item matches some level, from current bit density
+    pub estimated_fpr: f64,
+}
+
+/// False positive rate trend against the configured target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FprDrift {
+    pub target_fpr: f64,
+    /// Oldest first
+    pub samples: Vec<FprSample>,
+    /// Least-squares slope of `estimated_fpr`, per hour
+    pub trend_per_hour: f64,
+    /// Latest estimate exceeds `target_fpr * fpr_drift_tolerance`
+    pub diverging: bool,
+}
+
+/// Rolling samples plus the counters seen at the previous sample
+#[derive(Debug, Default)]
+pub(crate) struct FprTracker {
+    samples: VecDeque<FprSample>,
+    /// `(created_at, insert_count)` per level at the previous sample
+    last_counts: Vec<(u64, u64)>,
+    last_duplicates: u64,
+    last_sample_ms: u64,
+    /// Whether the latest sample was past the tolerance, so an alert is
+    /// raised once per excursion
+    pub(crate) diverging: bool,
+}
+
+impl FprTracker {
+    /// Record a sample from per-level `(created_at, insert_count)` pairs and
+    /// the lifetime probable-duplicate count
+    ///
+    /// A level whose creation time changed was rotated since the previous
+    /// sample, so all of its inserts are new.
+    pub(crate) fn record(
+        &mut self,
+        at_ms: u64,
+        counts: Vec<(u64, u64)>,
+        duplicates: u64,
+        estimated_fpr: f64,
+    ) -> FprSample {
+        let inserts = counts
+            .iter()
+            .enumerate()
+            .map(|(level, &(created_at, count))| {
+                match self.last_counts.get(level) {
+                    Some(&(last_created_at, last_count))
+                        if last_created_at == created_at =>
+                    {
+                        count.saturating_sub(last_count)
+                    }
+                    _ => count,
+                }
+            })
+            .sum::<u64>();
+        let probable_duplicates = duplicates.saturating_sub(self.last_duplicates);
+        let duplicate_rate = if inserts == 0 {
+            0.0
+        } else {
+            probable_duplicates as f64 / inserts as f64
+        };
+
+        let sample = FprSample {
+            at_ms,
+            inserts,
+            probable_duplicates,
+            duplicate_rate,
+            estimated_fpr,
+        };
+        if self.samples.len() == FPR_HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample.clone());
+        self.last_counts = counts;
+        self.last_duplicates = duplicates;
+        self.last_sample_ms = at_ms;
+        sample
+    }
+
+    pub(crate) fn last_sample_ms(&self) -> u64 {
+        self.last_sample_ms
+    }
+
+    pub(crate) fn report(&self, target_fpr: f64, tolerance: f64) -> FprDrift {
+        let diverging = self
+            .samples
+            .back()
+            .is_some_and(|sample| sample.estimated_fpr > target_fpr * tolerance);
+        FprDrift {
+            target_fpr,
+            samples: self.samples.iter().cloned().collect(),
+            trend_per_hour: self.trend_per_hour(),
+            diverging,
+        }
+    }
+
+    fn trend_per_hour(&self) -> f64 {
+        let Some(first) = self.samples.front() else {
+            return 0.0;
+        };
+        if self.samples.len() < 2 {
+            return 0.0;
+        }
+        let n = self.samples.len() as f64;
+        // Hours since the first sample keep the sums well conditioned
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|s| {
+                let hours = (s.at_ms - first.at_ms) as f64 / 3_600_000.0;
+                (hours, s.estimated_fpr)
+            })
+            .collect();
+        let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+        let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), &(x, y)| {
+            (
+                cov + (x - mean_x) * (y - mean_y),
+                var + (x - mean_x).powi(2),
+            )
+        });
+        if var == 0.0 { 0.0 } else { cov / var }
+    }
+}
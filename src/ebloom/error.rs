@@ -30,6 +30,29 @@ pub enum EbloomError {
 
     #[error("Time error: {0}")]
     TimeError(String),
+
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Insert refused: {0}")]
+    Saturated(SaturationError),
+}
+
+/// An insert would push the current level past the FPR guardrail, see
+/// `ExpiringBloomFilter::set_fpr_guardrail`
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error(
+    "level {level} would reach an estimated FPR of {estimated_fpr:.6} with \
+     {insert_count} items, above the limit of {limit}"
+)]
+pub struct SaturationError {
+    pub level: usize,
+    /// Items the level would hold after the insert
+    pub insert_count: u64,
+    /// Estimated false positive rate of the level after the insert
+    pub estimated_fpr: f64,
+    /// Configured guardrail
+    pub limit: f64,
 }
 
 // Conversion from String to EbloomError (for validation errors)
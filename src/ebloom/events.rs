@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::config::{LevelMetadata, RotationReason};
+use crate::ebloom::drift::FprSample;
+use crate::ebloom::error::SaturationError;
+
+/// Event emitted when the filter rotates and a level is rotated out
+#[derive(Debug, Clone)]
+pub struct RotationEvent {
+    /// Index of the level that was rotated out (cleared and reused as current)
+    pub level: usize,
+    /// Metadata of the rotated-out level, captured before it was cleared
+    pub metadata: LevelMetadata,
+    /// Number of items inserted into the rotated-out level
+    pub insert_count: u64,
+    /// Fraction of bits set in the rotated-out level before it was cleared
+    pub fill_ratio: f64,
+    /// Why the rotation happened
+    pub reason: RotationReason,
+}
+
+/// Number of rotations kept by `ExpiringBloomFilter::rotation_history`
+pub const ROTATION_HISTORY_LEN: usize = 128;
+
+/// Summary of a single rotation kept in the rolling history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct RotationRecord {
+    /// Time of the rotation in milliseconds
+    pub rotated_at: u64,
+    /// Index of the rotated-out level
+    pub level: usize,
+    /// Number of items the rotated-out window carried
+    pub insert_count: u64,
+    /// Fraction of bits set in the rotated-out level
+    pub fill_ratio: f64,
+    /// Why the rotation happened
+    pub reason: RotationReason,
+}
+
+/// Callback invoked on every rotation
+pub type RotationCallback = Box<dyn Fn(&RotationEvent) + Send + Sync>;
+
+/// What happens to an insert that trips the FPR guardrail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardrailAction {
+    /// Fail the insert with `EbloomError::Saturated`; the default
+    Reject,
+    /// Insert anyway
+    Allow,
+}
+
+/// Policy deciding what happens to inserts that trip the FPR guardrail
+pub type GuardrailPolicy =
+    Arc<dyn Fn(&SaturationError) -> GuardrailAction + Send + Sync>;
+
+/// Event emitted after a snapshot was written to storage
+#[derive(Debug, Clone)]
+pub struct SnapshotEvent {
+    /// Current level at the time of the snapshot
+    pub level: usize,
+    /// Whether the whole current level was written (on rotation) rather
+    /// than only its dirty chunks
+    pub full: bool,
+    /// Number of chunks written, across all levels
+    pub chunks: usize,
+    /// Chunk bytes written, metadata excluded
+    pub bytes: usize,
+    /// Time spent building and committing the snapshot
+    pub duration: Duration,
+}
+
+/// Early warning that the current level is filling up faster than sized for
+#[derive(Debug, Clone)]
+pub struct SaturationWarning {
+    pub level: usize,
+    /// Fraction of bits set when the warning fired
+    pub fill_ratio: f64,
+    /// Configured `saturation_warning` threshold
+    pub threshold: f64,
+    /// False positive rate of this level right now
+    pub level_fpr: f64,
+    /// Expected false positive rate of this level at the end of its window,
+    /// if inserts keep their current pace; equals `level_fpr` when the
+    /// window is not time-based
+    pub projected_fpr: f64,
+}
+
+/// Hooks into the filter lifecycle, registered with
+/// `ExpiringBloomFilter::add_observer`
+///
+/// Every method has an empty default, so observers only implement what they
+/// need. Hooks run synchronously on the calling thread (`on_insert` on the
+/// insert path), so they should be cheap and must not call back into the
+/// filter's registration methods.
+pub trait FilterObserver: Send + Sync {
+    /// An item was inserted into `level`
+    fn on_insert(&self, _item: &[u8], _level: usize) {}
+
+    /// A level was rotated out
+    fn on_rotate(&self, _event: &RotationEvent) {}
+
+    /// A snapshot was committed
+    fn on_snapshot(&self, _event: &SnapshotEvent) {}
+
+    /// The current level exceeded `max_fill_ratio` and is about to rotate
+    fn on_saturation(&self, _level: usize, _fill_ratio: f64) {}
+
+    /// An insert brought `level` to the rotation policy's insert count
+    ///
+    /// Fired once per window, on the inserting thread. Inserts do not rotate
+    /// themselves since rotation writes to storage; call
+    /// `cleanup_expired_levels` from here (or wake the task that does) so a
+    /// burst does not keep filling the level.
+    fn on_rotation_due(&self, _level: usize, _insert_count: u64) {}
+
+    /// The current level crossed the `saturation_warning` threshold
+    fn on_saturation_warning(&self, _warning: &SaturationWarning) {}
+
+    /// The estimated false positive rate moved past
+    /// `target_fpr * fpr_drift_tolerance`
+    fn on_fpr_drift(&self, _sample: &FprSample, _target_fpr: f64) {}
+}
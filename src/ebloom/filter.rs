@@ -1,141 +1,300 @@
-use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
-use crate::ebloom::error::{EbloomError, Result};
+use crate::common::{BITSET_BATCH_LEN, Bitset};
+use crate::ebloom::batch::Batch;
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::bulk::{BulkContext, partitioned_import, with_scratch};
+use crate::ebloom::clock::{Clock, SystemClock};
+use crate::ebloom::config::{
+    ExpiringFilterConfig, InsertMode, LevelBacking, LevelMetadata, RotationReason,
+};
+use crate::ebloom::crdt::BloomCrdt;
+use crate::ebloom::drift::{FprDrift, FprSample, FprTracker};
+use crate::ebloom::error::{EbloomError, Result, SaturationError};
+use crate::ebloom::events::{
+    FilterObserver, GuardrailAction, GuardrailPolicy, ROTATION_HISTORY_LEN,
+    RotationCallback, RotationEvent, RotationRecord, SaturationWarning,
+};
+use crate::ebloom::frozen::FrozenExpiringBloomFilter;
+#[cfg(feature = "latency")]
+use crate::ebloom::latency::LatencyRecorder;
+#[cfg(feature = "metrics")]
+use crate::ebloom::metrics as filter_metrics;
+use crate::ebloom::oplog::{OpLogBatch, ReplicationLog};
+#[cfg(feature = "latency")]
+use crate::ebloom::stats::LatencyOperation;
+use crate::ebloom::stats::{
+    DUMP_HISTOGRAM_BUCKETS, DebugDump, ExpiringStats, InvariantViolation,
+    LevelDump, LevelStats, MemoryUsage, PersistenceHealth, SnapshotStats,
+};
+use crate::ebloom::statsd::StatsdEmitter;
+use crate::ebloom::sync::{
+    AtomicBool, AtomicU64, AtomicUsize, Mutex, MutexGuard, Ordering, RwLock,
+    RwLockReadGuard, RwLockWriteGuard, fence,
+};
 use crate::ebloom::traits::{
     BulkExpiringBloomFilterOps, ExpiringBloomFilterOps, ExpiringBloomFilterStats,
 };
-use crate::hash::{
-    default_hash_function, optimal_bit_vector_size, optimal_num_hashes,
+use crate::ebloom::union::{
+    ChunkDelta, DELTA_CHUNK_BYTES, DeltaLevel, SeedSource, UnionLevel,
+    UnionPayload, layout_fingerprint,
 };
-use bitvec::prelude::*;
-use std::sync::{
-    Arc, RwLock,
-    atomic::{AtomicUsize, Ordering},
-};
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::hash::{HashFunction, HashIntoFunction, optimal_num_hashes};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "fjall")]
-use crate::ebloom::storage::{ExpiringStorageBackend, FjallExpiringBackend};
+use crate::ebloom::events::SnapshotEvent;
+#[cfg(feature = "fjall")]
+use crate::ebloom::storage::{
+    ExpiringStorageBackend, FilterStorage, FjallExpiringBackend, SnapshotBatch,
+};
+#[cfg(feature = "fjall")]
+use crate::ebloom::write_behind::WriteBehind;
 
 pub struct ExpiringBloomFilter {
     config: ExpiringFilterConfig,
     bit_vector_size: usize,
     num_hashes: usize,
+    hash_fn: HashFunction,
+    hash_into: HashIntoFunction,
 
-    // Level data
-    levels: Arc<RwLock<Vec<BitVec<usize, Lsb0>>>>,
+    // Level data, written and read without locks
+    levels: Arc<Vec<AtomicBitVec>>,
 
-    // Metadata
-    metadata: Arc<RwLock<Vec<LevelMetadata>>>,
+    // Metadata, one lock per level; per-level insert counts and creation
+    // times live in atomics so inserts and queries never take a metadata lock
+    metadata: Arc<Vec<RwLock<LevelMetadata>>>,
+    insert_counts: Arc<Vec<AtomicU64>>,
+    created_ats: Arc<Vec<AtomicU64>>,
     current_level: AtomicUsize,
 
+    // Time source
+    clock: Arc<dyn Clock>,
+
     // Persistence support
     #[cfg(feature = "fjall")]
-    storage: Option<FjallExpiringBackend>,
+    storage: Option<FilterStorage>,
+    // Background chunk writer, when configured
+    #[cfg(feature = "fjall")]
+    write_behind: Option<WriteBehind>,
     chunk_size_bytes: usize,
-    dirty_chunks: Option<Arc<RwLock<BitVec<usize, Lsb0>>>>,
+    dirty_chunks: Option<Arc<AtomicBitVec>>,
+    // Non-current levels modified since the last snapshot
+    dirty_levels: Option<Arc<AtomicBitVec>>,
+    // Time of the last committed snapshot (ms), 0 if none yet
+    last_snapshot_ms: AtomicU64,
+    // Incremental and full snapshot totals
+    snapshot_stats: RwLock<(SnapshotStats, SnapshotStats)>,
+
+    // Bits carried into the next level on rotation (grace overlap, smooth
+    // decay)
+    grace_bits: Option<Arc<AtomicBitVec>>,
+
+    // Rotation subscribers and rolling history
+    rotation_listeners: Arc<RwLock<Vec<RotationCallback>>>,
+    rotation_history: Arc<RwLock<VecDeque<RotationRecord>>>,
+
+    // Lifecycle observers; the flag keeps the insert path lock-free while
+    // none are registered
+    observers: Arc<RwLock<Vec<Arc<dyn FilterObserver>>>>,
+    has_observers: AtomicBool,
+    // Set once the current window raised its saturation warning
+    saturation_warned: AtomicBool,
+    // FPR limit on inserts as `f64` bits, 0 when off, and the policy
+    // applied when an insert exceeds it
+    fpr_guardrail: AtomicU64,
+    guardrail_policy: RwLock<Option<GuardrailPolicy>>,
+
+    // Inserts whose bits were all set already, and the sampled FPR trend
+    probable_duplicates: AtomicU64,
+    fpr_tracker: Mutex<FprTracker>,
+    // StatsD emitter, when configured
+    statsd: Option<StatsdEmitter>,
+
+    // Per-operation latency histograms
+    #[cfg(feature = "latency")]
+    latency: LatencyRecorder,
 }
 
 impl ExpiringBloomFilter {
     pub fn new(config: ExpiringFilterConfig) -> Result<Self> {
+        let clock = config.clock_mode.build_clock(0)?;
+        Self::with_clock(config, clock)
+    }
+
+    /// Create in-memory filter driven by a custom clock
+    pub fn with_clock(
+        config: ExpiringFilterConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
         config.validate()?;
 
-        let bit_vector_size =
-            optimal_bit_vector_size(config.capacity_per_level, config.target_fpr);
+        let bit_vector_size = config.level_bit_size();
+        let hash_fn = config.hash_function();
+        let hash_into = config.hash_into_function();
         let num_hashes =
             optimal_num_hashes(config.capacity_per_level, bit_vector_size);
 
-        let levels = (0..config.num_levels)
-            .map(|_| bitvec![0; bit_vector_size])
-            .collect();
+        let levels = allocate_levels(&config, bit_vector_size)?;
+        let insert_counts =
+            (0..config.num_levels).map(|_| AtomicU64::new(0)).collect();
 
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-
-        let metadata: Vec<LevelMetadata> = (0..config.num_levels)
-            .map(|i| LevelMetadata {
-                // Only the first level (current) has a timestamp (in milliseconds)
-                // Others are not yet active (created_at = 0 means not initialized)
-                created_at: if i == 0 { now_ms } else { 0 },
-                insert_count: 0,
-                last_snapshot_at: 0,
-            })
-            .collect();
+        let now_ms = clock.now_ms()?;
+
+        let metadata = initial_metadata(&config, now_ms);
+
+        let grace_bits = (config.grace_overlap.is_some()
+            || config.insert_mode == InsertMode::CurrentAndNext)
+            .then(|| Arc::new(AtomicBitVec::new(bit_vector_size)));
+        let statsd =
+            config.statsd.as_ref().map(StatsdEmitter::new).transpose()?;
 
         Ok(Self {
             config,
             bit_vector_size,
             num_hashes,
-            levels: Arc::new(RwLock::new(levels)),
-            metadata: Arc::new(RwLock::new(metadata)),
+            hash_fn,
+            hash_into,
+            levels: Arc::new(levels),
+            created_ats: Arc::new(created_at_counters(&metadata)),
+            metadata: Arc::new(metadata.into_iter().map(RwLock::new).collect()),
+            insert_counts: Arc::new(insert_counts),
             current_level: AtomicUsize::new(0),
+            clock,
             #[cfg(feature = "fjall")]
             storage: None,
+            #[cfg(feature = "fjall")]
+            write_behind: None,
             chunk_size_bytes: 0,
             dirty_chunks: None,
+            dirty_levels: None,
+            last_snapshot_ms: AtomicU64::new(0),
+            snapshot_stats: RwLock::default(),
+            grace_bits,
+            rotation_listeners: Arc::new(RwLock::new(Vec::new())),
+            rotation_history: Arc::new(RwLock::new(VecDeque::new())),
+            observers: Arc::new(RwLock::new(Vec::new())),
+            has_observers: AtomicBool::new(false),
+            saturation_warned: AtomicBool::new(false),
+            fpr_guardrail: AtomicU64::new(0),
+            guardrail_policy: RwLock::new(None),
+            probable_duplicates: AtomicU64::new(0),
+            fpr_tracker: Mutex::new(FprTracker::default()),
+            statsd,
+            #[cfg(feature = "latency")]
+            latency: LatencyRecorder::new(),
         })
     }
 
     /// Internal builder for creating filter with optional persistence
     async fn build_filter(
         config: ExpiringFilterConfig,
-        #[cfg(feature = "fjall")] storage: Option<FjallExpiringBackend>,
+        clock: Arc<dyn Clock>,
+        #[cfg(feature = "fjall")] storage: Option<FilterStorage>,
     ) -> Result<Self> {
         config.validate()?;
 
-        let bit_vector_size =
-            optimal_bit_vector_size(config.capacity_per_level, config.target_fpr);
+        let bit_vector_size = config.level_bit_size();
+        let hash_fn = config.hash_function();
+        let hash_into = config.hash_into_function();
         let num_hashes =
             optimal_num_hashes(config.capacity_per_level, bit_vector_size);
 
-        let levels = (0..config.num_levels)
-            .map(|_| bitvec![0; bit_vector_size])
-            .collect();
+        let levels = allocate_levels(&config, bit_vector_size)?;
+        let insert_counts =
+            (0..config.num_levels).map(|_| AtomicU64::new(0)).collect();
 
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        let now_ms = clock.now_ms()?;
 
-        let metadata: Vec<LevelMetadata> = (0..config.num_levels)
-            .map(|i| LevelMetadata {
-                created_at: if i == 0 { now_ms } else { 0 },
-                insert_count: 0,
-                last_snapshot_at: 0,
-            })
-            .collect();
+        let metadata = initial_metadata(&config, now_ms);
 
         // Setup dirty chunks if persistence enabled
-        let (chunk_size_bytes, dirty_chunks) = if config.persistence.is_some() {
-            let chunk_size =
-                config.persistence.as_ref().unwrap().chunk_size_bytes;
-            let chunk_count =
-                (bit_vector_size + chunk_size * 8 - 1).div_ceil(chunk_size * 8);
-            (
-                chunk_size,
-                Some(Arc::new(RwLock::new(bitvec![0; chunk_count]))),
-            )
-        } else {
-            (0, None)
+        let (chunk_size_bytes, dirty_chunks) =
+            if let Some(ref persistence) = config.persistence {
+                let chunk_size = persistence.chunk_size_bytes;
+                let chunk_count = (bit_vector_size + chunk_size * 8 - 1)
+                    .div_ceil(chunk_size * 8);
+                (chunk_size, Some(Arc::new(AtomicBitVec::new(chunk_count))))
+            } else {
+                (0, None)
+            };
+        let dirty_levels = config
+            .persistence
+            .as_ref()
+            .map(|_| Arc::new(AtomicBitVec::new(config.num_levels)));
+
+        let grace_bits = (config.grace_overlap.is_some()
+            || config.insert_mode == InsertMode::CurrentAndNext)
+            .then(|| Arc::new(AtomicBitVec::new(bit_vector_size)));
+        let statsd =
+            config.statsd.as_ref().map(StatsdEmitter::new).transpose()?;
+
+        let levels = Arc::new(levels);
+        #[cfg(feature = "fjall")]
+        let write_behind = match (
+            storage.as_ref().and_then(FilterStorage::fjall),
+            config
+                .persistence
+                .as_ref()
+                .and_then(|pers| pers.write_behind_capacity),
+        ) {
+            (Some(backend), Some(capacity)) => Some(WriteBehind::spawn(
+                Arc::clone(backend),
+                Arc::clone(&levels),
+                chunk_size_bytes,
+                capacity,
+            )?),
+            _ => None,
         };
 
         Ok(Self {
             config,
             bit_vector_size,
             num_hashes,
-            levels: Arc::new(RwLock::new(levels)),
-            metadata: Arc::new(RwLock::new(metadata)),
+            hash_fn,
+            hash_into,
+            levels,
+            created_ats: Arc::new(created_at_counters(&metadata)),
+            metadata: Arc::new(metadata.into_iter().map(RwLock::new).collect()),
+            insert_counts: Arc::new(insert_counts),
             current_level: AtomicUsize::new(0),
+            clock,
             #[cfg(feature = "fjall")]
             storage,
+            #[cfg(feature = "fjall")]
+            write_behind,
             chunk_size_bytes,
             dirty_chunks,
+            dirty_levels,
+            last_snapshot_ms: AtomicU64::new(0),
+            snapshot_stats: RwLock::default(),
+            grace_bits,
+            rotation_listeners: Arc::new(RwLock::new(Vec::new())),
+            rotation_history: Arc::new(RwLock::new(VecDeque::new())),
+            observers: Arc::new(RwLock::new(Vec::new())),
+            has_observers: AtomicBool::new(false),
+            saturation_warned: AtomicBool::new(false),
+            fpr_guardrail: AtomicU64::new(0),
+            guardrail_policy: RwLock::new(None),
+            probable_duplicates: AtomicU64::new(0),
+            fpr_tracker: Mutex::new(FprTracker::default()),
+            statsd,
+            #[cfg(feature = "latency")]
+            latency: LatencyRecorder::new(),
         })
     }
 
     /// Create new filter (overwrites existing DB if present)
     pub async fn create(config: ExpiringFilterConfig) -> Result<Self> {
+        let clock = config.clock_mode.build_clock(0)?;
+        Self::create_with_clock(config, clock).await
+    }
+
+    /// Create new filter driven by a custom clock
+    pub async fn create_with_clock(
+        config: ExpiringFilterConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
         #[cfg(feature = "fjall")]
         let storage = if let Some(ref pers) = config.persistence {
             // Create parent directory if needed
@@ -161,33 +320,27 @@ impl ExpiringBloomFilter {
                 pers.db_path.clone(),
                 config.num_levels,
             )
-            .await?;
+            .await?
+            .with_group_commit(pers.group_commit_window);
 
             // Save initial config
             backend.save_config(&config).await?;
             backend.save_current_level(0).await?;
 
             // Save initial metadata
-            let now_ms = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
-            let metadata: Vec<LevelMetadata> = (0..config.num_levels)
-                .map(|i| LevelMetadata {
-                    created_at: if i == 0 { now_ms } else { 0 },
-                    insert_count: 0,
-                    last_snapshot_at: 0,
-                })
-                .collect();
-            backend.save_level_metadata(&metadata).await?;
+            let now_ms = clock.now_ms()?;
+            backend
+                .save_level_metadata(&initial_metadata(&config, now_ms))
+                .await?;
 
-            Some(backend)
+            Some(FilterStorage::Fjall(Arc::new(backend)))
         } else {
             None
         };
 
         Self::build_filter(
             config,
+            clock,
             #[cfg(feature = "fjall")]
             storage,
         )
@@ -196,6 +349,7 @@ impl ExpiringBloomFilter {
 
     /// Load existing filter from DB
     #[cfg(feature = "fjall")]
+    #[tracing::instrument(level = "debug")]
     pub async fn load(db_path: std::path::PathBuf) -> Result<Self> {
         use crate::ebloom::storage::ExpiringStorageBackend;
 
@@ -211,15 +365,144 @@ impl ExpiringBloomFilter {
         drop(temp_backend);
 
         // Create backend with correct num_levels
-        let backend =
-            FjallExpiringBackend::new(db_path, config.num_levels).await?;
+        let group_commit_window = config
+            .persistence
+            .as_ref()
+            .and_then(|pers| pers.group_commit_window);
+        let backend = FjallExpiringBackend::new(db_path, config.num_levels)
+            .await?
+            .with_group_commit(group_commit_window);
+
+        Self::load_from(config, FilterStorage::Fjall(Arc::new(backend)), None)
+            .await
+    }
+
+    /// Create a filter persisted through the crate's gRPC storage service
+    ///
+    /// Same as `create`, with the database on the remote server: existing
+    /// data there is overwritten. `config.persistence` is still required
+    /// for the chunk size and snapshot settings; its `db_path` is ignored
+    /// and write-behind is not available remotely.
+    #[cfg(all(feature = "fjall", feature = "grpc"))]
+    pub async fn create_remote(
+        config: ExpiringFilterConfig,
+        backend: crate::grpc::storage::GrpcStorageBackend,
+    ) -> Result<Self> {
+        let clock = config.clock_mode.build_clock(0)?;
+        Self::create_on(config, clock, FilterStorage::Remote(Arc::new(backend)))
+            .await
+    }
+
+    /// Load a filter persisted through the crate's gRPC storage service
+    #[cfg(all(feature = "fjall", feature = "grpc"))]
+    pub async fn load_remote(
+        backend: crate::grpc::storage::GrpcStorageBackend,
+    ) -> Result<Self> {
+        let config = backend.load_config().await?;
+        Self::load_from(config, FilterStorage::Remote(Arc::new(backend)), None)
+            .await
+    }
+
+    /// Create a filter persisted to any storage backend
+    ///
+    /// Existing data in `backend` is overwritten. As with `create_remote`,
+    /// `config.persistence` supplies the chunk size and its `db_path` is
+    /// ignored.
+    #[cfg(feature = "fjall")]
+    pub async fn create_with_storage(
+        config: ExpiringFilterConfig,
+        clock: Arc<dyn Clock>,
+        backend: Arc<dyn ExpiringStorageBackend + Send + Sync>,
+    ) -> Result<Self> {
+        Self::create_on(config, clock, FilterStorage::Custom(backend)).await
+    }
+
+    /// Load a filter from any storage backend, driven by `clock`
+    #[cfg(feature = "fjall")]
+    pub async fn load_with_storage(
+        clock: Arc<dyn Clock>,
+        backend: Arc<dyn ExpiringStorageBackend + Send + Sync>,
+    ) -> Result<Self> {
+        let config = backend.load_config().await?;
+        Self::load_from(config, FilterStorage::Custom(backend), Some(clock)).await
+    }
+
+    /// Create a filter over an open Fjall backend, overwriting its data;
+    /// used by `FilterKeyspace`
+    #[cfg(feature = "fjall")]
+    pub(crate) async fn create_on_fjall(
+        config: ExpiringFilterConfig,
+        backend: Arc<FjallExpiringBackend>,
+    ) -> Result<Self> {
+        let clock = config.clock_mode.build_clock(0)?;
+        Self::create_on(config, clock, FilterStorage::Fjall(backend)).await
+    }
+
+    /// Load a filter from an open Fjall backend; used by `FilterKeyspace`
+    #[cfg(feature = "fjall")]
+    pub(crate) async fn load_on_fjall(
+        config: ExpiringFilterConfig,
+        backend: Arc<FjallExpiringBackend>,
+    ) -> Result<Self> {
+        Self::load_from(config, FilterStorage::Fjall(backend), None).await
+    }
+
+    /// Reset `storage` and build a new filter over it
+    #[cfg(feature = "fjall")]
+    async fn create_on(
+        config: ExpiringFilterConfig,
+        clock: Arc<dyn Clock>,
+        storage: FilterStorage,
+    ) -> Result<Self> {
+        if config.persistence.is_none() {
+            return Err(EbloomError::InvalidConfig(
+                "Storage backends need a persistence config".to_string(),
+            ));
+        }
+        config.validate()?;
+
+        for level in 0..config.num_levels {
+            storage.delete_level(level).await?;
+        }
+        storage.save_rotation_log(&[]).await?;
+        storage.save_config(&config).await?;
+        storage.save_current_level(0).await?;
+        storage
+            .save_level_metadata(&initial_metadata(&config, clock.now_ms()?))
+            .await?;
+
+        Self::build_filter(config, clock, Some(storage)).await
+    }
 
-        // Build filter
-        let mut filter = Self::build_filter(config, Some(backend)).await?;
+    /// Build a filter over `storage` and restore its persisted state
+    ///
+    /// Without a `clock`, one is built from the config's clock mode.
+    #[cfg(feature = "fjall")]
+    async fn load_from(
+        config: ExpiringFilterConfig,
+        storage: FilterStorage,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<Self> {
+        let fixed_clock = clock.is_some();
+        let mut filter = Self::build_filter(
+            config,
+            clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            Some(storage),
+        )
+        .await?;
 
         // Reconstruct all levels from storage
         filter.reconstruct_from_storage().await?;
 
+        // Never let a monotonic clock start behind persisted timestamps
+        if !fixed_clock {
+            let latest_ms = filter.latest_timestamp()?;
+            filter.clock = filter.config.clock_mode.build_clock(latest_ms)?;
+        }
+
+        // Catch up on windows that elapsed while the filter was offline
+        filter.cleanup_expired_levels().await?;
+
         Ok(filter)
     }
 
@@ -240,213 +523,2202 @@ impl ExpiringBloomFilter {
         Self::create(config).await
     }
 
+    /// Configuration the filter was built with
+    pub fn config(&self) -> &ExpiringFilterConfig {
+        &self.config
+    }
+
+    /// Time source driving rotation
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// Take an immutable snapshot of all levels
+    ///
+    /// The returned handle is read-only and never rotates, so queries
+    /// against it stay stable while this filter keeps ingesting. Every level
+    /// is copied, costing `num_levels` times the level size in memory and a
+    /// pass over all bits; the live levels are written in place, so nothing
+    /// cheaper gives a stable view. Clones of the handle share the copy.
+    pub fn freeze(&self) -> Result<FrozenExpiringBloomFilter> {
+        let metadata = self.metadata_snapshot()?;
+
+        Ok(FrozenExpiringBloomFilter {
+            config: self.config.clone(),
+            bit_vector_size: self.bit_vector_size,
+            num_hashes: self.num_hashes,
+            levels: Arc::new(self.levels.as_ref().clone()),
+            metadata: Arc::new(metadata),
+            current_level: self.current_level.load(Ordering::Acquire),
+            frozen_at: self.clock.now_ms()?,
+        })
+    }
+
+    /// Rebuild a writable in-memory filter from a frozen snapshot
+    pub(crate) fn from_frozen(
+        frozen: &FrozenExpiringBloomFilter,
+    ) -> Result<Self> {
+        let mut config = frozen.config.clone();
+        config.persistence = None;
+        // Frozen bits are copied onto the heap; never reopen (and truncate)
+        // the mapping files of the filter that was frozen
+        config.level_backing = LevelBacking::Heap;
+
+        let mut filter = Self::new(config)?;
+        filter.levels = Arc::new(frozen.levels.as_ref().clone());
+        filter.metadata =
+            Arc::new(frozen.metadata.iter().cloned().map(RwLock::new).collect());
+        filter.insert_counts = Arc::new(
+            frozen
+                .metadata
+                .iter()
+                .map(|m| AtomicU64::new(m.insert_count))
+                .collect(),
+        );
+        filter.created_ats = Arc::new(created_at_counters(&frozen.metadata));
+        filter.current_level = AtomicUsize::new(frozen.current_level);
+        Ok(filter)
+    }
+
     /// Get current active level index
     pub fn get_active_level(&self) -> usize {
-        self.current_level.load(Ordering::Relaxed)
+        self.current_level.load(Ordering::Acquire)
+    }
+
+    /// Metadata snapshot for a single level
+    pub fn level_metadata(&self, level_index: usize) -> Result<LevelMetadata> {
+        self.metadata_snapshot()?
+            .into_iter()
+            .nth(level_index)
+            .ok_or(EbloomError::InvalidLevel {
+                level: level_index,
+                max_levels: self.config.num_levels,
+            })
+    }
+
+    /// Metadata snapshot for all levels, indexed by level
+    pub fn all_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
+        self.metadata_snapshot()
+    }
+
+    /// Copy of the metadata with insert counts and creation times filled in
+    /// from the counters
+    ///
+    /// Levels are locked one at a time, so the copy is consistent per level
+    /// rather than across levels.
+    fn metadata_snapshot(&self) -> Result<Vec<LevelMetadata>> {
+        (0..self.metadata.len())
+            .map(|level_idx| {
+                let mut meta = self.read_metadata(level_idx)?.clone();
+                meta.insert_count =
+                    self.insert_counts[level_idx].load(Ordering::Relaxed);
+                meta.created_at =
+                    self.created_ats[level_idx].load(Ordering::Acquire);
+                Ok(meta)
+            })
+            .collect()
+    }
+
+    /// Creation time of a level (ms), without taking its metadata lock
+    fn level_created_at(&self, level_index: usize) -> Result<u64> {
+        self.created_ats
+            .get(level_index)
+            .map(|created_at| created_at.load(Ordering::Acquire))
+            .ok_or(EbloomError::InvalidLevel {
+                level: level_index,
+                max_levels: self.config.num_levels,
+            })
+    }
+
+    /// Read lock on a single level's metadata
+    fn read_metadata(
+        &self,
+        level_index: usize,
+    ) -> Result<RwLockReadGuard<'_, LevelMetadata>> {
+        self.metadata
+            .get(level_index)
+            .ok_or(EbloomError::InvalidLevel {
+                level: level_index,
+                max_levels: self.config.num_levels,
+            })?
+            .read()
+            .map_err(|_| {
+                EbloomError::LockError("Failed to read metadata".to_string())
+            })
+    }
+
+    /// Write lock on a single level's metadata
+    fn write_metadata(
+        &self,
+        level_index: usize,
+    ) -> Result<RwLockWriteGuard<'_, LevelMetadata>> {
+        self.metadata
+            .get(level_index)
+            .ok_or(EbloomError::InvalidLevel {
+                level: level_index,
+                max_levels: self.config.num_levels,
+            })?
+            .write()
+            .map_err(|_| {
+                EbloomError::LockError("Failed to write metadata".to_string())
+            })
     }
 
-    /// Check if a level has expired based on its creation time
+    /// Check if a level has outlived the retention of its age position
+    ///
+    /// The current level expires once its window has passed; an older
+    /// level once `retention_for_age` of its age has.
     pub fn is_level_expired(&self, level_index: usize) -> Result<bool> {
-        let metadata = self.metadata.read().map_err(|_| {
-            EbloomError::LockError("Failed to read metadata".to_string())
-        })?;
-        if let Some(level_meta) = metadata.get(level_index) {
-            if level_meta.created_at == 0 {
-                return Ok(false); // Not initialized yet
-            }
-            let now_ms = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| EbloomError::TimeError(e.to_string()))?
-                .as_millis() as u64;
-            let level_age_ms = now_ms - level_meta.created_at; // Both in milliseconds
-            Ok(level_age_ms > self.config.level_duration.as_millis() as u64)
-        } else {
-            Ok(false) // Index out of bounds
+        if level_index >= self.metadata.len() {
+            return Ok(false); // Index out of bounds
+        }
+        let created_at = self.level_created_at(level_index)?;
+        if created_at == 0 {
+            return Ok(false); // Not initialized yet
         }
+        let now_ms = self.clock.now_ms()?;
+        // Wall clock may jump backwards (NTP, VM resume); treat as age 0
+        let level_age_ms = now_ms.saturating_sub(created_at);
+        let retention =
+            self.config.retention_for_age(self.level_age(level_index));
+        Ok(level_age_ms > retention.as_millis() as u64)
     }
 
-    /// Rotate levels: move to next level in circular fashion
-    /// The new current level is cleared (oldest data expires)
-    pub async fn rotate_levels(&self) -> Result<()> {
-        let current_idx = self.current_level.load(Ordering::Relaxed);
+    /// Rotations since a level was current (0 for the current level)
+    pub fn level_age(&self, level_index: usize) -> usize {
+        let num_levels = self.config.num_levels;
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        (current_idx + num_levels - level_index % num_levels) % num_levels
+    }
 
-        // Calculate next level index (circular)
-        let new_current_idx = (current_idx + 1) % self.config.num_levels;
+    /// Check membership as of a past timestamp (milliseconds)
+    ///
+    /// Only levels that already existed at `timestamp_ms` are consulted, so
+    /// backfill jobs can reproduce the decision made at event time. Items
+    /// inserted into those levels after `timestamp_ms` are still visible.
+    pub fn contains_at(&self, item: &[u8], timestamp_ms: u64) -> Result<bool> {
+        let active: Vec<bool> = self
+            .created_ats
+            .iter()
+            .map(|created_at| {
+                let created_at = created_at.load(Ordering::Acquire);
+                created_at != 0 && created_at <= timestamp_ms
+            })
+            .collect();
 
-        // 1. Save FULL snapshot of current level (freeze it forever)
-        self.save_full_snapshot().await?;
+        let indices = (self.hash_fn)(item, self.num_hashes, self.bit_vector_size);
 
-        // 2. Get write locks and clear the new current level
-        {
-            let mut levels = self.levels.write().map_err(|_| {
-                EbloomError::LockError("Failed to write levels".to_string())
-            })?;
-            levels[new_current_idx].fill(false);
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            if active.get(level_idx).copied().unwrap_or(false)
+                && level_contains(&indices, self.bit_vector_size, level)?
+            {
+                return Ok(true);
+            }
         }
+        Ok(false)
+    }
 
-        // 3. Delete new current level's old data from DB (both chunks AND dirty)
-        #[cfg(feature = "fjall")]
-        if let Some(ref backend) = self.storage {
-            backend.delete_level(new_current_idx).await?;
+    /// Check membership and score how much the match can be trusted
+    ///
+    /// Returns `None` when no level matches. Otherwise the newest matching
+    /// level is scored as `(1 - density^k) * (num_levels - age) / num_levels`,
+    /// where `density` is that level's fraction of set bits, `k` the number of
+    /// hashes and `age` how many rotations ago the level was current. Fresh
+    /// matches in sparse levels score close to 1.0, near-expiry matches in
+    /// dense levels score close to 0.0.
+    pub fn contains_scored(&self, item: &[u8]) -> Result<Option<f64>> {
+        let indices = (self.hash_fn)(item, self.num_hashes, self.bit_vector_size);
+        let num_levels = self.config.num_levels;
+        let current_idx = self.current_level.load(Ordering::Acquire);
+
+        // Walk from the current level back to the oldest one
+        for age in 0..num_levels {
+            let level_idx = (current_idx + num_levels - age) % num_levels;
+            let level = &self.levels[level_idx];
+            if level_contains(&indices, self.bit_vector_size, level)? {
+                let level_fpr = level.fill_ratio().powi(self.num_hashes as i32);
+                let recency = (num_levels - age) as f64 / num_levels as f64;
+                return Ok(Some((1.0 - level_fpr) * recency));
+            }
         }
+        Ok(None)
+    }
 
-        // 4. Update metadata for the new current level
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| EbloomError::TimeError(e.to_string()))?
-            .as_millis() as u64;
-
-        let new_metadata = {
-            let mut metadata = self.metadata.write().map_err(|_| {
-                EbloomError::LockError("Failed to write metadata".to_string())
-            })?;
-            metadata[new_current_idx] = LevelMetadata {
-                created_at: now_ms,
-                insert_count: 0,
-                last_snapshot_at: 0,
-            };
-            metadata.clone()
+    /// Estimate how long an item will remain visible
+    ///
+    /// Uses the oldest level the item matches and the time-based schedule:
+    /// the remainder of the current window plus the windows of every level
+    /// that becomes current before the matching level is cleared. Returns
+    /// `None` when the item is not found.
+    pub fn ttl_estimate(&self, item: &[u8]) -> Result<Option<Duration>> {
+        let indices = (self.hash_fn)(item, self.num_hashes, self.bit_vector_size);
+        let num_levels = self.config.num_levels;
+        let current_idx = self.current_level.load(Ordering::Acquire);
+
+        let mut oldest_age = None;
+        for age in (0..num_levels).rev() {
+            let level_idx = (current_idx + num_levels - age) % num_levels;
+            if level_contains(
+                &indices,
+                self.bit_vector_size,
+                &self.levels[level_idx],
+            )? {
+                oldest_age = Some(age);
+                break;
+            }
+        }
+        let Some(age) = oldest_age else {
+            return Ok(None);
         };
 
-        // 5. Save metadata and current level pointer to DB
-        #[cfg(feature = "fjall")]
-        if let Some(ref backend) = self.storage {
-            backend.save_level_metadata(&new_metadata).await?;
-            backend.save_current_level(new_current_idx).await?;
+        // The matching level is dropped a lifetime after its window started
+        let level_idx = (current_idx + num_levels - age) % num_levels;
+        let dropped_at = self.level_created_at(level_idx)?
+            + self.config.level_lifetime().as_millis() as u64;
+        let ttl_ms = dropped_at.saturating_sub(self.clock.now_ms()?);
+        Ok(Some(Duration::from_millis(ttl_ms)))
+    }
+
+    /// Insert many items, reusing the buffers in `ctx`
+    ///
+    /// Same as `insert_bulk`, for callers that keep one `BulkContext` per
+    /// worker across many small batches.
+    pub fn insert_bulk_with(
+        &self,
+        ctx: &mut BulkContext,
+        items: &[&[u8]],
+    ) -> Result<()> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::InsertBulk);
+        // Get the current level index
+        let current_level_idx = self.current_level.load(Ordering::Acquire);
+        self.check_fpr_guardrail(current_level_idx, items.len() as u64)?;
+        // Counted up front, as in `insert`
+        let previous_count = self.insert_counts[current_level_idx]
+            .fetch_add(items.len() as u64, Ordering::Relaxed);
+        self.check_rotation_due(
+            current_level_idx,
+            previous_count,
+            items.len() as u64,
+        )?;
+
+        let mut duplicates = 0;
+        for item in items {
+            ctx.indices.clear();
+            (self.hash_into)(
+                item,
+                self.num_hashes,
+                self.bit_vector_size,
+                &mut ctx.indices,
+            );
+            duplicates += set_indices(
+                &ctx.indices,
+                current_level_idx,
+                self.bit_vector_size,
+                self.chunk_size_bytes,
+                self.dirty_chunks.as_deref(),
+                &self.levels,
+            )? as u64;
+            self.queue_write_behind(current_level_idx, &ctx.indices)?;
         }
 
-        // 7. Update current level pointer in memory
-        self.current_level.store(new_current_idx, Ordering::Relaxed);
+        if duplicates > 0 {
+            self.probable_duplicates
+                .fetch_add(duplicates, Ordering::Relaxed);
+        }
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_inserts(items.len());
+        if let Some(ref statsd) = self.statsd {
+            statsd.record_inserts(items.len());
+        }
+        self.notify_observers(|observer| {
+            for item in items {
+                observer.on_insert(item, current_level_idx);
+            }
+        })?;
 
-        // 8. Clear dirty chunks tracker (for new current level)
-        if let Some(ref dirty_chunks_arc) = self.dirty_chunks {
-            let mut dirty = dirty_chunks_arc.write().map_err(|_| {
-                EbloomError::LockError("Failed to write dirty chunks".to_string())
-            })?;
-            dirty.fill(false);
+        // Carry the items into the next level in smooth-decay mode or when
+        // close to rotation
+        if self.carries_into_next(current_level_idx)? {
+            self.record_grace(items);
         }
 
         Ok(())
     }
 
-    /// Clean up expired levels by rotating when current level expires
-    pub async fn cleanup_expired_levels(&self) -> Result<()> {
-        let current_level = self.current_level.load(Ordering::Relaxed);
-
-        if self.is_level_expired(current_level)? {
-            self.rotate_levels().await?;
+    /// Load a large initial dataset into the current level on
+    /// `parallelism` threads
+    ///
+    /// Much faster than `insert_bulk` for hundreds of millions of keys:
+    /// each thread fills a disjoint region of the level, and the per-item
+    /// bookkeeping is done once at the end. Observers, write-behind,
+    /// smooth decay, the grace window and the FPR guardrail are bypassed;
+    /// with persistence the whole level is written by the next
+    /// `save_snapshot`. Meant for seeding a filter before it serves
+    /// traffic. Returns the number of items imported.
+    pub fn bulk_import<T: AsRef<[u8]> + Sync>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        parallelism: usize,
+    ) -> Result<u64> {
+        let current_level_idx = self.current_level.load(Ordering::Acquire);
+        let imported = partitioned_import(
+            &self.levels[current_level_idx],
+            items,
+            self.hash_into,
+            self.num_hashes,
+            parallelism,
+        );
+
+        self.insert_counts[current_level_idx]
+            .fetch_add(imported, Ordering::Relaxed);
+        self.mark_level_dirty(current_level_idx);
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_inserts(imported as usize);
+        if let Some(ref statsd) = self.statsd {
+            statsd.record_inserts(imported as usize);
         }
+        Ok(imported)
+    }
 
-        Ok(())
+    /// Start buffering inserts to apply and persist together, see
+    /// `ebloom::batch`
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
     }
 
-    /// Save incremental dirty chunks for CURRENT level (crash recovery)
-    pub async fn save_snapshot(&self) -> Result<()> {
-        #[cfg(feature = "fjall")]
-        if let Some(ref backend) = self.storage {
-            let current_idx = self.current_level.load(Ordering::Relaxed);
-            let dirty_chunks = self.extract_dirty_chunks()?;
+    /// Check many items, reusing the buffers in `ctx`
+    ///
+    /// Results are left in `ctx` and returned as a slice, so no vector is
+    /// allocated per call once the context has grown to the batch size.
+    pub fn contains_bulk_with<'a>(
+        &self,
+        ctx: &'a mut BulkContext,
+        items: &[&[u8]],
+    ) -> Result<&'a [bool]> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::ContainsBulk);
+        contains_batched(
+            items,
+            self.hash_into,
+            self.num_hashes,
+            self.bit_vector_size,
+            &self.levels,
+            ctx,
+        )?;
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_queries(
+            items.len(),
+            ctx.results.iter().filter(|&&found| found).count(),
+        );
+        Ok(ctx.results())
+    }
 
-            if !dirty_chunks.is_empty() {
-                backend
-                    .save_dirty_chunks(current_idx, &dirty_chunks)
-                    .await?;
+    /// Check many items on `parallelism` threads, each taking a contiguous
+    /// share of `items`
+    ///
+    /// Pays off when probes wait on I/O rather than on memory: with
+    /// file-mapped levels that are not resident, every cold probe is a
+    /// blocking page fault, and one thread would fetch them one after
+    /// another. `contains_bulk` switches to this on its own for such levels
+    /// and batches of at least `PARALLEL_CONTAINS_MIN_ITEMS`.
+    pub fn contains_bulk_parallel(
+        &self,
+        items: &[&[u8]],
+        parallelism: usize,
+    ) -> Result<Vec<bool>> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::ContainsBulk);
+        let share = items.len().div_ceil(parallelism.max(1)).max(1);
+        let shares = std::thread::scope(|scope| {
+            let workers: Vec<_> = items
+                .chunks(share)
+                .map(|share| {
+                    scope.spawn(move || {
+                        let mut ctx = BulkContext::with_capacity(
+                            share.len(),
+                            self.num_hashes,
+                        );
+                        contains_batched(
+                            share,
+                            self.hash_into,
+                            self.num_hashes,
+                            self.bit_vector_size,
+                            &self.levels,
+                            &mut ctx,
+                        )
+                        .map(|()| ctx.results)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<Vec<bool>>>>()
+        })?;
+        let found = shares.concat();
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_queries(
+            found.len(),
+            found.iter().filter(|&&hit| hit).count(),
+        );
+        Ok(found)
+    }
 
-                // Update last_snapshot_at
-                let now_ms = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .map_err(|e| EbloomError::TimeError(e.to_string()))?
-                    .as_millis() as u64;
+    /// Insert an item into the level whose window covers `timestamp_ms`
+    ///
+    /// Used to replay an event log while preserving per-level placement.
+    /// Returns `false` without inserting when the timestamp is older than
+    /// every level still held by the filter.
+    pub fn insert_at(&self, item: &[u8], timestamp_ms: u64) -> Result<bool> {
+        let Some(target_level) = self.level_at(timestamp_ms) else {
+            return Ok(false);
+        };
 
-                let updated_metadata = {
-                    let mut metadata = self.metadata.write().map_err(|_| {
-                        EbloomError::LockError(
-                            "Failed to write metadata".to_string(),
-                        )
-                    })?;
-                    metadata[current_idx].last_snapshot_at = now_ms;
-                    metadata.clone()
-                };
+        if target_level == self.current_level.load(Ordering::Acquire) {
+            self.insert(item)?;
+            return Ok(true);
+        }
 
-                backend.save_level_metadata(&updated_metadata).await?;
-            }
+        let (indices, _) = insert_internal(
+            item,
+            self.hash_fn,
+            target_level,
+            self.num_hashes,
+            self.bit_vector_size,
+            self.chunk_size_bytes,
+            None,
+            &self.levels,
+        )?;
+        self.queue_write_behind(target_level, &indices)?;
+        self.insert_counts[target_level].fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_inserts(1);
+        if let Some(ref statsd) = self.statsd {
+            statsd.record_inserts(1);
         }
-        Ok(())
+        self.notify_observers(|observer| observer.on_insert(item, target_level))?;
+        self.mark_level_dirty(target_level);
+
+        Ok(true)
     }
 
-    /// Save full snapshot of CURRENT level (called on rotation)
-    async fn save_full_snapshot(&self) -> Result<()> {
-        #[cfg(feature = "fjall")]
-        if let Some(ref backend) = self.storage {
-            let current_idx = self.current_level.load(Ordering::Relaxed);
-            let chunks = self.extract_all_chunks()?;
+    /// Export the levels holding bits, for another filter's `apply_union`
+    ///
+    /// See `ebloom::union` for how levels are matched up.
+    pub fn export_union(&self) -> Result<UnionPayload> {
+        let now_ms = self.clock.now_ms()?;
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        let created_ats: Vec<u64> = self
+            .created_ats
+            .iter()
+            .map(|created_at| created_at.load(Ordering::Acquire))
+            .collect();
 
-            backend.save_level_chunks(current_idx, &chunks).await?;
+        let mut levels: Vec<UnionLevel> = created_ats
+            .iter()
+            .enumerate()
+            .filter(|&(level_idx, &created_at)| {
+                created_at != 0 && self.levels[level_idx].any()
+            })
+            .map(|(level_idx, &created_at)| {
+                let last_written_at = if level_idx == current_idx {
+                    now_ms
+                } else {
+                    created_ats
+                        .iter()
+                        .copied()
+                        .filter(|&other| other > created_at)
+                        .min()
+                        .map_or(now_ms, |next| next - 1)
+                };
+                UnionLevel {
+                    created_at,
+                    last_written_at,
+                    bits: self.levels[level_idx]
+                        .read_bytes(0, self.bit_vector_size),
+                }
+            })
+            .collect();
+        levels.sort_by_key(|level| level.created_at);
 
-            // Update last_snapshot_at
-            let now_ms = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| EbloomError::TimeError(e.to_string()))?
-                .as_millis() as u64;
-
-            let updated_metadata = {
-                let mut metadata = self.metadata.write().map_err(|_| {
-                    EbloomError::LockError("Failed to write metadata".to_string())
-                })?;
-                metadata[current_idx].last_snapshot_at = now_ms;
-                metadata.clone()
-            };
+        Ok(UnionPayload {
+            layout_fingerprint: self.layout_fingerprint(),
+            exported_at: now_ms,
+            levels,
+        })
+    }
 
-            backend.save_level_metadata(&updated_metadata).await?;
+    /// OR the levels of a payload from `export_union` into this filter,
+    /// returning how many levels were merged
+    ///
+    /// Each level goes into the newest local level created no later than
+    /// it was last written; levels older than every local level are
+    /// skipped. Merged levels are persisted by the next `save_snapshot`.
+    pub fn apply_union(&self, payload: &UnionPayload) -> Result<usize> {
+        if payload.layout_fingerprint != self.layout_fingerprint() {
+            return Err(EbloomError::InvalidConfig(
+                "Union payload was exported by a filter with a different bit layout"
+                    .to_string(),
+            ));
         }
-        Ok(())
+        let level_bytes = self.level_bytes();
+        if let Some(level) =
+            payload.levels.iter().find(|l| l.bits.len() != level_bytes)
+        {
+            return Err(EbloomError::SerializationError(format!(
+                "Union level has {} bytes, expected {level_bytes}",
+                level.bits.len()
+            )));
+        }
+
+        let mut merged = 0;
+        for level in &payload.levels {
+            let Some(target_level) = self.level_at(level.last_written_at) else {
+                continue;
+            };
+            self.levels[target_level].or_bytes(0, &level.bits);
+            self.mark_level_dirty(target_level);
+            merged += 1;
+        }
+        Ok(merged)
     }
 
-    /// Extract dirty chunks for current level only
-    fn extract_dirty_chunks(&self) -> Result<Vec<(usize, Vec<u8>)>> {
-        let mut chunks = Vec::new();
+    /// OR every level of `source` into this filter's oldest level,
+    /// returning how many levels were merged
+    ///
+    /// Meant for a filter that was just created, after a deploy or a
+    /// config change that kept the bit layout: keys the old filter saw,
+    /// even in levels that have expired since, stay present until the
+    /// oldest level rotates out, instead of all looking new at once.
+    /// Unlike `apply_union`, level times are ignored. An oldest level not
+    /// used yet is dated one full ring before the current level. Seeded
+    /// bits are persisted by the next `save_snapshot`.
+    pub fn seed_from<S: SeedSource + ?Sized>(&self, source: &S) -> Result<usize> {
+        let payload = source.union_payload()?;
+        if payload.layout_fingerprint != self.layout_fingerprint() {
+            return Err(EbloomError::InvalidConfig(
+                "Seed source has a different bit layout".to_string(),
+            ));
+        }
+        let level_bytes = self.level_bytes();
+        if let Some(level) =
+            payload.levels.iter().find(|l| l.bits.len() != level_bytes)
+        {
+            return Err(EbloomError::SerializationError(format!(
+                "Seed level has {} bytes, expected {level_bytes}",
+                level.bits.len()
+            )));
+        }
 
-        if let Some(ref dirty_chunks_arc) = self.dirty_chunks {
-            let current_idx = self.current_level.load(Ordering::Relaxed);
-            let levels = self.levels.read().map_err(|_| {
-                EbloomError::LockError("Failed to read levels".to_string())
-            })?;
-            let dirty = dirty_chunks_arc.read().map_err(|_| {
-                EbloomError::LockError("Failed to read dirty chunks".to_string())
-            })?;
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        let oldest_idx = (current_idx + 1) % self.config.num_levels;
+        if self.level_created_at(oldest_idx)? == 0 {
+            let ring_ms = self.config.duration_for_age(0).as_millis() as u64
+                * (self.config.num_levels as u64 - 1);
+            let created_at = self
+                .level_created_at(current_idx)?
+                .saturating_sub(ring_ms)
+                .max(1);
+            self.write_metadata(oldest_idx)?.created_at = created_at;
+            self.created_ats[oldest_idx].store(created_at, Ordering::Release);
+        }
+        for level in &payload.levels {
+            self.levels[oldest_idx].or_bytes(0, &level.bits);
+        }
+        self.mark_level_dirty(oldest_idx);
+        Ok(payload.levels.len())
+    }
 
-            let chunk_size_bits = self.chunk_size_bytes * 8;
+    /// Chunks of this filter holding bits `other` lacks, for
+    /// `other.apply_delta`
+    ///
+    /// Chunks are the persistence `chunk_size_bytes`, or
+    /// `DELTA_CHUNK_BYTES` for in-memory filters. Levels are matched by
+    /// time as in `apply_union`; levels `other` would skip are left out.
+    pub fn compute_delta(
+        &self,
+        other: &ExpiringBloomFilter,
+    ) -> Result<ChunkDelta> {
+        if other.layout_fingerprint() != self.layout_fingerprint() {
+            return Err(EbloomError::InvalidConfig(
+                "Filters have different bit layouts".to_string(),
+            ));
+        }
+        let chunk_bytes = if self.chunk_size_bytes > 0 {
+            self.chunk_size_bytes
+        } else {
+            DELTA_CHUNK_BYTES
+        };
+        let payload = self.export_union()?;
+        let levels = payload
+            .levels
+            .into_iter()
+            .filter_map(|level| {
+                let target_level = other.level_at(level.last_written_at)?;
+                let theirs = other.levels[target_level]
+                    .read_bytes(0, other.bit_vector_size);
+                let chunks: Vec<(usize, Vec<u8>)> = level
+                    .bits
+                    .chunks(chunk_bytes)
+                    .zip(theirs.chunks(chunk_bytes))
+                    .enumerate()
+                    .filter(|(_, (ours, theirs))| {
+                        ours.iter().zip(theirs.iter()).any(|(a, b)| a & !b != 0)
+                    })
+                    .map(|(idx, (ours, _))| (idx, ours.to_vec()))
+                    .collect();
+                (!chunks.is_empty()).then_some(DeltaLevel {
+                    created_at: level.created_at,
+                    last_written_at: level.last_written_at,
+                    chunks,
+                })
+            })
+            .collect();
+        Ok(ChunkDelta {
+            layout_fingerprint: payload.layout_fingerprint,
+            chunk_bytes,
+            levels,
+        })
+    }
 
-            for chunk_id in 0..dirty.len() {
-                if dirty[chunk_id] {
-                    let chunk_data = extract_chunk_bytes(
-                        &levels[current_idx],
-                        chunk_id,
-                        chunk_size_bits,
-                    );
-                    chunks.push((chunk_id, chunk_data));
+    /// OR the chunks of a delta into this filter, returning how many
+    /// levels were merged
+    ///
+    /// Levels are matched by time as in `apply_union`. Nothing is merged
+    /// if any chunk lies outside a level.
+    pub fn apply_delta(&self, delta: &ChunkDelta) -> Result<usize> {
+        if delta.layout_fingerprint != self.layout_fingerprint() {
+            return Err(EbloomError::InvalidConfig(
+                "Delta comes from a filter with a different bit layout"
+                    .to_string(),
+            ));
+        }
+        let level_bytes = self.level_bytes();
+        for level in &delta.levels {
+            for (chunk_idx, chunk) in &level.chunks {
+                let end = chunk_idx
+                    .checked_mul(delta.chunk_bytes)
+                    .and_then(|start| start.checked_add(chunk.len()));
+                if chunk.len() > delta.chunk_bytes
+                    || end.is_none_or(|end| end > level_bytes)
+                {
+                    return Err(EbloomError::SerializationError(format!(
+                        "Delta chunk {chunk_idx} exceeds the level size"
+                    )));
                 }
             }
         }
 
-        Ok(chunks)
+        let mut merged = 0;
+        for level in &delta.levels {
+            let Some(target_level) = self.level_at(level.last_written_at) else {
+                continue;
+            };
+            for (chunk_idx, chunk) in &level.chunks {
+                let start_bit = chunk_idx * delta.chunk_bytes * 8;
+                self.levels[target_level].or_bytes(start_bit, chunk);
+            }
+            self.mark_level_dirty(target_level);
+            merged += 1;
+        }
+        Ok(merged)
     }
 
-    /// Extract all chunks for current level only
-    fn extract_all_chunks(&self) -> Result<Vec<(usize, Vec<u8>)>> {
-        let current_idx = self.current_level.load(Ordering::Relaxed);
-        let levels = self.levels.read().map_err(|_| {
-            EbloomError::LockError("Failed to read levels".to_string())
-        })?;
-
-        let chunk_size_bits = self.chunk_size_bytes * 8;
+    /// Merge persisted filters into a new database at `output`
+    ///
+    /// Offline consolidation of per-worker filters after a batch job: every
+    /// input is loaded and must have the same config (apart from its path)
+    /// and current level. Level `i` of the output is the bitwise OR of level
+    /// `i` of every input, created at the earliest input's time with the
+    /// inputs' insert counts added up. Existing data at `output` is
+    /// overwritten.
+    ///
+    /// Filters whose rings rotated independently should be folded with
+    /// `export_union` and `apply_union` instead, which match levels by time.
+    #[cfg(feature = "fjall")]
+    pub async fn merge_persisted(
+        inputs: &[std::path::PathBuf],
+        output: std::path::PathBuf,
+    ) -> Result<Self> {
+        if inputs.contains(&output) {
+            return Err(EbloomError::InvalidConfig(format!(
+                "Merge output {output:?} is also an input"
+            )));
+        }
+        let mut sources = Vec::with_capacity(inputs.len());
+        for path in inputs {
+            sources.push(Self::load(path.clone()).await?);
+        }
+        let Some(first) = sources.first() else {
+            return Err(EbloomError::InvalidConfig(
+                "No filters to merge".to_string(),
+            ));
+        };
+
+        let with_output = |config: &ExpiringFilterConfig| -> Result<Vec<u8>> {
+            let mut config = config.clone();
+            if let Some(ref mut persistence) = config.persistence {
+                persistence.db_path = output.clone();
+            }
+            config.to_bytes()
+        };
+        let expected_config = with_output(&first.config)?;
+        let current_level = first.current_level.load(Ordering::Acquire);
+        for (path, source) in inputs.iter().zip(&sources).skip(1) {
+            if with_output(&source.config)? != expected_config {
+                return Err(EbloomError::InvalidConfig(format!(
+                    "Filter at {path:?} has a different config than {:?}",
+                    inputs[0]
+                )));
+            }
+            let level = source.current_level.load(Ordering::Acquire);
+            if level != current_level {
+                return Err(EbloomError::InvalidConfig(format!(
+                    "Filter at {path:?} is on level {level}, {:?} is on level {current_level}",
+                    inputs[0]
+                )));
+            }
+        }
+
+        let mut config = first.config.clone();
+        if let Some(ref mut persistence) = config.persistence {
+            persistence.db_path = output;
+        }
+        let merged = Self::create(config).await?;
+        for level in 0..merged.config.num_levels {
+            let mut metadata = merged.read_metadata(level)?.clone();
+            metadata.insert_count = 0;
+            metadata.created_at = 0;
+            for source in &sources {
+                merged.levels[level].or_bytes(
+                    0,
+                    &source.levels[level].read_bytes(0, source.bit_vector_size),
+                );
+                let source_metadata = source.read_metadata(level)?;
+                metadata.insert_count += source_metadata.insert_count;
+                if source_metadata.created_at != 0
+                    && (metadata.created_at == 0
+                        || source_metadata.created_at < metadata.created_at)
+                {
+                    metadata.created_at = source_metadata.created_at;
+                    metadata.rotation_reason = source_metadata.rotation_reason;
+                }
+            }
+            merged.insert_counts[level]
+                .store(metadata.insert_count, Ordering::Relaxed);
+            merged.created_ats[level]
+                .store(metadata.created_at, Ordering::Release);
+            *merged.write_metadata(level)? = metadata;
+            merged.mark_level_dirty(level);
+        }
+        merged.current_level.store(current_level, Ordering::Release);
+
+        if let Some(ref backend) = merged.storage {
+            backend.save_current_level(current_level).await?;
+            merged.save_snapshot().await?;
+            backend
+                .save_level_metadata(&merged.metadata_snapshot()?)
+                .await?;
+        }
+        merged.flush().await?;
+        Ok(merged)
+    }
+
+    /// Export the levels as a CRDT state, see `ebloom::crdt`
+    pub fn crdt_state(&self) -> Result<BloomCrdt> {
+        let windows = self
+            .created_ats
+            .iter()
+            .map(|created_at| created_at.load(Ordering::Acquire))
+            .enumerate()
+            .filter(|&(_, created_at)| created_at != 0)
+            .map(|(level_idx, created_at)| {
+                let bits =
+                    self.levels[level_idx].read_bytes(0, self.bit_vector_size);
+                (created_at, bits)
+            })
+            .collect();
+        Ok(BloomCrdt::new(
+            self.layout_fingerprint(),
+            self.config.num_levels,
+            windows,
+        ))
+    }
+
+    /// OR a (merged) CRDT state into this filter
+    ///
+    /// Each window goes into the newest local level created no later than
+    /// the window ended, as in `apply_union`; the newest window is still
+    /// open and goes into the current level.
+    pub fn merge_crdt(&self, state: &BloomCrdt) -> Result<()> {
+        let windows: Vec<(u64, &[u8])> = state.levels().collect();
+        let levels = windows
+            .iter()
+            .enumerate()
+            .map(|(idx, &(created_at, bits))| UnionLevel {
+                created_at,
+                last_written_at: windows
+                    .get(idx + 1)
+                    .map_or(u64::MAX, |&(next, _)| next - 1),
+                bits: bits.to_vec(),
+            })
+            .collect();
+        self.apply_union(&UnionPayload {
+            layout_fingerprint: state.layout_fingerprint(),
+            exported_at: self.clock.now_ms()?,
+            levels,
+        })
+        .map(|_| ())
+    }
+
+    /// Size of one level's bits as `Lsb0` bytes
+    pub(crate) fn level_bytes(&self) -> usize {
+        self.bit_vector_size.div_ceil(8)
+    }
+
+    fn layout_fingerprint(&self) -> u64 {
+        layout_fingerprint(
+            self.bit_vector_size,
+            self.num_hashes,
+            self.config.blocked_layout,
+        )
+    }
+
+    /// Newest level created no later than `timestamp_ms`
+    fn level_at(&self, timestamp_ms: u64) -> Option<usize> {
+        self.created_ats
+            .iter()
+            .map(|created_at| created_at.load(Ordering::Acquire))
+            .enumerate()
+            .filter(|&(_, created_at)| {
+                created_at != 0 && created_at <= timestamp_ms
+            })
+            .max_by_key(|&(_, created_at)| created_at)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Log every insert from now on for followers, keeping the newest
+    /// `max_entries` entries, see `ebloom::oplog`
+    pub fn replication_log(
+        &self,
+        max_entries: usize,
+    ) -> Result<Arc<ReplicationLog>> {
+        if max_entries == 0 {
+            return Err(EbloomError::InvalidConfig(
+                "Replication log must keep at least one entry".to_string(),
+            ));
+        }
+        let log = Arc::new(ReplicationLog::new(
+            self.layout_fingerprint(),
+            self.hash_fn,
+            self.num_hashes,
+            self.bit_vector_size,
+            self.clock.clone(),
+            max_entries,
+        ));
+        self.add_observer(log.clone())?;
+        Ok(log)
+    }
+
+    /// Replay a leader's logged inserts, returning how many were applied
+    ///
+    /// Each entry goes into the level that was current at its timestamp,
+    /// as with `insert_at`; entries older than every level are skipped.
+    /// Observers are not notified, so followers can log in turn without
+    /// echoing entries back.
+    pub fn apply_oplog(&self, batch: &OpLogBatch) -> Result<usize> {
+        if batch.layout_fingerprint != self.layout_fingerprint() {
+            return Err(EbloomError::InvalidConfig(
+                "Replication log comes from a filter with a different bit layout"
+                    .to_string(),
+            ));
+        }
+        let mut applied = 0;
+        for entry in &batch.entries {
+            let Some(target_level) = self.level_at(entry.timestamp_ms) else {
+                continue;
+            };
+            let indices: Vec<usize> =
+                entry.indices.iter().map(|&idx| idx as usize).collect();
+            let current_level_idx = self.current_level.load(Ordering::Acquire);
+            let is_current = target_level == current_level_idx;
+            set_indices(
+                &indices,
+                target_level,
+                self.bit_vector_size,
+                self.chunk_size_bytes,
+                if is_current {
+                    self.dirty_chunks.as_deref()
+                } else {
+                    None
+                },
+                &self.levels,
+            )?;
+            self.queue_write_behind(target_level, &indices)?;
+            if !is_current {
+                self.mark_level_dirty(target_level);
+            }
+            self.insert_counts[target_level].fetch_add(1, Ordering::Relaxed);
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// Hand the chunks holding `indices` to the background writer
+    fn queue_write_behind(
+        &self,
+        level_index: usize,
+        indices: &[usize],
+    ) -> Result<()> {
+        #[cfg(feature = "fjall")]
+        if let Some(ref write_behind) = self.write_behind {
+            write_behind.enqueue(level_index, indices)?;
+        }
+        #[cfg(not(feature = "fjall"))]
+        let _ = (level_index, indices);
+        Ok(())
+    }
+
+    /// Flag a non-current level for the next snapshot
+    fn mark_level_dirty(&self, level_index: usize) {
+        if let Some(ref dirty_levels) = self.dirty_levels {
+            dirty_levels.set(level_index);
+        }
+    }
+
+    /// Newest timestamp recorded in level metadata
+    #[cfg(feature = "fjall")]
+    fn latest_timestamp(&self) -> Result<u64> {
+        Ok(self
+            .metadata_snapshot()?
+            .iter()
+            .map(|m| m.created_at.max(m.last_snapshot_at))
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// Register a callback fired after every rotation with the rotated-out
+    /// level index, its metadata and insert count
+    pub fn on_rotation<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(&RotationEvent) + Send + Sync + 'static,
+    {
+        let mut listeners = self.rotation_listeners.write().map_err(|_| {
+            EbloomError::LockError(
+                "Failed to write rotation listeners".to_string(),
+            )
+        })?;
+        listeners.push(Box::new(callback));
+        Ok(())
+    }
+
+    /// Register an observer for inserts, rotations, snapshots and saturation
+    pub fn add_observer(&self, observer: Arc<dyn FilterObserver>) -> Result<()> {
+        let mut observers = self.observers.write().map_err(|_| {
+            EbloomError::LockError("Failed to write observers".to_string())
+        })?;
+        observers.push(observer);
+        self.has_observers.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Refuse inserts that would push the current level's estimated false
+    /// positive rate above `max_fpr`; `None` turns the guardrail off
+    ///
+    /// The estimate comes from the level's insert count, so the check
+    /// costs no pass over the bits; repeated items count every time.
+    /// Refused inserts fail with `EbloomError::Saturated` unless a policy
+    /// set with `on_fpr_guardrail` allows them. Concurrent inserts may
+    /// overshoot the limit by a few items. Not part of the persisted
+    /// config: set it again after `load`.
+    pub fn set_fpr_guardrail(&self, max_fpr: Option<f64>) -> Result<()> {
+        let bits = match max_fpr {
+            // Written so that NaN fails too
+            Some(limit) if !(limit > 0.0 && limit < 1.0) => {
+                return Err(EbloomError::InvalidConfig(
+                    "FPR guardrail must be between 0 and 1".to_string(),
+                ));
+            }
+            Some(limit) => limit.to_bits(),
+            None => 0,
+        };
+        self.fpr_guardrail.store(bits, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Current FPR guardrail, if set
+    pub fn fpr_guardrail(&self) -> Option<f64> {
+        match self.fpr_guardrail.load(Ordering::Relaxed) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    /// Decide per insert what happens once the FPR guardrail trips, e.g.
+    /// allow it and schedule a rotation, instead of rejecting it
+    ///
+    /// The policy runs on the inserting thread and must not insert into
+    /// this filter.
+    pub fn on_fpr_guardrail<F>(&self, policy: F) -> Result<()>
+    where
+        F: Fn(&SaturationError) -> GuardrailAction + Send + Sync + 'static,
+    {
+        let mut current = self.guardrail_policy.write().map_err(|_| {
+            EbloomError::LockError("Failed to write guardrail policy".to_string())
+        })?;
+        *current = Some(Arc::new(policy));
+        Ok(())
+    }
+
+    /// Apply the FPR guardrail to `added` inserts into `level`
+    fn check_fpr_guardrail(&self, level: usize, added: u64) -> Result<()> {
+        let Some(limit) = self.fpr_guardrail() else {
+            return Ok(());
+        };
+        let insert_count = self.level_insert_count(level).saturating_add(added);
+        let estimated_fpr =
+            expected_fpr(insert_count, self.num_hashes, self.bit_vector_size);
+        if estimated_fpr <= limit {
+            return Ok(());
+        }
+
+        let error = SaturationError {
+            level,
+            insert_count,
+            estimated_fpr,
+            limit,
+        };
+        // Copied out so the policy runs without the lock
+        let policy = self
+            .guardrail_policy
+            .read()
+            .map_err(|_| {
+                EbloomError::LockError(
+                    "Failed to read guardrail policy".to_string(),
+                )
+            })?
+            .clone();
+        match policy.map_or(GuardrailAction::Reject, |policy| policy(&error)) {
+            GuardrailAction::Allow => Ok(()),
+            GuardrailAction::Reject => Err(EbloomError::Saturated(error)),
+        }
+    }
+
+    /// Run `f` for every registered observer
+    fn notify_observers(&self, f: impl Fn(&dyn FilterObserver)) -> Result<()> {
+        if !self.has_observers.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let observers = self.observers.read().map_err(|_| {
+            EbloomError::LockError("Failed to read observers".to_string())
+        })?;
+        for observer in observers.iter() {
+            f(observer.as_ref());
+        }
+        Ok(())
+    }
+
+    /// Rotate levels: move to next level in circular fashion
+    /// The new current level is cleared (oldest data expires)
+    pub async fn rotate_levels(&self) -> Result<()> {
+        let now_ms = self.clock.now_ms()?;
+        self.rotate_levels_at(now_ms, RotationReason::Manual).await
+    }
+
+    /// Rotate levels, stamping the new current level with `created_at`
+    ///
+    /// Metadata and history locks are only taken inside the synchronous
+    /// helpers; every storage write awaits on data they returned.
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn rotate_levels_at(
+        &self,
+        created_at: u64,
+        reason: RotationReason,
+    ) -> Result<()> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::Rotation);
+        let current_idx = self.current_level.load(Ordering::Acquire);
+
+        // Calculate next level index (circular)
+        let new_current_idx = (current_idx + 1) % self.config.num_levels;
+
+        // 1. Save FULL snapshot of current level (freeze it forever)
+        self.save_full_snapshot().await?;
+
+        // 2. Clear the new current level, then carry over items inserted
+        //    during the grace overlap or, with smooth decay, the whole window
+        let new_level = &self.levels[new_current_idx];
+        let rotated_out_fill = new_level.fill_ratio();
+        new_level.clear();
+
+        let carried_bits = match self.grace_bits {
+            Some(ref grace_bits) => grace_bits.take_ones(),
+            None => Vec::new(),
+        };
+        for &idx in &carried_bits {
+            new_level.set(idx);
+        }
+
+        // 3. Delete new current level's old data from DB (both chunks AND dirty)
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            // Drain queued writes first so none lands after the delete
+            if let Some(ref write_behind) = self.write_behind {
+                write_behind.flush()?;
+            }
+            backend.delete_level(new_current_idx).await?;
+        }
+
+        // 4. Update metadata for the new current level
+        let created_at = self.config.window_start(created_at);
+        let rotated_out =
+            self.reset_level_metadata(new_current_idx, created_at, reason)?;
+
+        // 5. Save metadata and current level pointer to DB
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            let new_metadata = self.metadata_snapshot()?;
+            backend.save_level_metadata(&new_metadata).await?;
+            backend.save_current_level(new_current_idx).await?;
+        }
+
+        // 7. Clear dirty chunks tracker (for new current level). Done before
+        //    the level is published, so marks from inserts that already
+        //    write to it are kept for the next snapshot
+        if let Some(ref dirty) = self.dirty_chunks {
+            dirty.clear();
+            for idx in carried_bits {
+                let chunk_id = idx / (self.chunk_size_bytes * 8);
+                if chunk_id < dirty.len() {
+                    dirty.set(chunk_id);
+                }
+            }
+        }
+        if let Some(ref dirty_levels) = self.dirty_levels {
+            dirty_levels.reset(new_current_idx);
+        }
+
+        // 8. Publish the new current level; inserts that see it also see it
+        //    cleared
+        self.current_level.store(new_current_idx, Ordering::Release);
+        self.saturation_warned.store(false, Ordering::Relaxed);
+        advise_level(&self.levels[current_idx], false);
+        advise_level(&self.levels[new_current_idx], true);
+
+        // 9. Record rotation history and notify subscribers
+        self.record_rotation(RotationRecord {
+            rotated_at: self.clock.now_ms()?,
+            level: new_current_idx,
+            insert_count: rotated_out.insert_count,
+            fill_ratio: rotated_out_fill,
+            reason,
+        })?;
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage
+            && self.persists_rotation_log()
+        {
+            let log = self.rotation_history(ROTATION_HISTORY_LEN)?;
+            backend.save_rotation_log(&log).await?;
+        }
+        #[cfg(feature = "metrics")]
+        {
+            filter_metrics::record_rotation(reason, new_current_idx);
+            filter_metrics::record_level_density(&self.levels);
+        }
+        if let Some(ref statsd) = self.statsd {
+            statsd.record_rotation(reason);
+        }
+        self.notify_rotation(&RotationEvent {
+            level: new_current_idx,
+            reason,
+            fill_ratio: rotated_out_fill,
+            insert_count: rotated_out.insert_count,
+            metadata: rotated_out,
+        })?;
+
+        Ok(())
+    }
+
+    /// Clear a non-current level and mark it unused, ahead of the rotation
+    /// that would reuse it
+    async fn drop_level(&self, level_idx: usize) -> Result<()> {
+        self.levels[level_idx].clear();
+        {
+            let mut metadata = self.write_metadata(level_idx)?;
+            metadata.created_at = 0;
+            metadata.insert_count = 0;
+            metadata.last_snapshot_at = 0;
+            self.insert_counts[level_idx].store(0, Ordering::Relaxed);
+            self.created_ats[level_idx].store(0, Ordering::Release);
+        }
+        if let Some(ref dirty_levels) = self.dirty_levels {
+            dirty_levels.reset(level_idx);
+        }
+
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            if let Some(ref write_behind) = self.write_behind {
+                write_behind.flush()?;
+            }
+            backend.delete_level(level_idx).await?;
+            let metadata = self.metadata_snapshot()?;
+            backend.save_level_metadata(&metadata).await?;
+        }
+        Ok(())
+    }
+
+    /// Start a new window in a level's metadata and return the metadata it
+    /// replaced, with the window's final insert count
+    fn reset_level_metadata(
+        &self,
+        level_idx: usize,
+        created_at: u64,
+        reason: RotationReason,
+    ) -> Result<LevelMetadata> {
+        let mut metadata = self.write_metadata(level_idx)?;
+        let mut rotated_out = metadata.clone();
+        rotated_out.insert_count =
+            self.insert_counts[level_idx].swap(0, Ordering::Relaxed);
+        *metadata = LevelMetadata {
+            created_at,
+            insert_count: 0,
+            last_snapshot_at: 0,
+            rotation_reason: reason,
+        };
+        self.created_ats[level_idx].store(created_at, Ordering::Release);
+        Ok(rotated_out)
+    }
+
+    /// Append to the rotation history, dropping the oldest record when full
+    fn record_rotation(&self, record: RotationRecord) -> Result<()> {
+        let mut history = self.rotation_history.write().map_err(|_| {
+            EbloomError::LockError("Failed to write rotation history".to_string())
+        })?;
+        if history.len() == ROTATION_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(record);
+        Ok(())
+    }
+
+    /// Up to `limit` most recent rotations, oldest first
+    ///
+    /// The log keeps the last `ROTATION_HISTORY_LEN` rotations; with
+    /// `persist_rotation_log` it survives restarts.
+    pub fn rotation_history(&self, limit: usize) -> Result<Vec<RotationRecord>> {
+        let history = self.rotation_history.read().map_err(|_| {
+            EbloomError::LockError("Failed to read rotation history".to_string())
+        })?;
+        let skip = history.len().saturating_sub(limit);
+        Ok(history.iter().skip(skip).cloned().collect())
+    }
+
+    #[cfg(feature = "fjall")]
+    fn persists_rotation_log(&self) -> bool {
+        self.config
+            .persistence
+            .as_ref()
+            .is_some_and(|pers| pers.persist_rotation_log)
+    }
+
+    fn notify_rotation(&self, event: &RotationEvent) -> Result<()> {
+        let listeners = self.rotation_listeners.read().map_err(|_| {
+            EbloomError::LockError(
+                "Failed to read rotation listeners".to_string(),
+            )
+        })?;
+        for listener in listeners.iter() {
+            listener(event);
+        }
+        self.notify_observers(|observer| observer.on_rotate(event))
+    }
+
+    /// Clean up expired levels by rotating when current level expires
+    ///
+    /// After a long gap every elapsed window is rotated out in one call, so
+    /// stale data never stays current. New levels keep the original window
+    /// schedule; once all levels have been cleared the schedule restarts now.
+    /// The rotation policy decides whether time, insert count or both roll
+    /// the current level. With `max_fill_ratio` set, a saturated current
+    /// level rotates early.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn cleanup_expired_levels(&self) -> Result<()> {
+        self.check_saturation_warning()?;
+        if let Some(interval) = self.config.fpr_sample_interval {
+            let last_sample_ms = self.lock_fpr_tracker()?.last_sample_ms();
+            if self.clock.now_ms()?.saturating_sub(last_sample_ms)
+                >= interval.as_millis() as u64
+            {
+                self.sample_fpr()?;
+            }
+        }
+
+        let num_levels = self.config.num_levels;
+        let time_rotations = if self.config.rotation_policy.uses_time() {
+            num_levels
+        } else {
+            0
+        };
+
+        for rotation in 0..time_rotations {
+            let current_level = self.current_level.load(Ordering::Acquire);
+            if !self.is_level_expired(current_level)? {
+                break;
+            }
+
+            let created_at = if rotation + 1 == num_levels {
+                self.clock.now_ms()?
+            } else {
+                self.window_end(current_level)?
+            };
+            self.rotate_levels_at(created_at, RotationReason::Time)
+                .await?;
+        }
+
+        // Drop older levels whose age position keeps them for less than the
+        // ring would
+        if time_rotations > 0 {
+            for age in 1..num_levels {
+                let current_level = self.current_level.load(Ordering::Acquire);
+                let level_idx = (current_level + num_levels - age) % num_levels;
+                if self.is_level_expired(level_idx)? {
+                    self.drop_level(level_idx).await?;
+                }
+            }
+        }
+
+        // Rotate once the current level received enough inserts
+        if let Some(max_inserts) = self.config.rotation_policy.max_inserts() {
+            let current_level = self.current_level.load(Ordering::Acquire);
+            if self.level_insert_count(current_level) >= max_inserts {
+                let now_ms = self.clock.now_ms()?;
+                self.rotate_levels_at(now_ms, RotationReason::InsertCount)
+                    .await?;
+            }
+        }
+
+        // Rotate early when the current level is too dense to meet the FPR
+        if let Some(max_fill_ratio) = self.config.max_fill_ratio {
+            let current_level = self.current_level.load(Ordering::Acquire);
+            let fill_ratio = self.level_fill_ratio(current_level)?;
+            if fill_ratio > max_fill_ratio {
+                self.notify_observers(|observer| {
+                    observer.on_saturation(current_level, fill_ratio)
+                })?;
+                let now_ms = self.clock.now_ms()?;
+                self.rotate_levels_at(now_ms, RotationReason::Saturation)
+                    .await?;
+            }
+        }
+
+        if let Some(ref statsd) = self.statsd
+            && statsd.flush_due(self.clock.now_ms()?)
+        {
+            self.flush_statsd()?;
+        }
+
+        Ok(())
+    }
+
+    /// Send StatsD counters and gauges now rather than at the next due
+    /// flush in `cleanup_expired_levels`; does nothing without `statsd`
+    pub fn flush_statsd(&self) -> Result<()> {
+        let Some(ref statsd) = self.statsd else {
+            return Ok(());
+        };
+        let fill_ratios: Vec<(usize, f64)> = self
+            .created_ats
+            .iter()
+            .enumerate()
+            .filter(|(_, created_at)| created_at.load(Ordering::Acquire) != 0)
+            .map(|(level, _)| (level, self.levels[level].fill_ratio()))
+            .collect();
+        statsd.flush(
+            self.clock.now_ms()?,
+            self.probable_duplicates.load(Ordering::Relaxed),
+            self.current_level.load(Ordering::Acquire),
+            &fill_ratios,
+        );
+        Ok(())
+    }
+
+    /// Raise the saturation warning once per window when the current level
+    /// crosses `saturation_warning`. Runs on cleanup and snapshots rather
+    /// than per insert, since density takes a pass over the level.
+    fn check_saturation_warning(&self) -> Result<()> {
+        let Some(threshold) = self.config.saturation_warning else {
+            return Ok(());
+        };
+        if self.saturation_warned.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let level = self.current_level.load(Ordering::Acquire);
+        let fill_ratio = self.level_fill_ratio(level)?;
+        if fill_ratio < threshold
+            || self.saturation_warned.swap(true, Ordering::Relaxed)
+        {
+            return Ok(());
+        }
+
+        let warning = SaturationWarning {
+            level,
+            fill_ratio,
+            threshold,
+            level_fpr: fill_ratio.powi(self.num_hashes as i32),
+            projected_fpr: self
+                .projected_fill_ratio(level, fill_ratio)?
+                .powi(self.num_hashes as i32),
+        };
+        tracing::warn!(
+            level,
+            fill_ratio,
+            threshold,
+            level_fpr = warning.level_fpr,
+            projected_fpr = warning.projected_fpr,
+            target_fpr = self.config.target_fpr,
+            "Expiring bloom filter level is saturating; consider a larger \
+             capacity_per_level or shorter level_duration"
+        );
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_saturation_warning(level);
+        self.notify_observers(|observer| observer.on_saturation_warning(&warning))
+    }
+
+    /// Density the level will reach at the end of its time window if items
+    /// keep arriving at the pace seen so far
+    ///
+    /// Each bit stays clear with probability `(1 - 1/m)^(k*n)`, so scaling
+    /// the insert count `n` by `1 / elapsed` raises the clear fraction to
+    /// that power.
+    fn projected_fill_ratio(&self, level: usize, fill_ratio: f64) -> Result<f64> {
+        if !self.config.rotation_policy.uses_time() {
+            return Ok(fill_ratio);
+        }
+        let created_at = self.level_created_at(level)?;
+        let duration_ms = self
+            .config
+            .duration_for_age(self.level_age(level))
+            .as_millis() as f64;
+        let age_ms = self.clock.now_ms()?.saturating_sub(created_at) as f64;
+        let elapsed = age_ms / duration_ms;
+        if created_at == 0 || !(0.0..1.0).contains(&elapsed) || elapsed == 0.0 {
+            return Ok(fill_ratio);
+        }
+        Ok(1.0 - (1.0 - fill_ratio).powf(1.0 / elapsed))
+    }
+
+    /// Record a point of the false positive rate trend
+    ///
+    /// Pairs the density-based FPR estimate with the share of inserts since
+    /// the previous sample whose bits were all set in the current level
+    /// already. Raises an alert (log, observers) when the estimate moves
+    /// past `target_fpr * fpr_drift_tolerance`. Called automatically with
+    /// `fpr_sample_interval`; takes a pass over every level.
+    pub fn sample_fpr(&self) -> Result<FprSample> {
+        let now_ms = self.clock.now_ms()?;
+        let counts: Vec<(u64, u64)> = self
+            .created_ats
+            .iter()
+            .zip(self.insert_counts.iter())
+            .map(|(created_at, count)| {
+                (
+                    created_at.load(Ordering::Acquire),
+                    count.load(Ordering::Relaxed),
+                )
+            })
+            .collect();
+        let estimated_fpr = estimate_fpr(
+            counts
+                .iter()
+                .zip(self.levels.iter())
+                .filter(|((created_at, _), _)| *created_at != 0)
+                .map(|(_, level)| level.fill_ratio()),
+            self.num_hashes,
+        );
+
+        let target_fpr = self.config.target_fpr;
+        let (sample, alert) = {
+            let mut tracker = self.lock_fpr_tracker()?;
+            let sample = tracker.record(
+                now_ms,
+                counts,
+                self.probable_duplicates.load(Ordering::Relaxed),
+                estimated_fpr,
+            );
+            let diverging = sample.estimated_fpr
+                > target_fpr * self.config.fpr_drift_tolerance;
+            let alert = diverging && !tracker.diverging;
+            tracker.diverging = diverging;
+            (sample, alert)
+        };
+
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_fpr_sample(&sample);
+        if alert {
+            tracing::warn!(
+                estimated_fpr = sample.estimated_fpr,
+                duplicate_rate = sample.duplicate_rate,
+                target_fpr,
+                "Expiring bloom filter false positive rate drifted past target"
+            );
+            self.notify_observers(|observer| {
+                observer.on_fpr_drift(&sample, target_fpr)
+            })?;
+        }
+        Ok(sample)
+    }
+
+    /// Sampled false positive rate trend, see `sample_fpr`
+    pub fn fpr_drift(&self) -> Result<FprDrift> {
+        Ok(self
+            .lock_fpr_tracker()?
+            .report(self.config.target_fpr, self.config.fpr_drift_tolerance))
+    }
+
+    fn lock_fpr_tracker(&self) -> Result<MutexGuard<'_, FprTracker>> {
+        self.fpr_tracker.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock FPR tracker".to_string())
+        })
+    }
+
+    fn level_insert_count(&self, level_index: usize) -> u64 {
+        self.insert_counts
+            .get(level_index)
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    /// Tell observers once the inserts counted from `previous_count` reach
+    /// the rotation policy's insert count
+    fn check_rotation_due(
+        &self,
+        level: usize,
+        previous_count: u64,
+        added: u64,
+    ) -> Result<()> {
+        let Some(max_inserts) = self.config.rotation_policy.max_inserts() else {
+            return Ok(());
+        };
+        let insert_count = previous_count + added;
+        if previous_count < max_inserts && insert_count >= max_inserts {
+            self.notify_observers(|observer| {
+                observer.on_rotation_due(level, insert_count)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Whether the current level reached the rotation policy's insert count
+    /// and waits for `cleanup_expired_levels` to rotate it
+    pub fn is_rotation_due(&self) -> bool {
+        let current_level = self.current_level.load(Ordering::Acquire);
+        self.config
+            .rotation_policy
+            .max_inserts()
+            .is_some_and(|max| self.level_insert_count(current_level) >= max)
+    }
+
+    /// Whether the current level is within the grace overlap of its expiry
+    fn in_grace_window(&self, level_index: usize) -> Result<bool> {
+        let Some(grace_overlap) = self.config.grace_overlap else {
+            return Ok(false);
+        };
+        if !self.config.rotation_policy.uses_time() {
+            return Ok(false);
+        }
+        let window_end = self.window_end(level_index)?;
+        let now_ms = self.clock.now_ms()?;
+        Ok(now_ms + grace_overlap.as_millis() as u64 >= window_end)
+    }
+
+    /// Whether inserts into the current level are also carried into the
+    /// next one
+    fn carries_into_next(&self, level_index: usize) -> Result<bool> {
+        if self.config.insert_mode == InsertMode::CurrentAndNext {
+            return Ok(true);
+        }
+        self.in_grace_window(level_index)
+    }
+
+    /// Remember items so they are carried into the next level on rotation
+    fn record_grace(&self, items: &[&[u8]]) {
+        let Some(ref grace) = self.grace_bits else {
+            return;
+        };
+        for item in items {
+            for idx in (self.hash_fn)(item, self.num_hashes, self.bit_vector_size)
+            {
+                grace.set(idx);
+            }
+        }
+    }
+
+    /// Fraction of bits set in a level
+    pub fn level_fill_ratio(&self, level_index: usize) -> Result<f64> {
+        let level =
+            self.levels
+                .get(level_index)
+                .ok_or(EbloomError::InvalidLevel {
+                    level: level_index,
+                    max_levels: self.config.num_levels,
+                })?;
+        Ok(level.fill_ratio())
+    }
+
+    /// Fill ratio of `buckets` equal slices of a level, showing how evenly
+    /// bits are spread
+    pub fn level_density_histogram(
+        &self,
+        level_index: usize,
+        buckets: usize,
+    ) -> Result<Vec<f64>> {
+        let level =
+            self.levels
+                .get(level_index)
+                .ok_or(EbloomError::InvalidLevel {
+                    level: level_index,
+                    max_levels: self.config.num_levels,
+                })?;
+        Ok(level.density_histogram(buckets))
+    }
+
+    /// Memory held by the filter, broken down by purpose
+    ///
+    /// Counts allocated words and buffers; allocator overhead and the
+    /// storage backend's own caches are not included.
+    pub fn memory_usage(&self) -> Result<MemoryUsage> {
+        let bitmaps = |bits: Option<&AtomicBitVec>| {
+            bits.map_or(0, AtomicBitVec::memory_bytes)
+        };
+        #[cfg(feature = "fjall")]
+        let write_behind = self
+            .write_behind
+            .as_ref()
+            .map_or(0, WriteBehind::memory_bytes);
+        #[cfg(not(feature = "fjall"))]
+        let write_behind = 0;
+
+        let history_capacity = self
+            .rotation_history
+            .read()
+            .map_err(|_| {
+                EbloomError::LockError(
+                    "Failed to read rotation history".to_string(),
+                )
+            })?
+            .capacity();
+        let metadata = self.config.num_levels
+            * (size_of::<RwLock<LevelMetadata>>() + 2 * size_of::<AtomicU64>())
+            + history_capacity * size_of::<RotationRecord>();
+
+        Ok(MemoryUsage {
+            levels: self.levels.iter().map(AtomicBitVec::memory_bytes).sum(),
+            dirty_tracking: bitmaps(self.dirty_chunks.as_deref())
+                + bitmaps(self.dirty_levels.as_deref()),
+            grace_bits: bitmaps(self.grace_bits.as_deref()),
+            write_behind,
+            metadata,
+        })
+    }
+
+    /// Total bytes from `memory_usage`
+    pub fn approx_memory_bytes(&self) -> Result<usize> {
+        Ok(self.memory_usage()?.total())
+    }
+
+    /// Bits of one level per item of `capacity_per_level`
+    pub fn bits_per_item(&self) -> f64 {
+        self.bit_vector_size as f64 / self.config.capacity_per_level as f64
+    }
+
+    /// Snapshot of configuration, per-level density, age and inserts,
+    /// memory use and persistence lag
+    pub fn stats(&self) -> Result<ExpiringStats> {
+        let now_ms = self.clock.now_ms()?;
+        let metadata = self.metadata_snapshot()?;
+        let levels: Vec<LevelStats> = metadata
+            .iter()
+            .enumerate()
+            .map(|(level_idx, meta)| {
+                let age_ms = (meta.created_at != 0)
+                    .then(|| now_ms.saturating_sub(meta.created_at));
+                let retention =
+                    self.config.retention_for_age(self.level_age(level_idx));
+                LevelStats {
+                    level: level_idx,
+                    fill_ratio: self.levels[level_idx].fill_ratio(),
+                    insert_count: meta.insert_count,
+                    age_ms,
+                    expired: age_ms
+                        .is_some_and(|age| age > retention.as_millis() as u64),
+                }
+            })
+            .collect();
+
+        let estimated_fpr = estimate_fpr(
+            levels
+                .iter()
+                .filter(|level| level.age_ms.is_some())
+                .map(|level| level.fill_ratio),
+            self.num_hashes,
+        );
+
+        let memory_bytes = self.memory_usage()?.total();
+
+        let current_level = self.current_level.load(Ordering::Acquire);
+        #[cfg(feature = "fjall")]
+        let snapshot_lag_ms = self.storage.as_ref().map(|_| {
+            let meta = &metadata[current_level];
+            now_ms.saturating_sub(meta.created_at.max(meta.last_snapshot_at))
+        });
+        #[cfg(not(feature = "fjall"))]
+        let snapshot_lag_ms = None;
+
+        let (incremental_snapshots, full_snapshots) =
+            *self.snapshot_stats.read().map_err(|_| {
+                EbloomError::LockError(
+                    "Failed to read snapshot stats".to_string(),
+                )
+            })?;
+
+        Ok(ExpiringStats {
+            capacity_per_level: self.config.capacity_per_level,
+            num_levels: self.config.num_levels,
+            target_fpr: self.config.target_fpr,
+            estimated_fpr,
+            bit_vector_size: self.bit_vector_size,
+            num_hashes: self.num_hashes,
+            current_level,
+            total_insert_count: levels.iter().map(|l| l.insert_count).sum(),
+            memory_bytes,
+            snapshot_lag_ms,
+            incremental_snapshots,
+            full_snapshots,
+            levels,
+            #[cfg(feature = "latency")]
+            latency: self.latency.summaries(),
+            #[cfg(not(feature = "latency"))]
+            latency: Vec::new(),
+        })
+    }
+
+    /// Start the latency histograms over, e.g. after warm-up
+    #[cfg(feature = "latency")]
+    pub fn reset_latency(&self) {
+        self.latency.reset();
+    }
+
+    /// Configuration, per-level metadata and density, sampled bit
+    /// histograms and recent rotations, for bug reports
+    ///
+    /// Read-only: unlike `health`, nothing is written to the backend.
+    pub fn dump_state(&self) -> Result<DebugDump> {
+        let levels = self
+            .metadata_snapshot()?
+            .into_iter()
+            .enumerate()
+            .map(|(level, metadata)| {
+                Ok(LevelDump {
+                    level,
+                    metadata,
+                    fill_ratio: self.levels[level].fill_ratio(),
+                    density_histogram: self
+                        .level_density_histogram(level, DUMP_HISTOGRAM_BUCKETS)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(DebugDump {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            backend: self.backend_kind().to_string(),
+            config: self.config.clone(),
+            stats: self.stats()?,
+            levels,
+            rotations: self.rotation_history(ROTATION_HISTORY_LEN)?,
+            fpr_drift: self.fpr_drift()?,
+        })
+    }
+
+    /// Check the filter's internal invariants, for tests and bug reports
+    ///
+    /// Verifies that the current level is in range, that levels, metadata
+    /// and per-level counters have one entry per level, that every level
+    /// has `bit_vector_size` bits and that the dirty-chunk, dirty-level and
+    /// grace bitmaps match what they track. Returns every violation found;
+    /// empty means consistent. Only built in debug builds or with the
+    /// `test_support` feature.
+    #[cfg(any(debug_assertions, feature = "test_support"))]
+    pub fn validate_invariants(&self) -> Result<Vec<InvariantViolation>> {
+        let num_levels = self.config.num_levels;
+        let mut violations = Vec::new();
+
+        let current_level = self.current_level.load(Ordering::Acquire);
+        if current_level >= num_levels {
+            violations.push(InvariantViolation::CurrentLevelOutOfRange {
+                current_level,
+                num_levels,
+            });
+        }
+
+        let metadata_len = self.metadata_snapshot()?.len();
+        for (collection, found) in [
+            ("levels", self.levels.len()),
+            ("metadata", metadata_len),
+            ("insert_counts", self.insert_counts.len()),
+            ("created_ats", self.created_ats.len()),
+        ] {
+            if found != num_levels {
+                violations.push(InvariantViolation::LevelCountMismatch {
+                    collection: collection.to_string(),
+                    found,
+                    num_levels,
+                });
+            }
+        }
+
+        for (level, bits) in self.levels.iter().enumerate() {
+            if bits.len() != self.bit_vector_size {
+                violations.push(InvariantViolation::LevelBitLength {
+                    level,
+                    found: bits.len(),
+                    expected: self.bit_vector_size,
+                });
+            }
+        }
+
+        let chunk_size_bits = self.chunk_size_bytes * 8;
+        let bitmaps = [
+            (
+                "dirty_chunks",
+                self.dirty_chunks.as_deref(),
+                (self.bit_vector_size + chunk_size_bits.max(1) - 1)
+                    .div_ceil(chunk_size_bits.max(1)),
+            ),
+            ("dirty_levels", self.dirty_levels.as_deref(), num_levels),
+            (
+                "grace_bits",
+                self.grace_bits.as_deref(),
+                self.bit_vector_size,
+            ),
+        ];
+        for (bitmap, bits, expected) in bitmaps {
+            if let Some(bits) = bits
+                && bits.len() != expected
+            {
+                violations.push(InvariantViolation::BitmapLength {
+                    bitmap: bitmap.to_string(),
+                    found: bits.len(),
+                    expected,
+                });
+            }
+        }
+
+        Ok(violations)
+    }
+
+    /// Clear all levels by rotating through every one of them
+    ///
+    /// Unlike `clear`, this goes through the regular rotation path, so
+    /// rotation subscribers and history see every dropped window and the
+    /// persisted levels, metadata and current level pointer stay in sync.
+    pub async fn clear_by_rotation(&self) -> Result<()> {
+        for _ in 0..self.config.num_levels {
+            let now_ms = self.clock.now_ms()?;
+            self.rotate_levels_at(now_ms, RotationReason::Clear).await?;
+        }
+        Ok(())
+    }
+
+    /// End of the window that started at the level's creation time
+    fn window_end(&self, level_index: usize) -> Result<u64> {
+        let created_at = self.level_created_at(level_index)?;
+        let duration = self.config.duration_for_age(self.level_age(level_index));
+        Ok(created_at + duration.as_millis() as u64)
+    }
+
+    /// Save incremental dirty chunks for CURRENT level (crash recovery)
+    ///
+    /// Never blocks inserts: the dirty chunk set is swapped out for an empty
+    /// one and the chunks are copied from the live atomic words. Bits set
+    /// while the copy runs re-mark their chunk for the next snapshot.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn save_snapshot(&self) -> Result<()> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::Snapshot);
+        self.check_saturation_warning()?;
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            let pending = self.take_incremental_snapshot()?;
+            self.commit_pending_snapshot(backend, pending).await?;
+            #[cfg(feature = "metrics")]
+            filter_metrics::record_level_density(&self.levels);
+        }
+        Ok(())
+    }
+
+    /// Take the dirty chunks of the current level and the dirty historical
+    /// levels as one batch
+    #[cfg(feature = "fjall")]
+    fn take_incremental_snapshot(&self) -> Result<PendingSnapshot> {
+        let started = std::time::Instant::now();
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        let dirty_chunks = self.take_dirty_chunks();
+        let taken_chunk_ids: Vec<usize> =
+            dirty_chunks.iter().map(|(chunk_id, _)| *chunk_id).collect();
+        let mut batch = SnapshotBatch::default();
+
+        if !dirty_chunks.is_empty() {
+            batch.dirty_chunks.push((current_idx, dirty_chunks));
+
+            // Update last_snapshot_at
+            let now_ms = self.clock.now_ms()?;
+            self.write_metadata(current_idx)?.last_snapshot_at = now_ms;
+            batch.metadata = Some(self.metadata_snapshot()?);
+        }
+
+        // Historical levels written out of band (e.g. `insert_at`).
+        // Load prefers dirty chunks, so keep both partitions in sync.
+        let taken_levels = self.take_dirty_levels();
+        for &level_idx in &taken_levels {
+            let chunks = self.extract_level_chunks(level_idx)?;
+            batch.level_chunks.push((level_idx, chunks.clone()));
+            batch.dirty_chunks.push((level_idx, chunks));
+        }
+
+        Ok(PendingSnapshot {
+            level: current_idx,
+            full: false,
+            batch,
+            taken_chunk_ids,
+            taken_levels,
+            started,
+        })
+    }
+
+    /// Write a taken snapshot in one atomic commit
+    ///
+    /// On failure the dirty marks it consumed are put back, so the next
+    /// snapshot retries the work.
+    #[cfg(feature = "fjall")]
+    async fn commit_pending_snapshot(
+        &self,
+        backend: &FilterStorage,
+        pending: PendingSnapshot,
+    ) -> Result<()> {
+        let PendingSnapshot {
+            level,
+            full,
+            batch,
+            taken_chunk_ids,
+            taken_levels,
+            started,
+        } = pending;
+        let (chunks, bytes) = (batch.chunk_count(), batch.byte_len());
+        if let Err(e) = backend.commit_snapshot(batch).await {
+            if let Some(ref dirty) = self.dirty_chunks
+                && self.current_level.load(Ordering::Acquire) == level
+            {
+                taken_chunk_ids.iter().for_each(|&id| dirty.set(id));
+            }
+            for level_idx in taken_levels {
+                self.mark_level_dirty(level_idx);
+            }
+            return Err(e);
+        }
+        self.snapshot_committed(SnapshotEvent {
+            level,
+            full,
+            chunks,
+            bytes,
+            duration: started.elapsed(),
+        })
+    }
+
+    /// Persistence status for a readiness probe
+    ///
+    /// Probes the backend with a small write, so call it at probe rate
+    /// rather than per request. Backend failures are reported in the result
+    /// instead of as an error.
+    pub fn health(&self) -> Result<PersistenceHealth> {
+        let pending_dirty_chunks =
+            self.dirty_chunks.as_ref().map_or(0, |d| d.count_ones());
+        let pending_dirty_levels =
+            self.dirty_levels.as_ref().map_or(0, |d| d.count_ones());
+
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            let error = backend.probe().err().map(|e| e.to_string());
+            let last_snapshot_ms = self.last_snapshot_ms.load(Ordering::Relaxed);
+            let now_ms = self.clock.now_ms()?;
+            return Ok(PersistenceHealth {
+                persistent: true,
+                writable: error.is_none(),
+                error,
+                since_last_snapshot_ms: (last_snapshot_ms != 0)
+                    .then(|| now_ms.saturating_sub(last_snapshot_ms)),
+                pending_dirty_chunks,
+                pending_dirty_levels,
+            });
+        }
+
+        Ok(PersistenceHealth {
+            persistent: false,
+            writable: true,
+            error: None,
+            since_last_snapshot_ms: None,
+            pending_dirty_chunks,
+            pending_dirty_levels,
+        })
+    }
+
+    /// Where level data lives: `fjall` or `grpc` when persistent, `memory`
+    /// otherwise
+    pub fn backend_kind(&self) -> &'static str {
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            return backend.kind();
+        }
+        "memory"
+    }
+
+    /// Make every write issued so far durable
+    ///
+    /// Only needed with a group commit window, which may defer the sync of
+    /// recent snapshots, or a write-behind queue, which is drained first.
+    pub async fn flush(&self) -> Result<()> {
+        #[cfg(feature = "fjall")]
+        if let Some(ref write_behind) = self.write_behind {
+            write_behind.flush()?;
+        }
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            backend.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Collect and reset non-current levels marked dirty
+    #[cfg(feature = "fjall")]
+    fn take_dirty_levels(&self) -> Vec<usize> {
+        self.dirty_levels
+            .as_ref()
+            .map_or_else(Vec::new, |dirty_levels| dirty_levels.take_ones())
+    }
+
+    /// Save full snapshot of CURRENT level (called on rotation)
+    ///
+    /// The level is copied straight from its atomic words, so inserts keep
+    /// running while the copy is taken and written.
+    async fn save_full_snapshot(&self) -> Result<()> {
+        #[cfg(feature = "fjall")]
+        if let Some(ref backend) = self.storage {
+            let pending = self.take_full_snapshot()?;
+            self.commit_pending_snapshot(backend, pending).await?;
+        }
+        Ok(())
+    }
+
+    /// Copy the whole current level as one batch
+    #[cfg(feature = "fjall")]
+    fn take_full_snapshot(&self) -> Result<PendingSnapshot> {
+        let started = std::time::Instant::now();
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        let chunks = self.extract_all_chunks()?;
+
+        // Update last_snapshot_at
+        let now_ms = self.clock.now_ms()?;
+        self.write_metadata(current_idx)?.last_snapshot_at = now_ms;
+
+        // Load prefers dirty chunks, so overwrite any left by earlier
+        // incremental snapshots of this level
+        Ok(PendingSnapshot {
+            level: current_idx,
+            full: true,
+            batch: SnapshotBatch {
+                level_chunks: vec![(current_idx, chunks.clone())],
+                dirty_chunks: vec![(current_idx, chunks)],
+                metadata: Some(self.metadata_snapshot()?),
+            },
+            taken_chunk_ids: Vec::new(),
+            taken_levels: Vec::new(),
+            started,
+        })
+    }
+
+    /// Report a committed snapshot to metrics and observers
+    #[cfg(feature = "fjall")]
+    fn snapshot_committed(&self, event: SnapshotEvent) -> Result<()> {
+        self.last_snapshot_ms
+            .fetch_max(self.clock.now_ms()?, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_snapshot(&event);
+        {
+            let mut snapshot_stats =
+                self.snapshot_stats.write().map_err(|_| {
+                    EbloomError::LockError(
+                        "Failed to write snapshot stats".to_string(),
+                    )
+                })?;
+            let (incremental, full) = &mut *snapshot_stats;
+            if event.full {
+                full.record(&event);
+            } else {
+                incremental.record(&event);
+            }
+        }
+        self.notify_observers(|observer| observer.on_snapshot(&event))
+    }
+
+    /// Extract and reset dirty chunks for current level only
+    #[cfg(feature = "fjall")]
+    fn take_dirty_chunks(&self) -> Vec<(usize, Vec<u8>)> {
+        let mut chunks = Vec::new();
+
+        if let Some(ref dirty) = self.dirty_chunks {
+            let current_idx = self.current_level.load(Ordering::Acquire);
+            let chunk_size_bits = self.chunk_size_bytes * 8;
+
+            let chunk_ids = dirty.take_ones();
+            // Pairs with the release fence in `insert_internal`: every bit
+            // set before its chunk was marked is visible below
+            fence(Ordering::Acquire);
+
+            for chunk_id in chunk_ids {
+                let chunk_data = extract_chunk_bytes(
+                    &self.levels[current_idx],
+                    chunk_id,
+                    chunk_size_bits,
+                );
+                chunks.push((chunk_id, chunk_data));
+            }
+        }
+
+        chunks
+    }
+
+    /// Extract all chunks for current level only
+    fn extract_all_chunks(&self) -> Result<Vec<(usize, Vec<u8>)>> {
+        let current_idx = self.current_level.load(Ordering::Acquire);
+        self.extract_level_chunks(current_idx)
+    }
+
+    /// Extract all chunks for a specific level
+    fn extract_level_chunks(
+        &self,
+        level_idx: usize,
+    ) -> Result<Vec<(usize, Vec<u8>)>> {
+        let chunk_size_bits = self.chunk_size_bytes * 8;
         let num_chunks = (self.bit_vector_size + chunk_size_bits - 1)
             .div_ceil(chunk_size_bits);
 
         let mut chunks = Vec::new();
         for chunk_id in 0..num_chunks {
             let chunk_data = extract_chunk_bytes(
-                &levels[current_idx],
+                &self.levels[level_idx],
                 chunk_id,
                 chunk_size_bits,
             );
@@ -456,291 +2728,570 @@ impl ExpiringBloomFilter {
         Ok(chunks)
     }
 
+    /// Apply the current level, metadata and rotation log read from storage
+    #[cfg(feature = "fjall")]
+    fn restore_state(
+        &self,
+        current_idx: usize,
+        metadata: Vec<LevelMetadata>,
+        rotation_log: Option<Vec<RotationRecord>>,
+    ) -> Result<()> {
+        if current_idx >= self.config.num_levels {
+            return Err(EbloomError::InvalidLevel {
+                level: current_idx,
+                max_levels: self.config.num_levels,
+            });
+        }
+        self.current_level.store(current_idx, Ordering::Release);
+
+        if metadata.len() > self.config.num_levels {
+            return Err(EbloomError::StorageError(format!(
+                "Found metadata for {} levels, filter has {}",
+                metadata.len(),
+                self.config.num_levels
+            )));
+        }
+        let last_snapshot_ms = metadata
+            .iter()
+            .map(|meta| meta.last_snapshot_at)
+            .max()
+            .unwrap_or(0);
+        self.last_snapshot_ms
+            .store(last_snapshot_ms, Ordering::Relaxed);
+        for (level_idx, meta) in metadata.into_iter().enumerate() {
+            self.insert_counts[level_idx]
+                .store(meta.insert_count, Ordering::Relaxed);
+            self.created_ats[level_idx].store(meta.created_at, Ordering::Release);
+            *self.write_metadata(level_idx)? = meta;
+        }
+
+        if let Some(log) = rotation_log {
+            let skip = log.len().saturating_sub(ROTATION_HISTORY_LEN);
+            *self.rotation_history.write().map_err(|_| {
+                EbloomError::LockError(
+                    "Failed to write rotation history".to_string(),
+                )
+            })? = log.into_iter().skip(skip).collect();
+        }
+        Ok(())
+    }
+
     /// Reconstruct all N levels from storage (on load)
     async fn reconstruct_from_storage(&mut self) -> Result<()> {
         #[cfg(feature = "fjall")]
         if let Some(ref backend) = self.storage {
-            use crate::ebloom::storage::ExpiringStorageBackend;
-
-            // Load current level index
+            // Read everything first, then restore it without awaiting
             let current_idx = backend.load_current_level().await?;
-            self.current_level.store(current_idx, Ordering::Relaxed);
-
-            // Load all data from DB first (no locks held)
-            let loaded_metadata = backend.load_level_metadata().await?;
-
-            // Load all N levels from DB
-            let mut loaded_levels_data = Vec::new();
-            for level_idx in 0..self.config.num_levels {
-                // Try dirty chunks first, fallback to full chunks
-                let dirty_chunks = backend.load_dirty_chunks(level_idx).await?;
-                if !dirty_chunks.is_empty() {
-                    loaded_levels_data.push((level_idx, dirty_chunks));
-                } else {
-                    let chunks = backend.load_level_chunks(level_idx).await?;
-                    loaded_levels_data.push((level_idx, chunks));
-                }
-            }
-
-            // Now acquire locks and write data (no await points)
-            {
-                let mut metadata = self.metadata.write().map_err(|_| {
-                    EbloomError::LockError("Failed to write metadata".to_string())
-                })?;
-                *metadata = loaded_metadata;
-            }
-
-            let mut levels = self.levels.write().map_err(|_| {
-                EbloomError::LockError("Failed to write levels".to_string())
-            })?;
-
-            for (level_idx, chunks) in loaded_levels_data {
-                if !chunks.is_empty() {
+            let metadata = backend.load_level_metadata().await?;
+            let rotation_log = if self.persists_rotation_log() {
+                Some(backend.load_rotation_log().await?)
+            } else {
+                None
+            };
+            self.restore_state(current_idx, metadata, rotation_log)?;
+
+            let chunk_size_bytes = self.chunk_size_bytes;
+            let Some(backend) = backend.fjall() else {
+                // Remote levels arrive over the network one at a time
+                for (level_idx, level) in self.levels.iter().enumerate() {
+                    let mut chunks = backend.load_dirty_chunks(level_idx).await?;
+                    if chunks.is_empty() {
+                        chunks = backend.load_level_chunks(level_idx).await?;
+                    }
                     reconstruct_level_from_chunks(
-                        &mut levels[level_idx],
+                        level,
                         &chunks,
-                        self.chunk_size_bytes,
+                        chunk_size_bytes,
                     )?;
                 }
-            }
+                return Ok(());
+            };
+
+            // Read and decode every level on its own thread; levels are
+            // independent and written without locks. Chunks are streamed
+            // straight into the level, so peak memory stays at the levels
+            // themselves
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = self
+                    .levels
+                    .iter()
+                    .enumerate()
+                    .map(|(level_idx, level)| {
+                        scope.spawn(move || {
+                            backend
+                                .stream_level(level_idx, |chunk_id, bytes| {
+                                    write_level_chunk(
+                                        level,
+                                        chunk_id,
+                                        bytes,
+                                        chunk_size_bytes,
+                                    )
+                                })
+                                .map(|_| ())
+                        })
+                    })
+                    .collect();
+
+                workers.into_iter().try_for_each(|worker| {
+                    worker.join().map_err(|_| {
+                        EbloomError::StorageError(
+                            "Level reconstruction thread panicked".to_string(),
+                        )
+                    })?
+                })
+            })?;
         }
         Ok(())
     }
 }
 
-/// Helper: extract chunk bytes from BitVec
+/// Snapshot copied out of a filter, owned so it is committed without
+/// holding any of the filter's locks
+#[cfg(feature = "fjall")]
+struct PendingSnapshot {
+    level: usize,
+    full: bool,
+    batch: SnapshotBatch,
+    /// Dirty marks consumed by the batch, restored if the commit fails
+    taken_chunk_ids: Vec<usize>,
+    taken_levels: Vec<usize>,
+    started: std::time::Instant,
+}
+
+/// Metadata of a newly created filter: only the first level (current) has
+/// a timestamp, `created_at = 0` marks levels not yet used
+fn initial_metadata(
+    config: &ExpiringFilterConfig,
+    now_ms: u64,
+) -> Vec<LevelMetadata> {
+    (0..config.num_levels)
+        .map(|i| LevelMetadata {
+            created_at: if i == 0 {
+                config.window_start(now_ms)
+            } else {
+                0
+            },
+            insert_count: 0,
+            last_snapshot_at: 0,
+            rotation_reason: RotationReason::Created,
+        })
+        .collect()
+}
+
+/// Helper: allocate every level with the configured backing
+fn allocate_levels(
+    config: &ExpiringFilterConfig,
+    bit_vector_size: usize,
+) -> Result<Vec<AtomicBitVec>> {
+    let levels = (0..config.num_levels)
+        .map(|level_idx| allocate_level(config, level_idx, bit_vector_size))
+        .collect::<Result<Vec<_>>>()?;
+    for (level_idx, level) in levels.iter().enumerate() {
+        // Huge page advice must precede the first touch to take effect
+        if config.huge_pages {
+            let _ = level.advise_huge_pages();
+        }
+        if config.prefault_levels {
+            level.prefault();
+        }
+        advise_level(level, level_idx == 0);
+    }
+    Ok(levels)
+}
+
+fn allocate_level(
+    config: &ExpiringFilterConfig,
+    level_idx: usize,
+    bit_vector_size: usize,
+) -> Result<AtomicBitVec> {
+    match config.level_backing {
+        LevelBacking::Heap if !config.huge_pages => {
+            Ok(AtomicBitVec::new(bit_vector_size))
+        }
+        #[cfg(feature = "mmap")]
+        LevelBacking::Heap | LevelBacking::AnonymousMmap => {
+            AtomicBitVec::new_mapped_anon(bit_vector_size).map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to map level {level_idx}: {e}"
+                ))
+            })
+        }
+        #[cfg(feature = "mmap")]
+        LevelBacking::FileMmap(ref dir) => {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to create level mapping directory: {e}"
+                ))
+            })?;
+            let path = dir.join(format!("level_{level_idx}.bits"));
+            AtomicBitVec::new_mapped_file(&path, bit_vector_size).map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to map level {level_idx} to {path:?}: {e}"
+                ))
+            })
+        }
+        #[cfg(not(feature = "mmap"))]
+        _ => Err(EbloomError::InvalidConfig(format!(
+            "Memory-mapped level {level_idx} requires the `mmap` feature"
+        ))),
+    }
+}
+
+/// Helper: chance that a new item matches at least one of the levels with
+/// the given densities
+fn estimate_fpr(
+    fill_ratios: impl Iterator<Item = f64>,
+    num_hashes: usize,
+) -> f64 {
+    1.0 - fill_ratios
+        .map(|fill_ratio| 1.0 - fill_ratio.powi(num_hashes as i32))
+        .product::<f64>()
+}
+
+/// Expected false positive rate of one level holding `insert_count` items
+fn expected_fpr(
+    insert_count: u64,
+    num_hashes: usize,
+    bit_vector_size: usize,
+) -> f64 {
+    let k = num_hashes as f64;
+    let fill_ratio =
+        1.0 - (-k * insert_count as f64 / bit_vector_size as f64).exp();
+    fill_ratio.powi(num_hashes as i32)
+}
+
+/// Helper: per-level creation time counters seeded from metadata
+fn created_at_counters(metadata: &[LevelMetadata]) -> Vec<AtomicU64> {
+    metadata
+        .iter()
+        .map(|meta| AtomicU64::new(meta.created_at))
+        .collect()
+}
+
+/// Helper: hint the OS whether a mapped level is current (hot) or only
+/// probed at random. Hints are best effort, failures are ignored.
+fn advise_level(level: &AtomicBitVec, hot: bool) {
+    let _ = level.advise_hot(hot);
+}
+
+/// Helper: extract chunk bytes from a level
 fn extract_chunk_bytes(
-    bits: &BitVec<usize, Lsb0>,
+    bits: &AtomicBitVec,
     chunk_id: usize,
     chunk_size_bits: usize,
 ) -> Vec<u8> {
     let start_bit = chunk_id * chunk_size_bits;
-    if start_bit >= bits.len() {
-        return Vec::new();
-    }
-
-    let end_bit = std::cmp::min(start_bit + chunk_size_bits, bits.len());
-    let chunk_bits = &bits[start_bit..end_bit];
-
-    let mut bytes = Vec::new();
-    for byte_chunk in chunk_bits.chunks(8) {
-        let mut byte = 0u8;
-        for (bit_pos, bit) in byte_chunk.iter().enumerate() {
-            if *bit {
-                byte |= 1 << bit_pos;
-            }
-        }
-        bytes.push(byte);
-    }
-    bytes
+    bits.read_bytes(start_bit, start_bit + chunk_size_bits)
 }
 
 /// Helper: reconstruct level from chunks
-fn reconstruct_level_from_chunks(
-    level_bits: &mut BitVec<usize, Lsb0>,
+pub(crate) fn reconstruct_level_from_chunks(
+    level_bits: &AtomicBitVec,
     chunks: &[(usize, Vec<u8>)],
     chunk_size_bytes: usize,
 ) -> Result<()> {
-    let chunk_size_bits = chunk_size_bytes * 8;
+    chunks.iter().try_for_each(|(chunk_id, chunk_bytes)| {
+        write_level_chunk(level_bits, *chunk_id, chunk_bytes, chunk_size_bytes)
+    })
+}
 
-    for (chunk_id, chunk_bytes) in chunks {
-        let start_bit = chunk_id * chunk_size_bits;
-        for (byte_idx, &byte) in chunk_bytes.iter().enumerate() {
-            for bit_pos in 0..8 {
-                let bit_idx = start_bit + byte_idx * 8 + bit_pos;
-                if bit_idx < level_bits.len() {
-                    let bit_value = (byte & (1 << bit_pos)) != 0;
-                    level_bits.set(bit_idx, bit_value);
-                }
-            }
-        }
+/// Write one stored chunk into a level, checking its id and length
+pub(crate) fn write_level_chunk(
+    level_bits: &AtomicBitVec,
+    chunk_id: usize,
+    chunk_bytes: &[u8],
+    chunk_size_bytes: usize,
+) -> Result<()> {
+    let chunk_size_bits = chunk_size_bytes * 8;
+    // Same count the snapshot writer uses, which can include one trailing
+    // empty chunk
+    let num_chunks =
+        (level_bits.len() + chunk_size_bits - 1).div_ceil(chunk_size_bits);
+
+    // Chunk ids come from storage keys; a corrupt one must not overflow
+    // the bit offset
+    if chunk_id >= num_chunks {
+        return Err(EbloomError::StorageError(format!(
+            "Chunk {chunk_id} out of range, level has {num_chunks} chunks"
+        )));
+    }
+    // Every chunk is written whole, so a short one is a torn write
+    let start_bit = chunk_id * chunk_size_bits;
+    let expected = (start_bit + chunk_size_bits)
+        .min(level_bits.len())
+        .saturating_sub(start_bit)
+        .div_ceil(8);
+    if chunk_bytes.len() != expected {
+        return Err(EbloomError::StorageError(format!(
+            "Chunk {chunk_id} has {} bytes, expected {expected}",
+            chunk_bytes.len()
+        )));
     }
+    level_bits.write_bytes(start_bit, chunk_bytes);
     Ok(())
 }
 
-/// Helper function to insert an item into the filter with already-held locks
+/// Helper function to insert an item into a level
+#[allow(clippy::too_many_arguments)]
 fn insert_internal(
     item: &[u8],
+    hash_fn: HashFunction,
     current_level_idx: usize,
     num_hashes: usize,
     bit_vector_size: usize,
     chunk_size_bytes: usize,
-    dirty: Option<&mut BitVec<usize, Lsb0>>,
-    levels: &mut [BitVec<usize, Lsb0>],
-) -> Result<()> {
+    dirty: Option<&AtomicBitVec>,
+    levels: &[AtomicBitVec],
+) -> Result<(Vec<usize>, bool)> {
     // Calculate hash indices
-    let indices = default_hash_function(item, num_hashes, bit_vector_size);
-
-    // Mark dirty chunks (if dirty tracker provided)
-    if let Some(dirty_bits) = dirty {
-        for &idx in &indices {
-            let chunk_id = (idx as usize) / (chunk_size_bytes * 8);
-            if chunk_id < dirty_bits.len() {
-                dirty_bits.set(chunk_id, true);
-            }
-        }
-    }
+    let indices = hash_fn(item, num_hashes, bit_vector_size);
+    let duplicate = set_indices(
+        &indices,
+        current_level_idx,
+        bit_vector_size,
+        chunk_size_bytes,
+        dirty,
+        levels,
+    )?;
+    Ok((indices, duplicate))
+}
 
-    // Insert into current level only
-    if let Some(current_level) = levels.get_mut(current_level_idx) {
-        for idx in indices {
-            let idx = idx as usize;
+/// Helper function to set precomputed indices in a level
+///
+/// Returns whether every bit was already set, i.e. the item was a probable
+/// duplicate within this level.
+fn set_indices(
+    indices: &[usize],
+    level_idx: usize,
+    bit_vector_size: usize,
+    chunk_size_bytes: usize,
+    dirty: Option<&AtomicBitVec>,
+    levels: &[AtomicBitVec],
+) -> Result<bool> {
+    let mut all_set = true;
+    if let Some(level) = levels.get(level_idx) {
+        for &idx in indices {
             if idx >= bit_vector_size {
                 return Err(EbloomError::IndexOutOfBounds {
                     index: idx,
                     capacity: bit_vector_size,
                 });
             }
-            current_level.set(idx, true);
+            all_set &= level.test_and_set(idx);
         }
     }
 
-    Ok(())
+    // Mark dirty chunks after the bits are set, so a snapshot that takes the
+    // mark also sees the bits (if dirty tracker provided)
+    if let Some(dirty_bits) = dirty {
+        fence(Ordering::Release);
+        for &idx in indices {
+            let chunk_id = idx / (chunk_size_bytes * 8);
+            if chunk_id < dirty_bits.len() {
+                dirty_bits.set(chunk_id);
+            }
+        }
+    }
+
+    Ok(all_set)
 }
 
-/// Helper function to check if an item exists with already-held lock
-fn contains_internal(
+/// Helper function to check if an item exists in any level
+pub(crate) fn contains_internal(
     item: &[u8],
+    hash_fn: HashFunction,
     num_hashes: usize,
     bit_vector_size: usize,
-    levels: &[BitVec<usize, Lsb0>],
+    levels: &[AtomicBitVec],
 ) -> Result<bool> {
     // Calculate hash indices
-    let indices = default_hash_function(item, num_hashes, bit_vector_size);
+    let indices = hash_fn(item, num_hashes, bit_vector_size);
 
-    // Check all levels
+    // Check all levels, found in any level means present
     for level in levels.iter() {
-        let mut all_bits_set = true;
+        if level_contains(&indices, bit_vector_size, level)? {
+            return Ok(true);
+        }
+    }
 
-        for idx in &indices {
-            let idx = *idx as usize;
-            if idx >= bit_vector_size {
-                return Err(EbloomError::IndexOutOfBounds {
-                    index: idx,
-                    capacity: bit_vector_size,
-                });
-            }
+    // Not found in any level
+    Ok(false)
+}
 
-            if !level[idx] {
-                all_bits_set = false;
-                break;
-            }
+/// Items hashed and probed together by `contains_batched`
+const CONTAINS_BATCH_ITEMS: usize = 16;
+
+/// Smallest batch `contains_bulk` splits across threads for file-mapped
+/// levels; below it thread startup costs more than overlapping page faults
+/// saves
+pub const PARALLEL_CONTAINS_MIN_ITEMS: usize = 4096;
+
+/// Helper function to check many items against all levels
+///
+/// Items are processed in batches of `CONTAINS_BATCH_ITEMS`: all hash
+/// indices of a batch are computed up front, then each level tests every
+/// still-unmatched item, gathering its words with AVX2 or NEON where the
+/// CPU has them (see `AtomicBitVec::all_set_each`). Keeping many independent
+/// loads in flight hides memory latency far better than probing one item at
+/// a time.
+pub(crate) fn contains_batched(
+    items: &[&[u8]],
+    hash_into: HashIntoFunction,
+    num_hashes: usize,
+    bit_vector_size: usize,
+    levels: &[AtomicBitVec],
+    ctx: &mut BulkContext,
+) -> Result<()> {
+    ctx.results.clear();
+    if num_hashes == 0 {
+        // No probes: every item matches any level, as in `contains_internal`
+        ctx.results.resize(items.len(), !levels.is_empty());
+        return Ok(());
+    }
+    ctx.results.reserve(items.len());
+
+    for batch in items.chunks(CONTAINS_BATCH_ITEMS) {
+        ctx.indices.clear();
+        for item in batch {
+            hash_into(item, num_hashes, bit_vector_size, &mut ctx.indices);
+        }
+        if let Some(&idx) =
+            ctx.indices.iter().find(|&&idx| idx >= bit_vector_size)
+        {
+            return Err(EbloomError::IndexOutOfBounds {
+                index: idx,
+                capacity: bit_vector_size,
+            });
         }
 
-        // If found in any level, return true
-        if all_bits_set {
-            return Ok(true);
+        let mut found = [false; CONTAINS_BATCH_ITEMS];
+        for level in levels {
+            level.all_set_each(
+                &ctx.indices,
+                num_hashes,
+                &mut found[..batch.len()],
+            );
         }
+        ctx.results.extend_from_slice(&found[..batch.len()]);
     }
 
-    // Not found in any level
-    Ok(false)
+    Ok(())
+}
+
+/// Helper function to check precomputed indices against a single level
+fn level_contains(
+    indices: &[usize],
+    bit_vector_size: usize,
+    level: &AtomicBitVec,
+) -> Result<bool> {
+    for &idx in indices {
+        if idx >= bit_vector_size {
+            return Err(EbloomError::IndexOutOfBounds {
+                index: idx,
+                capacity: bit_vector_size,
+            });
+        }
+
+        if !level.get(idx) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
 }
 
 #[async_trait::async_trait]
 impl ExpiringBloomFilterOps for ExpiringBloomFilter {
     fn insert(&self, item: &[u8]) -> Result<()> {
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::Insert);
         // Get the current level index
-        let current_level_idx = self.current_level.load(Ordering::Relaxed);
-
-        // Mark dirty chunks (if persistence enabled)
-        let mut dirty_guard = if let Some(ref dirty_chunks_arc) =
-            self.dirty_chunks
-        {
-            Some(dirty_chunks_arc.write().map_err(|_| {
-                EbloomError::LockError("Failed to write dirty chunks".to_string())
-            })?)
-        } else {
-            None
-        };
+        let current_level_idx = self.current_level.load(Ordering::Acquire);
+        self.check_fpr_guardrail(current_level_idx, 1)?;
 
-        // Get write lock on levels
-        let mut levels = self.levels.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on levels".to_string(),
-            )
-        })?;
+        // Count before the chunk is marked dirty, so the snapshot that takes
+        // the mark also stores the count
+        let previous_count =
+            self.insert_counts[current_level_idx].fetch_add(1, Ordering::Relaxed);
+        self.check_rotation_due(current_level_idx, previous_count, 1)?;
 
-        // Perform the insertion
-        insert_internal(
+        // Perform the insertion, marking dirty chunks if persistence enabled
+        let (indices, duplicate) = insert_internal(
             item,
+            self.hash_fn,
             current_level_idx,
             self.num_hashes,
             self.bit_vector_size,
             self.chunk_size_bytes,
-            dirty_guard.as_deref_mut(),
-            &mut levels,
+            self.dirty_chunks.as_deref(),
+            &self.levels,
         )?;
+        self.queue_write_behind(current_level_idx, &indices)?;
+        if duplicate {
+            self.probable_duplicates.fetch_add(1, Ordering::Relaxed);
+        }
 
-        // Update metadata for current level
-        let mut metadata = self.metadata.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on metadata".to_string(),
-            )
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_inserts(1);
+        if let Some(ref statsd) = self.statsd {
+            statsd.record_inserts(1);
+        }
+        self.notify_observers(|observer| {
+            observer.on_insert(item, current_level_idx)
         })?;
-        if let Some(meta) = metadata.get_mut(current_level_idx) {
-            meta.insert_count += 1;
+
+        // Carry the item into the next level in smooth-decay mode or when
+        // close to rotation
+        if self.carries_into_next(current_level_idx)? {
+            self.record_grace(&[item]);
         }
 
         Ok(())
     }
 
     fn contains(&self, item: &[u8]) -> Result<bool> {
-        // Get read lock on levels
-        let levels = self.levels.read().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire read lock on levels".to_string(),
-            )
-        })?;
-
-        contains_internal(item, self.num_hashes, self.bit_vector_size, &levels)
+        #[cfg(feature = "latency")]
+        let _timer = self.latency.start(LatencyOperation::Contains);
+        let found = contains_internal(
+            item,
+            self.hash_fn,
+            self.num_hashes,
+            self.bit_vector_size,
+            &self.levels,
+        )?;
+        #[cfg(feature = "metrics")]
+        filter_metrics::record_queries(1, found as usize);
+        Ok(found)
     }
 
     fn clear(&self) -> Result<()> {
-        // Get write lock on levels
-        let mut levels = self.levels.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on levels".to_string(),
-            )
-        })?;
-
         // Clear all levels
-        for level in levels.iter_mut() {
-            level.fill(false);
+        for level in self.levels.iter() {
+            level.clear();
         }
 
         // Reset all metadata
-        let mut metadata = self.metadata.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on metadata".to_string(),
-            )
-        })?;
-
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| EbloomError::TimeError(e.to_string()))?
-            .as_millis() as u64;
+        let now_ms = self.clock.now_ms()?;
 
-        for meta in metadata.iter_mut() {
-            meta.created_at = now_ms; // Store in milliseconds
+        for level_idx in 0..self.metadata.len() {
+            let mut meta = self.write_metadata(level_idx)?;
+            // Store in milliseconds
+            meta.created_at = self.config.window_start(now_ms);
             meta.insert_count = 0;
             meta.last_snapshot_at = 0;
+            meta.rotation_reason = RotationReason::Created;
+            self.insert_counts[level_idx].store(0, Ordering::Relaxed);
+            self.created_ats[level_idx].store(meta.created_at, Ordering::Release);
         }
 
         // Reset to level 0 as current
-        self.current_level.store(0, Ordering::Relaxed);
+        self.current_level.store(0, Ordering::Release);
+        self.saturation_warned.store(false, Ordering::Relaxed);
 
         Ok(())
     }
 
     async fn cleanup_expired_levels(&self) -> Result<()> {
-        let current_level = self.current_level.load(Ordering::Relaxed);
-
-        if self.is_level_expired(current_level)? {
-            self.rotate_levels().await?;
-        }
-
-        Ok(())
+        ExpiringBloomFilter::cleanup_expired_levels(self).await
     }
 }
 
@@ -754,8 +3305,10 @@ impl ExpiringBloomFilterStats for ExpiringBloomFilter {
     }
 
     fn total_insert_count(&self) -> u64 {
-        let metadata = self.metadata.read().unwrap();
-        metadata.iter().map(|m| m.insert_count).sum()
+        self.insert_counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
     }
 
     fn active_levels(&self) -> usize {
@@ -765,75 +3318,52 @@ impl ExpiringBloomFilterStats for ExpiringBloomFilter {
     fn num_levels(&self) -> usize {
         self.config.num_levels
     }
-}
-
-impl BulkExpiringBloomFilterOps for ExpiringBloomFilter {
-    fn insert_bulk(&self, items: &[&[u8]]) -> Result<()> {
-        // Get the current level index
-        let current_level_idx = self.current_level.load(Ordering::Relaxed);
 
-        // Mark dirty chunks (if persistence enabled)
-        let mut dirty_guard = if let Some(ref dirty_chunks_arc) =
-            self.dirty_chunks
-        {
-            Some(dirty_chunks_arc.write().map_err(|_| {
-                EbloomError::LockError("Failed to write dirty chunks".to_string())
-            })?)
-        } else {
-            None
-        };
+    fn fill_ratio(&self, level: usize) -> Result<f64> {
+        self.level_fill_ratio(level)
+    }
 
-        // Get write lock on levels
-        let mut levels = self.levels.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on levels".to_string(),
-            )
-        })?;
+    fn bit_vector_size(&self) -> usize {
+        self.bit_vector_size
+    }
 
-        // Perform all insertions with single lock
-        for item in items {
-            insert_internal(
-                item,
-                current_level_idx,
-                self.num_hashes,
-                self.bit_vector_size,
-                self.chunk_size_bytes,
-                dirty_guard.as_deref_mut(),
-                &mut levels,
-            )?;
-        }
+    fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
 
-        // Update metadata for current level with total count
-        let mut metadata = self.metadata.write().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire write lock on metadata".to_string(),
-            )
-        })?;
-        if let Some(meta) = metadata.get_mut(current_level_idx) {
-            meta.insert_count += items.len() as u64;
-        }
+    fn level_bit_counts(&self) -> Result<Vec<usize>> {
+        Ok(self.levels.iter().map(AtomicBitVec::count_ones).collect())
+    }
+}
 
-        Ok(())
+impl BulkExpiringBloomFilterOps for ExpiringBloomFilter {
+    fn insert_bulk(&self, items: &[&[u8]]) -> Result<()> {
+        with_scratch(|ctx| self.insert_bulk_with(ctx, items))
     }
 
+    /// Large batches against file-mapped levels are split across threads,
+    /// see `contains_bulk_parallel`
     fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>> {
-        // Get read lock on levels once
-        let levels = self.levels.read().map_err(|_| {
-            EbloomError::LockError(
-                "Failed to acquire read lock on levels".to_string(),
-            )
-        })?;
-
-        // Check all items with single lock
-        let mut results = Vec::with_capacity(items.len());
-        for item in items {
-            results.push(contains_internal(
-                item,
-                self.num_hashes,
-                self.bit_vector_size,
-                &levels,
-            )?);
+        if matches!(self.config.level_backing, LevelBacking::FileMmap(_))
+            && items.len() >= PARALLEL_CONTAINS_MIN_ITEMS
+        {
+            let parallelism =
+                std::thread::available_parallelism().map_or(1, |n| n.get());
+            return self.contains_bulk_parallel(items, parallelism);
         }
-        Ok(results)
+        with_scratch(|ctx| {
+            self.contains_bulk_with(ctx, items).map(<[bool]>::to_vec)
+        })
+    }
+
+    fn contains_bulk_bitset(&self, items: &[&[u8]]) -> Result<Bitset> {
+        // Batches keep the `bool` scratch small however many items there are
+        with_scratch(|ctx| {
+            let mut found = Bitset::with_capacity(items.len());
+            for batch in items.chunks(BITSET_BATCH_LEN) {
+                found.extend(self.contains_bulk_with(ctx, batch)?.iter());
+            }
+            Ok(found)
+        })
     }
 }
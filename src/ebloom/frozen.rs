@@ -0,0 +1,91 @@
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::bulk::{BulkContext, with_scratch};
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::{
+    ExpiringBloomFilter, contains_batched, contains_internal,
+};
+use std::sync::Arc;
+
+/// Immutable point-in-time view of an `ExpiringBloomFilter`
+///
+/// Created by `ExpiringBloomFilter::freeze`, which copies every level, so
+/// analytics queries can run against a stable view while the live filter
+/// keeps ingesting and rotating. Clones of the handle share that copy.
+#[derive(Clone)]
+pub struct FrozenExpiringBloomFilter {
+    pub(crate) config: ExpiringFilterConfig,
+    pub(crate) bit_vector_size: usize,
+    pub(crate) num_hashes: usize,
+    pub(crate) levels: Arc<Vec<AtomicBitVec>>,
+    pub(crate) metadata: Arc<Vec<LevelMetadata>>,
+    pub(crate) current_level: usize,
+    pub(crate) frozen_at: u64,
+}
+
+impl FrozenExpiringBloomFilter {
+    /// Check if an item existed in any level at freeze time
+    pub fn contains(&self, item: &[u8]) -> Result<bool> {
+        contains_internal(
+            item,
+            self.config.hash_function(),
+            self.num_hashes,
+            self.bit_vector_size,
+            &self.levels,
+        )
+    }
+
+    /// Check many items against the frozen view
+    pub fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>> {
+        with_scratch(|ctx| {
+            self.contains_bulk_with(ctx, items).map(<[bool]>::to_vec)
+        })
+    }
+
+    /// Check many items, reusing the buffers in `ctx`
+    pub fn contains_bulk_with<'a>(
+        &self,
+        ctx: &'a mut BulkContext,
+        items: &[&[u8]],
+    ) -> Result<&'a [bool]> {
+        contains_batched(
+            items,
+            self.config.hash_into_function(),
+            self.num_hashes,
+            self.bit_vector_size,
+            &self.levels,
+            ctx,
+        )?;
+        Ok(ctx.results())
+    }
+
+    pub fn config(&self) -> &ExpiringFilterConfig {
+        &self.config
+    }
+
+    /// Index of the current level at freeze time
+    pub fn active_level(&self) -> usize {
+        self.current_level
+    }
+
+    /// Time the snapshot was taken, in milliseconds
+    pub fn frozen_at(&self) -> u64 {
+        self.frozen_at
+    }
+
+    /// Metadata for a single level at freeze time
+    pub fn level_metadata(&self, level_index: usize) -> Result<LevelMetadata> {
+        self.metadata
+            .get(level_index)
+            .cloned()
+            .ok_or(EbloomError::InvalidLevel {
+                level: level_index,
+                max_levels: self.config.num_levels,
+            })
+    }
+
+    /// Build a new writable in-memory filter from this snapshot
+    pub fn thaw(&self) -> Result<ExpiringBloomFilter> {
+        ExpiringBloomFilter::from_frozen(self)
+    }
+}
@@ -0,0 +1,285 @@
+//! Anti-entropy sync between peer filters, without a central broker
+//!
+//! Each round a node trades digests with one peer: per level a hash of
+//! every chunk, plus the level timestamps. Either side then ships only the
+//! chunks whose hash differs from the level they will land in, and the
+//! receiver ORs them in. Levels are matched by time as in `ebloom::union`,
+//! so the peers may rotate independently, and since OR is idempotent a
+//! chunk that arrives twice is harmless. Repeated rounds converge the
+//! cluster on the union of recently seen keys.
+//!
+//! Transport is up to the caller: implement [`GossipPeer`] over HTTP, gRPC
+//! or a message bus with the `to_bytes`/`from_bytes` encodings of
+//! [`SyncDigest`] and [`ChunkDelta`]. [`AntiEntropy`] itself implements
+//! the trait, so in-process nodes can sync directly.
+//!
+//! Peers must share the bit layout (level size, hash count, layout) and
+//! `chunk_bytes`. Computing a digest copies every level, so pick an
+//! interval long enough for that to be cheap relative to the traffic.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::union::UnionPayload;
+pub use crate::ebloom::union::{ChunkDelta, DeltaLevel};
+use crate::hash::hash_fnv64;
+
+#[derive(Debug, Clone, Builder, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct GossipConfig {
+    /// Time between rounds of [`AntiEntropy::run`]
+    #[builder(default = "Duration::from_secs(5)")]
+    pub interval: Duration,
+    /// Bytes of level bits covered by one digest hash; smaller chunks
+    /// ship less data per difference but make digests larger
+    #[builder(default = "4096")]
+    pub chunk_bytes: usize,
+}
+
+/// Chunk hashes of every non-empty level of a filter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct SyncDigest {
+    pub layout_fingerprint: u64,
+    pub chunk_bytes: usize,
+    pub levels: Vec<LevelDigest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct LevelDigest {
+    pub created_at: u64,
+    pub last_written_at: u64,
+    pub chunk_hashes: Vec<u64>,
+}
+
+impl SyncDigest {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(digest, _)| digest)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+}
+
+/// Chunks moved in one [`AntiEntropy::sync_with`] round
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    pub chunks_sent: usize,
+    pub chunks_received: usize,
+}
+
+/// Remote end of a sync round
+#[async_trait]
+pub trait GossipPeer: Send + Sync {
+    /// The peer's current digest
+    async fn digest(&self) -> Result<SyncDigest>;
+
+    /// Merge chunks into the peer's filter
+    async fn push(&self, delta: ChunkDelta) -> Result<()>;
+
+    /// Chunks the peer holds that differ from `digest`
+    async fn pull(&self, digest: SyncDigest) -> Result<ChunkDelta>;
+}
+
+/// Syncs one filter with a set of peers
+pub struct AntiEntropy {
+    filter: Arc<ExpiringBloomFilter>,
+    config: GossipConfig,
+    peers: Vec<Arc<dyn GossipPeer>>,
+    next_peer: AtomicUsize,
+}
+
+impl AntiEntropy {
+    pub fn new(
+        filter: Arc<ExpiringBloomFilter>,
+        config: GossipConfig,
+    ) -> Result<Self> {
+        if config.chunk_bytes == 0 {
+            return Err(EbloomError::InvalidConfig(
+                "Gossip chunk size must be greater than 0".to_string(),
+            ));
+        }
+        Ok(Self {
+            filter,
+            config,
+            peers: Vec::new(),
+            next_peer: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn add_peer(&mut self, peer: Arc<dyn GossipPeer>) {
+        self.peers.push(peer);
+    }
+
+    pub fn filter(&self) -> &Arc<ExpiringBloomFilter> {
+        &self.filter
+    }
+
+    /// Digest of the local filter
+    pub fn digest(&self) -> Result<SyncDigest> {
+        let payload = self.filter.export_union()?;
+        let levels = payload
+            .levels
+            .iter()
+            .map(|level| LevelDigest {
+                created_at: level.created_at,
+                last_written_at: level.last_written_at,
+                chunk_hashes: level
+                    .bits
+                    .chunks(self.config.chunk_bytes)
+                    .map(hash_fnv64)
+                    .collect(),
+            })
+            .collect();
+        Ok(SyncDigest {
+            layout_fingerprint: payload.layout_fingerprint,
+            chunk_bytes: self.config.chunk_bytes,
+            levels,
+        })
+    }
+
+    /// Local chunks that differ from the level of `peer` they would be
+    /// merged into
+    pub fn delta_for(&self, peer: &SyncDigest) -> Result<ChunkDelta> {
+        let payload = self.filter.export_union()?;
+        self.check_compatible(
+            peer.layout_fingerprint,
+            peer.chunk_bytes,
+            &payload,
+        )?;
+
+        let levels = payload
+            .levels
+            .into_iter()
+            .filter_map(|level| {
+                // Same matching as `apply_union` on the peer. Digests
+                // list non-empty levels only, so with no match the whole
+                // level is sent
+                let target = peer
+                    .levels
+                    .iter()
+                    .filter(|p| p.created_at <= level.last_written_at)
+                    .max_by_key(|p| p.created_at);
+                let chunks: Vec<(usize, Vec<u8>)> = level
+                    .bits
+                    .chunks(self.config.chunk_bytes)
+                    .enumerate()
+                    .filter(|&(idx, chunk)| {
+                        chunk.iter().any(|&b| b != 0)
+                            && target.is_none_or(|t| {
+                                t.chunk_hashes.get(idx)
+                                    != Some(&hash_fnv64(chunk))
+                            })
+                    })
+                    .map(|(idx, chunk)| (idx, chunk.to_vec()))
+                    .collect();
+                (!chunks.is_empty()).then_some(DeltaLevel {
+                    created_at: level.created_at,
+                    last_written_at: level.last_written_at,
+                    chunks,
+                })
+            })
+            .collect();
+        Ok(ChunkDelta {
+            layout_fingerprint: payload.layout_fingerprint,
+            chunk_bytes: self.config.chunk_bytes,
+            levels,
+        })
+    }
+
+    /// OR a peer's chunks into the local filter, returning how many
+    /// levels were merged
+    pub fn apply(&self, delta: &ChunkDelta) -> Result<usize> {
+        if delta.chunk_bytes != self.config.chunk_bytes {
+            return Err(chunk_size_mismatch(delta.chunk_bytes));
+        }
+        self.filter.apply_delta(delta)
+    }
+
+    /// Push local differences to `peer` and pull its differences back
+    pub async fn sync_with(&self, peer: &dyn GossipPeer) -> Result<SyncStats> {
+        let outgoing = self.delta_for(&peer.digest().await?)?;
+        let chunks_sent = outgoing.chunk_count();
+        if !outgoing.is_empty() {
+            peer.push(outgoing).await?;
+        }
+        let incoming = peer.pull(self.digest()?).await?;
+        self.apply(&incoming)?;
+        Ok(SyncStats {
+            chunks_sent,
+            chunks_received: incoming.chunk_count(),
+        })
+    }
+
+    /// Sync with the next peer, round-robin; `None` without peers
+    pub async fn sync_round(&self) -> Result<Option<SyncStats>> {
+        if self.peers.is_empty() {
+            return Ok(None);
+        }
+        let idx =
+            self.next_peer.fetch_add(1, Ordering::Relaxed) % self.peers.len();
+        self.sync_with(self.peers[idx].as_ref()).await.map(Some)
+    }
+
+    /// Run a round every `interval` forever; failed rounds are logged and
+    /// retried with the next peer
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sync_round().await {
+                tracing::warn!(error = %e, "Gossip sync round failed");
+            }
+        }
+    }
+
+    fn check_compatible(
+        &self,
+        layout_fingerprint: u64,
+        chunk_bytes: usize,
+        local: &UnionPayload,
+    ) -> Result<()> {
+        if layout_fingerprint != local.layout_fingerprint {
+            return Err(EbloomError::InvalidConfig(
+                "Gossip peer uses a different bit layout".to_string(),
+            ));
+        }
+        if chunk_bytes != self.config.chunk_bytes {
+            return Err(chunk_size_mismatch(chunk_bytes));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GossipPeer for AntiEntropy {
+    async fn digest(&self) -> Result<SyncDigest> {
+        AntiEntropy::digest(self)
+    }
+
+    async fn push(&self, delta: ChunkDelta) -> Result<()> {
+        self.apply(&delta).map(|_| ())
+    }
+
+    async fn pull(&self, digest: SyncDigest) -> Result<ChunkDelta> {
+        self.delta_for(&digest)
+    }
+}
+
+fn chunk_size_mismatch(chunk_bytes: usize) -> EbloomError {
+    EbloomError::InvalidConfig(format!(
+        "Gossip peer uses {chunk_bytes}-byte chunks"
+    ))
+}
@@ -0,0 +1,125 @@
+//! Greylisting on top of expiring filters
+//!
+//! Greylisting defers the first delivery attempt from an unknown sender
+//! and accepts a retry within a window; spam senders rarely retry.
+//! [`SeenRecently`] implements that with two filters: `seen` remembers
+//! first attempts for the retry window, `passed` remembers keys that
+//! retried so they skip greylisting for a longer period.
+
+use std::time::Duration;
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::config::ExpiringFilterConfigBuilder;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+#[derive(Debug, Clone, Builder, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct GreylistConfig {
+    /// Expected keys per level, for both filters
+    #[builder(default = "1_000_000")]
+    pub capacity_per_level: usize,
+    #[builder(default = "0.01")]
+    pub target_fpr: f64,
+    /// A second sighting within this span passes
+    #[builder(default = "Duration::from_secs(4 * 60 * 60)")]
+    pub retry_window: Duration,
+    /// Keys that passed skip greylisting for this long
+    #[builder(default = "Duration::from_secs(36 * 24 * 60 * 60)")]
+    pub pass_duration: Duration,
+    /// Levels per filter; more levels make expiry closer to exact
+    #[builder(default = "4")]
+    pub num_levels: usize,
+}
+
+/// Outcome of [`SeenRecently::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// First sighting within the retry window: ask the sender to retry
+    Defer,
+    /// Seen before within the window, or passed earlier
+    Accept,
+}
+
+/// "Must be seen at least twice within a window" policy
+pub struct SeenRecently {
+    seen: ExpiringBloomFilter,
+    passed: ExpiringBloomFilter,
+}
+
+impl SeenRecently {
+    /// Build both filters from `config`
+    ///
+    /// Each filter splits its span across `num_levels` levels, so a key is
+    /// remembered for between `(n - 1) / n` and all of the span.
+    pub fn from_config(config: &GreylistConfig) -> Result<Self> {
+        let filter = |span: Duration| {
+            if config.num_levels == 0 {
+                return Err(EbloomError::InvalidConfig(
+                    "Number of levels must be greater than 0".to_string(),
+                ));
+            }
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(config.capacity_per_level)
+                .target_fpr(config.target_fpr)
+                .num_levels(config.num_levels)
+                .level_duration(span / config.num_levels as u32)
+                .build()
+                .map_err(|e| EbloomError::ConfigError(e.to_string()))?;
+            ExpiringBloomFilter::new(config)
+        };
+        Ok(Self::new(
+            filter(config.retry_window)?,
+            filter(config.pass_duration)?,
+        ))
+    }
+
+    /// Use existing filters, e.g. persistent ones or a custom clock
+    pub fn new(seen: ExpiringBloomFilter, passed: ExpiringBloomFilter) -> Self {
+        Self { seen, passed }
+    }
+
+    /// Record `key`, returning `true` if it was not seen within the retry
+    /// window
+    ///
+    /// Check and insert are separate steps, so two threads racing on the
+    /// same new key may both get `true`.
+    pub fn first_seen(&self, key: &[u8]) -> Result<bool> {
+        if self.seen.contains(key)? {
+            return Ok(false);
+        }
+        self.seen.insert(key)?;
+        Ok(true)
+    }
+
+    /// Apply the greylisting policy to `key` and record the attempt
+    pub fn check(&self, key: &[u8]) -> Result<Verdict> {
+        if self.passed.contains(key)? {
+            return Ok(Verdict::Accept);
+        }
+        if self.first_seen(key)? {
+            return Ok(Verdict::Defer);
+        }
+        self.passed.insert(key)?;
+        Ok(Verdict::Accept)
+    }
+
+    /// Rotate out expired levels of both filters; call periodically
+    pub async fn cleanup_expired_levels(&self) -> Result<()> {
+        self.seen.cleanup_expired_levels().await?;
+        self.passed.cleanup_expired_levels().await
+    }
+
+    /// Filter of first sightings
+    pub fn seen(&self) -> &ExpiringBloomFilter {
+        &self.seen
+    }
+
+    /// Filter of keys that passed
+    pub fn passed(&self) -> &ExpiringBloomFilter {
+        &self.passed
+    }
+}
@@ -0,0 +1,309 @@
+//! Several named expiring filters in one Fjall database
+//!
+//! A persistent [`ExpiringBloomFilter`] normally opens a keyspace of its
+//! own, with its own journal, flush and compaction workers and file
+//! handles. A [`FilterKeyspace`] opens one keyspace and gives each filter a
+//! separate set of partitions, prefixed `f#<name>#`, so a service with
+//! dozens of small filters pays for one journal and one set of background
+//! workers, and a sync makes every filter's writes durable at once.
+//!
+//! The filters stay independent: each has its own config, levels, current
+//! level and rotation schedule. Only Fjall's workers are shared; a filter
+//! with `write_behind_capacity` still runs a writer thread of its own. One
+//! maintenance loop can drive all of them:
+//!
+//! ```ignore
+//! let db = FilterKeyspace::open("filters.fjall")?;
+//! let sessions = db.create_or_load("sessions", sessions_config).await?;
+//! let tokens = db.create_or_load("tokens", tokens_config).await?;
+//! loop {
+//!     db.cleanup_expired_levels().await?;
+//!     db.save_snapshots().await?;
+//!     tokio::time::sleep(Duration::from_secs(10)).await;
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::ebloom::config::{
+    ExpiringFilterConfig, ExpiringPersistenceConfigBuilder,
+};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::storage::{ExpiringStorageBackend, FjallExpiringBackend};
+
+/// Longest filter name; keeps every partition name within Fjall's 255
+/// byte limit
+pub const MAX_FILTER_NAME_LEN: usize = 200;
+
+/// Partition holding a filter's config, present once it was created
+const CONFIG_PARTITION: &str = "expiring_config";
+
+/// Fjall database hosting named expiring filters
+pub struct FilterKeyspace {
+    path: PathBuf,
+    keyspace: Arc<fjall::Keyspace>,
+    /// Filters opened through this keyspace, by name
+    filters: RwLock<BTreeMap<String, Arc<ExpiringBloomFilter>>>,
+}
+
+impl FilterKeyspace {
+    /// Open or create the database at `path`
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let keyspace = fjall::Config::new(&path).open().map_err(|e| {
+            EbloomError::StorageError(format!("Failed to open Fjall DB: {e}"))
+        })?;
+        Ok(Self {
+            path,
+            keyspace: Arc::new(keyspace),
+            filters: RwLock::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Names of the filters stored in the database, open or not, sorted
+    pub fn stored_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .keyspace
+            .list_partitions()
+            .iter()
+            .filter_map(|partition| {
+                partition
+                    .strip_prefix("f#")?
+                    .strip_suffix(CONFIG_PARTITION)?
+                    .strip_suffix('#')
+                    .map(str::to_string)
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Names of the filters opened through this keyspace
+    pub fn open_names(&self) -> Result<Vec<String>> {
+        Ok(self.read_filters()?.keys().cloned().collect())
+    }
+
+    /// Filter `name`, if it was opened through this keyspace
+    pub fn get(&self, name: &str) -> Result<Option<Arc<ExpiringBloomFilter>>> {
+        Ok(self.read_filters()?.get(name).cloned())
+    }
+
+    /// Create filter `name`, replacing any stored filter of that name
+    ///
+    /// Every partition of the replaced filter is deleted first, so none of
+    /// its levels survive a smaller level count. Like `remove`, fails while
+    /// the filter is open and still in use outside the keyspace.
+    ///
+    /// The persistence settings of `config` apply to this filter only;
+    /// its `db_path` is replaced by the keyspace path, and a config without
+    /// persistence gets the default settings.
+    pub async fn create(
+        &self,
+        name: &str,
+        config: ExpiringFilterConfig,
+    ) -> Result<Arc<ExpiringBloomFilter>> {
+        let config = self.with_keyspace_path(config)?;
+        self.remove(name)?;
+        let backend = self.backend(name, config.num_levels)?;
+        let filter = ExpiringBloomFilter::create_on_fjall(config, backend)
+            .await
+            .map(Arc::new)?;
+        self.register(name, &filter)?;
+        Ok(filter)
+    }
+
+    /// Load stored filter `name`, or return it if it is already open
+    pub async fn load(&self, name: &str) -> Result<Arc<ExpiringBloomFilter>> {
+        if let Some(filter) = self.get(name)? {
+            return Ok(filter);
+        }
+        if !self.is_stored(name)? {
+            return Err(EbloomError::StorageError(format!(
+                "No filter named {name:?} in {:?}",
+                self.path
+            )));
+        }
+        // Only the config and metadata partitions until the level count
+        // is known
+        let config = self.backend(name, 0)?.load_config().await?;
+        let backend = self.backend(name, config.num_levels)?;
+        let filter = ExpiringBloomFilter::load_on_fjall(config, backend)
+            .await
+            .map(Arc::new)?;
+        self.register(name, &filter)?;
+        Ok(filter)
+    }
+
+    /// Load filter `name` if it is stored, create it from `config`
+    /// otherwise
+    pub async fn create_or_load(
+        &self,
+        name: &str,
+        config: ExpiringFilterConfig,
+    ) -> Result<Arc<ExpiringBloomFilter>> {
+        if self.get(name)?.is_some() || self.is_stored(name)? {
+            self.load(name).await
+        } else {
+            self.create(name, config).await
+        }
+    }
+
+    /// Delete filter `name` and its data
+    ///
+    /// Fails while the filter is still in use outside the keyspace, i.e.
+    /// another `Arc` returned for it is alive.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let prefix = prefix(name)?;
+        let removed = self.write_filters()?.remove(name);
+        if let Some(filter) = removed {
+            if Arc::strong_count(&filter) > 1 {
+                self.register(name, &filter)?;
+                return Err(EbloomError::InvalidConfig(format!(
+                    "Filter {name:?} is still in use"
+                )));
+            }
+            // Stops its write-behind worker before the partitions go
+            drop(filter);
+        }
+        let partitions = self.keyspace.list_partitions();
+        for partition in partitions.iter().filter(|p| p.starts_with(&prefix)) {
+            let handle = self
+                .keyspace
+                .open_partition(
+                    partition,
+                    fjall::PartitionCreateOptions::default(),
+                )
+                .and_then(|handle| self.keyspace.delete_partition(handle));
+            handle.map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to delete partition {partition} of {name:?}: {e}"
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Rotate expired levels of every open filter
+    pub async fn cleanup_expired_levels(&self) -> Result<()> {
+        for filter in self.open_filters()? {
+            filter.cleanup_expired_levels().await?;
+        }
+        Ok(())
+    }
+
+    /// Save the dirty chunks of every open filter
+    pub async fn save_snapshots(&self) -> Result<()> {
+        for filter in self.open_filters()? {
+            filter.save_snapshot().await?;
+        }
+        Ok(())
+    }
+
+    /// Make deferred writes of every open filter durable
+    pub async fn flush(&self) -> Result<()> {
+        for filter in self.open_filters()? {
+            filter.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn is_stored(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .keyspace
+            .partition_exists(&format!("{}{CONFIG_PARTITION}", prefix(name)?)))
+    }
+
+    fn backend(
+        &self,
+        name: &str,
+        num_levels: usize,
+    ) -> Result<Arc<FjallExpiringBackend>> {
+        FjallExpiringBackend::with_keyspace(
+            Arc::clone(&self.keyspace),
+            &prefix(name)?,
+            num_levels,
+        )
+        .map(Arc::new)
+    }
+
+    fn with_keyspace_path(
+        &self,
+        mut config: ExpiringFilterConfig,
+    ) -> Result<ExpiringFilterConfig> {
+        match config.persistence {
+            Some(ref mut persistence) => persistence.db_path = self.path.clone(),
+            None => {
+                config.persistence = Some(
+                    ExpiringPersistenceConfigBuilder::default()
+                        .db_path(self.path.clone())
+                        .build()
+                        .map_err(|e| EbloomError::InvalidConfig(e.to_string()))?,
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    fn register(
+        &self,
+        name: &str,
+        filter: &Arc<ExpiringBloomFilter>,
+    ) -> Result<()> {
+        self.write_filters()?
+            .insert(name.to_string(), Arc::clone(filter));
+        Ok(())
+    }
+
+    /// Open filters, copied out so no lock is held while they are awaited
+    fn open_filters(&self) -> Result<Vec<Arc<ExpiringBloomFilter>>> {
+        Ok(self.read_filters()?.values().cloned().collect())
+    }
+
+    fn read_filters(
+        &self,
+    ) -> Result<
+        std::sync::RwLockReadGuard<
+            '_,
+            BTreeMap<String, Arc<ExpiringBloomFilter>>,
+        >,
+    > {
+        self.filters.read().map_err(|_| {
+            EbloomError::LockError("Failed to read keyspace filters".to_string())
+        })
+    }
+
+    fn write_filters(
+        &self,
+    ) -> Result<
+        std::sync::RwLockWriteGuard<
+            '_,
+            BTreeMap<String, Arc<ExpiringBloomFilter>>,
+        >,
+    > {
+        self.filters.write().map_err(|_| {
+            EbloomError::LockError("Failed to write keyspace filters".to_string())
+        })
+    }
+}
+
+/// Partition name prefix of filter `name`
+fn prefix(name: &str) -> Result<String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_FILTER_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(EbloomError::InvalidConfig(format!(
+            "Filter name {name:?} must be 1 to {MAX_FILTER_NAME_LEN} ASCII letters, digits, '_', '-' or '.'"
+        )));
+    }
+    Ok(format!("f#{name}#"))
+}
@@ -0,0 +1,103 @@
+//! Per-operation latency histograms (`latency` feature)
+//!
+//! Each operation records into its own HDR histogram behind a mutex, so the
+//! feature costs a clock read and an uncontended lock per call. Summaries
+//! show up in `ExpiringStats::latency`.
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use hdrhistogram::Histogram;
+
+use crate::ebloom::stats::{LatencyOperation, OperationLatency};
+
+/// Significant figures kept by the histograms
+const SIGFIG: u8 = 3;
+
+const OPERATIONS: [LatencyOperation; 6] = [
+    LatencyOperation::Insert,
+    LatencyOperation::Contains,
+    LatencyOperation::InsertBulk,
+    LatencyOperation::ContainsBulk,
+    LatencyOperation::Rotation,
+    LatencyOperation::Snapshot,
+];
+
+/// One histogram of nanoseconds per operation
+pub(crate) struct LatencyRecorder {
+    histograms: Vec<Mutex<Histogram<u64>>>,
+}
+
+impl LatencyRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            histograms: OPERATIONS
+                .iter()
+                .map(|_| {
+                    Mutex::new(
+                        Histogram::new(SIGFIG)
+                            .expect("3 significant figures is in range"),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Time until the returned guard is dropped
+    pub(crate) fn start(&self, operation: LatencyOperation) -> LatencyTimer<'_> {
+        LatencyTimer {
+            recorder: self,
+            operation,
+            started: Instant::now(),
+        }
+    }
+
+    fn record(&self, operation: LatencyOperation, nanos: u64) {
+        // A poisoned histogram only loses samples
+        if let Ok(mut histogram) = self.histograms[operation as usize].lock() {
+            histogram.saturating_record(nanos.max(1));
+        }
+    }
+
+    /// Operations recorded at least once
+    pub(crate) fn summaries(&self) -> Vec<OperationLatency> {
+        OPERATIONS
+            .iter()
+            .filter_map(|&operation| {
+                let histogram =
+                    self.histograms[operation as usize].lock().ok()?;
+                (!histogram.is_empty()).then(|| OperationLatency {
+                    operation,
+                    count: histogram.len(),
+                    mean_ns: histogram.mean(),
+                    p50_ns: histogram.value_at_quantile(0.5),
+                    p99_ns: histogram.value_at_quantile(0.99),
+                    p999_ns: histogram.value_at_quantile(0.999),
+                    max_ns: histogram.max(),
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn reset(&self) {
+        for histogram in &self.histograms {
+            if let Ok(mut histogram) = histogram.lock() {
+                histogram.reset();
+            }
+        }
+    }
+}
+
+/// Records the elapsed time into its operation's histogram on drop
+pub(crate) struct LatencyTimer<'a> {
+    recorder: &'a LatencyRecorder,
+    operation: LatencyOperation,
+    started: Instant,
+}
+
+impl Drop for LatencyTimer<'_> {
+    fn drop(&mut self) {
+        let nanos = self.started.elapsed().as_nanos() as u64;
+        self.recorder.record(self.operation, nanos);
+    }
+}
@@ -0,0 +1,224 @@
+//! Log line deduplication over an expiring filter
+//!
+//! A flapping dependency can log the same line thousands of times a minute.
+//! [`LogDeduper`] drops lines whose fingerprint was written within the
+//! filter's window and periodically writes a `suppressed N duplicate log
+//! lines` summary instead. [`DedupWriter`] applies it to any `Write`, and
+//! [`DedupMakeWriter`] to a `tracing_subscriber::fmt` writer:
+//!
+//! ```ignore
+//! let deduper = Arc::new(LogDeduper::new(filter));
+//! tracing_subscriber::fmt()
+//!     .with_writer(DedupMakeWriter::new(deduper, std::io::stderr))
+//!     .init();
+//! ```
+//!
+//! Rotation stays with the caller: keep calling `cleanup_expired_levels`
+//! on [`LogDeduper::filter`]. A false positive drops a line that was not a
+//! duplicate, so size the filter for a low FPR.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+type FingerprintFn = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// Decides which log lines are written
+pub struct LogDeduper {
+    filter: ExpiringBloomFilter,
+    fingerprint: FingerprintFn,
+    summary_interval: Duration,
+    /// Lines dropped since the last summary
+    suppressed: AtomicU64,
+    last_summary: Mutex<Instant>,
+}
+
+impl LogDeduper {
+    /// Fingerprints lines with [`strip_leading_timestamp`] and summarizes
+    /// once a minute
+    pub fn new(filter: ExpiringBloomFilter) -> Self {
+        Self {
+            filter,
+            fingerprint: Box::new(|line| strip_leading_timestamp(line).to_vec()),
+            summary_interval: Duration::from_secs(60),
+            suppressed: AtomicU64::new(0),
+            last_summary: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Replace the fingerprint; lines with equal fingerprints are
+    /// duplicates
+    pub fn with_fingerprint<F>(mut self, fingerprint: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.fingerprint = Box::new(fingerprint);
+        self
+    }
+
+    /// Write the suppressed count at most this often
+    pub fn with_summary_interval(mut self, interval: Duration) -> Self {
+        self.summary_interval = interval;
+        self
+    }
+
+    /// Record `line` (without its newline), returning whether to write it
+    ///
+    /// Lines are written when the filter fails: losing logs is worse than
+    /// repeating them.
+    pub fn admit(&self, line: &[u8]) -> bool {
+        let key = (self.fingerprint)(line);
+        match self.filter.contains(&key) {
+            Ok(true) => {
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Ok(false) => {
+                let _ = self.filter.insert(&key);
+                true
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Lines dropped since the last summary
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// Create a writer that dedups lines into `inner`
+    pub fn writer<W: Write>(self: &Arc<Self>, inner: W) -> DedupWriter<W> {
+        DedupWriter {
+            deduper: Arc::clone(self),
+            inner,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Underlying filter, e.g. to drive rotation
+    pub fn filter(&self) -> &ExpiringBloomFilter {
+        &self.filter
+    }
+
+    /// Summary line when the interval passed and lines were dropped
+    fn take_summary(&self) -> Option<String> {
+        if self.suppressed.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut last_summary = self.last_summary.lock().ok()?;
+        if last_summary.elapsed() < self.summary_interval {
+            return None;
+        }
+        *last_summary = Instant::now();
+        let count = self.suppressed.swap(0, Ordering::Relaxed);
+        (count > 0).then(|| format!("suppressed {count} duplicate log lines\n"))
+    }
+}
+
+/// `Write` adapter passing through only lines a [`LogDeduper`] admits
+///
+/// Lines are judged once complete; a trailing partial line is judged on
+/// flush or drop.
+pub struct DedupWriter<W: Write> {
+    deduper: Arc<LogDeduper>,
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> DedupWriter<W> {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        if self.deduper.admit(content) {
+            self.inner.write_all(line)?;
+        }
+        if let Some(summary) = self.deduper.take_summary() {
+            self.inner.write_all(summary.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for DedupWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.write_line(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.write_line(&line)?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for DedupWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// `MakeWriter` wrapping every writer of `make` in a [`DedupWriter`]
+pub struct DedupMakeWriter<M> {
+    deduper: Arc<LogDeduper>,
+    make: M,
+}
+
+impl<M> DedupMakeWriter<M> {
+    pub fn new(deduper: Arc<LogDeduper>, make: M) -> Self {
+        Self { deduper, make }
+    }
+}
+
+impl<'a, M> MakeWriter<'a> for DedupMakeWriter<M>
+where
+    M: MakeWriter<'a>,
+{
+    type Writer = DedupWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.deduper.writer(self.make.make_writer())
+    }
+}
+
+/// `line` without a leading timestamp token and the ANSI styling around
+/// it, so `tracing_subscriber::fmt` lines differing only in time match
+pub fn strip_leading_timestamp(line: &[u8]) -> &[u8] {
+    let rest = skip_styling(line);
+    if !rest.first().is_some_and(u8::is_ascii_digit) {
+        return line;
+    }
+    let token_end = rest
+        .iter()
+        .position(|&b| b == b' ' || b == 0x1b)
+        .unwrap_or(rest.len());
+    skip_styling(&rest[token_end..])
+}
+
+/// Skip spaces and ANSI escape sequences (`ESC [ ... letter`)
+fn skip_styling(mut bytes: &[u8]) -> &[u8] {
+    loop {
+        match bytes {
+            [b' ', rest @ ..] => bytes = rest,
+            [0x1b, b'[', rest @ ..] => {
+                let end = rest
+                    .iter()
+                    .position(u8::is_ascii_alphabetic)
+                    .map_or(rest.len(), |i| i + 1);
+                bytes = &rest[end..];
+            }
+            _ => return bytes,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Filter metrics reported through the [`metrics`](https://docs.rs/metrics)
+//! facade
+//!
+//! Nothing is exported by the crate itself: install a recorder such as
+//! `metrics-exporter-prometheus` and the values below show up under these
+//! names. Level densities are refreshed on rotation and snapshot, not on
+//! every insert.
+
+use metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge,
+    histogram,
+};
+
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::config::RotationReason;
+use crate::ebloom::drift::FprSample;
+use crate::ebloom::events::SnapshotEvent;
+
+pub const INSERTS: &str = "ebloom_inserts_total";
+pub const QUERIES: &str = "ebloom_queries_total";
+/// Queries answered "present", i.e. probable duplicates
+pub const PROBABLE_DUPLICATES: &str = "ebloom_probable_duplicates_total";
+pub const ROTATIONS: &str = "ebloom_rotations_total";
+pub const SNAPSHOT_DURATION: &str = "ebloom_snapshot_duration_seconds";
+pub const SNAPSHOT_BYTES: &str = "ebloom_snapshot_bytes_total";
+pub const SNAPSHOT_CHUNKS: &str = "ebloom_snapshot_chunks_total";
+pub const LEVEL_FILL_RATIO: &str = "ebloom_level_fill_ratio";
+pub const CURRENT_LEVEL: &str = "ebloom_current_level";
+pub const SATURATION_WARNINGS: &str = "ebloom_saturation_warnings_total";
+pub const ESTIMATED_FPR: &str = "ebloom_estimated_fpr";
+pub const DUPLICATE_RATE: &str = "ebloom_probable_duplicate_rate";
+
+/// Register descriptions and units with the installed recorder
+///
+/// Optional; call once after installing the recorder.
+pub fn describe_metrics() {
+    describe_counter!(INSERTS, Unit::Count, "Items inserted");
+    describe_counter!(QUERIES, Unit::Count, "Membership queries");
+    describe_counter!(
+        PROBABLE_DUPLICATES,
+        Unit::Count,
+        "Queries that found the item in some level"
+    );
+    describe_counter!(ROTATIONS, Unit::Count, "Level rotations, by reason");
+    describe_histogram!(
+        SNAPSHOT_DURATION,
+        Unit::Seconds,
+        "Time to write a snapshot, by kind"
+    );
+    describe_counter!(
+        SNAPSHOT_BYTES,
+        Unit::Bytes,
+        "Chunk bytes persisted, by kind"
+    );
+    describe_counter!(SNAPSHOT_CHUNKS, Unit::Count, "Chunks persisted, by kind");
+    describe_gauge!(LEVEL_FILL_RATIO, "Fraction of bits set, by level");
+    describe_gauge!(CURRENT_LEVEL, "Index of the level receiving inserts");
+    describe_counter!(
+        SATURATION_WARNINGS,
+        Unit::Count,
+        "Levels that crossed the saturation warning threshold"
+    );
+    describe_gauge!(
+        ESTIMATED_FPR,
+        "False positive rate expected from bit density, at the last sample"
+    );
+    describe_gauge!(
+        DUPLICATE_RATE,
+        "Share of inserts already present, between the last two samples"
+    );
+}
+
+pub(crate) fn record_inserts(count: usize) {
+    counter!(INSERTS).increment(count as u64);
+}
+
+pub(crate) fn record_queries(count: usize, hits: usize) {
+    counter!(QUERIES).increment(count as u64);
+    if hits > 0 {
+        counter!(PROBABLE_DUPLICATES).increment(hits as u64);
+    }
+}
+
+pub(crate) fn record_rotation(reason: RotationReason, current_level: usize) {
+    counter!(ROTATIONS, "reason" => reason.as_str()).increment(1);
+    gauge!(CURRENT_LEVEL).set(current_level as f64);
+}
+
+/// Snapshot kinds are labelled `full` (on rotation) or `incremental`
+pub(crate) fn record_snapshot(event: &SnapshotEvent) {
+    let kind = if event.full { "full" } else { "incremental" };
+    histogram!(SNAPSHOT_DURATION, "kind" => kind)
+        .record(event.duration.as_secs_f64());
+    counter!(SNAPSHOT_BYTES, "kind" => kind).increment(event.bytes as u64);
+    counter!(SNAPSHOT_CHUNKS, "kind" => kind).increment(event.chunks as u64);
+}
+
+pub(crate) fn record_level_density(levels: &[AtomicBitVec]) {
+    for (level_idx, level) in levels.iter().enumerate() {
+        gauge!(LEVEL_FILL_RATIO, "level" => level_idx.to_string())
+            .set(level.fill_ratio());
+    }
+}
+
+pub(crate) fn record_saturation_warning(level: usize) {
+    counter!(SATURATION_WARNINGS, "level" => level.to_string()).increment(1);
+}
+
+pub(crate) fn record_fpr_sample(sample: &FprSample) {
+    gauge!(ESTIMATED_FPR).set(sample.estimated_fpr);
+    gauge!(DUPLICATE_RATE).set(sample.duplicate_rate);
+}
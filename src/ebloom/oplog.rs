@@ -0,0 +1,172 @@
+//! Append-only insert log for followers
+//!
+//! `ExpiringBloomFilter::replication_log` attaches a [`ReplicationLog`] to
+//! a leader filter. Every insert appends an entry with a sequence number,
+//! the insert time and the bit indices of the key, never the key itself.
+//! Followers tail the log with [`ReplicationLog::read_from`], ship the
+//! batches however they like (`OpLogBatch::to_bytes`) and replay them with
+//! `ExpiringBloomFilter::apply_oplog`, which places each entry in the
+//! level that was current at its timestamp. A follower can thus be kept
+//! warm, or rebuilt from a snapshot plus the entries since, without
+//! re-reading the source event stream.
+//!
+//! The log is kept in memory up to `max_entries`; a follower that falls
+//! further behind gets an error and has to resync from a snapshot. Both
+//! sides must share the bit layout, which batches carry as a fingerprint.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::clock::Clock;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::events::FilterObserver;
+use crate::hash::HashFunction;
+
+/// One logged insert
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Decode, Encode)]
+pub struct OpLogEntry {
+    pub seq: u64,
+    /// Leader clock at insert time (ms)
+    pub timestamp_ms: u64,
+    /// Bit indices of the key
+    pub indices: Vec<u64>,
+}
+
+/// Consecutive entries read from a [`ReplicationLog`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Decode, Encode)]
+pub struct OpLogBatch {
+    pub layout_fingerprint: u64,
+    pub entries: Vec<OpLogEntry>,
+    /// Sequence number to read from next
+    pub next_seq: u64,
+}
+
+impl OpLogBatch {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(batch, _)| batch)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+}
+
+/// Bounded in-memory log of a filter's inserts
+pub struct ReplicationLog {
+    layout_fingerprint: u64,
+    hash_fn: HashFunction,
+    num_hashes: usize,
+    bit_vector_size: usize,
+    clock: Arc<dyn Clock>,
+    max_entries: usize,
+    state: Mutex<LogState>,
+}
+
+struct LogState {
+    entries: VecDeque<OpLogEntry>,
+    next_seq: u64,
+}
+
+impl ReplicationLog {
+    pub(crate) fn new(
+        layout_fingerprint: u64,
+        hash_fn: HashFunction,
+        num_hashes: usize,
+        bit_vector_size: usize,
+        clock: Arc<dyn Clock>,
+        max_entries: usize,
+    ) -> Self {
+        Self {
+            layout_fingerprint,
+            hash_fn,
+            num_hashes,
+            bit_vector_size,
+            clock,
+            max_entries,
+            state: Mutex::new(LogState {
+                entries: VecDeque::new(),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// Up to `max` entries starting at `seq`
+    ///
+    /// Fails if entries from `seq` on were already trimmed; the follower
+    /// then needs a fresh snapshot.
+    pub fn read_from(&self, seq: u64, max: usize) -> Result<OpLogBatch> {
+        let state = self.lock_state()?;
+        let oldest = state.next_seq - state.entries.len() as u64;
+        if seq < oldest {
+            return Err(EbloomError::InvalidConfig(format!(
+                "Replication log was trimmed past {seq}, oldest entry is {oldest}"
+            )));
+        }
+        let entries: Vec<OpLogEntry> = state
+            .entries
+            .iter()
+            .skip((seq - oldest) as usize)
+            .take(max)
+            .cloned()
+            .collect();
+        let next_seq = entries.last().map_or(seq.max(oldest), |e| e.seq + 1);
+        Ok(OpLogBatch {
+            layout_fingerprint: self.layout_fingerprint,
+            entries,
+            next_seq,
+        })
+    }
+
+    /// Sequence number the next insert will get
+    pub fn next_seq(&self) -> Result<u64> {
+        Ok(self.lock_state()?.next_seq)
+    }
+
+    /// Sequence number of the oldest retained entry
+    pub fn oldest_seq(&self) -> Result<u64> {
+        let state = self.lock_state()?;
+        Ok(state.next_seq - state.entries.len() as u64)
+    }
+
+    fn lock_state(&self) -> Result<std::sync::MutexGuard<'_, LogState>> {
+        self.state.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock replication log".to_string())
+        })
+    }
+}
+
+impl FilterObserver for ReplicationLog {
+    fn on_insert(&self, item: &[u8], _level: usize) {
+        let timestamp_ms = match self.clock.now_ms() {
+            Ok(now_ms) => now_ms,
+            Err(e) => {
+                tracing::warn!(error = %e, "Replication log skipped an insert");
+                return;
+            }
+        };
+        let indices = (self.hash_fn)(item, self.num_hashes, self.bit_vector_size)
+            .into_iter()
+            .map(|idx| idx as u64)
+            .collect();
+        let Ok(mut state) = self.state.lock() else {
+            tracing::warn!("Replication log skipped an insert");
+            return;
+        };
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.entries.push_back(OpLogEntry {
+            seq,
+            timestamp_ms,
+            indices,
+        });
+        if state.entries.len() > self.max_entries {
+            state.entries.pop_front();
+        }
+    }
+}
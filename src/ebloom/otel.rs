@@ -0,0 +1,240 @@
+//! OpenTelemetry export (`otel` feature)
+//!
+//! [`OtelObserver`] records filter metrics through an OpenTelemetry meter,
+//! tagged with the filter name and backend type. Spans come from the
+//! `tracing` instrumentation on rotation, cleanup, snapshot and load, and
+//! reach the collector through the `tracing-opentelemetry` layer.
+//! [`init_otlp`] wires both to an OTLP endpoint for the common case; set
+//! the usual `OTEL_EXPORTER_OTLP_*` variables to point it elsewhere.
+
+use std::sync::Arc;
+
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge, Histogram, Meter},
+    trace::TracerProvider as _,
+};
+use opentelemetry_otlp::{MetricExporter, SpanExporter};
+use opentelemetry_sdk::{
+    Resource,
+    metrics::{PeriodicReader, SdkMeterProvider},
+    runtime,
+    trace::TracerProvider,
+};
+use tracing_subscriber::{
+    EnvFilter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::ebloom::drift::FprSample;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::events::{
+    FilterObserver, RotationEvent, SaturationWarning, SnapshotEvent,
+};
+use crate::ebloom::filter::ExpiringBloomFilter;
+
+/// Instrumentation scope of the meter and tracer
+pub const SCOPE: &str = "probabilistic-rs";
+/// Resource and metric attribute carrying the filter name
+pub const FILTER_NAME: &str = "ebloom.filter.name";
+/// Resource and metric attribute carrying the backend type
+pub const FILTER_BACKEND: &str = "ebloom.filter.backend";
+
+/// Default resource extended with the filter name and backend type
+pub fn resource(filter_name: &str, backend: &str) -> Resource {
+    Resource::default().merge(&Resource::new([
+        KeyValue::new(FILTER_NAME, filter_name.to_string()),
+        KeyValue::new(FILTER_BACKEND, backend.to_string()),
+    ]))
+}
+
+/// Filter observer recording OpenTelemetry metrics
+///
+/// Instrument names match the `metrics` feature so dashboards carry over.
+pub struct OtelObserver {
+    attributes: Vec<KeyValue>,
+    inserts: Counter<u64>,
+    rotations: Counter<u64>,
+    snapshot_duration: Histogram<f64>,
+    snapshot_bytes: Counter<u64>,
+    snapshot_chunks: Counter<u64>,
+    saturation_warnings: Counter<u64>,
+    estimated_fpr: Gauge<f64>,
+    duplicate_rate: Gauge<f64>,
+}
+
+impl OtelObserver {
+    pub fn new(meter: &Meter, filter_name: &str, backend: &str) -> Self {
+        Self {
+            attributes: vec![
+                KeyValue::new(FILTER_NAME, filter_name.to_string()),
+                KeyValue::new(FILTER_BACKEND, backend.to_string()),
+            ],
+            inserts: meter
+                .u64_counter("ebloom_inserts_total")
+                .with_description("Items inserted")
+                .build(),
+            rotations: meter
+                .u64_counter("ebloom_rotations_total")
+                .with_description("Level rotations")
+                .build(),
+            snapshot_duration: meter
+                .f64_histogram("ebloom_snapshot_duration_seconds")
+                .with_unit("s")
+                .with_description("Time to write a snapshot")
+                .build(),
+            snapshot_bytes: meter
+                .u64_counter("ebloom_snapshot_bytes_total")
+                .with_unit("By")
+                .with_description("Chunk bytes written by snapshots")
+                .build(),
+            snapshot_chunks: meter
+                .u64_counter("ebloom_snapshot_chunks_total")
+                .with_description("Chunks written by snapshots")
+                .build(),
+            saturation_warnings: meter
+                .u64_counter("ebloom_saturation_warnings_total")
+                .with_description(
+                    "Levels that crossed the saturation warning threshold",
+                )
+                .build(),
+            estimated_fpr: meter
+                .f64_gauge("ebloom_estimated_fpr")
+                .with_description(
+                    "Estimated false positive rate at the last drift alert",
+                )
+                .build(),
+            duplicate_rate: meter
+                .f64_gauge("ebloom_probable_duplicate_rate")
+                .with_description(
+                    "Share of inserts already present at the last drift alert",
+                )
+                .build(),
+        }
+    }
+
+    /// Register an observer on `filter` using the global meter provider
+    pub fn attach(
+        filter: &ExpiringBloomFilter,
+        filter_name: &str,
+    ) -> Result<Arc<Self>> {
+        let meter = global::meter(SCOPE);
+        let observer =
+            Arc::new(Self::new(&meter, filter_name, filter.backend_kind()));
+        filter.add_observer(observer.clone())?;
+        Ok(observer)
+    }
+
+    fn attributes_with(
+        &self,
+        key: &'static str,
+        value: &'static str,
+    ) -> Vec<KeyValue> {
+        let mut attributes = self.attributes.clone();
+        attributes.push(KeyValue::new(key, value));
+        attributes
+    }
+}
+
+impl FilterObserver for OtelObserver {
+    fn on_insert(&self, _item: &[u8], _level: usize) {
+        self.inserts.add(1, &self.attributes);
+    }
+
+    fn on_rotate(&self, event: &RotationEvent) {
+        self.rotations
+            .add(1, &self.attributes_with("reason", event.reason.as_str()));
+    }
+
+    fn on_snapshot(&self, event: &SnapshotEvent) {
+        let kind = if event.full { "full" } else { "incremental" };
+        let attributes = self.attributes_with("kind", kind);
+        self.snapshot_duration
+            .record(event.duration.as_secs_f64(), &attributes);
+        self.snapshot_bytes.add(event.bytes as u64, &attributes);
+        self.snapshot_chunks.add(event.chunks as u64, &attributes);
+    }
+
+    fn on_saturation_warning(&self, _warning: &SaturationWarning) {
+        self.saturation_warnings.add(1, &self.attributes);
+    }
+
+    fn on_fpr_drift(&self, sample: &FprSample, _target_fpr: f64) {
+        self.estimated_fpr
+            .record(sample.estimated_fpr, &self.attributes);
+        self.duplicate_rate
+            .record(sample.duplicate_rate, &self.attributes);
+    }
+}
+
+/// Flushes and shuts down the exporters installed by [`init_otlp`] on drop
+pub struct OtelGuard {
+    tracer_provider: TracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            tracing::warn!(
+                error = %e,
+                "Failed to shut down OpenTelemetry tracer"
+            );
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            tracing::warn!(
+                error = %e,
+                "Failed to shut down OpenTelemetry meter"
+            );
+        }
+    }
+}
+
+/// Export spans and metrics over OTLP, alongside plain `tracing` output
+///
+/// Installs the global meter provider and a `tracing` subscriber with an
+/// `RUST_LOG` filter, a fmt layer and the OpenTelemetry layer, then attaches
+/// an [`OtelObserver`] to `filter`. Must run inside a Tokio runtime; keep
+/// the guard alive for as long as telemetry should flow.
+pub fn init_otlp(
+    filter: &ExpiringBloomFilter,
+    filter_name: &str,
+) -> Result<OtelGuard> {
+    let resource = resource(filter_name, filter.backend_kind());
+
+    let span_exporter = SpanExporter::builder()
+        .with_tonic()
+        .build()
+        .map_err(|e| EbloomError::ConfigError(e.to_string()))?;
+    let tracer_provider = TracerProvider::builder()
+        .with_batch_exporter(span_exporter, runtime::Tokio)
+        .with_resource(resource.clone())
+        .build();
+
+    let metric_exporter = MetricExporter::builder()
+        .with_tonic()
+        .build()
+        .map_err(|e| EbloomError::ConfigError(e.to_string()))?;
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(
+            PeriodicReader::builder(metric_exporter, runtime::Tokio).build(),
+        )
+        .with_resource(resource)
+        .build();
+    global::set_meter_provider(meter_provider.clone());
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer())
+        .with(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer_provider.tracer(SCOPE)),
+        )
+        .try_init()
+        .map_err(|e| EbloomError::ConfigError(e.to_string()))?;
+
+    OtelObserver::attach(filter, filter_name)?;
+    Ok(OtelGuard {
+        tracer_provider,
+        meter_provider,
+    })
+}
@@ -0,0 +1,242 @@
+//! Approximate per-key rate limiting over an expiring filter
+//!
+//! A bloom filter cannot count, but it can hold numbered copies of a key:
+//! the `n`-th hit of `key` is stored as `key ‖ n`, and the hits within the
+//! filter's window are the copies still present. As levels expire, their
+//! copies drop out and the budget refills, so the window slides with the
+//! level duration. False positives only ever overcount, so the limiter
+//! errs on the side of rejecting.
+//!
+//! Every hit is one item: size `capacity_per_level` for the hits per level,
+//! not the keys. A check costs `limit` lookups, which suits limits in the
+//! tens to hundreds.
+//!
+//! With the `tower` feature, [`RateLimitLayer`] applies the limiter to
+//! HTTP services.
+
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::{BulkExpiringBloomFilterOps, ExpiringBloomFilterOps};
+
+/// Outcome of one [`ApproxRateLimiter::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateDecision {
+    pub allowed: bool,
+    /// Configured hits per window
+    pub limit: u32,
+    /// Estimated hits left in the window after this one
+    pub remaining: u32,
+}
+
+/// Allows at most `limit` hits per key within the filter's window
+pub struct ApproxRateLimiter {
+    filter: ExpiringBloomFilter,
+    limit: u32,
+}
+
+impl ApproxRateLimiter {
+    pub fn new(filter: ExpiringBloomFilter, limit: u32) -> Result<Self> {
+        if limit == 0 {
+            return Err(EbloomError::InvalidConfig(
+                "Rate limit must be greater than 0".to_string(),
+            ));
+        }
+        Ok(Self { filter, limit })
+    }
+
+    /// Count a hit for `key` unless its budget is spent
+    ///
+    /// Counting and recording are separate steps, so concurrent hits on the
+    /// same key may each see the same remaining budget.
+    pub fn check(&self, key: &[u8]) -> Result<RateDecision> {
+        let slots: Vec<Vec<u8>> =
+            (0..self.limit).map(|n| slot_key(key, n)).collect();
+        let slot_refs: Vec<&[u8]> = slots.iter().map(Vec::as_slice).collect();
+        let present = self.filter.contains_bulk(&slot_refs)?;
+
+        let used = present.iter().filter(|&&hit| hit).count() as u32;
+        let Some(free) = present.iter().position(|&hit| !hit) else {
+            return Ok(RateDecision {
+                allowed: false,
+                limit: self.limit,
+                remaining: 0,
+            });
+        };
+        self.filter.insert(slot_refs[free])?;
+        Ok(RateDecision {
+            allowed: true,
+            limit: self.limit,
+            remaining: self.limit - used - 1,
+        })
+    }
+
+    /// Estimated hits left for `key`, without counting one
+    pub fn remaining(&self, key: &[u8]) -> Result<u32> {
+        let slots: Vec<Vec<u8>> =
+            (0..self.limit).map(|n| slot_key(key, n)).collect();
+        let slot_refs: Vec<&[u8]> = slots.iter().map(Vec::as_slice).collect();
+        let used = self
+            .filter
+            .contains_bulk(&slot_refs)?
+            .into_iter()
+            .filter(|&hit| hit)
+            .count() as u32;
+        Ok(self.limit - used)
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Underlying filter, e.g. to drive rotation
+    pub fn filter(&self) -> &ExpiringBloomFilter {
+        &self.filter
+    }
+}
+
+/// `key ‖ n`; the fixed-width suffix keeps keys of different lengths apart
+fn slot_key(key: &[u8], n: u32) -> Vec<u8> {
+    let mut slot = Vec::with_capacity(key.len() + 4);
+    slot.extend_from_slice(key);
+    slot.extend_from_slice(&n.to_le_bytes());
+    slot
+}
+
+#[cfg(feature = "tower")]
+pub use layer::{LIMIT_HEADER, REMAINING_HEADER, RateLimit, RateLimitLayer};
+
+#[cfg(feature = "tower")]
+mod layer {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use http::{HeaderValue, Request, Response, StatusCode};
+    use tower::{Layer, Service};
+
+    use super::{ApproxRateLimiter, RateDecision};
+
+    /// Hits allowed per window
+    pub const LIMIT_HEADER: &str = "x-ratelimit-limit";
+    /// Estimated hits left in the window
+    pub const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+    type KeyFn<B> = Arc<dyn Fn(&Request<B>) -> Option<Vec<u8>> + Send + Sync>;
+
+    /// Rejects requests over the per-key budget with `429 Too Many Requests`
+    ///
+    /// `key` picks the key of a request, e.g. a client address or API
+    /// token; requests without one are not limited. Both accepted and
+    /// rejected responses carry the limit and remaining budget headers.
+    /// If the filter fails, the request is let through.
+    pub struct RateLimitLayer<B> {
+        limiter: Arc<ApproxRateLimiter>,
+        key: KeyFn<B>,
+    }
+
+    impl<B> RateLimitLayer<B> {
+        pub fn new<K>(limiter: Arc<ApproxRateLimiter>, key: K) -> Self
+        where
+            K: Fn(&Request<B>) -> Option<Vec<u8>> + Send + Sync + 'static,
+        {
+            Self {
+                limiter,
+                key: Arc::new(key),
+            }
+        }
+    }
+
+    impl<B> Clone for RateLimitLayer<B> {
+        fn clone(&self) -> Self {
+            Self {
+                limiter: Arc::clone(&self.limiter),
+                key: Arc::clone(&self.key),
+            }
+        }
+    }
+
+    impl<S, B> Layer<S> for RateLimitLayer<B> {
+        type Service = RateLimit<S, B>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            RateLimit {
+                inner,
+                limiter: Arc::clone(&self.limiter),
+                key: Arc::clone(&self.key),
+            }
+        }
+    }
+
+    /// Service produced by [`RateLimitLayer`]
+    pub struct RateLimit<S, B> {
+        inner: S,
+        limiter: Arc<ApproxRateLimiter>,
+        key: KeyFn<B>,
+    }
+
+    impl<S: Clone, B> Clone for RateLimit<S, B> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+                limiter: Arc::clone(&self.limiter),
+                key: Arc::clone(&self.key),
+            }
+        }
+    }
+
+    impl<S, B, ResBody> Service<Request<B>> for RateLimit<S, B>
+    where
+        S: Service<Request<B>, Response = Response<ResBody>>,
+        S::Future: Send + 'static,
+        ResBody: Default + Send + 'static,
+    {
+        type Response = Response<ResBody>;
+        type Error = S::Error;
+        type Future = Pin<
+            Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>,
+        >;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: Request<B>) -> Self::Future {
+            let decision = (self.key)(&request).and_then(|key| {
+                self.limiter
+                    .check(&key)
+                    .inspect_err(|e| {
+                        tracing::warn!(error = %e, "Rate limiter check failed");
+                    })
+                    .ok()
+            });
+            match decision {
+                Some(decision) if !decision.allowed => {
+                    let mut response = Response::new(ResBody::default());
+                    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    set_headers(&mut response, decision);
+                    Box::pin(async move { Ok(response) })
+                }
+                _ => {
+                    let future = self.inner.call(request);
+                    Box::pin(async move {
+                        let mut response = future.await?;
+                        if let Some(decision) = decision {
+                            set_headers(&mut response, decision);
+                        }
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+
+    fn set_headers<T>(response: &mut Response<T>, decision: RateDecision) {
+        let headers = response.headers_mut();
+        headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+        headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    }
+}
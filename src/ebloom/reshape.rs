@@ -0,0 +1,262 @@
+//! Changing the parameters of a live expiring filter
+//!
+//! Capacity, false positive rate and level count fix a filter's bit layout,
+//! so they cannot change in place. [`ReshapingFilter`] wraps a filter and,
+//! on [`ReshapingFilter::begin_reshape`], starts a new filter with the new
+//! config: inserts go to it from then on, while the old filter keeps
+//! answering queries until every window it covers has passed, and is then
+//! dropped. Nothing is copied, and no item is forgotten before it would
+//! have expired in the old filter. Only filters rotating on time can be
+//! replaced, as the old filter's windows are then known in advance:
+//!
+//! ```ignore
+//! let filter = ReshapingFilter::new(ExpiringBloomFilter::new(config)?);
+//! // Traffic doubled; size for it without a restart
+//! filter.begin_reshape(bigger_config).await?;
+//! loop {
+//!     filter.cleanup_expired_levels().await?; // retires the old filter
+//!     tokio::time::sleep(Duration::from_secs(10)).await;
+//! }
+//! ```
+
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+
+use crate::ebloom::config::{ExpiringFilterConfig, RotationPolicy};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::{BulkExpiringBloomFilterOps, ExpiringBloomFilterOps};
+
+/// Filter replaced by a reshape, queried until `retire_at`
+struct Retiring {
+    filter: Arc<ExpiringBloomFilter>,
+    /// End of the last window the filter may still hold items for (ms)
+    retire_at: u64,
+}
+
+/// Expiring filter whose config can be changed while it serves traffic
+pub struct ReshapingFilter {
+    current: RwLock<Arc<ExpiringBloomFilter>>,
+    /// Oldest first
+    retiring: RwLock<Vec<Retiring>>,
+}
+
+impl ReshapingFilter {
+    pub fn new(filter: ExpiringBloomFilter) -> Self {
+        Self::from(Arc::new(filter))
+    }
+
+    /// Filter taking inserts
+    pub fn current(&self) -> Result<Arc<ExpiringBloomFilter>> {
+        let current = self.read_current()?;
+        Ok(Arc::clone(&current))
+    }
+
+    /// Whether replaced filters are still answering queries
+    pub fn is_reshaping(&self) -> Result<bool> {
+        Ok(!self.read_retiring()?.is_empty())
+    }
+
+    /// Time (ms) the last replaced filter retires, if any is left
+    pub fn reshape_done_at(&self) -> Result<Option<u64>> {
+        Ok(self.read_retiring()?.last().map(|r| r.retire_at))
+    }
+
+    /// Send inserts to a new filter built from `config`
+    ///
+    /// The new filter shares the current one's clock. A persistent config
+    /// must use a database path of its own; the old database is left on
+    /// disk once its filter retires. Reshaping again before the previous
+    /// reshape is done keeps every replaced filter until its own windows
+    /// have passed.
+    ///
+    /// Fails if the current filter does not use `RotationPolicy::Time`:
+    /// under an insert count its levels rotate with its inserts, which stop
+    /// once the new filter takes them, so no retirement time can be given.
+    pub async fn begin_reshape(
+        &self,
+        config: ExpiringFilterConfig,
+    ) -> Result<()> {
+        config.validate()?;
+        let old = self.current()?;
+        if !matches!(old.config().rotation_policy, RotationPolicy::Time) {
+            return Err(EbloomError::InvalidConfig(format!(
+                "Cannot reshape a filter rotating on {:?}; only time-based \
+                 rotation has a known retirement time",
+                old.config().rotation_policy
+            )));
+        }
+        if let Some(ref pers) = config.persistence {
+            let retiring = self.retiring_filters()?;
+            let in_use = std::iter::once(&old).chain(&retiring).any(|f| {
+                f.config()
+                    .persistence
+                    .as_ref()
+                    .is_some_and(|p| p.db_path == pers.db_path)
+            });
+            if in_use {
+                return Err(EbloomError::InvalidConfig(format!(
+                    "{:?} is still used by a filter being reshaped",
+                    pers.db_path
+                )));
+            }
+        }
+
+        let new = Arc::new(
+            ExpiringBloomFilter::create_with_clock(config, old.clock()).await?,
+        );
+
+        let mut current = self.write_current()?;
+        let retire_at = retire_at(&current)?;
+        let replaced = std::mem::replace(&mut *current, new);
+        self.write_retiring()?.push(Retiring {
+            filter: replaced,
+            retire_at,
+        });
+        Ok(())
+    }
+
+    /// Drop replaced filters whose windows have all passed; returns how
+    /// many were dropped
+    pub fn retire_expired(&self) -> Result<usize> {
+        let now_ms = self.read_current()?.clock().now_ms()?;
+        let mut retiring = self.write_retiring()?;
+        let before = retiring.len();
+        retiring.retain(|r| r.retire_at > now_ms);
+        Ok(before - retiring.len())
+    }
+
+    /// Save the dirty chunks of the current and replaced filters
+    pub async fn save_snapshot(&self) -> Result<()> {
+        for filter in self.all_filters()? {
+            filter.save_snapshot().await?;
+        }
+        Ok(())
+    }
+
+    /// Make deferred writes of the current and replaced filters durable
+    pub async fn flush(&self) -> Result<()> {
+        for filter in self.all_filters()? {
+            filter.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Replaced filters, copied out so no lock is held while they are
+    /// awaited
+    fn retiring_filters(&self) -> Result<Vec<Arc<ExpiringBloomFilter>>> {
+        Ok(self
+            .read_retiring()?
+            .iter()
+            .map(|r| Arc::clone(&r.filter))
+            .collect())
+    }
+
+    /// Current filter first, then the replaced ones
+    fn all_filters(&self) -> Result<Vec<Arc<ExpiringBloomFilter>>> {
+        let mut filters = vec![self.current()?];
+        filters.extend(self.retiring_filters()?);
+        Ok(filters)
+    }
+
+    fn read_current(
+        &self,
+    ) -> Result<std::sync::RwLockReadGuard<'_, Arc<ExpiringBloomFilter>>> {
+        self.current.read().map_err(|_| {
+            EbloomError::LockError("Failed to read current filter".to_string())
+        })
+    }
+
+    fn write_current(
+        &self,
+    ) -> Result<std::sync::RwLockWriteGuard<'_, Arc<ExpiringBloomFilter>>> {
+        self.current.write().map_err(|_| {
+            EbloomError::LockError("Failed to write current filter".to_string())
+        })
+    }
+
+    fn read_retiring(
+        &self,
+    ) -> Result<std::sync::RwLockReadGuard<'_, Vec<Retiring>>> {
+        self.retiring.read().map_err(|_| {
+            EbloomError::LockError("Failed to read retiring filters".to_string())
+        })
+    }
+
+    fn write_retiring(
+        &self,
+    ) -> Result<std::sync::RwLockWriteGuard<'_, Vec<Retiring>>> {
+        self.retiring.write().map_err(|_| {
+            EbloomError::LockError("Failed to write retiring filters".to_string())
+        })
+    }
+}
+
+/// When a filter replaced now holds nothing anymore: an item inserted just
+/// before lives at most a level lifetime
+fn retire_at(filter: &ExpiringBloomFilter) -> Result<u64> {
+    let lifetime = filter.config().level_lifetime().as_millis() as u64;
+    Ok(filter.clock().now_ms()?.saturating_add(lifetime))
+}
+
+impl From<Arc<ExpiringBloomFilter>> for ReshapingFilter {
+    fn from(filter: Arc<ExpiringBloomFilter>) -> Self {
+        Self {
+            current: RwLock::new(filter),
+            retiring: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl ExpiringBloomFilterOps for ReshapingFilter {
+    fn insert(&self, item: &[u8]) -> Result<()> {
+        self.read_current()?.insert(item)
+    }
+
+    fn contains(&self, item: &[u8]) -> Result<bool> {
+        if self.read_current()?.contains(item)? {
+            return Ok(true);
+        }
+        for retiring in self.read_retiring()?.iter() {
+            if retiring.filter.contains(item)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Clear the current filter and drop the replaced ones
+    fn clear(&self) -> Result<()> {
+        self.write_retiring()?.clear();
+        ExpiringBloomFilterOps::clear(self.read_current()?.as_ref())
+    }
+
+    /// Rotate expired levels of every filter, then retire replaced filters
+    /// that no longer hold anything
+    async fn cleanup_expired_levels(&self) -> Result<()> {
+        for filter in self.all_filters()? {
+            filter.cleanup_expired_levels().await?;
+        }
+        self.retire_expired().map(|_| ())
+    }
+}
+
+impl BulkExpiringBloomFilterOps for ReshapingFilter {
+    fn insert_bulk(&self, items: &[&[u8]]) -> Result<()> {
+        self.read_current()?.insert_bulk(items)
+    }
+
+    fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>> {
+        let mut found = self.read_current()?.contains_bulk(items)?;
+        for retiring in self.read_retiring()?.iter() {
+            if found.iter().all(|&hit| hit) {
+                break;
+            }
+            let hits = retiring.filter.contains_bulk(items)?;
+            found.iter_mut().zip(hits).for_each(|(f, hit)| *f |= hit);
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,158 @@
+//! Consistent-hash routing over named filters
+//!
+//! [`FilterRing`] places every filter on a hash ring at `virtual_nodes`
+//! points and sends a key to the first `replication_factor` distinct
+//! filters clockwise from the key's hash. Adding or removing a filter only
+//! moves the keys between it and its ring neighbours, so a keyspace spread
+//! over several persisted stores can grow without rehashing everything.
+//!
+//! Inserts go to every replica; a lookup is positive if any replica has
+//! the key, so a replica that missed writes (e.g. a store restored from an
+//! older snapshot) never causes a false negative while others have it.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+use crate::hash::hash_murmur64;
+
+#[derive(Debug, Clone, Builder, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct RingConfig {
+    /// Filters each key is stored in
+    #[builder(default = "1")]
+    pub replication_factor: usize,
+    /// Ring points per filter; more points spread keys more evenly
+    #[builder(default = "128")]
+    pub virtual_nodes: usize,
+}
+
+/// Routes keys to named filters by consistent hashing
+pub struct FilterRing<F = ExpiringBloomFilter> {
+    config: RingConfig,
+    filters: BTreeMap<String, Arc<F>>,
+    /// `(point, filter name)`, sorted by point
+    points: Vec<(u64, String)>,
+}
+
+impl<F: ExpiringBloomFilterOps> FilterRing<F> {
+    pub fn new(config: RingConfig) -> Result<Self> {
+        if config.replication_factor == 0 || config.virtual_nodes == 0 {
+            return Err(EbloomError::InvalidConfig(
+                "Replication factor and virtual nodes must be greater than 0"
+                    .to_string(),
+            ));
+        }
+        Ok(Self {
+            config,
+            filters: BTreeMap::new(),
+            points: Vec::new(),
+        })
+    }
+
+    /// Add a filter, replacing one with the same name
+    pub fn add(&mut self, name: impl Into<String>, filter: Arc<F>) {
+        let name = name.into();
+        self.points.retain(|(_, point_name)| *point_name != name);
+        self.points.extend(
+            (0..self.config.virtual_nodes)
+                .map(|vnode| (point_hash(&name, vnode), name.clone())),
+        );
+        self.points.sort_unstable();
+        self.filters.insert(name, filter);
+    }
+
+    /// Take a filter off the ring; its keys move to the next filters
+    pub fn remove(&mut self, name: &str) -> Option<Arc<F>> {
+        self.points.retain(|(_, point_name)| point_name != name);
+        self.filters.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<F>> {
+        self.filters.get(name)
+    }
+
+    /// Names of the filters on the ring, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.filters.keys().map(String::as_str)
+    }
+
+    /// Filters `key` is stored in, primary first
+    ///
+    /// Fewer than `replication_factor` when the ring has fewer filters.
+    pub fn replicas_for(&self, key: &[u8]) -> Vec<&str> {
+        let wanted = self.config.replication_factor.min(self.filters.len());
+        let mut replicas: Vec<&str> = Vec::with_capacity(wanted);
+        if wanted == 0 {
+            return replicas;
+        }
+        let hash = hash_murmur64(key);
+        let start = self.points.partition_point(|(point, _)| *point < hash);
+        let clockwise = self.points[start..].iter().chain(&self.points[..start]);
+        for (_, name) in clockwise {
+            if !replicas.contains(&name.as_str()) {
+                replicas.push(name);
+                if replicas.len() == wanted {
+                    break;
+                }
+            }
+        }
+        replicas
+    }
+
+    fn replica_filters(&self, key: &[u8]) -> Result<Vec<&Arc<F>>> {
+        let replicas: Vec<&Arc<F>> = self
+            .replicas_for(key)
+            .into_iter()
+            .filter_map(|name| self.filters.get(name))
+            .collect();
+        if replicas.is_empty() {
+            return Err(EbloomError::InvalidConfig(
+                "Filter ring has no filters".to_string(),
+            ));
+        }
+        Ok(replicas)
+    }
+}
+
+fn point_hash(name: &str, vnode: usize) -> u64 {
+    hash_murmur64(format!("{name}#{vnode}").as_bytes())
+}
+
+#[async_trait]
+impl<F> ExpiringBloomFilterOps for FilterRing<F>
+where
+    F: ExpiringBloomFilterOps + Send + Sync,
+{
+    fn insert(&self, item: &[u8]) -> Result<()> {
+        self.replica_filters(item)?
+            .into_iter()
+            .try_for_each(|filter| filter.insert(item))
+    }
+
+    fn contains(&self, item: &[u8]) -> Result<bool> {
+        for filter in self.replica_filters(item)? {
+            if filter.contains(item)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.filters.values().try_for_each(|filter| filter.clear())
+    }
+
+    async fn cleanup_expired_levels(&self) -> Result<()> {
+        for filter in self.filters.values() {
+            filter.cleanup_expired_levels().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+//! Key-space sharding over independent expiring filters
+//!
+//! [`ShardedExpiringFilter`] routes every key by hash to one of M
+//! sub-filters. Each shard has its own levels, locks and, with
+//! persistence, its own database under `db_path/shard-<i>`, so writers on
+//! different shards never contend and a snapshot only copies one shard's
+//! dirty chunks. `capacity_per_level` is the total across shards.
+//!
+//! Routing takes the high bits of a 64-bit Murmur3 hash, while the shards
+//! index their bits modulo the level size, so a shard's keys still spread
+//! over all of its bits. The shard count is part of the on-disk layout:
+//! reopen a persistent filter with the count it was created with.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::ebloom::clock::Clock;
+use crate::ebloom::config::ExpiringFilterConfig;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::{
+    BulkExpiringBloomFilterOps, ExpiringBloomFilterOps, ExpiringBloomFilterStats,
+};
+use crate::hash::hash_murmur64;
+
+pub struct ShardedExpiringFilter {
+    shards: Vec<ExpiringBloomFilter>,
+}
+
+impl ShardedExpiringFilter {
+    /// In-memory filter split into `num_shards` shards
+    pub fn new(config: ExpiringFilterConfig, num_shards: usize) -> Result<Self> {
+        let clock = config.clock_mode.build_clock(0)?;
+        Self::with_clock(config, num_shards, clock)
+    }
+
+    /// In-memory filter whose shards share a custom clock
+    pub fn with_clock(
+        config: ExpiringFilterConfig,
+        num_shards: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        let shards = shard_configs(&config, num_shards)?
+            .into_iter()
+            .map(|config| ExpiringBloomFilter::with_clock(config, clock.clone()))
+            .collect::<Result<_>>()?;
+        Ok(Self { shards })
+    }
+
+    /// Create a new filter, overwriting existing shard databases
+    pub async fn create(
+        config: ExpiringFilterConfig,
+        num_shards: usize,
+    ) -> Result<Self> {
+        let mut shards = Vec::with_capacity(num_shards);
+        for config in shard_configs(&config, num_shards)? {
+            shards.push(ExpiringBloomFilter::create(config).await?);
+        }
+        Ok(Self { shards })
+    }
+
+    /// Load each shard from its database, creating missing ones
+    pub async fn create_or_load(
+        config: ExpiringFilterConfig,
+        num_shards: usize,
+    ) -> Result<Self> {
+        if let Some(ref pers) = config.persistence
+            && shard_path(&pers.db_path, num_shards).exists()
+        {
+            return Err(EbloomError::InvalidConfig(format!(
+                "{:?} holds more than {num_shards} shards",
+                pers.db_path
+            )));
+        }
+        let mut shards = Vec::with_capacity(num_shards);
+        for config in shard_configs(&config, num_shards)? {
+            shards.push(ExpiringBloomFilter::create_or_load(config).await?);
+        }
+        Ok(Self { shards })
+    }
+
+    /// Index of the shard owning `item`
+    pub fn shard_for(&self, item: &[u8]) -> usize {
+        let hash = hash_murmur64(item) as u128;
+        ((hash * self.shards.len() as u128) >> 64) as usize
+    }
+
+    pub fn shard(&self, index: usize) -> Option<&ExpiringBloomFilter> {
+        self.shards.get(index)
+    }
+
+    pub fn shards(&self) -> &[ExpiringBloomFilter] {
+        &self.shards
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Save each shard's dirty chunks, one shard at a time
+    pub async fn save_snapshot(&self) -> Result<()> {
+        for shard in &self.shards {
+            shard.save_snapshot().await?;
+        }
+        Ok(())
+    }
+
+    /// Make deferred writes of every shard durable
+    pub async fn flush(&self) -> Result<()> {
+        for shard in &self.shards {
+            shard.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Items grouped by shard, with their positions in `items`
+    fn group<'a>(&self, items: &[&'a [u8]]) -> Vec<(Vec<usize>, Vec<&'a [u8]>)> {
+        let mut groups = vec![(Vec::new(), Vec::new()); self.shards.len()];
+        for (position, &item) in items.iter().enumerate() {
+            let (positions, shard_items) = &mut groups[self.shard_for(item)];
+            positions.push(position);
+            shard_items.push(item);
+        }
+        groups
+    }
+}
+
+/// Per-shard configs: the capacity split evenly, and one database
+/// directory per shard
+fn shard_configs(
+    config: &ExpiringFilterConfig,
+    num_shards: usize,
+) -> Result<Vec<ExpiringFilterConfig>> {
+    if num_shards == 0 {
+        return Err(EbloomError::InvalidConfig(
+            "Number of shards must be greater than 0".to_string(),
+        ));
+    }
+    Ok((0..num_shards)
+        .map(|shard_idx| {
+            let mut shard = config.clone();
+            shard.capacity_per_level =
+                config.capacity_per_level.div_ceil(num_shards);
+            if let Some(ref mut pers) = shard.persistence {
+                pers.db_path = shard_path(&pers.db_path, shard_idx);
+            }
+            shard
+        })
+        .collect())
+}
+
+fn shard_path(db_path: &std::path::Path, shard_idx: usize) -> PathBuf {
+    db_path.join(format!("shard-{shard_idx}"))
+}
+
+#[async_trait]
+impl ExpiringBloomFilterOps for ShardedExpiringFilter {
+    fn insert(&self, item: &[u8]) -> Result<()> {
+        self.shards[self.shard_for(item)].insert(item)
+    }
+
+    fn contains(&self, item: &[u8]) -> Result<bool> {
+        self.shards[self.shard_for(item)].contains(item)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.shards
+            .iter()
+            .try_for_each(ExpiringBloomFilterOps::clear)
+    }
+
+    async fn cleanup_expired_levels(&self) -> Result<()> {
+        for shard in &self.shards {
+            shard.cleanup_expired_levels().await?;
+        }
+        Ok(())
+    }
+}
+
+impl BulkExpiringBloomFilterOps for ShardedExpiringFilter {
+    fn insert_bulk(&self, items: &[&[u8]]) -> Result<()> {
+        for (shard, (_, shard_items)) in self.shards.iter().zip(self.group(items))
+        {
+            if !shard_items.is_empty() {
+                shard.insert_bulk(&shard_items)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>> {
+        let mut results = vec![false; items.len()];
+        for (shard, (positions, shard_items)) in
+            self.shards.iter().zip(self.group(items))
+        {
+            if shard_items.is_empty() {
+                continue;
+            }
+            for (position, found) in positions
+                .into_iter()
+                .zip(shard.contains_bulk(&shard_items)?)
+            {
+                results[position] = found;
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl ExpiringBloomFilterStats for ShardedExpiringFilter {
+    /// Total across shards
+    fn capacity_per_level(&self) -> usize {
+        self.shards.iter().map(|s| s.capacity_per_level()).sum()
+    }
+
+    fn target_fpr(&self) -> f64 {
+        self.shards[0].target_fpr()
+    }
+
+    fn total_insert_count(&self) -> u64 {
+        self.shards.iter().map(|s| s.total_insert_count()).sum()
+    }
+
+    fn active_levels(&self) -> usize {
+        self.shards[0].active_levels()
+    }
+
+    fn num_levels(&self) -> usize {
+        self.shards[0].num_levels()
+    }
+
+    /// Mean fill ratio of the level across shards
+    fn fill_ratio(&self, level: usize) -> Result<f64> {
+        let total = self
+            .shards
+            .iter()
+            .map(|s| s.level_fill_ratio(level))
+            .sum::<Result<f64>>()?;
+        Ok(total / self.shards.len() as f64)
+    }
+
+    /// Total across shards
+    fn bit_vector_size(&self) -> usize {
+        self.shards.iter().map(|s| s.bit_vector_size()).sum()
+    }
+
+    fn num_hashes(&self) -> usize {
+        self.shards[0].num_hashes()
+    }
+
+    /// Total across shards
+    fn level_bit_counts(&self) -> Result<Vec<usize>> {
+        let mut totals = vec![0; self.num_levels()];
+        for shard in &self.shards {
+            for (total, count) in totals.iter_mut().zip(shard.level_bit_counts()?)
+            {
+                *total += count;
+            }
+        }
+        Ok(totals)
+    }
+}
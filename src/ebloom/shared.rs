@@ -0,0 +1,108 @@
+//! Shared filter handle for web handlers
+//!
+//! Every filter operation takes `&self`, so an `Arc` is all a web app
+//! needs to share one; no `Mutex` around it. [`SharedFilter`] is that
+//! `Arc` with extractor glue: with the `server` feature it is an axum
+//! extractor for any state it can be pulled from with `FromRef`, and with
+//! the `actix` feature an actix-web extractor over `App::app_data`.
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::ebloom::config::ExpiringFilterConfig;
+use crate::ebloom::error::Result;
+use crate::ebloom::filter::ExpiringBloomFilter;
+
+/// Cheaply cloned handle to one `ExpiringBloomFilter`
+#[derive(Clone)]
+pub struct SharedFilter(Arc<ExpiringBloomFilter>);
+
+impl SharedFilter {
+    pub fn new(filter: ExpiringBloomFilter) -> Self {
+        Self(Arc::new(filter))
+    }
+
+    /// Build the filter and wrap it in one step
+    pub fn from_config(config: ExpiringFilterConfig) -> Result<Self> {
+        ExpiringBloomFilter::new(config).map(Self::new)
+    }
+
+    /// The underlying `Arc`, e.g. to drive rotation from a background task
+    pub fn arc(&self) -> Arc<ExpiringBloomFilter> {
+        Arc::clone(&self.0)
+    }
+}
+
+impl Deref for SharedFilter {
+    type Target = ExpiringBloomFilter;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<ExpiringBloomFilter> for SharedFilter {
+    fn from(filter: ExpiringBloomFilter) -> Self {
+        Self::new(filter)
+    }
+}
+
+impl From<Arc<ExpiringBloomFilter>> for SharedFilter {
+    fn from(filter: Arc<ExpiringBloomFilter>) -> Self {
+        Self(filter)
+    }
+}
+
+impl From<SharedFilter> for Arc<ExpiringBloomFilter> {
+    fn from(filter: SharedFilter) -> Self {
+        filter.0
+    }
+}
+
+/// Handlers take `SharedFilter` directly when the router state is one, or
+/// when a composite state implements `FromRef` for it
+#[cfg(feature = "server")]
+impl<S> axum::extract::FromRequestParts<S> for SharedFilter
+where
+    SharedFilter: axum::extract::FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        _parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        Ok(axum::extract::FromRef::from_ref(state))
+    }
+}
+
+#[cfg(feature = "actix")]
+impl From<SharedFilter> for actix_web::web::Data<ExpiringBloomFilter> {
+    fn from(filter: SharedFilter) -> Self {
+        actix_web::web::Data::from(filter.0)
+    }
+}
+
+/// Resolved from `App::app_data(shared)` or from
+/// `App::app_data(web::Data<ExpiringBloomFilter>)`
+#[cfg(feature = "actix")]
+impl actix_web::FromRequest for SharedFilter {
+    type Error = actix_web::Error;
+    type Future = std::future::Ready<std::result::Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        let filter = req.app_data::<SharedFilter>().cloned().or_else(|| {
+            req.app_data::<actix_web::web::Data<ExpiringBloomFilter>>()
+                .map(|data| Self(data.clone().into_inner()))
+        });
+        std::future::ready(filter.ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(
+                "No SharedFilter registered with App::app_data",
+            )
+        }))
+    }
+}
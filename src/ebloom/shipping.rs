@@ -0,0 +1,440 @@
+//! Leader/follower snapshot shipping for read replicas
+//!
+//! A [`SnapshotPublisher`] freezes the leader filter on every
+//! [`SnapshotPublisher::publish`] and hands a [`ShippedSnapshot`] to a
+//! [`SnapshotSink`]: a full copy of the non-empty chunks first and every
+//! `full_every` publishes, otherwise only the chunks that changed since
+//! the previous publish (including chunks cleared by rotation). Sinks
+//! exist for a directory and for channels; object stores or message buses
+//! implement the trait with `ShippedSnapshot::to_bytes`.
+//!
+//! A [`ReadReplica`] applies the snapshots in order and answers queries
+//! read-only. Each update builds a new frozen view and swaps it in, so
+//! queries never see a half-applied snapshot. An incremental snapshot that
+//! does not follow the replica's current one is rejected; the follower
+//! then waits for the next full snapshot.
+//!
+//! Replicas mirror the leader's levels as they are: they do not rotate on
+//! their own, so publish at least as often as the leader rotates.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::frozen::FrozenExpiringBloomFilter;
+use crate::hash::{hash_fnv64, optimal_num_hashes};
+
+/// File extension of snapshots written by [`DirectorySink`]
+const SNAPSHOT_EXTENSION: &str = "snap";
+
+/// One published state of the leader
+#[derive(Debug, Clone, Serialize, Deserialize, Decode, Encode)]
+pub struct ShippedSnapshot {
+    pub seq: u64,
+    /// Snapshot this one applies on top of; `None` for full snapshots
+    pub base_seq: Option<u64>,
+    pub config: ExpiringFilterConfig,
+    pub current_level: usize,
+    pub metadata: Vec<LevelMetadata>,
+    /// Leader clock when the snapshot was taken (ms)
+    pub taken_at: u64,
+    pub chunk_bytes: usize,
+    /// `(level, chunk index, chunk bytes)`; chunks of a full snapshot
+    /// that are not listed are empty
+    pub chunks: Vec<(usize, usize, Vec<u8>)>,
+}
+
+impl ShippedSnapshot {
+    pub fn is_full(&self) -> bool {
+        self.base_seq.is_none()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(snapshot, _)| snapshot)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+}
+
+/// Destination of published snapshots
+pub trait SnapshotSink: Send + Sync {
+    fn publish(&self, snapshot: &ShippedSnapshot) -> Result<()>;
+
+    /// Highest sequence number already published, so a restarted leader
+    /// continues after it instead of starting over at 0
+    fn last_seq(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+impl SnapshotSink for std::sync::mpsc::SyncSender<ShippedSnapshot> {
+    fn publish(&self, snapshot: &ShippedSnapshot) -> Result<()> {
+        self.send(snapshot.clone()).map_err(|_| {
+            EbloomError::StorageError("Snapshot channel closed".to_string())
+        })
+    }
+}
+
+impl SnapshotSink for std::sync::mpsc::Sender<ShippedSnapshot> {
+    fn publish(&self, snapshot: &ShippedSnapshot) -> Result<()> {
+        self.send(snapshot.clone()).map_err(|_| {
+            EbloomError::StorageError("Snapshot channel closed".to_string())
+        })
+    }
+}
+
+/// Publishes snapshots of a leader filter
+pub struct SnapshotPublisher {
+    filter: Arc<ExpiringBloomFilter>,
+    sink: Box<dyn SnapshotSink>,
+    chunk_bytes: usize,
+    full_every: u64,
+    next_seq: AtomicU64,
+    /// Chunk hashes per level as of the last publish
+    published: Mutex<Option<Vec<Vec<u64>>>>,
+}
+
+impl SnapshotPublisher {
+    /// Publish to `sink`, sending a full snapshot every `full_every`
+    /// publishes
+    pub fn new(
+        filter: Arc<ExpiringBloomFilter>,
+        sink: impl SnapshotSink + 'static,
+        chunk_bytes: usize,
+        full_every: u64,
+    ) -> Result<Self> {
+        if chunk_bytes == 0 || full_every == 0 {
+            return Err(EbloomError::InvalidConfig(
+                "Chunk size and full snapshot interval must be greater than 0"
+                    .to_string(),
+            ));
+        }
+        let next_seq = sink.last_seq()?.map_or(0, |seq| seq + 1);
+        Ok(Self {
+            filter,
+            sink: Box::new(sink),
+            chunk_bytes,
+            full_every,
+            next_seq: AtomicU64::new(next_seq),
+            published: Mutex::new(None),
+        })
+    }
+
+    /// Freeze the leader and publish a full or incremental snapshot
+    ///
+    /// Call periodically. If the sink fails, the next publish is a full
+    /// snapshot.
+    pub fn publish(&self) -> Result<ShippedSnapshot> {
+        let mut published = self.published.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock publisher state".to_string())
+        })?;
+        let frozen = self.filter.freeze()?;
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let previous = published
+            .take()
+            .filter(|_| !seq.is_multiple_of(self.full_every));
+
+        let mut hashes = Vec::with_capacity(frozen.levels.len());
+        let mut chunks = Vec::new();
+        for (level_idx, level) in frozen.levels.iter().enumerate() {
+            let bytes = level.read_bytes(0, frozen.bit_vector_size);
+            let level_hashes: Vec<u64> =
+                bytes.chunks(self.chunk_bytes).map(hash_fnv64).collect();
+            for (chunk_idx, chunk) in bytes.chunks(self.chunk_bytes).enumerate() {
+                let changed = match previous {
+                    Some(ref previous) => {
+                        previous[level_idx][chunk_idx] != level_hashes[chunk_idx]
+                    }
+                    None => chunk.iter().any(|&b| b != 0),
+                };
+                if changed {
+                    chunks.push((level_idx, chunk_idx, chunk.to_vec()));
+                }
+            }
+            hashes.push(level_hashes);
+        }
+
+        let snapshot = ShippedSnapshot {
+            seq,
+            base_seq: previous.is_some().then(|| seq - 1),
+            config: frozen.config.clone(),
+            current_level: frozen.current_level,
+            metadata: frozen.metadata.as_ref().clone(),
+            taken_at: frozen.frozen_at,
+            chunk_bytes: self.chunk_bytes,
+            chunks,
+        };
+        self.sink.publish(&snapshot)?;
+        *published = Some(hashes);
+        Ok(snapshot)
+    }
+}
+
+/// Read-only filter following a leader's snapshots
+pub struct ReadReplica {
+    view: RwLock<Option<(u64, FrozenExpiringBloomFilter)>>,
+    /// Serializes updates; queries only take `view` briefly
+    apply_lock: Mutex<()>,
+}
+
+impl Default for ReadReplica {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadReplica {
+    /// Replica without state; queries fail until a full snapshot arrives
+    pub fn new() -> Self {
+        Self {
+            view: RwLock::new(None),
+            apply_lock: Mutex::new(()),
+        }
+    }
+
+    /// Apply the next snapshot
+    ///
+    /// Full snapshots replace the state. Incremental ones must build on the
+    /// snapshot applied last; others fail and leave the state unchanged.
+    pub fn apply(&self, snapshot: &ShippedSnapshot) -> Result<()> {
+        let _guard = self.apply_lock.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock replica updates".to_string())
+        })?;
+        let current = self.view()?;
+        let levels = match (snapshot.base_seq, current) {
+            (None, _) => {
+                let bit_vector_size = snapshot.config.level_bit_size();
+                (0..snapshot.metadata.len())
+                    .map(|_| AtomicBitVec::new(bit_vector_size))
+                    .collect()
+            }
+            (Some(base_seq), Some((seq, ref view))) if base_seq == seq => {
+                view.levels.as_ref().clone()
+            }
+            (Some(base_seq), _) => {
+                return Err(EbloomError::InvalidConfig(format!(
+                    "Snapshot {} builds on {base_seq}, which was not applied",
+                    snapshot.seq
+                )));
+            }
+        };
+        let view = build_view(snapshot, levels)?;
+        *self.view.write().map_err(|_| {
+            EbloomError::LockError("Failed to write replica view".to_string())
+        })? = Some((snapshot.seq, view));
+        Ok(())
+    }
+
+    /// Apply every snapshot in `dir` after the current one, in order
+    ///
+    /// Returns how many were applied. If the replica cannot continue from
+    /// its state it starts over from the newest full snapshot.
+    pub fn catch_up(&self, dir: &Path) -> Result<usize> {
+        let after = self.seq()?;
+        let snapshots = read_snapshots(dir, after)?;
+        let chain_ok = snapshots
+            .first()
+            .is_none_or(|first| first.is_full() || first.base_seq == after);
+        let start = if chain_ok {
+            0
+        } else {
+            match snapshots.iter().rposition(ShippedSnapshot::is_full) {
+                Some(idx) => idx,
+                None => return Ok(0),
+            }
+        };
+        for snapshot in &snapshots[start..] {
+            self.apply(snapshot)?;
+        }
+        Ok(snapshots.len() - start)
+    }
+
+    /// Sequence number of the applied snapshot
+    pub fn seq(&self) -> Result<Option<u64>> {
+        Ok(self.view()?.map(|(seq, _)| seq))
+    }
+
+    /// Current read-only view; cheap to clone and query
+    pub fn view_snapshot(&self) -> Result<FrozenExpiringBloomFilter> {
+        self.view()?.map(|(_, view)| view).ok_or_else(|| {
+            EbloomError::InvalidConfig(
+                "Replica has not received a full snapshot yet".to_string(),
+            )
+        })
+    }
+
+    pub fn contains(&self, item: &[u8]) -> Result<bool> {
+        self.view_snapshot()?.contains(item)
+    }
+
+    pub fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>> {
+        self.view_snapshot()?.contains_bulk(items)
+    }
+
+    fn view(&self) -> Result<Option<(u64, FrozenExpiringBloomFilter)>> {
+        self.view.read().map(|view| view.clone()).map_err(|_| {
+            EbloomError::LockError("Failed to read replica view".to_string())
+        })
+    }
+}
+
+/// Frozen view of `levels` with the chunks of `snapshot` written over them
+fn build_view(
+    snapshot: &ShippedSnapshot,
+    levels: Vec<AtomicBitVec>,
+) -> Result<FrozenExpiringBloomFilter> {
+    let bit_vector_size = snapshot.config.level_bit_size();
+    if levels.len() != snapshot.metadata.len()
+        || levels.iter().any(|level| level.len() != bit_vector_size)
+        || snapshot.chunk_bytes == 0
+    {
+        return Err(EbloomError::InvalidConfig(
+            "Snapshot does not match the replica's layout".to_string(),
+        ));
+    }
+    for (level_idx, chunk_idx, chunk) in &snapshot.chunks {
+        let level = levels.get(*level_idx).ok_or(EbloomError::InvalidLevel {
+            level: *level_idx,
+            max_levels: levels.len(),
+        })?;
+        // Chunk indices come off the wire; never let one address bits
+        // outside the level
+        let start_bit = chunk_idx
+            .checked_mul(snapshot.chunk_bytes)
+            .and_then(|byte| byte.checked_mul(8))
+            .filter(|&bit| bit < bit_vector_size)
+            .ok_or(EbloomError::IndexOutOfBounds {
+                index: *chunk_idx,
+                capacity: bit_vector_size
+                    .div_ceil(8)
+                    .div_ceil(snapshot.chunk_bytes),
+            })?;
+        if chunk.len() > snapshot.chunk_bytes {
+            return Err(EbloomError::InvalidConfig(format!(
+                "Snapshot chunk {chunk_idx} holds {} bytes, more than the \
+                 chunk size of {}",
+                chunk.len(),
+                snapshot.chunk_bytes
+            )));
+        }
+        level.write_bytes(start_bit, chunk);
+    }
+    Ok(FrozenExpiringBloomFilter {
+        config: snapshot.config.clone(),
+        bit_vector_size,
+        num_hashes: optimal_num_hashes(
+            snapshot.config.capacity_per_level,
+            bit_vector_size,
+        ),
+        levels: Arc::new(levels),
+        metadata: Arc::new(snapshot.metadata.clone()),
+        current_level: snapshot.current_level,
+        frozen_at: snapshot.taken_at,
+    })
+}
+
+/// Writes each snapshot to `<seq>.snap` in a directory
+///
+/// Files are written under a temporary name and renamed, so readers never
+/// see partial files. After a full snapshot, older files are removed.
+pub struct DirectorySink {
+    dir: PathBuf,
+}
+
+impl DirectorySink {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| {
+            EbloomError::StorageError(format!(
+                "Failed to create snapshot directory {}: {e}",
+                dir.display()
+            ))
+        })?;
+        Ok(Self { dir })
+    }
+}
+
+impl SnapshotSink for DirectorySink {
+    fn publish(&self, snapshot: &ShippedSnapshot) -> Result<()> {
+        let storage_error = |e: std::io::Error| {
+            EbloomError::StorageError(format!("Failed to publish snapshot: {e}"))
+        };
+        let path = self.dir.join(snapshot_file_name(snapshot.seq));
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, snapshot.to_bytes()?).map_err(storage_error)?;
+        fs::rename(&tmp_path, &path).map_err(storage_error)?;
+
+        if snapshot.is_full() {
+            for (seq, old_path) in list_snapshots(&self.dir)? {
+                if seq != snapshot.seq {
+                    fs::remove_file(old_path).map_err(storage_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn last_seq(&self) -> Result<Option<u64>> {
+        Ok(list_snapshots(&self.dir)?.last().map(|&(seq, _)| seq))
+    }
+}
+
+fn snapshot_file_name(seq: u64) -> String {
+    format!("{seq:020}.{SNAPSHOT_EXTENSION}")
+}
+
+/// `(seq, path)` of the snapshot files in `dir`, oldest first
+fn list_snapshots(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        EbloomError::StorageError(format!(
+            "Failed to read snapshot directory {}: {e}",
+            dir.display()
+        ))
+    })?;
+    let mut snapshots: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == SNAPSHOT_EXTENSION)
+        })
+        .filter_map(|path| {
+            let seq = path.file_stem()?.to_str()?.parse().ok()?;
+            Some((seq, path))
+        })
+        .collect();
+    snapshots.sort_unstable();
+    Ok(snapshots)
+}
+
+/// Snapshots in `dir` newer than `after`, oldest first
+fn read_snapshots(
+    dir: &Path,
+    after: Option<u64>,
+) -> Result<Vec<ShippedSnapshot>> {
+    list_snapshots(dir)?
+        .into_iter()
+        .filter(|&(seq, _)| after.is_none_or(|after| seq > after))
+        .map(|(_, path)| {
+            let bytes = fs::read(&path).map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to read snapshot {}: {e}",
+                    path.display()
+                ))
+            })?;
+            ShippedSnapshot::from_bytes(&bytes)
+        })
+        .collect()
+}
@@ -0,0 +1,152 @@
+//! Vectorized probe tests for bulk queries
+//!
+//! `contains_bulk` spends most of its time loading the words its probes
+//! land on. With AVX2, each item's probes are gathered four per
+//! instruction and any leftover probes are loaded one by one. aarch64 has
+//! no gather, so NEON tests two probes per register after loading their
+//! words one by one. The kernel is picked at run time, and CPUs with
+//! neither use the scalar loop. Both kernels skip items an earlier level
+//! already found, as the scalar loop does.
+//!
+//! The AVX2 gather reads words that inserts update with relaxed
+//! `fetch_or`. Each lane is an aligned 8-byte load, which x86-64 performs
+//! atomically, so it sees a word either before or after a concurrent insert,
+//! exactly like the relaxed `load` of the scalar path.
+
+use crate::ebloom::sync::{AtomicU64, Ordering};
+
+/// For every item of `num_hashes` probes in `indices`, set its entry in
+/// `found` if all of its bits are set; items already found are skipped
+///
+/// Every probe must be below `words.len() * 64`.
+pub(crate) fn all_set_each(
+    words: &[AtomicU64],
+    indices: &[usize],
+    num_hashes: usize,
+    found: &mut [bool],
+) {
+    debug_assert!(indices.iter().all(|&idx| idx / 64 < words.len()));
+    #[cfg(all(target_arch = "x86_64", not(ebloom_loom)))]
+    if std::arch::is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available, and the caller keeps every probe in
+        // range
+        unsafe { avx2::all_set_each(words, indices, num_hashes, found) };
+        return;
+    }
+    #[cfg(all(target_arch = "aarch64", not(ebloom_loom)))]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON is available
+        unsafe { neon::all_set_each(words, indices, num_hashes, found) };
+        return;
+    }
+    scalar_all_set_each(words, indices, num_hashes, found);
+}
+
+fn scalar_all_set_each(
+    words: &[AtomicU64],
+    indices: &[usize],
+    num_hashes: usize,
+    found: &mut [bool],
+) {
+    for (probes, found) in indices.chunks(num_hashes).zip(found) {
+        *found |= !*found
+            && probes.iter().fold(true, |acc, &idx| {
+                let word = words[idx / 64].load(Ordering::Relaxed);
+                acc & ((word >> (idx % 64)) & 1 == 1)
+            });
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(ebloom_loom)))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use crate::ebloom::sync::{AtomicU64, Ordering};
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2 and every probe must be below
+    /// `words.len() * 64`.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn all_set_each(
+        words: &[AtomicU64],
+        indices: &[usize],
+        num_hashes: usize,
+        found: &mut [bool],
+    ) {
+        let base = words.as_ptr().cast::<i64>();
+        let low_bits = _mm256_set1_epi64x(63);
+        for (probes, found) in indices.chunks(num_hashes).zip(found) {
+            if *found {
+                continue;
+            }
+            let mut acc = _mm256_set1_epi64x(-1);
+            let mut lanes = probes.chunks_exact(4);
+            for lane in lanes.by_ref() {
+                // SAFETY: `lane` holds four 64-bit `usize`s, and every
+                // probe, and so every word index, is in range
+                let gathered_bits = unsafe {
+                    let idx = _mm256_loadu_si256(lane.as_ptr().cast());
+                    let gathered = _mm256_i64gather_epi64::<8>(
+                        base,
+                        _mm256_srli_epi64::<6>(idx),
+                    );
+                    // Move each probed bit to the lane's sign bit
+                    _mm256_sllv_epi64(
+                        gathered,
+                        _mm256_sub_epi64(
+                            low_bits,
+                            _mm256_and_si256(idx, low_bits),
+                        ),
+                    )
+                };
+                acc = _mm256_and_si256(acc, gathered_bits);
+            }
+            let tail = lanes.remainder().iter().fold(true, |all, &idx| {
+                let word = words[idx / 64].load(Ordering::Relaxed);
+                all & ((word >> (idx % 64)) & 1 == 1)
+            });
+            *found =
+                tail && _mm256_movemask_pd(_mm256_castsi256_pd(acc)) == 0b1111;
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(ebloom_loom)))]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use crate::ebloom::sync::{AtomicU64, Ordering};
+
+    /// # Safety
+    ///
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn all_set_each(
+        words: &[AtomicU64],
+        indices: &[usize],
+        num_hashes: usize,
+        found: &mut [bool],
+    ) {
+        let word = |idx: usize| words[idx / 64].load(Ordering::Relaxed);
+        // Negative shifts shift right
+        let shift = |idx: usize| -((idx % 64) as i64);
+        for (probes, found) in indices.chunks(num_hashes).zip(found) {
+            if *found {
+                continue;
+            }
+            let mut acc = vdupq_n_u64(1);
+            for lanes in probes.chunks(2) {
+                let (low, high) = (lanes[0], *lanes.last().unwrap_or(&lanes[0]));
+                let loaded =
+                    vcombine_u64(vcreate_u64(word(low)), vcreate_u64(word(high)));
+                let shifts = vcombine_s64(
+                    vcreate_s64(shift(low) as u64),
+                    vcreate_s64(shift(high) as u64),
+                );
+                acc = vandq_u64(acc, vshlq_u64(loaded, shifts));
+            }
+            *found = vgetq_lane_u64::<0>(acc) & vgetq_lane_u64::<1>(acc) & 1 == 1;
+        }
+    }
+}
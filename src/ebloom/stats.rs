@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::drift::FprDrift;
+use crate::ebloom::events::{RotationRecord, SnapshotEvent};
+
+/// Buckets per level in the density histograms of `DebugDump`
+pub const DUMP_HISTOGRAM_BUCKETS: usize = 64;
+
+/// Point-in-time view of an expiring filter, see `ExpiringBloomFilter::stats`
+///
+/// Counters are read without locks, so under concurrent inserts the numbers
+/// are consistent per level rather than across the whole filter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpiringStats {
+    pub capacity_per_level: usize,
+    pub num_levels: usize,
+    pub target_fpr: f64,
+    /// False positive rate of a query right now, from the density of every
+    /// level in use
+    pub estimated_fpr: f64,
+    pub bit_vector_size: usize,
+    pub num_hashes: usize,
+    pub current_level: usize,
+    pub total_insert_count: u64,
+    /// Bytes held by the filter, see `ExpiringBloomFilter::memory_usage`
+    pub memory_bytes: usize,
+    /// Time since the current level was last persisted (or created, if it
+    /// never was); `None` without persistence
+    pub snapshot_lag_ms: Option<u64>,
+    /// Dirty-chunk snapshots (`save_snapshot`)
+    pub incremental_snapshots: SnapshotStats,
+    /// Whole-level snapshots written on rotation
+    pub full_snapshots: SnapshotStats,
+    pub levels: Vec<LevelStats>,
+    /// Per-operation latency, for operations seen so far; always empty
+    /// without the `latency` feature
+    pub latency: Vec<OperationLatency>,
+}
+
+/// Running totals for one kind of snapshot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotStats {
+    pub count: u64,
+    pub total_chunks: u64,
+    pub total_bytes: u64,
+    pub total_duration_ms: f64,
+    pub last_chunks: usize,
+    pub last_bytes: usize,
+    pub last_duration_ms: f64,
+}
+
+impl SnapshotStats {
+    pub(crate) fn record(&mut self, event: &SnapshotEvent) {
+        let duration_ms = event.duration.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.total_chunks += event.chunks as u64;
+        self.total_bytes += event.bytes as u64;
+        self.total_duration_ms += duration_ms;
+        self.last_chunks = event.chunks;
+        self.last_bytes = event.bytes;
+        self.last_duration_ms = duration_ms;
+    }
+
+    pub fn mean_duration_ms(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total_duration_ms / self.count as f64
+    }
+
+    pub fn mean_bytes(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total_bytes as f64 / self.count as f64
+    }
+}
+
+/// Approximate heap (or mapped) memory held by an expiring filter, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Level bit vectors
+    pub levels: usize,
+    /// Dirty chunk and dirty level bitmaps used by snapshots
+    pub dirty_tracking: usize,
+    /// Bits carried over by the grace overlap
+    pub grace_bits: usize,
+    /// Write-behind queue bitmaps and channel slots
+    pub write_behind: usize,
+    /// Level metadata, per-level counters and rotation history
+    pub metadata: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.levels
+            + self.dirty_tracking
+            + self.grace_bits
+            + self.write_behind
+            + self.metadata
+    }
+}
+
+/// Operation timed by the `latency` feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LatencyOperation {
+    Insert,
+    Contains,
+    InsertBulk,
+    ContainsBulk,
+    Rotation,
+    /// `save_snapshot`, i.e. incremental snapshots
+    Snapshot,
+}
+
+/// Latency distribution of one operation, in nanoseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationLatency {
+    pub operation: LatencyOperation,
+    pub count: u64,
+    pub mean_ns: f64,
+    pub p50_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+/// Per-level part of `ExpiringStats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub level: usize,
+    /// Fraction of bits set
+    pub fill_ratio: f64,
+    pub insert_count: u64,
+    /// Time since the level became current; `None` for levels never used
+    pub age_ms: Option<u64>,
+    pub expired: bool,
+}
+
+/// Filter state for bug reports, see `ExpiringBloomFilter::dump_state`
+///
+/// Holds configuration and derived numbers but no bits, so it stays small
+/// enough to paste into an issue regardless of filter size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugDump {
+    /// Version of this crate that produced the dump
+    pub crate_version: String,
+    /// `fjall` or `memory`
+    pub backend: String,
+    pub config: ExpiringFilterConfig,
+    pub stats: ExpiringStats,
+    pub levels: Vec<LevelDump>,
+    /// Most recent rotations, oldest first
+    pub rotations: Vec<RotationRecord>,
+    pub fpr_drift: FprDrift,
+}
+
+/// Per-level part of `DebugDump`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelDump {
+    pub level: usize,
+    pub metadata: LevelMetadata,
+    pub fill_ratio: f64,
+    /// Fraction of bits set per bucket, `DUMP_HISTOGRAM_BUCKETS` buckets
+    /// across the level; uneven buckets point at a hashing problem
+    pub density_histogram: Vec<f64>,
+}
+
+/// Broken internal invariant, see `ExpiringBloomFilter::validate_invariants`
+///
+/// Any of these is a bug in the crate; the numbers are meant to be pasted
+/// into the issue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvariantViolation {
+    /// Current level pointer is not a valid level index
+    CurrentLevelOutOfRange {
+        current_level: usize,
+        num_levels: usize,
+    },
+    /// A per-level collection does not have one entry per level
+    LevelCountMismatch {
+        /// `levels`, `metadata`, `insert_counts` or `created_ats`
+        collection: String,
+        found: usize,
+        num_levels: usize,
+    },
+    /// A level's bit vector does not match `bit_vector_size`
+    LevelBitLength {
+        level: usize,
+        found: usize,
+        expected: usize,
+    },
+    /// A tracking bitmap does not match what it tracks
+    BitmapLength {
+        /// `dirty_chunks`, `dirty_levels` or `grace_bits`
+        bitmap: String,
+        found: usize,
+        expected: usize,
+    },
+}
+
+impl std::fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CurrentLevelOutOfRange {
+                current_level,
+                num_levels,
+            } => write!(
+                f,
+                "current level {current_level} out of range for {num_levels} levels"
+            ),
+            Self::LevelCountMismatch {
+                collection,
+                found,
+                num_levels,
+            } => write!(
+                f,
+                "{collection} has {found} entries, filter has {num_levels} levels"
+            ),
+            Self::LevelBitLength {
+                level,
+                found,
+                expected,
+            } => write!(f, "level {level} has {found} bits, expected {expected}"),
+            Self::BitmapLength {
+                bitmap,
+                found,
+                expected,
+            } => write!(f, "{bitmap} has {found} bits, expected {expected}"),
+        }
+    }
+}
+
+/// Persistence status for readiness probes, see `ExpiringBloomFilter::health`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistenceHealth {
+    /// Whether the filter has a storage backend at all
+    pub persistent: bool,
+    /// Backend accepted a probe write and returned it on read; always
+    /// `true` without persistence
+    pub writable: bool,
+    /// Why the probe failed
+    pub error: Option<String>,
+    /// Time since the last successful snapshot; `None` when there has not
+    /// been one or the filter is not persistent
+    pub since_last_snapshot_ms: Option<u64>,
+    /// Chunks of the current level changed since the last snapshot
+    pub pending_dirty_chunks: usize,
+    /// Other levels waiting to be written by the next snapshot
+    pub pending_dirty_levels: usize,
+}
+
+impl PersistenceHealth {
+    /// Healthy when the backend is writable and snapshots are no older
+    /// than `max_snapshot_age_ms`
+    pub fn is_healthy(&self, max_snapshot_age_ms: u64) -> bool {
+        self.writable
+            && (!self.persistent
+                || self
+                    .since_last_snapshot_ms
+                    .is_some_and(|age| age <= max_snapshot_age_ms))
+    }
+}
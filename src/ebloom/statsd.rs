@@ -0,0 +1,151 @@
+//! StatsD metrics over UDP, for deployments without Prometheus
+//!
+//! Enabled with `ExpiringFilterConfig::statsd`. Inserts are counted on the
+//! insert path and sent in batches from `cleanup_expired_levels` at most
+//! every `flush_interval`; rotations are sent as they happen. With prefix
+//! `ebloom` the filter emits:
+//!
+//! * `ebloom.inserts:<n>|c` and `ebloom.probable_duplicates:<n>|c`
+//! * `ebloom.duplicate_rate:<ratio>|g` over the flush interval
+//! * `ebloom.rotations.<reason>:1|c`
+//! * `ebloom.current_level:<i>|g` and `ebloom.level.<i>.fill_ratio:<r>|g`
+//!
+//! Sending is fire-and-forget: a lost datagram or an unreachable daemon
+//! never fails a filter operation.
+
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::ebloom::config::{RotationReason, StatsdConfig};
+use crate::ebloom::error::{EbloomError, Result};
+
+/// Metric lines are packed into datagrams of at most this size, which
+/// fits a typical MTU
+const MAX_DATAGRAM: usize = 1432;
+
+pub(crate) struct StatsdEmitter {
+    socket: UdpSocket,
+    prefix: String,
+    flush_interval_ms: u64,
+    last_flush_ms: AtomicU64,
+    /// Inserts since the last flush
+    pending_inserts: AtomicU64,
+    /// Filter duplicate total at the last flush
+    reported_duplicates: AtomicU64,
+}
+
+impl StatsdEmitter {
+    pub(crate) fn new(config: &StatsdConfig) -> Result<Self> {
+        let statsd_error = |e: std::io::Error| {
+            EbloomError::InvalidConfig(format!(
+                "StatsD host {}: {e}",
+                config.host
+            ))
+        };
+        let addr = config
+            .host
+            .to_socket_addrs()
+            .map_err(statsd_error)?
+            .next()
+            .ok_or_else(|| {
+                EbloomError::InvalidConfig(format!(
+                    "StatsD host {} did not resolve",
+                    config.host
+                ))
+            })?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).map_err(statsd_error)?;
+        socket.connect(addr).map_err(statsd_error)?;
+        socket.set_nonblocking(true).map_err(statsd_error)?;
+        Ok(Self {
+            socket,
+            prefix: config.prefix.clone(),
+            flush_interval_ms: config.flush_interval.as_millis() as u64,
+            last_flush_ms: AtomicU64::new(0),
+            pending_inserts: AtomicU64::new(0),
+            reported_duplicates: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn record_inserts(&self, count: usize) {
+        self.pending_inserts
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rotation(&self, reason: RotationReason) {
+        self.send(&[format!(
+            "{}.rotations.{}:1|c",
+            self.prefix,
+            reason.as_str()
+        )]);
+    }
+
+    /// Whether `flush_interval` passed since the last flush
+    pub(crate) fn flush_due(&self, now_ms: u64) -> bool {
+        now_ms.saturating_sub(self.last_flush_ms.load(Ordering::Relaxed))
+            >= self.flush_interval_ms
+    }
+
+    /// Send counters accumulated since the last flush and current gauges
+    ///
+    /// `fill_ratios` lists the levels in use as `(level, fill_ratio)`.
+    pub(crate) fn flush(
+        &self,
+        now_ms: u64,
+        duplicates_total: u64,
+        current_level: usize,
+        fill_ratios: &[(usize, f64)],
+    ) {
+        self.last_flush_ms.store(now_ms, Ordering::Relaxed);
+        let inserts = self.pending_inserts.swap(0, Ordering::Relaxed);
+        let duplicates = duplicates_total.saturating_sub(
+            self.reported_duplicates
+                .swap(duplicates_total, Ordering::Relaxed),
+        );
+
+        let prefix = &self.prefix;
+        let mut lines = vec![
+            format!("{prefix}.inserts:{inserts}|c"),
+            format!("{prefix}.probable_duplicates:{duplicates}|c"),
+            format!("{prefix}.current_level:{current_level}|g"),
+        ];
+        if inserts > 0 {
+            let rate = duplicates as f64 / inserts as f64;
+            lines.push(format!("{prefix}.duplicate_rate:{rate}|g"));
+        }
+        lines.extend(fill_ratios.iter().map(|(level, ratio)| {
+            format!("{prefix}.level.{level}.fill_ratio:{ratio}|g")
+        }));
+        self.send(&lines);
+    }
+
+    /// Pack lines into newline-separated datagrams and send them
+    fn send(&self, lines: &[String]) {
+        let mut datagram = String::new();
+        for line in lines {
+            if !datagram.is_empty()
+                && datagram.len() + 1 + line.len() > MAX_DATAGRAM
+            {
+                self.send_datagram(&datagram);
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(line);
+        }
+        if !datagram.is_empty() {
+            self.send_datagram(&datagram);
+        }
+    }
+
+    fn send_datagram(&self, datagram: &str) {
+        if let Err(e) = self.socket.send(datagram.as_bytes()) {
+            tracing::debug!(error = %e, "Failed to send StatsD metrics");
+        }
+    }
+}
@@ -1,11 +1,62 @@
+#[cfg(any(feature = "fjall", fuzzing))]
+use crate::common::decode_config;
 use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
 use crate::ebloom::error::EbloomError;
+use crate::ebloom::events::RotationRecord;
 use async_trait::async_trait;
 use bincode;
 use std::sync::Arc;
+#[cfg(feature = "fjall")]
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "fjall")]
+use std::time::{Duration, Instant};
 
 type Result<T> = std::result::Result<T, EbloomError>;
 
+/// Chunk data for one level: `(chunk_id, bytes)` pairs
+pub type LevelChunks = Vec<(usize, Vec<u8>)>;
+
+/// Snapshot writes committed together by `commit_snapshot`
+#[derive(Debug, Default, Clone)]
+pub struct SnapshotBatch {
+    /// Full chunks per level
+    pub level_chunks: Vec<(usize, LevelChunks)>,
+    /// Dirty chunks per level
+    pub dirty_chunks: Vec<(usize, LevelChunks)>,
+    /// Metadata for all levels, if it changed
+    pub metadata: Option<Vec<LevelMetadata>>,
+}
+
+impl SnapshotBatch {
+    pub fn is_empty(&self) -> bool {
+        self.level_chunks.is_empty()
+            && self.dirty_chunks.is_empty()
+            && self.metadata.is_none()
+    }
+
+    /// Number of chunks in the batch, across all levels
+    pub fn chunk_count(&self) -> usize {
+        self.level_chunks
+            .iter()
+            .chain(&self.dirty_chunks)
+            .map(|(_, chunks)| chunks.len())
+            .sum()
+    }
+
+    /// Total chunk bytes in the batch, metadata excluded
+    pub fn byte_len(&self) -> usize {
+        self.level_chunks
+            .iter()
+            .chain(&self.dirty_chunks)
+            .flat_map(|(_, chunks)| chunks)
+            .map(|(_, bytes)| bytes.len())
+            .sum()
+    }
+}
+
 /// Storage backend trait for expiring bloom filter persistence
 #[async_trait]
 pub trait ExpiringStorageBackend {
@@ -56,6 +107,31 @@ pub trait ExpiringStorageBackend {
 
     /// Delete all data for a specific level (during rotation)
     async fn delete_level(&self, level: usize) -> Result<()>;
+
+    /// Replace the stored rotation log; backends without one drop it
+    async fn save_rotation_log(&self, _log: &[RotationRecord]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the stored rotation log, oldest first
+    async fn load_rotation_log(&self) -> Result<Vec<RotationRecord>> {
+        Ok(Vec::new())
+    }
+
+    /// Write a whole snapshot; backends that support it commit atomically
+    /// with a single sync
+    async fn commit_snapshot(&self, batch: SnapshotBatch) -> Result<()> {
+        for (level, chunks) in &batch.level_chunks {
+            self.save_level_chunks(*level, chunks).await?;
+        }
+        for (level, chunks) in &batch.dirty_chunks {
+            self.save_dirty_chunks(*level, chunks).await?;
+        }
+        if let Some(ref metadata) = batch.metadata {
+            self.save_level_metadata(metadata).await?;
+        }
+        Ok(())
+    }
 }
 
 /// In-memory storage backend for testing
@@ -171,6 +247,75 @@ pub struct FjallExpiringBackend {
     chunks_partitions: Vec<Arc<fjall::Partition>>,
     dirty_partitions: Vec<Arc<fjall::Partition>>,
     max_levels: usize,
+    group_commit: Option<Arc<GroupCommit>>,
+}
+
+/// Coalesces syncs requested within `window` of the last one
+///
+/// Writes are always committed to the journal right away; only the fsync is
+/// deferred. Deferred writes become durable on the next sync outside the
+/// window, on `FjallExpiringBackend::flush`, or when the window ends: the
+/// first deferred write starts a timer thread that syncs then, retrying
+/// every window while the sync fails. A failed sync leaves the writes
+/// pending, so a later flush retries them.
+#[cfg(feature = "fjall")]
+struct GroupCommit {
+    window: Duration,
+    last_sync: Mutex<Option<Instant>>,
+    pending: AtomicBool,
+}
+
+#[cfg(feature = "fjall")]
+impl GroupCommit {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_sync: Mutex::new(None),
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Run `sync` unless the last one was within the window; returns the
+    /// delay of the deferred sync when this write started deferring
+    fn persist(
+        &self,
+        sync: impl FnOnce() -> Result<()>,
+    ) -> Result<Option<Duration>> {
+        let mut last_sync = self.lock_last_sync()?;
+        if let Some(elapsed) = last_sync.map(|at| at.elapsed())
+            && elapsed < self.window
+        {
+            let started = !self.pending.swap(true, Ordering::AcqRel);
+            return Ok(started.then(|| self.window - elapsed));
+        }
+
+        // Pending until the sync succeeds, so a failure is retried
+        self.pending.store(true, Ordering::Release);
+        sync()?;
+        *last_sync = Some(Instant::now());
+        self.pending.store(false, Ordering::Release);
+        Ok(None)
+    }
+
+    /// Run `sync` if writes are pending; they stay pending if it fails
+    fn flush(&self, sync: impl FnOnce() -> Result<()>) -> Result<()> {
+        let mut last_sync = self.lock_last_sync()?;
+        if !self.pending.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        sync()?;
+        *last_sync = Some(Instant::now());
+        self.pending.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn lock_last_sync(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, Option<Instant>>> {
+        self.last_sync.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock group commit".to_string())
+        })
+    }
 }
 
 #[cfg(feature = "fjall")]
@@ -183,12 +328,28 @@ impl FjallExpiringBackend {
         let keyspace = Arc::new(config.open().map_err(|e| {
             EbloomError::StorageError(format!("Failed to open Fjall DB: {e}"))
         })?);
+        Self::with_keyspace(keyspace, "", max_levels)
+    }
 
+    /// Backend over partitions of an already open keyspace, each name
+    /// starting with `prefix`
+    ///
+    /// Backends with different prefixes are independent filters sharing
+    /// the keyspace's journal and background workers, see
+    /// `ebloom::keyspace`. The empty prefix is the layout `new` uses.
+    pub fn with_keyspace(
+        keyspace: Arc<fjall::Keyspace>,
+        prefix: &str,
+        max_levels: usize,
+    ) -> Result<Self> {
         let options = fjall::PartitionCreateOptions::default();
 
         let config_partition = Arc::new(
             keyspace
-                .open_partition("expiring_config", options.clone())
+                .open_partition(
+                    &format!("{prefix}expiring_config"),
+                    options.clone(),
+                )
                 .map_err(|e| {
                     EbloomError::StorageError(format!(
                         "Failed to open config partition: {e}",
@@ -198,7 +359,10 @@ impl FjallExpiringBackend {
 
         let metadata_partition = Arc::new(
             keyspace
-                .open_partition("level_metadata", options.clone())
+                .open_partition(
+                    &format!("{prefix}level_metadata"),
+                    options.clone(),
+                )
                 .map_err(|e| {
                     EbloomError::StorageError(format!(
                         "Failed to open metadata partition: {e}"
@@ -214,7 +378,7 @@ impl FjallExpiringBackend {
             let chunks_partition = Arc::new(
                 keyspace
                     .open_partition(
-                        &format!("level_{level}_chunks"),
+                        &format!("{prefix}level_{level}_chunks"),
                         options.clone(),
                     )
                     .map_err(|e| {
@@ -229,7 +393,7 @@ impl FjallExpiringBackend {
             let dirty_partition = Arc::new(
                 keyspace
                     .open_partition(
-                        &format!("level_{level}_dirty"),
+                        &format!("{prefix}level_{level}_dirty"),
                         options.clone(),
                     )
                     .map_err(|e| {
@@ -249,9 +413,18 @@ impl FjallExpiringBackend {
             chunks_partitions,
             dirty_partitions,
             max_levels,
+            group_commit: None,
         })
     }
 
+    /// Coalesce syncs issued within `window` of each other; `None` syncs
+    /// every write
+    pub fn with_group_commit(mut self, window: Option<Duration>) -> Self {
+        self.group_commit =
+            window.map(|window| Arc::new(GroupCommit::new(window)));
+        self
+    }
+
     fn get_chunks_partition(
         &self,
         level: usize,
@@ -279,13 +452,7 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
                 EbloomError::StorageError(format!("Failed to save config: {e}"))
             })?;
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
-            .map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to persist config: {e}"
-                ))
-            })?;
+        self.persist("config")?;
 
         Ok(())
     }
@@ -320,26 +487,48 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
                 ))
             })?;
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
+        self.persist("level metadata")?;
+
+        Ok(())
+    }
+
+    async fn load_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
+        match self.metadata_partition.get("level_metadata") {
+            Ok(Some(metadata_bytes)) => decode_level_metadata(&metadata_bytes),
+            Ok(None) => Ok(vec![]), // No metadata yet
+            Err(e) => Err(EbloomError::StorageError(format!(
+                "Failed to load level metadata: {e}"
+            ))),
+        }
+    }
+
+    async fn save_rotation_log(&self, log: &[RotationRecord]) -> Result<()> {
+        let log_bytes = bincode::encode_to_vec(log, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))?;
+
+        self.metadata_partition
+            .insert("rotation_log", log_bytes)
             .map_err(|e| {
                 EbloomError::StorageError(format!(
-                    "Failed to persist level metadata: {e}"
+                    "Failed to save rotation log: {e}"
                 ))
             })?;
 
+        self.persist("rotation log")?;
+
         Ok(())
     }
 
-    async fn load_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
-        match self.metadata_partition.get("level_metadata") {
-            Ok(Some(metadata_bytes)) => {
-                let metadata = self.deserialize_metadata(&metadata_bytes)?;
-                Ok(metadata)
+    async fn load_rotation_log(&self) -> Result<Vec<RotationRecord>> {
+        match self.metadata_partition.get("rotation_log") {
+            Ok(Some(log_bytes)) => {
+                bincode::decode_from_slice(&log_bytes, decode_config())
+                    .map(|(log, _)| log)
+                    .map_err(|e| EbloomError::SerializationError(e.to_string()))
             }
-            Ok(None) => Ok(vec![]), // No metadata yet
+            Ok(None) => Ok(vec![]), // No rotations logged yet
             Err(e) => Err(EbloomError::StorageError(format!(
-                "Failed to load level metadata: {e}"
+                "Failed to load rotation log: {e}"
             ))),
         }
     }
@@ -362,13 +551,7 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
                 ))
             })?;
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
-            .map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to persist current level: {e}"
-                ))
-            })?;
+        self.persist("current level")?;
 
         Ok(())
     }
@@ -403,60 +586,20 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
             });
         };
 
+        let mut batch = self.keyspace.batch();
         for (chunk_id, chunk_data) in chunks {
             let key = format!("chunk_{chunk_id}");
-            partition.insert(&key, chunk_data).map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to save level {} chunk {}: {e}",
-                    level, chunk_id
-                ))
-            })?;
+            batch.insert(partition, key.as_bytes(), chunk_data.as_slice());
         }
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
-            .map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to persist level {} chunks: {e}",
-                    level
-                ))
-            })?;
-
-        Ok(())
+        self.commit_batch(batch, &format!("level {level} chunks"))
     }
 
     async fn load_level_chunks(
         &self,
         level: usize,
     ) -> Result<Vec<(usize, Vec<u8>)>> {
-        let Some(partition) = self.get_chunks_partition(level) else {
-            return Err(EbloomError::InvalidLevel {
-                level,
-                max_levels: self.max_levels,
-            });
-        };
-
-        let mut chunks = Vec::new();
-        let iter = partition.iter();
-
-        for item in iter {
-            let (key, value) = item.map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to read level {} chunk: {e}",
-                    level
-                ))
-            })?;
-
-            if let Some(chunk_id_str) = key.strip_prefix(b"chunk_")
-                && let Ok(chunk_id_str) = std::str::from_utf8(chunk_id_str)
-                && let Ok(chunk_id) = chunk_id_str.parse::<usize>()
-            {
-                chunks.push((chunk_id, value.to_vec()));
-            }
-        }
-
-        chunks.sort_by_key(|(id, _)| *id);
-        Ok(chunks)
+        self.read_level_chunks(level)
     }
 
     async fn save_dirty_chunks(
@@ -471,60 +614,20 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
             });
         };
 
+        let mut batch = self.keyspace.batch();
         for (chunk_id, chunk_data) in dirty_chunks {
             let key = format!("dirty_{chunk_id}");
-            partition.insert(&key, chunk_data).map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to save level {} dirty chunk {}: {e}",
-                    level, chunk_id
-                ))
-            })?;
+            batch.insert(partition, key.as_bytes(), chunk_data.as_slice());
         }
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
-            .map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to persist level {} dirty chunks: {e}",
-                    level
-                ))
-            })?;
-
-        Ok(())
+        self.commit_batch(batch, &format!("level {level} dirty chunks"))
     }
 
     async fn load_dirty_chunks(
         &self,
         level: usize,
     ) -> Result<Vec<(usize, Vec<u8>)>> {
-        let Some(partition) = self.get_dirty_partition(level) else {
-            return Err(EbloomError::InvalidLevel {
-                level,
-                max_levels: self.max_levels,
-            });
-        };
-
-        let mut chunks = Vec::new();
-        let iter = partition.iter();
-
-        for item in iter {
-            let (key, value) = item.map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to read level {} dirty chunk: {e}",
-                    level
-                ))
-            })?;
-
-            if let Some(chunk_id_str) = key.strip_prefix(b"dirty_")
-                && let Ok(chunk_id_str) = std::str::from_utf8(chunk_id_str)
-                && let Ok(chunk_id) = chunk_id_str.parse::<usize>()
-            {
-                chunks.push((chunk_id, value.to_vec()));
-            }
-        }
-
-        chunks.sort_by_key(|(id, _)| *id);
-        Ok(chunks)
+        self.read_dirty_chunks(level)
     }
 
     async fn delete_level(&self, level: usize) -> Result<()> {
@@ -582,29 +685,421 @@ impl ExpiringStorageBackend for FjallExpiringBackend {
             }
         }
 
-        self.keyspace
-            .persist(fjall::PersistMode::SyncAll)
-            .map_err(|e| {
-                EbloomError::StorageError(format!(
-                    "Failed to persist level {} deletion: {e}",
-                    level
-                ))
-            })?;
+        self.persist(&format!("level {level} deletion"))?;
 
         Ok(())
     }
+    async fn commit_snapshot(&self, batch: SnapshotBatch) -> Result<()> {
+        self.write_snapshot(&batch)
+    }
 }
 
 #[cfg(feature = "fjall")]
 impl FjallExpiringBackend {
+    /// Blocking form of `load_level_chunks`
+    pub fn read_level_chunks(&self, level: usize) -> Result<LevelChunks> {
+        let partition = self.level_partition(level, false)?;
+        let mut chunks = Vec::new();
+        scan_chunks(partition, b"chunk_", |chunk_id, bytes| {
+            chunks.push((chunk_id, bytes.to_vec()));
+            Ok(())
+        })
+        .map_err(|e| read_error(e, level, "chunk"))?;
+        chunks.sort_by_key(|(id, _)| *id);
+        Ok(chunks)
+    }
+
+    /// Blocking form of `load_dirty_chunks`
+    pub fn read_dirty_chunks(&self, level: usize) -> Result<LevelChunks> {
+        let partition = self.level_partition(level, true)?;
+        let mut chunks = Vec::new();
+        scan_chunks(partition, b"dirty_", |chunk_id, bytes| {
+            chunks.push((chunk_id, bytes.to_vec()));
+            Ok(())
+        })
+        .map_err(|e| read_error(e, level, "dirty chunk"))?;
+        chunks.sort_by_key(|(id, _)| *id);
+        Ok(chunks)
+    }
+
+    /// Chunks to rebuild a level from: dirty chunks when present, the last
+    /// full snapshot otherwise
+    pub fn read_level(&self, level: usize) -> Result<LevelChunks> {
+        let dirty_chunks = self.read_dirty_chunks(level)?;
+        if !dirty_chunks.is_empty() {
+            return Ok(dirty_chunks);
+        }
+        self.read_level_chunks(level)
+    }
+
+    /// Streaming form of `read_level`: hand each chunk to `apply` as it is
+    /// read, in no particular order, and return how many there were
+    ///
+    /// Only one chunk is held at a time, so rebuilding a level needs no
+    /// memory beyond the level itself. Stops at the first error `apply`
+    /// returns.
+    pub fn stream_level<F>(&self, level: usize, apply: F) -> Result<usize>
+    where
+        F: FnMut(usize, &[u8]) -> Result<()>,
+    {
+        let dirty = self.level_partition(level, true)?;
+        let has_dirty = !dirty.is_empty().map_err(|e| {
+            EbloomError::StorageError(format!(
+                "Failed to read level {level} dirty chunks: {e}"
+            ))
+        })?;
+        if has_dirty {
+            scan_chunks(dirty, b"dirty_", apply)
+                .map_err(|e| read_error(e, level, "dirty chunk"))
+        } else {
+            scan_chunks(self.level_partition(level, false)?, b"chunk_", apply)
+                .map_err(|e| read_error(e, level, "chunk"))
+        }
+    }
+
+    /// Chunk or dirty chunk partition of `level`
+    fn level_partition(
+        &self,
+        level: usize,
+        dirty: bool,
+    ) -> Result<&Arc<fjall::Partition>> {
+        let partition = if dirty {
+            self.get_dirty_partition(level)
+        } else {
+            self.get_chunks_partition(level)
+        };
+        partition.ok_or(EbloomError::InvalidLevel {
+            level,
+            max_levels: self.max_levels,
+        })
+    }
+
+    /// Blocking form of `commit_snapshot`, for callers outside async code
+    pub fn write_snapshot(&self, batch: &SnapshotBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut write = self.keyspace.batch();
+        for (level, chunks) in &batch.level_chunks {
+            let Some(partition) = self.get_chunks_partition(*level) else {
+                return Err(EbloomError::InvalidLevel {
+                    level: *level,
+                    max_levels: self.max_levels,
+                });
+            };
+            for (chunk_id, chunk_data) in chunks {
+                let key = format!("chunk_{chunk_id}");
+                write.insert(partition, key.as_bytes(), chunk_data.as_slice());
+            }
+        }
+        for (level, chunks) in &batch.dirty_chunks {
+            let Some(partition) = self.get_dirty_partition(*level) else {
+                return Err(EbloomError::InvalidLevel {
+                    level: *level,
+                    max_levels: self.max_levels,
+                });
+            };
+            for (chunk_id, chunk_data) in chunks {
+                let key = format!("dirty_{chunk_id}");
+                write.insert(partition, key.as_bytes(), chunk_data.as_slice());
+            }
+        }
+        if let Some(ref metadata) = batch.metadata {
+            let metadata_bytes = self.serialize_metadata(metadata)?;
+            write.insert(
+                &self.metadata_partition,
+                "level_metadata",
+                metadata_bytes,
+            );
+        }
+
+        self.commit_batch(write, "snapshot")
+    }
+
+    /// Commit a write batch and sync it to disk once
+    fn commit_batch(&self, batch: fjall::Batch, what: &str) -> Result<()> {
+        batch.commit().map_err(|e| {
+            EbloomError::StorageError(format!("Failed to save {what}: {e}"))
+        })?;
+
+        self.persist(what)
+    }
+
+    /// Sync to disk, or defer when group commit synced within its window
+    fn persist(&self, what: &str) -> Result<()> {
+        let Some(ref group) = self.group_commit else {
+            return self.sync_all(what);
+        };
+        if let Some(delay) = group.persist(|| self.sync_all(what))? {
+            self.schedule_sync(group, delay);
+        }
+        Ok(())
+    }
+
+    /// Sync deferred writes from a timer thread once `delay` has passed
+    ///
+    /// The thread holds no strong reference, so it does not keep a dropped
+    /// backend's database open.
+    fn schedule_sync(&self, group: &Arc<GroupCommit>, delay: Duration) {
+        let keyspace = Arc::downgrade(&self.keyspace);
+        let weak_group = Arc::downgrade(group);
+        let spawned = std::thread::Builder::new()
+            .name("ebloom-group-commit".to_string())
+            .spawn(move || {
+                let mut delay = delay;
+                loop {
+                    std::thread::sleep(delay);
+                    let (Some(keyspace), Some(group)) =
+                        (keyspace.upgrade(), weak_group.upgrade())
+                    else {
+                        return;
+                    };
+                    match group
+                        .flush(|| sync_keyspace(&keyspace, "deferred writes"))
+                    {
+                        Ok(()) => return,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Group commit sync failed, retrying");
+                            delay = group.window;
+                        }
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!(error = %e, "Failed to start group commit timer; writes stay pending until flush");
+        }
+    }
+
+    fn sync_all(&self, what: &str) -> Result<()> {
+        sync_keyspace(&self.keyspace, what)
+    }
+
+    /// Sync writes deferred by group commit; on failure they stay pending
+    pub fn flush(&self) -> Result<()> {
+        match self.group_commit {
+            Some(ref group) => group.flush(|| self.sync_all("pending writes")),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the database accepts writes and serves reads
+    ///
+    /// Writes a small probe key and reads it back. The write is not synced,
+    /// so probing is cheap enough for a readiness check.
+    pub fn probe(&self) -> Result<()> {
+        const PROBE_KEY: &str = "health_probe";
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_le_bytes();
+
+        self.config_partition
+            .insert(PROBE_KEY, nonce)
+            .map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Health probe write failed: {e}"
+                ))
+            })?;
+        match self.config_partition.get(PROBE_KEY) {
+            Ok(Some(bytes)) if *bytes == nonce => Ok(()),
+            Ok(_) => Err(EbloomError::StorageError(
+                "Health probe read back stale data".to_string(),
+            )),
+            Err(e) => Err(EbloomError::StorageError(format!(
+                "Health probe read failed: {e}"
+            ))),
+        }
+    }
+
+    /// Whether group commit is holding back a sync
+    pub fn has_pending_sync(&self) -> bool {
+        self.group_commit
+            .as_ref()
+            .is_some_and(|group| group.pending.load(Ordering::Acquire))
+    }
+
     fn serialize_metadata(&self, metadata: &[LevelMetadata]) -> Result<Vec<u8>> {
         bincode::encode_to_vec(metadata, bincode::config::standard())
             .map_err(|e| EbloomError::SerializationError(e.to_string()))
     }
+}
 
-    fn deserialize_metadata(&self, bytes: &[u8]) -> Result<Vec<LevelMetadata>> {
-        bincode::decode_from_slice(bytes, bincode::config::standard())
-            .map(|(metadata, _)| metadata)
-            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+#[cfg(feature = "fjall")]
+fn sync_keyspace(keyspace: &fjall::Keyspace, what: &str) -> Result<()> {
+    keyspace.persist(fjall::PersistMode::SyncAll).map_err(|e| {
+        EbloomError::StorageError(format!("Failed to persist {what}: {e}"))
+    })
+}
+
+/// Why a chunk scan stopped: the partition failed to read, or the
+/// callback returned an error
+#[cfg(feature = "fjall")]
+enum ScanError {
+    Read(fjall::Error),
+    Apply(EbloomError),
+}
+
+/// Call `apply` with the id and bytes of every chunk keyed `<key_prefix><id>`
+/// in `partition`, returning how many there were; other keys are skipped
+#[cfg(feature = "fjall")]
+fn scan_chunks<F>(
+    partition: &fjall::Partition,
+    key_prefix: &[u8],
+    mut apply: F,
+) -> std::result::Result<usize, ScanError>
+where
+    F: FnMut(usize, &[u8]) -> Result<()>,
+{
+    let mut count = 0;
+    for item in partition.iter() {
+        let (key, value) = item.map_err(ScanError::Read)?;
+        if let Some(chunk_id_str) = key.strip_prefix(key_prefix)
+            && let Ok(chunk_id_str) = std::str::from_utf8(chunk_id_str)
+            && let Ok(chunk_id) = chunk_id_str.parse::<usize>()
+        {
+            apply(chunk_id, &value).map_err(ScanError::Apply)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(feature = "fjall")]
+fn read_error(error: ScanError, level: usize, what: &str) -> EbloomError {
+    match error {
+        ScanError::Read(e) => EbloomError::StorageError(format!(
+            "Failed to read level {level} {what}: {e}"
+        )),
+        ScanError::Apply(e) => e,
+    }
+}
+
+/// Decode level metadata as written by `FjallExpiringBackend`
+#[cfg(any(feature = "fjall", fuzzing))]
+pub(crate) fn decode_level_metadata(bytes: &[u8]) -> Result<Vec<LevelMetadata>> {
+    bincode::decode_from_slice(bytes, decode_config())
+        .map(|(metadata, _)| metadata)
+        .map_err(|e| EbloomError::SerializationError(e.to_string()))
+}
+
+/// Backend a persistent `ExpiringBloomFilter` writes its snapshots to
+///
+/// Dereferences to the storage trait, so most of the filter does not care
+/// which backend it has. Local Fjall keeps its blocking fast paths (parallel
+/// level reads, write-behind).
+#[cfg(feature = "fjall")]
+pub(crate) enum FilterStorage {
+    Fjall(Arc<FjallExpiringBackend>),
+    /// Remote database served by `grpc::storage::ExpiringStorageService`
+    #[cfg(feature = "grpc")]
+    Remote(Arc<crate::grpc::storage::GrpcStorageBackend>),
+    /// Any other backend, e.g. `test_support::MockStorage`
+    Custom(Arc<dyn ExpiringStorageBackend + Send + Sync>),
+}
+
+#[cfg(feature = "fjall")]
+impl FilterStorage {
+    /// Local database, for the paths that need blocking access
+    pub(crate) fn fjall(&self) -> Option<&Arc<FjallExpiringBackend>> {
+        match self {
+            Self::Fjall(backend) => Some(backend),
+            #[cfg(feature = "grpc")]
+            Self::Remote(_) => None,
+            Self::Custom(_) => None,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Self::Fjall(_) => "fjall",
+            #[cfg(feature = "grpc")]
+            Self::Remote(_) => "grpc",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// See `FjallExpiringBackend::probe`; a remote backend reports the
+    /// outcome of its last call instead of probing; custom backends are
+    /// assumed healthy
+    pub(crate) fn probe(&self) -> Result<()> {
+        match self {
+            Self::Fjall(backend) => backend.probe(),
+            #[cfg(feature = "grpc")]
+            Self::Remote(backend) => backend.last_error(),
+            Self::Custom(_) => Ok(()),
+        }
+    }
+
+    /// Sync writes deferred by group commit; remote writes are synced by
+    /// the server before it answers
+    pub(crate) fn flush(&self) -> Result<()> {
+        match self {
+            Self::Fjall(backend) => backend.flush(),
+            #[cfg(feature = "grpc")]
+            Self::Remote(_) => Ok(()),
+            Self::Custom(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "fjall")]
+impl std::ops::Deref for FilterStorage {
+    type Target = dyn ExpiringStorageBackend + Send + Sync;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Fjall(backend) => backend.as_ref(),
+            #[cfg(feature = "grpc")]
+            Self::Remote(backend) => backend.as_ref(),
+            Self::Custom(backend) => backend.as_ref(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fjall"))]
+mod tests {
+    use super::*;
+
+    fn failing_sync() -> Result<()> {
+        Err(EbloomError::StorageError("disk full".to_string()))
+    }
+
+    #[test]
+    fn test_group_commit_keeps_pending_after_failed_flush() {
+        let group = GroupCommit::new(Duration::from_secs(60));
+        assert_eq!(group.persist(|| Ok(())).unwrap(), None);
+        assert_eq!(
+            group
+                .persist(|| Ok(()))
+                .unwrap()
+                .map(|d| d > Duration::ZERO),
+            Some(true)
+        );
+        // Only the first deferred write starts a timer
+        assert_eq!(group.persist(|| Ok(())).unwrap(), None);
+
+        assert!(group.flush(failing_sync).is_err());
+        assert!(group.pending.load(Ordering::Acquire));
+        group.flush(|| Ok(())).unwrap();
+        assert!(!group.pending.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn test_group_commit_keeps_pending_after_failed_sync() {
+        let group = GroupCommit::new(Duration::ZERO);
+        assert!(group.persist(failing_sync).is_err());
+        assert!(group.pending.load(Ordering::Acquire));
+
+        let mut synced = false;
+        group
+            .flush(|| {
+                synced = true;
+                Ok(())
+            })
+            .unwrap();
+        assert!(synced);
+        assert!(!group.pending.load(Ordering::Acquire));
     }
 }
@@ -0,0 +1,27 @@
+//! Synchronization primitives of the concurrent filter core
+//!
+//! Level bits, the current level pointer and level metadata use these
+//! instead of `std::sync` directly, so that building with
+//! `RUSTFLAGS="--cfg ebloom_loom"` swaps in [loom]'s versions and the
+//! interleavings of inserts, queries, rotations and snapshots can be
+//! explored exhaustively (see `tests/loom_tests.rs`).
+//!
+//! A crate-specific cfg is used rather than `loom`, which would also switch
+//! dependencies such as tokio into their own loom mode.
+//!
+//! [loom]: https://docs.rs/loom
+
+#[cfg(all(ebloom_loom, feature = "mmap"))]
+compile_error!("loom models build without the `mmap` feature");
+
+#[cfg(ebloom_loom)]
+pub(crate) use loom::sync::{
+    Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, fence},
+};
+
+#[cfg(not(ebloom_loom))]
+pub(crate) use std::sync::{
+    Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, fence},
+};
@@ -0,0 +1,431 @@
+//! Deterministic helpers for testing code built on expiring filters
+//! (`test_support` feature)
+//!
+//! Filters hash without random seeds, so with a [`MockClock`] and keys from
+//! [`seeded_keys`] every run sees the same bits, rotations and false
+//! positives. Expiry is driven by advancing the clock instead of sleeping:
+//!
+//! ```ignore
+//! let (filter, clock) = filter_with_mock_clock(config)?;
+//! filter.insert(b"key")?;
+//! expire_all(&filter, &clock).await?;
+//! assert!(!filter.contains(b"key")?);
+//! ```
+//!
+//! [`MockStorage`] is an in-memory `ExpiringStorageBackend` that records
+//! every call and can be made to fail or stall, for testing snapshot and
+//! recovery paths, or code written against the trait, without a database.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::events::RotationRecord;
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::storage::{
+    ExpiringStorageBackend, LevelChunks, SnapshotBatch,
+};
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+pub use crate::ebloom::clock::ManualClock as MockClock;
+
+pub mod crash;
+pub mod retention;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+
+/// Start time of [`mock_clock`]: 2023-11-14T22:13:20Z
+pub const MOCK_EPOCH_MS: u64 = 1_700_000_000_000;
+
+/// Clock fixed at [`MOCK_EPOCH_MS`] until advanced
+pub fn mock_clock() -> Arc<MockClock> {
+    Arc::new(MockClock::new(MOCK_EPOCH_MS))
+}
+
+/// In-memory filter driven by a fresh [`mock_clock`]
+pub fn filter_with_mock_clock(
+    config: ExpiringFilterConfig,
+) -> Result<(ExpiringBloomFilter, Arc<MockClock>)> {
+    let clock = mock_clock();
+    let filter = ExpiringBloomFilter::with_clock(config, clock.clone())?;
+    Ok((filter, clock))
+}
+
+/// Filter persisted to a fresh [`MockStorage`], driven by a [`mock_clock`]
+///
+/// A config without persistence gets the default chunk size.
+#[cfg(feature = "fjall")]
+pub async fn filter_with_mock_storage(
+    mut config: ExpiringFilterConfig,
+) -> Result<(ExpiringBloomFilter, Arc<MockClock>, Arc<MockStorage>)> {
+    if config.persistence.is_none() {
+        config.persistence = Some(
+            crate::ebloom::config::ExpiringPersistenceConfigBuilder::default()
+                .db_path(std::path::PathBuf::from("mock-storage"))
+                .build()
+                .map_err(|e| EbloomError::ConfigError(e.to_string()))?,
+        );
+    }
+    let clock = mock_clock();
+    let storage = Arc::new(MockStorage::new());
+    let filter = ExpiringBloomFilter::create_with_storage(
+        config,
+        clock.clone(),
+        storage.clone(),
+    )
+    .await?;
+    Ok((filter, clock, storage))
+}
+
+/// Advance `clock` by `by` and run the filter's expiry
+pub async fn advance_and_cleanup(
+    filter: &impl ExpiringBloomFilterOps,
+    clock: &MockClock,
+    by: Duration,
+) -> Result<()> {
+    clock.advance(by);
+    filter.cleanup_expired_levels().await
+}
+
+/// Advance through every level's window so nothing inserted so far is left
+pub async fn expire_all(
+    filter: &ExpiringBloomFilter,
+    clock: &MockClock,
+) -> Result<()> {
+    let config = filter.config();
+    // Expiry needs the window to be strictly exceeded
+    let step = config.duration_for_age(0) + Duration::from_millis(1);
+    for _ in 0..config.num_levels {
+        advance_and_cleanup(filter, clock, step).await?;
+    }
+    Ok(())
+}
+
+/// `count` distinct-looking 16-byte keys, the same for the same `seed`
+pub fn seeded_keys(seed: u64, count: usize) -> Vec<Vec<u8>> {
+    (0..count as u64)
+        .map(|index| seeded_key(seed, index))
+        .collect()
+}
+
+/// Key number `index` of [`seeded_keys`]
+pub fn seeded_key(seed: u64, index: u64) -> Vec<u8> {
+    let mut state = seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let mut key = Vec::with_capacity(16);
+    key.extend_from_slice(&splitmix64(&mut state).to_le_bytes());
+    key.extend_from_slice(&splitmix64(&mut state).to_le_bytes());
+    key
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Storage calls [`MockStorage`] records, and can fail or delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageOp {
+    SaveConfig,
+    LoadConfig,
+    SaveLevelMetadata,
+    LoadLevelMetadata,
+    SaveCurrentLevel,
+    LoadCurrentLevel,
+    SaveLevelChunks,
+    LoadLevelChunks,
+    SaveDirtyChunks,
+    LoadDirtyChunks,
+    DeleteLevel,
+    SaveRotationLog,
+    LoadRotationLog,
+    CommitSnapshot,
+}
+
+/// One call made to a [`MockStorage`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageCall {
+    pub op: StorageOp,
+    /// Level the call was for, if it takes one
+    pub level: Option<usize>,
+    /// Whether an injected fault failed the call
+    pub failed: bool,
+}
+
+/// In-memory storage backend with call recording and fault injection
+///
+/// Keeps everything a filter persists, so a filter can be reloaded from it
+/// with `ExpiringBloomFilter::load_with_storage`. Snapshots are committed
+/// all or nothing, like the Fjall backend.
+#[derive(Default)]
+pub struct MockStorage {
+    state: Mutex<MockState>,
+    /// Pending injected errors per call, returned in order
+    faults: Mutex<HashMap<StorageOp, VecDeque<EbloomError>>>,
+    fail_all: AtomicBool,
+    latency: Mutex<HashMap<StorageOp, Duration>>,
+    calls: Mutex<Vec<StorageCall>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    config: Option<ExpiringFilterConfig>,
+    metadata: Vec<LevelMetadata>,
+    current_level: usize,
+    level_chunks: HashMap<usize, HashMap<usize, Vec<u8>>>,
+    dirty_chunks: HashMap<usize, HashMap<usize, Vec<u8>>>,
+    rotation_log: Vec<RotationRecord>,
+}
+
+impl MockStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the next `times` calls of `op` with a `StorageError`
+    pub fn fail_next(&self, op: StorageOp, times: usize) {
+        for _ in 0..times {
+            self.fail_next_with(
+                op,
+                EbloomError::StorageError(format!("Injected {op:?} failure")),
+            );
+        }
+    }
+
+    /// Fail the next call of `op` with `error`, after any already queued
+    pub fn fail_next_with(&self, op: StorageOp, error: EbloomError) {
+        if let Ok(mut faults) = self.faults.lock() {
+            faults.entry(op).or_default().push_back(error);
+        }
+    }
+
+    /// Fail every call until switched off, like an unreachable database
+    pub fn fail_all(&self, fail: bool) {
+        self.fail_all.store(fail, Ordering::SeqCst);
+    }
+
+    /// Delay every call of `op` by `latency`; zero removes the delay
+    ///
+    /// The delay is a tokio sleep, so it honours a paused test clock.
+    pub fn set_latency(&self, op: StorageOp, latency: Duration) {
+        if let Ok(mut delays) = self.latency.lock() {
+            if latency.is_zero() {
+                delays.remove(&op);
+            } else {
+                delays.insert(op, latency);
+            }
+        }
+    }
+
+    /// How often `op` was called, failed calls included
+    pub fn calls(&self, op: StorageOp) -> usize {
+        self.calls
+            .lock()
+            .map_or(0, |calls| calls.iter().filter(|call| call.op == op).count())
+    }
+
+    /// Every call so far, oldest first
+    pub fn call_log(&self) -> Vec<StorageCall> {
+        self.calls
+            .lock()
+            .map(|calls| calls.clone())
+            .unwrap_or_default()
+    }
+
+    /// Forget recorded calls; stored data and pending faults are kept
+    pub fn clear_calls(&self) {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.clear();
+        }
+    }
+
+    /// Stored chunks of a level, sorted by chunk id
+    pub fn level_chunks(&self, level: usize) -> LevelChunks {
+        self.state
+            .lock()
+            .map(|state| sorted(state.level_chunks.get(&level)))
+            .unwrap_or_default()
+    }
+
+    /// Stored dirty chunks of a level, sorted by chunk id
+    pub fn dirty_chunks(&self, level: usize) -> LevelChunks {
+        self.state
+            .lock()
+            .map(|state| sorted(state.dirty_chunks.get(&level)))
+            .unwrap_or_default()
+    }
+
+    /// Apply the call's latency, record it, then fail it if a fault is
+    /// pending
+    async fn check(&self, op: StorageOp, level: Option<usize>) -> Result<()> {
+        let latency = self
+            .latency
+            .lock()
+            .ok()
+            .and_then(|delays| delays.get(&op).copied());
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+
+        let injected = if self.fail_all.load(Ordering::SeqCst) {
+            Some(EbloomError::StorageError(format!(
+                "Injected {op:?} failure"
+            )))
+        } else {
+            self.faults
+                .lock()
+                .ok()
+                .and_then(|mut faults| faults.get_mut(&op)?.pop_front())
+        };
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(StorageCall {
+                op,
+                level,
+                failed: injected.is_some(),
+            });
+        }
+        match injected {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn lock_state(&self) -> Result<std::sync::MutexGuard<'_, MockState>> {
+        self.state.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock mock storage".to_string())
+        })
+    }
+}
+
+fn sorted(chunks: Option<&HashMap<usize, Vec<u8>>>) -> LevelChunks {
+    let mut chunks: LevelChunks = chunks
+        .into_iter()
+        .flatten()
+        .map(|(&id, bytes)| (id, bytes.clone()))
+        .collect();
+    chunks.sort_unstable_by_key(|&(id, _)| id);
+    chunks
+}
+
+fn upsert(
+    partition: &mut HashMap<usize, HashMap<usize, Vec<u8>>>,
+    level: usize,
+    chunks: &[(usize, Vec<u8>)],
+) {
+    partition
+        .entry(level)
+        .or_default()
+        .extend(chunks.iter().cloned());
+}
+
+#[async_trait]
+impl ExpiringStorageBackend for MockStorage {
+    async fn save_config(&self, config: &ExpiringFilterConfig) -> Result<()> {
+        self.check(StorageOp::SaveConfig, None).await?;
+        self.lock_state()?.config = Some(config.clone());
+        Ok(())
+    }
+
+    async fn load_config(&self) -> Result<ExpiringFilterConfig> {
+        self.check(StorageOp::LoadConfig, None).await?;
+        self.lock_state()?.config.clone().ok_or_else(|| {
+            EbloomError::ConfigError("No config found".to_string())
+        })
+    }
+
+    async fn save_level_metadata(
+        &self,
+        metadata: &[LevelMetadata],
+    ) -> Result<()> {
+        self.check(StorageOp::SaveLevelMetadata, None).await?;
+        self.lock_state()?.metadata = metadata.to_vec();
+        Ok(())
+    }
+
+    async fn load_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
+        self.check(StorageOp::LoadLevelMetadata, None).await?;
+        Ok(self.lock_state()?.metadata.clone())
+    }
+
+    async fn save_current_level(&self, current_level: usize) -> Result<()> {
+        self.check(StorageOp::SaveCurrentLevel, None).await?;
+        self.lock_state()?.current_level = current_level;
+        Ok(())
+    }
+
+    async fn load_current_level(&self) -> Result<usize> {
+        self.check(StorageOp::LoadCurrentLevel, None).await?;
+        Ok(self.lock_state()?.current_level)
+    }
+
+    async fn save_level_chunks(
+        &self,
+        level: usize,
+        chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        self.check(StorageOp::SaveLevelChunks, Some(level)).await?;
+        upsert(&mut self.lock_state()?.level_chunks, level, chunks);
+        Ok(())
+    }
+
+    async fn load_level_chunks(&self, level: usize) -> Result<LevelChunks> {
+        self.check(StorageOp::LoadLevelChunks, Some(level)).await?;
+        Ok(sorted(self.lock_state()?.level_chunks.get(&level)))
+    }
+
+    async fn save_dirty_chunks(
+        &self,
+        level: usize,
+        dirty_chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        self.check(StorageOp::SaveDirtyChunks, Some(level)).await?;
+        upsert(&mut self.lock_state()?.dirty_chunks, level, dirty_chunks);
+        Ok(())
+    }
+
+    async fn load_dirty_chunks(&self, level: usize) -> Result<LevelChunks> {
+        self.check(StorageOp::LoadDirtyChunks, Some(level)).await?;
+        Ok(sorted(self.lock_state()?.dirty_chunks.get(&level)))
+    }
+
+    async fn delete_level(&self, level: usize) -> Result<()> {
+        self.check(StorageOp::DeleteLevel, Some(level)).await?;
+        let mut state = self.lock_state()?;
+        state.level_chunks.remove(&level);
+        state.dirty_chunks.remove(&level);
+        Ok(())
+    }
+
+    async fn save_rotation_log(&self, log: &[RotationRecord]) -> Result<()> {
+        self.check(StorageOp::SaveRotationLog, None).await?;
+        self.lock_state()?.rotation_log = log.to_vec();
+        Ok(())
+    }
+
+    async fn load_rotation_log(&self) -> Result<Vec<RotationRecord>> {
+        self.check(StorageOp::LoadRotationLog, None).await?;
+        Ok(self.lock_state()?.rotation_log.clone())
+    }
+
+    async fn commit_snapshot(&self, batch: SnapshotBatch) -> Result<()> {
+        self.check(StorageOp::CommitSnapshot, None).await?;
+        let mut state = self.lock_state()?;
+        for (level, chunks) in &batch.level_chunks {
+            upsert(&mut state.level_chunks, *level, chunks);
+        }
+        for (level, chunks) in &batch.dirty_chunks {
+            upsert(&mut state.dirty_chunks, *level, chunks);
+        }
+        if let Some(metadata) = batch.metadata {
+            state.metadata = metadata;
+        }
+        Ok(())
+    }
+}
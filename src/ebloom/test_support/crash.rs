@@ -0,0 +1,242 @@
+//! Crash injection for storage backends (`test_support` feature)
+//!
+//! [`CrashStorage`] wraps any `ExpiringStorageBackend` and lets a fixed
+//! number of writes through. The next write is the crash: depending on the
+//! [`CrashMode`] it is lost, lands with every chunk cut in half, or lands
+//! without its level metadata. It and every later write then fail, as if the
+//! process had died, while reads keep working.
+//!
+//! Sweeping the crash point over every write of a scenario, then loading
+//! the database with a fresh backend, checks that recovery never sees a
+//! state it cannot handle:
+//!
+//! ```ignore
+//! for crash_after in 0.. {
+//!     let storage = Arc::new(CrashStorage::new(fjall, crash_after, CrashMode::Lost));
+//!     run_scenario(&storage).await;
+//!     if !storage.crashed() { break; }
+//!     let loaded = ExpiringBloomFilter::load(path.clone()).await;
+//! }
+//! ```
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::events::RotationRecord;
+use crate::ebloom::storage::{
+    ExpiringStorageBackend, LevelChunks, SnapshotBatch,
+};
+
+/// What happens to the write a [`CrashStorage`] crashes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashMode {
+    /// The write never reaches the backend
+    Lost,
+    /// Chunk writes land with only the first half of every chunk; other
+    /// writes are lost
+    TornChunks,
+    /// Snapshots land without their level metadata; other writes are lost
+    MissingMetadata,
+}
+
+/// Storage wrapper that crashes after a number of writes
+pub struct CrashStorage<B> {
+    inner: B,
+    crash_after: usize,
+    mode: CrashMode,
+    /// Writes seen so far, the crashing one and later ones included
+    writes: Mutex<usize>,
+}
+
+/// Whether a write goes through, goes through damaged, or is dropped
+enum WriteOutcome {
+    Apply,
+    Crash,
+    Dead,
+}
+
+impl<B: ExpiringStorageBackend> CrashStorage<B> {
+    /// Let `crash_after` writes through, then crash on the next one
+    pub fn new(inner: B, crash_after: usize, mode: CrashMode) -> Self {
+        Self {
+            inner,
+            crash_after,
+            mode,
+            writes: Mutex::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Whether the crash point was reached
+    pub fn crashed(&self) -> bool {
+        self.writes() > self.crash_after
+    }
+
+    /// Writes attempted so far
+    pub fn writes(&self) -> usize {
+        self.writes.lock().map_or(0, |writes| *writes)
+    }
+
+    fn next_write(&self) -> Result<WriteOutcome> {
+        let mut writes = self.writes.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock crash storage".to_string())
+        })?;
+        let index = *writes;
+        *writes += 1;
+        Ok(match index.cmp(&self.crash_after) {
+            std::cmp::Ordering::Less => WriteOutcome::Apply,
+            std::cmp::Ordering::Equal => WriteOutcome::Crash,
+            std::cmp::Ordering::Greater => WriteOutcome::Dead,
+        })
+    }
+
+    /// Run a write that has no chunks or metadata to damage
+    async fn plain_write<F>(&self, write: F) -> Result<()>
+    where
+        F: std::future::Future<Output = Result<()>>,
+    {
+        match self.next_write()? {
+            WriteOutcome::Apply => write.await,
+            WriteOutcome::Crash | WriteOutcome::Dead => Err(crashed()),
+        }
+    }
+}
+
+fn crashed() -> EbloomError {
+    EbloomError::StorageError("Storage crashed".to_string())
+}
+
+fn torn(chunks: &[(usize, Vec<u8>)]) -> LevelChunks {
+    chunks
+        .iter()
+        .map(|(id, bytes)| (*id, bytes[..bytes.len() / 2].to_vec()))
+        .collect()
+}
+
+#[async_trait]
+impl<B> ExpiringStorageBackend for CrashStorage<B>
+where
+    B: ExpiringStorageBackend + Send + Sync,
+{
+    async fn save_config(&self, config: &ExpiringFilterConfig) -> Result<()> {
+        self.plain_write(self.inner.save_config(config)).await
+    }
+
+    async fn load_config(&self) -> Result<ExpiringFilterConfig> {
+        self.inner.load_config().await
+    }
+
+    async fn save_level_metadata(
+        &self,
+        metadata: &[LevelMetadata],
+    ) -> Result<()> {
+        self.plain_write(self.inner.save_level_metadata(metadata))
+            .await
+    }
+
+    async fn load_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
+        self.inner.load_level_metadata().await
+    }
+
+    async fn save_current_level(&self, current_level: usize) -> Result<()> {
+        self.plain_write(self.inner.save_current_level(current_level))
+            .await
+    }
+
+    async fn load_current_level(&self) -> Result<usize> {
+        self.inner.load_current_level().await
+    }
+
+    async fn save_level_chunks(
+        &self,
+        level: usize,
+        chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        match self.next_write()? {
+            WriteOutcome::Apply => {
+                self.inner.save_level_chunks(level, chunks).await
+            }
+            WriteOutcome::Crash if self.mode == CrashMode::TornChunks => {
+                self.inner.save_level_chunks(level, &torn(chunks)).await?;
+                Err(crashed())
+            }
+            WriteOutcome::Crash | WriteOutcome::Dead => Err(crashed()),
+        }
+    }
+
+    async fn load_level_chunks(&self, level: usize) -> Result<LevelChunks> {
+        self.inner.load_level_chunks(level).await
+    }
+
+    async fn save_dirty_chunks(
+        &self,
+        level: usize,
+        dirty_chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        match self.next_write()? {
+            WriteOutcome::Apply => {
+                self.inner.save_dirty_chunks(level, dirty_chunks).await
+            }
+            WriteOutcome::Crash if self.mode == CrashMode::TornChunks => {
+                self.inner
+                    .save_dirty_chunks(level, &torn(dirty_chunks))
+                    .await?;
+                Err(crashed())
+            }
+            WriteOutcome::Crash | WriteOutcome::Dead => Err(crashed()),
+        }
+    }
+
+    async fn load_dirty_chunks(&self, level: usize) -> Result<LevelChunks> {
+        self.inner.load_dirty_chunks(level).await
+    }
+
+    async fn delete_level(&self, level: usize) -> Result<()> {
+        self.plain_write(self.inner.delete_level(level)).await
+    }
+
+    async fn save_rotation_log(&self, log: &[RotationRecord]) -> Result<()> {
+        self.plain_write(self.inner.save_rotation_log(log)).await
+    }
+
+    async fn load_rotation_log(&self) -> Result<Vec<RotationRecord>> {
+        self.inner.load_rotation_log().await
+    }
+
+    async fn commit_snapshot(&self, batch: SnapshotBatch) -> Result<()> {
+        match self.next_write()? {
+            WriteOutcome::Apply => self.inner.commit_snapshot(batch).await,
+            WriteOutcome::Crash => {
+                let damaged = match self.mode {
+                    CrashMode::Lost => return Err(crashed()),
+                    CrashMode::TornChunks => SnapshotBatch {
+                        level_chunks: batch
+                            .level_chunks
+                            .iter()
+                            .map(|(level, chunks)| (*level, torn(chunks)))
+                            .collect(),
+                        dirty_chunks: batch
+                            .dirty_chunks
+                            .iter()
+                            .map(|(level, chunks)| (*level, torn(chunks)))
+                            .collect(),
+                        metadata: batch.metadata,
+                    },
+                    CrashMode::MissingMetadata => SnapshotBatch {
+                        metadata: None,
+                        ..batch
+                    },
+                };
+                self.inner.commit_snapshot(damaged).await?;
+                Err(crashed())
+            }
+            WriteOutcome::Dead => Err(crashed()),
+        }
+    }
+}
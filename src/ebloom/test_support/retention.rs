@@ -0,0 +1,157 @@
+//! Expiration accuracy simulation (`test_support` feature)
+//!
+//! [`simulate_retention`] drives a filter on a [`MockClock`](super::MockClock) with keys
+//! inserted at a steady rate, probes every live key after each clock step
+//! and records how long each one stayed visible. The report compares that
+//! with the configured window, the config's `level_lifetime`: a key
+//! inserted just before a rotation lives about one level less than one
+//! inserted just after it, so retention falls in a band below the window.
+//!
+//! ```ignore
+//! let report = simulate_retention(config, RetentionWorkload::default()).await?;
+//! println!("{} ms early at worst", report.early_by_max_ms());
+//! ```
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{advance_and_cleanup, filter_with_mock_clock, seeded_key};
+use crate::ebloom::clock::Clock;
+use crate::ebloom::config::ExpiringFilterConfig;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+/// Synthetic insert workload for [`simulate_retention`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionWorkload {
+    /// Keys to insert
+    pub inserts: usize,
+    /// Clock time between two inserts; rounded up to whole `step`s
+    pub insert_every: Duration,
+    /// Clock advance between probes, which is the resolution of every
+    /// measured retention
+    pub step: Duration,
+    /// Seed for the inserted keys, see `seeded_keys`
+    pub seed: u64,
+}
+
+impl Default for RetentionWorkload {
+    fn default() -> Self {
+        Self {
+            inserts: 1_000,
+            insert_every: Duration::from_secs(1),
+            step: Duration::from_secs(1),
+            seed: 0,
+        }
+    }
+}
+
+/// Measured retention against the configured window, see
+/// [`simulate_retention`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionReport {
+    /// Sum of all level durations
+    pub configured_ms: u64,
+    /// Clock advance between probes
+    pub resolution_ms: u64,
+    /// Keys whose expiry was observed
+    pub expired: usize,
+    /// Keys still visible when the simulation stopped, e.g. kept alive by
+    /// a false positive
+    pub censored: usize,
+    pub min_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    pub mean_ms: Option<f64>,
+    /// Expired keys that lived shorter than the configured window
+    pub expired_early: usize,
+    /// Expired keys that lived longer than the configured window
+    pub expired_late: usize,
+}
+
+impl RetentionReport {
+    /// Largest shortfall below the configured window
+    pub fn early_by_max_ms(&self) -> u64 {
+        self.min_ms
+            .map_or(0, |min| self.configured_ms.saturating_sub(min))
+    }
+
+    /// Largest overshoot past the configured window
+    pub fn late_by_max_ms(&self) -> u64 {
+        self.max_ms
+            .map_or(0, |max| max.saturating_sub(self.configured_ms))
+    }
+}
+
+/// Run `workload` against an in-memory filter built from `config` and
+/// measure how long each key stays visible
+///
+/// Each step inserts any keys that are due, probes every key not yet seen
+/// expired, then advances the clock by `step` and runs
+/// `cleanup_expired_levels`. The run stops once every key has expired, or
+/// two configured windows after the last insert.
+pub async fn simulate_retention(
+    config: ExpiringFilterConfig,
+    workload: RetentionWorkload,
+) -> Result<RetentionReport> {
+    if workload.step.is_zero() {
+        return Err(EbloomError::InvalidConfig(
+            "Simulation step must be greater than 0".to_string(),
+        ));
+    }
+    let configured_ms = config.level_lifetime().as_millis() as u64;
+
+    let (filter, clock) = filter_with_mock_clock(config)?;
+    let start_ms = clock.now_ms()?;
+    let insert_every_ms = workload.insert_every.as_millis() as u64;
+
+    let mut live: Vec<(Vec<u8>, u64)> = Vec::new();
+    let mut retentions: Vec<u64> = Vec::new();
+    let mut inserted = 0;
+    let mut deadline_ms = None;
+    loop {
+        let now_ms = clock.now_ms()?;
+        while inserted < workload.inserts
+            && start_ms + inserted as u64 * insert_every_ms <= now_ms
+        {
+            let key = seeded_key(workload.seed, inserted as u64);
+            filter.insert(&key)?;
+            live.push((key, now_ms));
+            inserted += 1;
+        }
+        if inserted == workload.inserts && deadline_ms.is_none() {
+            deadline_ms = Some(now_ms + 2 * configured_ms);
+        }
+
+        let mut still_live = Vec::with_capacity(live.len());
+        for (key, inserted_at) in live {
+            if filter.contains(&key)? {
+                still_live.push((key, inserted_at));
+            } else {
+                retentions.push(now_ms - inserted_at);
+            }
+        }
+        live = still_live;
+
+        if deadline_ms
+            .is_some_and(|deadline| live.is_empty() || now_ms >= deadline)
+        {
+            break;
+        }
+        advance_and_cleanup(&filter, &clock, workload.step).await?;
+    }
+
+    Ok(RetentionReport {
+        configured_ms,
+        resolution_ms: workload.step.as_millis() as u64,
+        expired: retentions.len(),
+        censored: live.len(),
+        min_ms: retentions.iter().min().copied(),
+        max_ms: retentions.iter().max().copied(),
+        mean_ms: (!retentions.is_empty()).then(|| {
+            retentions.iter().sum::<u64>() as f64 / retentions.len() as f64
+        }),
+        expired_early: retentions.iter().filter(|&&r| r < configured_ms).count(),
+        expired_late: retentions.iter().filter(|&&r| r > configured_ms).count(),
+    })
+}
@@ -0,0 +1,157 @@
+//! proptest strategies for expiring filters (`proptest` feature)
+//!
+//! [`config_strategy`] draws small but varied filter configurations and
+//! [`ops_strategy`] sequences of inserts, snapshots and clock moves.
+//! [`apply_op`] runs one operation against a filter and its [`MockClock`],
+//! recording inserts in a [`Model`] that knows which keys must still be
+//! present:
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn no_false_negatives((config, ops) in scenario_strategy(64)) {
+//!         let (filter, clock) = filter_with_mock_clock(config).unwrap();
+//!         let mut model = Model::new(filter.config());
+//!         for op in &ops {
+//!             block_on(apply_op(&filter, &clock, &mut model, op)).unwrap();
+//!         }
+//!         for key in model.must_contain(clock.now_ms().unwrap()) {
+//!             prop_assert!(filter.contains(key).unwrap());
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::time::Duration;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use super::MockClock;
+use crate::ebloom::config::{ExpiringFilterConfig, ExpiringFilterConfigBuilder};
+use crate::ebloom::error::Result;
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+/// One step of a generated scenario
+#[derive(Debug, Clone)]
+pub enum FilterOp {
+    Insert(Vec<u8>),
+    /// Move the clock forward (ms) and run `cleanup_expired_levels`
+    Advance(u64),
+    /// `save_snapshot`; a no-op for filters without storage
+    Snapshot,
+}
+
+/// Configs with 1-5 levels of 10 ms - 10 s and small capacities
+pub fn config_strategy() -> impl Strategy<Value = ExpiringFilterConfig> {
+    (10usize..2_000, 0.001f64..0.2, 1usize..=5, 10u64..10_000).prop_map(
+        |(capacity, fpr, num_levels, duration_ms)| {
+            ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(capacity)
+                .target_fpr(fpr)
+                .num_levels(num_levels)
+                .level_duration(Duration::from_millis(duration_ms))
+                .build()
+                .expect("strategy builds valid configs")
+        },
+    )
+}
+
+/// Keys of 0-32 arbitrary bytes
+pub fn key_strategy() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..32)
+}
+
+/// Inserts, snapshots, and clock moves of up to `max_advance_ms`
+pub fn op_strategy(max_advance_ms: u64) -> impl Strategy<Value = FilterOp> {
+    prop_oneof![
+        6 => key_strategy().prop_map(FilterOp::Insert),
+        2 => (0..=max_advance_ms).prop_map(FilterOp::Advance),
+        1 => Just(FilterOp::Snapshot),
+    ]
+}
+
+/// Up to `max_len` operations
+pub fn ops_strategy(
+    max_advance_ms: u64,
+    max_len: usize,
+) -> impl Strategy<Value = Vec<FilterOp>> {
+    vec(op_strategy(max_advance_ms), 0..max_len)
+}
+
+/// A config and operations whose clock moves are scaled to its windows
+pub fn scenario_strategy(
+    max_len: usize,
+) -> impl Strategy<Value = (ExpiringFilterConfig, Vec<FilterOp>)> {
+    config_strategy().prop_flat_map(move |config| {
+        // Up to two windows per move, so runs cover both fresh and
+        // fully expired filters
+        let max_advance_ms = config.level_duration.as_millis() as u64 * 2;
+        (Just(config), ops_strategy(max_advance_ms, max_len))
+    })
+}
+
+/// Reference model of what a filter must still contain
+#[derive(Debug, Clone)]
+pub struct Model {
+    /// Span after an insert during which the key is guaranteed present
+    retention_ms: u64,
+    inserts: Vec<(Vec<u8>, u64)>,
+}
+
+impl Model {
+    /// Model for a config with one `level_duration` for all levels, whose
+    /// filter runs `cleanup_expired_levels` whenever its clock moves
+    pub fn new(config: &ExpiringFilterConfig) -> Self {
+        // The insert lands in a level at most one window old, which is
+        // cleared `num_levels` windows after it became current
+        let retention = config.level_duration * (config.num_levels as u32 - 1);
+        Self {
+            retention_ms: retention.as_millis() as u64,
+            inserts: Vec::new(),
+        }
+    }
+
+    pub fn record_insert(&mut self, key: &[u8], now_ms: u64) {
+        self.inserts.push((key.to_vec(), now_ms));
+    }
+
+    /// Keys that must be reported present at `now_ms`
+    pub fn must_contain(&self, now_ms: u64) -> impl Iterator<Item = &[u8]> {
+        self.inserts
+            .iter()
+            .filter(move |(_, inserted_at)| {
+                now_ms.saturating_sub(*inserted_at) <= self.retention_ms
+            })
+            .map(|(key, _)| key.as_slice())
+    }
+
+    /// Every key inserted so far
+    pub fn inserted(&self) -> impl Iterator<Item = &[u8]> {
+        self.inserts.iter().map(|(key, _)| key.as_slice())
+    }
+}
+
+/// Run `op` against `filter`, recording it in `model`
+pub async fn apply_op(
+    filter: &ExpiringBloomFilter,
+    clock: &MockClock,
+    model: &mut Model,
+    op: &FilterOp,
+) -> Result<()> {
+    use crate::ebloom::clock::Clock;
+
+    match op {
+        FilterOp::Insert(key) => {
+            filter.insert(key)?;
+            model.record_insert(key, clock.now_ms()?);
+        }
+        FilterOp::Advance(ms) => {
+            clock.advance(Duration::from_millis(*ms));
+            filter.cleanup_expired_levels().await?;
+        }
+        FilterOp::Snapshot => filter.save_snapshot().await?,
+    }
+    Ok(())
+}
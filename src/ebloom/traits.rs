@@ -1,3 +1,4 @@
+use crate::common::{BITSET_BATCH_LEN, Bitset, Membership};
 use crate::ebloom::error::Result;
 
 use async_trait::async_trait;
@@ -11,6 +12,11 @@ pub trait ExpiringBloomFilterOps {
     /// Check if an item exists in any active level
     fn contains(&self, item: &[u8]) -> Result<bool>;
 
+    /// `contains` as a `Membership`, which can't be mistaken for certainty
+    fn contains_checked(&self, item: &[u8]) -> Result<Membership> {
+        self.contains(item).map(Membership::from)
+    }
+
     /// Clear all levels
     fn clear(&self) -> Result<()>;
 
@@ -22,6 +28,35 @@ pub trait ExpiringBloomFilterOps {
 pub trait BulkExpiringBloomFilterOps {
     fn insert_bulk(&self, items: &[&[u8]]) -> Result<()>;
     fn contains_bulk(&self, items: &[&[u8]]) -> Result<Vec<bool>>;
+
+    /// `contains_bulk` packed one bit per item
+    ///
+    /// A bitset takes an eighth of the memory of `Vec<bool>`, which adds up
+    /// when checking hundreds of millions of keys in a batch job.
+    fn contains_bulk_bitset(&self, items: &[&[u8]]) -> Result<Bitset> {
+        self.contains_bulk(items)
+            .map(|found| found.into_iter().collect())
+    }
+
+    /// `contains_bulk_bitset` over any iterator of keys, queried in batches
+    /// of `BITSET_BATCH_LEN`, so the keys never have to be held at once
+    fn contains_iter_bitset<I, K>(&self, items: I) -> Result<Bitset>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut found = Bitset::new();
+        let mut batch = Vec::with_capacity(BITSET_BATCH_LEN);
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            batch.extend(items.by_ref().take(BITSET_BATCH_LEN));
+            let refs: Vec<&[u8]> = batch.iter().map(AsRef::as_ref).collect();
+            found.extend_from_bitslice(&self.contains_bulk_bitset(&refs)?);
+            batch.clear();
+        }
+        Ok(found)
+    }
 }
 
 /// Statistics for expiring bloom filter
@@ -31,4 +66,12 @@ pub trait ExpiringBloomFilterStats {
     fn total_insert_count(&self) -> u64;
     fn active_levels(&self) -> usize;
     fn num_levels(&self) -> usize;
+    /// Fraction of bits set in a level
+    fn fill_ratio(&self, level: usize) -> Result<f64>;
+    /// Bits in each level
+    fn bit_vector_size(&self) -> usize;
+    /// Hash probes per item and level
+    fn num_hashes(&self) -> usize;
+    /// Bits currently set in each level, by level index
+    fn level_bit_counts(&self) -> Result<Vec<usize>>;
 }
@@ -0,0 +1,175 @@
+//! Union payloads for folding filters across nodes
+//!
+//! Workers export their levels with `ExpiringBloomFilter::export_union`
+//! and a central filter folds them in with `apply_union`. Levels are merged
+//! with bitwise OR, so applying the same payload twice, or payloads in any
+//! order, gives the same bits.
+//!
+//! Levels are matched by time rather than by index, as rings on different
+//! nodes rotate independently: each exported level goes into the newest
+//! central level created no later than the last moment it was written to.
+//! Items therefore never expire earlier than they would have had they been
+//! inserted centrally at that moment.
+//!
+//! `ExpiringBloomFilter::compute_delta` goes one step further for filters
+//! that are both at hand, e.g. a local store and a copy of a remote one:
+//! it only ships the chunks holding bits the other filter lacks, and
+//! `apply_delta` ORs them in with the same level matching.
+//!
+//! `seed_from` warms a new filter from an old one, or from a stored
+//! payload: every level, expired or not, goes into the new filter's oldest
+//! level regardless of time.
+//!
+//! Both filters must share a bit layout (level size, hash count and
+//! layout), which the payload carries as a fingerprint. Insert counts are
+//! not carried over: a payload re-sends whole levels, so adding them up
+//! across periodic folds would count items many times.
+
+use std::borrow::Cow;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::frozen::FrozenExpiringBloomFilter;
+use crate::hash::hash_fnv64;
+
+/// Chunk size of `compute_delta` for filters without persistence
+pub const DELTA_CHUNK_BYTES: usize = 4096;
+
+/// Levels of one filter, ready to be OR-ed into another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct UnionPayload {
+    /// Fingerprint of the exporting filter's bit layout
+    pub layout_fingerprint: u64,
+    /// Exporting node's clock at export time (ms)
+    pub exported_at: u64,
+    /// Levels with at least one bit set, oldest first
+    pub levels: Vec<UnionLevel>,
+}
+
+/// One exported level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct UnionLevel {
+    /// When the level became current (ms)
+    pub created_at: u64,
+    /// Last moment the level was current: just before the next level was
+    /// created, or the export time for levels still written to (ms)
+    pub last_written_at: u64,
+    /// Level bits as `Lsb0` bytes
+    pub bits: Vec<u8>,
+}
+
+/// Chunks of levels that differ from another filter, grouped by the level
+/// they came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct ChunkDelta {
+    pub layout_fingerprint: u64,
+    /// Bytes per chunk; the last chunk of a level may be shorter
+    pub chunk_bytes: usize,
+    pub levels: Vec<DeltaLevel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct DeltaLevel {
+    pub created_at: u64,
+    pub last_written_at: u64,
+    /// `(chunk index, chunk bytes)`
+    pub chunks: Vec<(usize, Vec<u8>)>,
+}
+
+impl ChunkDelta {
+    /// Number of chunks carried
+    pub fn chunk_count(&self) -> usize {
+        self.levels.iter().map(|level| level.chunks.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunk_count() == 0
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(delta, _)| delta)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+}
+
+impl UnionPayload {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(payload, _)| payload)
+            .map_err(|e| EbloomError::SerializationError(e.to_string()))
+    }
+}
+
+/// Fingerprint of everything that decides where an item's bits land
+/// Filter state `ExpiringBloomFilter::seed_from` can warm a filter from: a
+/// live or frozen filter, or a union payload, e.g. one read back with
+/// `UnionPayload::from_bytes`
+pub trait SeedSource {
+    fn union_payload(&self) -> Result<Cow<'_, UnionPayload>>;
+}
+
+impl SeedSource for UnionPayload {
+    fn union_payload(&self) -> Result<Cow<'_, UnionPayload>> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl SeedSource for ExpiringBloomFilter {
+    fn union_payload(&self) -> Result<Cow<'_, UnionPayload>> {
+        self.export_union().map(Cow::Owned)
+    }
+}
+
+impl SeedSource for FrozenExpiringBloomFilter {
+    /// Every level holding bits, stamped with the freeze time as its last
+    /// write
+    fn union_payload(&self) -> Result<Cow<'_, UnionPayload>> {
+        let mut levels: Vec<UnionLevel> = self
+            .levels
+            .iter()
+            .zip(self.metadata.iter())
+            .filter(|(level, _)| level.any())
+            .map(|(level, metadata)| UnionLevel {
+                created_at: metadata.created_at,
+                last_written_at: self.frozen_at,
+                bits: level.read_bytes(0, self.bit_vector_size),
+            })
+            .collect();
+        levels.sort_by_key(|level| level.created_at);
+        Ok(Cow::Owned(UnionPayload {
+            layout_fingerprint: layout_fingerprint(
+                self.bit_vector_size,
+                self.num_hashes,
+                self.config.blocked_layout,
+            ),
+            exported_at: self.frozen_at,
+            levels,
+        }))
+    }
+}
+
+pub(crate) fn layout_fingerprint(
+    bit_vector_size: usize,
+    num_hashes: usize,
+    blocked_layout: bool,
+) -> u64 {
+    let mut key = Vec::with_capacity(17);
+    key.extend_from_slice(&(bit_vector_size as u64).to_le_bytes());
+    key.extend_from_slice(&(num_hashes as u64).to_le_bytes());
+    key.push(blocked_layout as u8);
+    hash_fnv64(&key)
+}
@@ -0,0 +1,101 @@
+//! URL deduplication for crawlers (`url` feature)
+//!
+//! Byte-level dedup treats `https://Example.com/a?b=2&a=1#top` and
+//! `https://example.com/a?a=1&b=2` as different pages. [`UrlDeduper`]
+//! canonicalizes URLs first: scheme and host lowercased, default port,
+//! fragment and tracking parameters dropped, query keys sorted.
+
+use url::Url;
+
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+/// Query parameters dropped by default; a trailing `*` matches a prefix
+pub const DEFAULT_TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid",
+    "mc_cid", "mc_eid", "igshid", "_ga", "_gl",
+];
+
+/// Canonicalizes URLs before checking them against an expiring filter
+pub struct UrlDeduper {
+    filter: ExpiringBloomFilter,
+    tracking_params: Vec<String>,
+}
+
+impl UrlDeduper {
+    pub fn new(filter: ExpiringBloomFilter) -> Self {
+        Self {
+            filter,
+            tracking_params: DEFAULT_TRACKING_PARAMS
+                .iter()
+                .map(|param| param.to_string())
+                .collect(),
+        }
+    }
+
+    /// Replace the list of query parameters to drop
+    pub fn with_tracking_params<I, S>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tracking_params = params.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Canonical form used as the filter key
+    pub fn canonicalize(&self, url: &str) -> Result<String> {
+        let mut url = Url::parse(url.trim())
+            .map_err(|e| EbloomError::InvalidUrl(format!("{url}: {e}")))?;
+        // Parsing already lowercases scheme and host and drops default ports
+        url.set_fragment(None);
+
+        let mut pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| !self.is_tracking_param(key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        pairs.sort();
+        if pairs.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        Ok(url.into())
+    }
+
+    /// Record `url`, returning `true` the first time its canonical form is
+    /// seen within the filter's window
+    ///
+    /// Check and insert are separate steps, so two threads racing on the
+    /// same new URL may both get `true`.
+    pub fn check_and_insert(&self, url: &str) -> Result<bool> {
+        let key = self.canonicalize(url)?;
+        if self.filter.contains(key.as_bytes())? {
+            return Ok(false);
+        }
+        self.filter.insert(key.as_bytes())?;
+        Ok(true)
+    }
+
+    /// Whether the canonical form of `url` was (probably) seen
+    pub fn is_seen(&self, url: &str) -> Result<bool> {
+        let key = self.canonicalize(url)?;
+        self.filter.contains(key.as_bytes())
+    }
+
+    /// Underlying filter, e.g. to drive rotation
+    pub fn filter(&self) -> &ExpiringBloomFilter {
+        &self.filter
+    }
+
+    fn is_tracking_param(&self, key: &str) -> bool {
+        self.tracking_params
+            .iter()
+            .any(|param| match param.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => key == param,
+            })
+    }
+}
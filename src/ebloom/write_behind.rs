@@ -0,0 +1,198 @@
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::storage::{FjallExpiringBackend, SnapshotBatch};
+use std::collections::BTreeMap;
+use std::sync::{
+    Arc,
+    atomic::{Ordering, fence},
+    mpsc::{Receiver, SyncSender, sync_channel},
+};
+use std::thread::JoinHandle;
+
+/// Most chunks written in one batch by the background writer
+const MAX_BATCH_CHUNKS: usize = 1024;
+
+enum Message {
+    Chunk { level: usize, chunk_id: usize },
+    Flush(SyncSender<Result<()>>),
+}
+
+/// Background writer persisting chunks as soon as inserts touch them
+///
+/// Inserts push `(level, chunk)` pairs into a bounded channel, so a slow disk
+/// applies backpressure to writers instead of growing an unbounded queue. A
+/// chunk is queued at most once until the writer picks it up; the writer
+/// copies the live bits and commits each drained batch with one sync.
+pub(crate) struct WriteBehind {
+    sender: Option<SyncSender<Message>>,
+    queued: Arc<Vec<AtomicBitVec>>,
+    chunk_size_bits: usize,
+    capacity: usize,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl WriteBehind {
+    pub(crate) fn spawn(
+        backend: Arc<FjallExpiringBackend>,
+        levels: Arc<Vec<AtomicBitVec>>,
+        chunk_size_bytes: usize,
+        capacity: usize,
+    ) -> Result<Self> {
+        let chunk_size_bits = chunk_size_bytes * 8;
+        let chunk_count = levels
+            .first()
+            .map_or(0, |level| level.len().div_ceil(chunk_size_bits));
+        let queued: Arc<Vec<AtomicBitVec>> = Arc::new(
+            (0..levels.len())
+                .map(|_| AtomicBitVec::new(chunk_count))
+                .collect(),
+        );
+
+        let (sender, receiver) = sync_channel(capacity);
+        let writer_queued = Arc::clone(&queued);
+        let handle = std::thread::Builder::new()
+            .name("ebloom-write-behind".to_string())
+            .spawn(move || {
+                run_writer(
+                    &receiver,
+                    &backend,
+                    &levels,
+                    &writer_queued,
+                    chunk_size_bits,
+                )
+            })
+            .map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to start write-behind thread: {e}"
+                ))
+            })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            queued,
+            chunk_size_bits,
+            capacity,
+            handle: Some(handle),
+        })
+    }
+
+    /// Bytes held by the queued-chunk bitmaps and the channel slots
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.queued
+            .iter()
+            .map(AtomicBitVec::memory_bytes)
+            .sum::<usize>()
+            + self.capacity * size_of::<Message>()
+    }
+
+    /// Queue the chunks holding `indices` of `level`; blocks while the
+    /// queue is full
+    pub(crate) fn enqueue(&self, level: usize, indices: &[usize]) -> Result<()> {
+        let (Some(sender), Some(queued)) =
+            (self.sender.as_ref(), self.queued.get(level))
+        else {
+            return Ok(());
+        };
+
+        // Bits were set before this point; pairs with the writer's acquire
+        fence(Ordering::Release);
+        for &idx in indices {
+            let chunk_id = idx / self.chunk_size_bits;
+            if chunk_id < queued.len() && !queued.test_and_set(chunk_id) {
+                sender.send(Message::Chunk { level, chunk_id }).map_err(
+                    |_| {
+                        EbloomError::StorageError(
+                            "Write-behind thread stopped".to_string(),
+                        )
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until every chunk queued so far is written
+    pub(crate) fn flush(&self) -> Result<()> {
+        let Some(ref sender) = self.sender else {
+            return Ok(());
+        };
+        let stopped = || {
+            EbloomError::StorageError("Write-behind thread stopped".to_string())
+        };
+
+        let (ack_sender, ack_receiver) = sync_channel(1);
+        sender
+            .send(Message::Flush(ack_sender))
+            .map_err(|_| stopped())?;
+        ack_receiver.recv().map_err(|_| stopped())?
+    }
+}
+
+impl Drop for WriteBehind {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain what is queued and exit
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_writer(
+    receiver: &Receiver<Message>,
+    backend: &FjallExpiringBackend,
+    levels: &[AtomicBitVec],
+    queued: &[AtomicBitVec],
+    chunk_size_bits: usize,
+) {
+    let mut last_error = None;
+
+    while let Ok(first) = receiver.recv() {
+        let mut pending = BTreeMap::<usize, Vec<usize>>::new();
+        let mut flushes = Vec::new();
+        let mut next = Some(first);
+        let mut count = 0;
+
+        // Drain whatever is already queued into one batch
+        while let Some(message) = next.take() {
+            match message {
+                Message::Chunk { level, chunk_id } => {
+                    pending.entry(level).or_default().push(chunk_id);
+                    count += 1;
+                }
+                Message::Flush(ack) => flushes.push(ack),
+            }
+            if count < MAX_BATCH_CHUNKS {
+                next = receiver.try_recv().ok();
+            }
+        }
+
+        let mut batch = SnapshotBatch::default();
+        for (level, chunk_ids) in pending {
+            let chunks = chunk_ids
+                .into_iter()
+                .map(|chunk_id| {
+                    // Unmark first so later inserts queue the chunk again
+                    queued[level].reset(chunk_id);
+                    fence(Ordering::Acquire);
+                    let start_bit = chunk_id * chunk_size_bits;
+                    let bytes = levels[level]
+                        .read_bytes(start_bit, start_bit + chunk_size_bits);
+                    (chunk_id, bytes)
+                })
+                .collect();
+            batch.dirty_chunks.push((level, chunks));
+        }
+        if let Err(e) = backend.write_snapshot(&batch) {
+            last_error = Some(e);
+        }
+
+        for ack in flushes {
+            let result = match last_error.take() {
+                Some(e) => Err(e),
+                None => backend.flush(),
+            };
+            let _ = ack.send(result);
+        }
+    }
+}
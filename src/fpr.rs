@@ -0,0 +1,149 @@
+//! False positive rate measurement
+//!
+//! [`measure_fpr`] queries a filter with keys known not to be in it and
+//! reports how many came back present, with a confidence interval, so a
+//! production config can be checked against its target before rollout:
+//!
+//! ```ignore
+//! let report = measure_fpr(&filter, (0..100_000).map(|i| format!("absent-{i}")))?;
+//! assert!(!report.exceeds(config.target_fpr));
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::bloom::{AutoBloomFilter, BloomError, BloomFilter, BloomFilterOps};
+use crate::ebloom::error::EbloomError;
+use crate::ebloom::filter::ExpiringBloomFilter;
+use crate::ebloom::sharded::ShardedExpiringFilter;
+use crate::ebloom::traits::ExpiringBloomFilterOps;
+
+/// z-score of a two-sided 95% interval
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// A membership query `measure_fpr` can run
+///
+/// Implemented for the filters in this crate and for any
+/// `Fn(&[u8]) -> Result<bool, E>`, which covers other filters with a
+/// closure.
+pub trait MembershipQuery {
+    type Error;
+
+    fn query(&self, item: &[u8]) -> Result<bool, Self::Error>;
+}
+
+impl<F, E> MembershipQuery for F
+where
+    F: Fn(&[u8]) -> Result<bool, E>,
+{
+    type Error = E;
+
+    fn query(&self, item: &[u8]) -> Result<bool, E> {
+        self(item)
+    }
+}
+
+impl MembershipQuery for BloomFilter {
+    type Error = BloomError;
+
+    fn query(&self, item: &[u8]) -> Result<bool, BloomError> {
+        self.contains(item)
+    }
+}
+
+impl MembershipQuery for AutoBloomFilter {
+    type Error = BloomError;
+
+    fn query(&self, item: &[u8]) -> Result<bool, BloomError> {
+        self.contains(item)
+    }
+}
+
+impl MembershipQuery for ExpiringBloomFilter {
+    type Error = EbloomError;
+
+    fn query(&self, item: &[u8]) -> Result<bool, EbloomError> {
+        self.contains(item)
+    }
+}
+
+impl MembershipQuery for ShardedExpiringFilter {
+    type Error = EbloomError;
+
+    fn query(&self, item: &[u8]) -> Result<bool, EbloomError> {
+        self.contains(item)
+    }
+}
+
+/// Result of a false positive measurement, see [`measure_fpr`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FprReport {
+    /// Negative keys queried
+    pub queries: u64,
+    /// Queries that reported the key present
+    pub false_positives: u64,
+    /// `false_positives / queries`, 0 when nothing was queried
+    pub rate: f64,
+    /// Lower bound of the 95% Wilson score interval of the rate
+    pub ci_low: f64,
+    /// Upper bound of the 95% Wilson score interval of the rate
+    pub ci_high: f64,
+}
+
+impl FprReport {
+    /// Build a report from raw counts
+    pub fn from_counts(queries: u64, false_positives: u64) -> Self {
+        if queries == 0 {
+            return Self {
+                queries,
+                false_positives,
+                rate: 0.0,
+                ci_low: 0.0,
+                ci_high: 1.0,
+            };
+        }
+        let n = queries as f64;
+        let rate = false_positives as f64 / n;
+        let z2 = Z_95 * Z_95;
+        let denominator = 1.0 + z2 / n;
+        let center = (rate + z2 / (2.0 * n)) / denominator;
+        let margin = Z_95 * (rate * (1.0 - rate) / n + z2 / (4.0 * n * n)).sqrt()
+            / denominator;
+        Self {
+            queries,
+            false_positives,
+            rate,
+            ci_low: (center - margin).max(0.0),
+            ci_high: (center + margin).min(1.0),
+        }
+    }
+
+    /// Whether the measured rate is above `target_fpr` with 95% confidence
+    pub fn exceeds(&self, target_fpr: f64) -> bool {
+        self.ci_low > target_fpr
+    }
+}
+
+/// Query `filter` with every key of `negatives` and report the false
+/// positive rate
+///
+/// Every key must be one that was never inserted; any hit is counted as a
+/// false positive. Stops at the first query error.
+pub fn measure_fpr<Q, I, K>(
+    filter: &Q,
+    negatives: I,
+) -> Result<FprReport, Q::Error>
+where
+    Q: MembershipQuery + ?Sized,
+    I: IntoIterator<Item = K>,
+    K: AsRef<[u8]>,
+{
+    let mut queries = 0;
+    let mut false_positives = 0;
+    for key in negatives {
+        queries += 1;
+        if filter.query(key.as_ref())? {
+            false_positives += 1;
+        }
+    }
+    Ok(FprReport::from_counts(queries, false_positives))
+}
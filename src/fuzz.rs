@@ -0,0 +1,53 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`
+//!
+//! Only built under `--cfg fuzzing`, which `cargo fuzz` sets. Each function
+//! runs one decode or reconstruction step of `load` on untrusted bytes; a
+//! corrupt input must come back as an error, never a panic or a huge
+//! allocation.
+
+use bitvec::bitvec;
+
+use crate::bloom::{BloomFilterConfig, BloomResult, filter};
+use crate::ebloom::bits::AtomicBitVec;
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::Result;
+use crate::ebloom::{filter as ebloom_filter, storage};
+
+/// Decode and validate a `BloomFilterConfig` as stored by the Fjall backend
+pub fn bloom_config(bytes: &[u8]) -> BloomResult<BloomFilterConfig> {
+    let config = BloomFilterConfig::from_bytes(bytes)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Decode and validate an `ExpiringFilterConfig` as stored by the Fjall backend
+pub fn expiring_config(bytes: &[u8]) -> Result<ExpiringFilterConfig> {
+    let config = ExpiringFilterConfig::from_bytes(bytes)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Decode the `level_metadata` record of an expiring filter
+pub fn level_metadata(bytes: &[u8]) -> Result<Vec<LevelMetadata>> {
+    storage::decode_level_metadata(bytes)
+}
+
+/// Write snapshot chunks into a fresh `bit_len`-bit `BloomFilter` bit vector
+pub fn bloom_chunks(
+    bit_len: usize,
+    chunk_size_bytes: usize,
+    chunks: &[(usize, Vec<u8>)],
+) -> BloomResult<()> {
+    let mut bits = bitvec![usize, bitvec::order::Lsb0; 0; bit_len];
+    filter::reconstruct_bits_from_chunks(&mut bits, chunks, chunk_size_bytes)
+}
+
+/// Write snapshot chunks into a fresh `bit_len`-bit expiring filter level
+pub fn level_chunks(
+    bit_len: usize,
+    chunk_size_bytes: usize,
+    chunks: &[(usize, Vec<u8>)],
+) -> Result<()> {
+    let level = AtomicBitVec::new(bit_len);
+    ebloom_filter::reconstruct_level_from_chunks(&level, chunks, chunk_size_bytes)
+}
@@ -0,0 +1,172 @@
+//! gRPC service for a shared `ExpiringBloomFilter` (`grpc` feature)
+//!
+//! The API is defined in `proto/ebloom.proto`, so clients in any language
+//! can generate stubs from it. [`ExpiringBloomService`] implements it over
+//! an `Arc<ExpiringBloomFilter>`; serve it with
+//! `tonic::transport::Server::builder().add_service(service.into_server())`.
+//! Rotation on schedule stays with the caller, as with the library API:
+//! keep calling `cleanup_expired_levels` next to the server.
+//!
+//! [`storage`] serves filter storage itself, for filters that run in
+//! another process.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::ebloom::{
+    error::EbloomError,
+    filter::ExpiringBloomFilter,
+    stats::ExpiringStats,
+    traits::{BulkExpiringBloomFilterOps, ExpiringBloomFilterOps},
+};
+
+pub mod storage;
+
+/// Messages and stubs generated from `proto/ebloom.proto`
+pub mod proto {
+    tonic::include_proto!("ebloom.v1");
+}
+
+use proto::expiring_bloom_server::{ExpiringBloom, ExpiringBloomServer};
+use proto::{
+    BulkContainsRequest, BulkContainsResponse, BulkInsertRequest,
+    BulkInsertResponse, ContainsRequest, ContainsResponse, InsertRequest,
+    InsertResponse, RotateRequest, RotateResponse, StatsRequest, StatsResponse,
+};
+
+/// gRPC front end of a shared filter
+#[derive(Clone)]
+pub struct ExpiringBloomService {
+    filter: Arc<ExpiringBloomFilter>,
+}
+
+impl ExpiringBloomService {
+    pub fn new(filter: Arc<ExpiringBloomFilter>) -> Self {
+        Self { filter }
+    }
+
+    /// Wrap in the generated tonic server
+    pub fn into_server(self) -> ExpiringBloomServer<Self> {
+        ExpiringBloomServer::new(self)
+    }
+}
+
+type BulkContainsStream =
+    Pin<Box<dyn Stream<Item = Result<BulkContainsResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl ExpiringBloom for ExpiringBloomService {
+    async fn insert(
+        &self,
+        request: Request<InsertRequest>,
+    ) -> Result<Response<InsertResponse>, Status> {
+        self.filter
+            .insert(&request.into_inner().item)
+            .map_err(to_status)?;
+        Ok(Response::new(InsertResponse {}))
+    }
+
+    async fn contains(
+        &self,
+        request: Request<ContainsRequest>,
+    ) -> Result<Response<ContainsResponse>, Status> {
+        let present = self
+            .filter
+            .contains(&request.into_inner().item)
+            .map_err(to_status)?;
+        Ok(Response::new(ContainsResponse { present }))
+    }
+
+    async fn bulk_insert(
+        &self,
+        request: Request<BulkInsertRequest>,
+    ) -> Result<Response<BulkInsertResponse>, Status> {
+        let request = request.into_inner();
+        let items: Vec<&[u8]> = request.items.iter().map(Vec::as_slice).collect();
+        self.filter.insert_bulk(&items).map_err(to_status)?;
+        Ok(Response::new(BulkInsertResponse {
+            inserted: items.len() as u64,
+        }))
+    }
+
+    type BulkContainsStream = BulkContainsStream;
+
+    async fn bulk_contains(
+        &self,
+        request: Request<Streaming<BulkContainsRequest>>,
+    ) -> Result<Response<Self::BulkContainsStream>, Status> {
+        let filter = Arc::clone(&self.filter);
+        let responses = request.into_inner().map(move |request| {
+            let request = request?;
+            let items: Vec<&[u8]> =
+                request.items.iter().map(Vec::as_slice).collect();
+            let present = filter.contains_bulk(&items).map_err(to_status)?;
+            Ok(BulkContainsResponse { present })
+        });
+        Ok(Response::new(Box::pin(responses)))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        let stats = self.filter.stats().map_err(to_status)?;
+        Ok(Response::new(stats.into()))
+    }
+
+    async fn rotate(
+        &self,
+        _request: Request<RotateRequest>,
+    ) -> Result<Response<RotateResponse>, Status> {
+        self.filter.rotate_levels().await.map_err(to_status)?;
+        Ok(Response::new(RotateResponse {
+            current_level: self.filter.get_active_level() as u64,
+        }))
+    }
+}
+
+impl From<ExpiringStats> for StatsResponse {
+    fn from(stats: ExpiringStats) -> Self {
+        Self {
+            capacity_per_level: stats.capacity_per_level as u64,
+            num_levels: stats.num_levels as u64,
+            target_fpr: stats.target_fpr,
+            estimated_fpr: stats.estimated_fpr,
+            bit_vector_size: stats.bit_vector_size as u64,
+            num_hashes: stats.num_hashes as u64,
+            current_level: stats.current_level as u64,
+            total_insert_count: stats.total_insert_count,
+            memory_bytes: stats.memory_bytes as u64,
+            snapshot_lag_ms: stats.snapshot_lag_ms,
+            levels: stats
+                .levels
+                .into_iter()
+                .map(|level| proto::LevelStats {
+                    level: level.level as u64,
+                    fill_ratio: level.fill_ratio,
+                    insert_count: level.insert_count,
+                    age_ms: level.age_ms,
+                    expired: level.expired,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Caller mistakes map to `InvalidArgument`, inserts refused by the FPR
+/// guardrail to `ResourceExhausted`, everything else is internal
+fn to_status(err: EbloomError) -> Status {
+    match err {
+        EbloomError::InvalidConfig(_)
+        | EbloomError::ConfigError(_)
+        | EbloomError::InvalidLevel { .. }
+        | EbloomError::IndexOutOfBounds { .. } => {
+            Status::invalid_argument(err.to_string())
+        }
+        EbloomError::Saturated(_) => Status::resource_exhausted(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
@@ -0,0 +1,499 @@
+//! Filter storage over gRPC
+//!
+//! [`ExpiringStorageService`] exposes any `ExpiringStorageBackend` (usually
+//! a `FjallExpiringBackend`) as the `ExpiringStorage` service of
+//! `proto/ebloom.proto`. [`GrpcStorageBackend`] is the matching client: it
+//! implements the same trait, so a process without local disk can run an
+//! `ExpiringBloomFilter` with `create_remote` / `load_remote` and keep the
+//! rest of the filter API unchanged.
+//!
+//! Every storage call is one round trip. Snapshots go through
+//! `CommitSnapshot`, so a save is still a single request; the server
+//! answers once its backend has committed.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+
+use super::proto::expiring_storage_client::ExpiringStorageClient;
+use super::proto::expiring_storage_server::{
+    ExpiringStorage, ExpiringStorageServer,
+};
+use super::proto::{
+    Chunk, CurrentLevel, LevelRequest, SnapshotRequest, StorageBlob, StorageEmpty,
+};
+use super::to_status;
+use crate::ebloom::config::{ExpiringFilterConfig, LevelMetadata};
+use crate::ebloom::error::{EbloomError, Result};
+use crate::ebloom::events::RotationRecord;
+use crate::ebloom::storage::{
+    ExpiringStorageBackend, LevelChunks, SnapshotBatch,
+};
+
+type ProtoLevelChunks = super::proto::LevelChunks;
+type RpcResult<T> = std::result::Result<Response<T>, Status>;
+
+/// gRPC front end of a storage backend
+pub struct ExpiringStorageService<B> {
+    backend: Arc<B>,
+}
+
+impl<B> Clone for ExpiringStorageService<B> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: Arc::clone(&self.backend),
+        }
+    }
+}
+
+impl<B> ExpiringStorageService<B>
+where
+    B: ExpiringStorageBackend + Send + Sync + 'static,
+{
+    /// Serve `backend`; it must have room for the remote filter's levels
+    pub fn new(backend: Arc<B>) -> Self {
+        Self { backend }
+    }
+
+    /// Wrap in the generated tonic server
+    pub fn into_server(self) -> ExpiringStorageServer<Self> {
+        ExpiringStorageServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<B> ExpiringStorage for ExpiringStorageService<B>
+where
+    B: ExpiringStorageBackend + Send + Sync + 'static,
+{
+    async fn save_config(
+        &self,
+        request: Request<StorageBlob>,
+    ) -> RpcResult<StorageEmpty> {
+        let config: ExpiringFilterConfig =
+            decode(&request.into_inner().data).map_err(to_status)?;
+        self.backend.save_config(&config).await.map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_config(
+        &self,
+        _request: Request<StorageEmpty>,
+    ) -> RpcResult<StorageBlob> {
+        let config = self.backend.load_config().await.map_err(to_status)?;
+        blob(&config)
+    }
+
+    async fn save_level_metadata(
+        &self,
+        request: Request<StorageBlob>,
+    ) -> RpcResult<StorageEmpty> {
+        let metadata: Vec<LevelMetadata> =
+            decode(&request.into_inner().data).map_err(to_status)?;
+        self.backend
+            .save_level_metadata(&metadata)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_level_metadata(
+        &self,
+        _request: Request<StorageEmpty>,
+    ) -> RpcResult<StorageBlob> {
+        let metadata = self
+            .backend
+            .load_level_metadata()
+            .await
+            .map_err(to_status)?;
+        blob(&metadata)
+    }
+
+    async fn save_current_level(
+        &self,
+        request: Request<CurrentLevel>,
+    ) -> RpcResult<StorageEmpty> {
+        self.backend
+            .save_current_level(request.into_inner().level as usize)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_current_level(
+        &self,
+        _request: Request<StorageEmpty>,
+    ) -> RpcResult<CurrentLevel> {
+        let level = self.backend.load_current_level().await.map_err(to_status)?;
+        Ok(Response::new(CurrentLevel {
+            level: level as u64,
+        }))
+    }
+
+    async fn save_level_chunks(
+        &self,
+        request: Request<ProtoLevelChunks>,
+    ) -> RpcResult<StorageEmpty> {
+        let (level, chunks) = from_proto(request.into_inner());
+        self.backend
+            .save_level_chunks(level, &chunks)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_level_chunks(
+        &self,
+        request: Request<LevelRequest>,
+    ) -> RpcResult<ProtoLevelChunks> {
+        let level = request.into_inner().level as usize;
+        let chunks = self
+            .backend
+            .load_level_chunks(level)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(to_proto(level, chunks)))
+    }
+
+    async fn save_dirty_chunks(
+        &self,
+        request: Request<ProtoLevelChunks>,
+    ) -> RpcResult<StorageEmpty> {
+        let (level, chunks) = from_proto(request.into_inner());
+        self.backend
+            .save_dirty_chunks(level, &chunks)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_dirty_chunks(
+        &self,
+        request: Request<LevelRequest>,
+    ) -> RpcResult<ProtoLevelChunks> {
+        let level = request.into_inner().level as usize;
+        let chunks = self
+            .backend
+            .load_dirty_chunks(level)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(to_proto(level, chunks)))
+    }
+
+    async fn delete_level(
+        &self,
+        request: Request<LevelRequest>,
+    ) -> RpcResult<StorageEmpty> {
+        self.backend
+            .delete_level(request.into_inner().level as usize)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn save_rotation_log(
+        &self,
+        request: Request<StorageBlob>,
+    ) -> RpcResult<StorageEmpty> {
+        let log: Vec<RotationRecord> =
+            decode(&request.into_inner().data).map_err(to_status)?;
+        self.backend
+            .save_rotation_log(&log)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+
+    async fn load_rotation_log(
+        &self,
+        _request: Request<StorageEmpty>,
+    ) -> RpcResult<StorageBlob> {
+        let log = self.backend.load_rotation_log().await.map_err(to_status)?;
+        blob(&log)
+    }
+
+    async fn commit_snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> RpcResult<StorageEmpty> {
+        let request = request.into_inner();
+        let batch = SnapshotBatch {
+            level_chunks: request
+                .level_chunks
+                .into_iter()
+                .map(from_proto)
+                .collect(),
+            dirty_chunks: request
+                .dirty_chunks
+                .into_iter()
+                .map(from_proto)
+                .collect(),
+            metadata: request
+                .metadata
+                .map(|bytes| decode(&bytes))
+                .transpose()
+                .map_err(to_status)?,
+        };
+        self.backend
+            .commit_snapshot(batch)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(StorageEmpty {}))
+    }
+}
+
+/// Storage backend that forwards every call to an `ExpiringStorageService`
+pub struct GrpcStorageBackend {
+    client: ExpiringStorageClient<Channel>,
+    /// Error of the last failed call, cleared by the next successful one
+    last_error: Mutex<Option<String>>,
+}
+
+impl GrpcStorageBackend {
+    /// Connect to a storage service, e.g. `http://10.0.0.5:50052`
+    pub async fn connect(endpoint: impl Into<String>) -> Result<Self> {
+        let endpoint = endpoint.into();
+        let channel = Endpoint::from_shared(endpoint.clone())
+            .map_err(|e| EbloomError::InvalidConfig(format!("{endpoint}: {e}")))?
+            .connect()
+            .await
+            .map_err(|e| {
+                EbloomError::StorageError(format!(
+                    "Failed to connect to {endpoint}: {e}"
+                ))
+            })?;
+        Ok(Self::new(channel))
+    }
+
+    /// Use an existing channel, e.g. one with TLS or timeouts configured
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: ExpiringStorageClient::new(channel),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    /// Outcome of the last call, for health reporting
+    pub fn last_error(&self) -> Result<()> {
+        let last_error = self.last_error.lock().map_err(|_| {
+            EbloomError::LockError("Failed to lock remote storage".to_string())
+        })?;
+        match *last_error {
+            Some(ref error) => Err(EbloomError::StorageError(error.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Record the outcome of a call and map its status
+    fn track<T>(&self, result: RpcResult<T>) -> Result<T> {
+        let result = result.map(Response::into_inner).map_err(from_status);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = result.as_ref().err().map(ToString::to_string);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl ExpiringStorageBackend for GrpcStorageBackend {
+    async fn save_config(&self, config: &ExpiringFilterConfig) -> Result<()> {
+        let data = encode(config)?;
+        let result = self.client.clone().save_config(StorageBlob { data }).await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_config(&self) -> Result<ExpiringFilterConfig> {
+        let result = self.client.clone().load_config(StorageEmpty {}).await;
+        decode(&self.track(result)?.data)
+    }
+
+    async fn save_level_metadata(
+        &self,
+        metadata: &[LevelMetadata],
+    ) -> Result<()> {
+        let data = encode(metadata)?;
+        let result = self
+            .client
+            .clone()
+            .save_level_metadata(StorageBlob { data })
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_level_metadata(&self) -> Result<Vec<LevelMetadata>> {
+        let result = self
+            .client
+            .clone()
+            .load_level_metadata(StorageEmpty {})
+            .await;
+        decode(&self.track(result)?.data)
+    }
+
+    async fn save_current_level(&self, current_level: usize) -> Result<()> {
+        let result = self
+            .client
+            .clone()
+            .save_current_level(CurrentLevel {
+                level: current_level as u64,
+            })
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_current_level(&self) -> Result<usize> {
+        let result = self
+            .client
+            .clone()
+            .load_current_level(StorageEmpty {})
+            .await;
+        Ok(self.track(result)?.level as usize)
+    }
+
+    async fn save_level_chunks(
+        &self,
+        level: usize,
+        chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        let result = self
+            .client
+            .clone()
+            .save_level_chunks(to_proto(level, chunks.to_vec()))
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_level_chunks(&self, level: usize) -> Result<LevelChunks> {
+        let result = self
+            .client
+            .clone()
+            .load_level_chunks(LevelRequest {
+                level: level as u64,
+            })
+            .await;
+        Ok(from_proto(self.track(result)?).1)
+    }
+
+    async fn save_dirty_chunks(
+        &self,
+        level: usize,
+        dirty_chunks: &[(usize, Vec<u8>)],
+    ) -> Result<()> {
+        let result = self
+            .client
+            .clone()
+            .save_dirty_chunks(to_proto(level, dirty_chunks.to_vec()))
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_dirty_chunks(&self, level: usize) -> Result<LevelChunks> {
+        let result = self
+            .client
+            .clone()
+            .load_dirty_chunks(LevelRequest {
+                level: level as u64,
+            })
+            .await;
+        Ok(from_proto(self.track(result)?).1)
+    }
+
+    async fn delete_level(&self, level: usize) -> Result<()> {
+        let result = self
+            .client
+            .clone()
+            .delete_level(LevelRequest {
+                level: level as u64,
+            })
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn save_rotation_log(&self, log: &[RotationRecord]) -> Result<()> {
+        let data = encode(log)?;
+        let result = self
+            .client
+            .clone()
+            .save_rotation_log(StorageBlob { data })
+            .await;
+        self.track(result).map(drop)
+    }
+
+    async fn load_rotation_log(&self) -> Result<Vec<RotationRecord>> {
+        let result = self.client.clone().load_rotation_log(StorageEmpty {}).await;
+        decode(&self.track(result)?.data)
+    }
+
+    async fn commit_snapshot(&self, batch: SnapshotBatch) -> Result<()> {
+        let request = SnapshotRequest {
+            level_chunks: batch
+                .level_chunks
+                .into_iter()
+                .map(|(level, chunks)| to_proto(level, chunks))
+                .collect(),
+            dirty_chunks: batch
+                .dirty_chunks
+                .into_iter()
+                .map(|(level, chunks)| to_proto(level, chunks))
+                .collect(),
+            metadata: batch.metadata.as_deref().map(encode).transpose()?,
+        };
+        let result = self.client.clone().commit_snapshot(request).await;
+        self.track(result).map(drop)
+    }
+}
+
+fn to_proto(level: usize, chunks: LevelChunks) -> ProtoLevelChunks {
+    ProtoLevelChunks {
+        level: level as u64,
+        chunks: chunks
+            .into_iter()
+            .map(|(id, data)| Chunk {
+                id: id as u64,
+                data,
+            })
+            .collect(),
+    }
+}
+
+fn from_proto(chunks: ProtoLevelChunks) -> (usize, LevelChunks) {
+    (
+        chunks.level as usize,
+        chunks
+            .chunks
+            .into_iter()
+            .map(|chunk| (chunk.id as usize, chunk.data))
+            .collect(),
+    )
+}
+
+fn encode<T: Encode + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    bincode::encode_to_vec(value, bincode::config::standard())
+        .map_err(|e| EbloomError::SerializationError(e.to_string()))
+}
+
+fn decode<T: Decode<()>>(bytes: &[u8]) -> Result<T> {
+    bincode::decode_from_slice(bytes, bincode::config::standard())
+        .map(|(value, _)| value)
+        .map_err(|e| EbloomError::SerializationError(e.to_string()))
+}
+
+fn blob<T: Encode>(value: &T) -> RpcResult<StorageBlob> {
+    let data = encode(value).map_err(to_status)?;
+    Ok(Response::new(StorageBlob { data }))
+}
+
+/// Inverse of `to_status`, as far as the status code allows
+fn from_status(status: Status) -> EbloomError {
+    match status.code() {
+        Code::InvalidArgument => {
+            EbloomError::ConfigError(status.message().to_string())
+        }
+        _ => EbloomError::StorageError(format!(
+            "Remote storage failed: {}",
+            status.message()
+        )),
+    }
+}
@@ -1,5 +1,5 @@
 use fnv::FnvHasher;
-use murmur3::murmur3_32;
+use murmur3::{murmur3_32, murmur3_x64_128};
 use std::hash::Hasher;
 use std::io::Cursor;
 
@@ -20,7 +20,7 @@ use std::io::Cursor;
 ///
 /// **Returns:**
 ///
-/// - `Vec<u32>`
+/// - `Vec<usize>`
 ///   - A vector of hash indices corresponding to positions in the bit vector.
 ///
 /// **Usage:**
@@ -28,7 +28,11 @@ use std::io::Cursor;
 /// The hash function computes `num_hashes` hash indices for the given `item`,
 /// ensuring each index is within the range `[0, capacity)`. These indices are
 /// used to set or check bits in the Bloom filter's bit vector.
-pub type HashFunction = fn(&[u8], usize, usize) -> Vec<u32>;
+pub type HashFunction = fn(&[u8], usize, usize) -> Vec<usize>;
+
+/// Variant of `HashFunction` that appends the indices to `out`, letting
+/// callers reuse one buffer across many items.
+pub type HashIntoFunction = fn(&[u8], usize, usize, &mut Vec<usize>);
 
 pub(crate) fn hash_murmur32(key: &[u8]) -> u32 {
     let mut cursor = Cursor::new(key);
@@ -41,6 +45,27 @@ pub(crate) fn hash_fnv32(key: &[u8]) -> u32 {
     hasher.finish() as u32
 }
 
+pub(crate) fn hash_murmur64(key: &[u8]) -> u64 {
+    let mut cursor = Cursor::new(key);
+    murmur3_x64_128(&mut cursor, 0).expect("Failed to compute Murmur3 hash")
+        as u64
+}
+
+pub(crate) fn hash_fnv64(key: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(key);
+    hasher.finish()
+}
+
+/// Whether `capacity` still fits the 32-bit hashing scheme
+///
+/// Filters up to `u32::MAX` bits keep the 32-bit arithmetic, so their bit
+/// layout (and anything persisted from it) is unchanged; larger filters
+/// switch to 64-bit hashes to reach every bit.
+fn fits_u32(capacity: usize) -> bool {
+    capacity <= u32::MAX as usize
+}
+
 /// Implements the default double-hashing scheme for Bloom filters.
 ///
 /// This function uses a technique called "double hashing" to generate multiple hash values
@@ -57,6 +82,9 @@ pub(crate) fn hash_fnv32(key: &[u8]) -> u32 {
 /// This approach provides good distribution while being computationally efficient.
 /// The wrapping operations prevent integer overflow on large values.
 ///
+/// Capacities above `u32::MAX` use 64-bit Murmur3 and FNV hashes with the
+/// same formula.
+///
 /// Parameters:
 /// - `item`: The byte slice to hash
 /// - `num_hashes`: The number of hash values to generate
@@ -68,12 +96,89 @@ pub fn default_hash_function(
     item: &[u8],
     num_hashes: usize,
     capacity: usize,
-) -> Vec<u32> {
+) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(num_hashes);
+    default_hash_into(item, num_hashes, capacity, &mut indices);
+    indices
+}
+
+/// `default_hash_function` appending into an existing buffer
+pub fn default_hash_into(
+    item: &[u8],
+    num_hashes: usize,
+    capacity: usize,
+    out: &mut Vec<usize>,
+) {
+    if !fits_u32(capacity) {
+        let h1 = hash_murmur64(item);
+        let h2 = hash_fnv64(item);
+        out.extend((0..num_hashes).map(|i| {
+            (h1.wrapping_add((i as u64).wrapping_mul(h2)) % capacity as u64)
+                as usize
+        }));
+        return;
+    }
+
     let h1 = hash_murmur32(item);
     let h2 = hash_fnv32(item);
-    (0..num_hashes)
-        .map(|i| h1.wrapping_add((i as u32).wrapping_mul(h2)) % capacity as u32)
-        .collect()
+    out.extend((0..num_hashes).map(|i| {
+        (h1.wrapping_add((i as u32).wrapping_mul(h2)) % capacity as u32) as usize
+    }));
+}
+
+/// Number of bits in one cache-line block (64 bytes)
+pub const CACHE_LINE_BITS: usize = 512;
+
+/// Cache-line blocked variant of the double-hashing scheme.
+///
+/// The first hash selects one `CACHE_LINE_BITS` block and every probe of the
+/// item lands inside it, so an insert or lookup touches a single cache line
+/// instead of `num_hashes` random ones. Confining probes to a block makes
+/// bits less uniformly loaded, which raises the false positive rate slightly
+/// compared to `default_hash_function` at the same size.
+///
+/// The formula used is: h(i) = block * 512 + (h2 + i * h3) mod 512
+/// Where:
+/// - block is h1 mod (capacity / 512)
+/// - h3 is h1 rotated and forced odd, so probes cycle through the block
+///
+/// Bits past the last whole block are never used, so `capacity` should be a
+/// multiple of `CACHE_LINE_BITS`. Capacities below one block use a single
+/// block of `capacity` bits.
+pub fn blocked_hash_function(
+    item: &[u8],
+    num_hashes: usize,
+    capacity: usize,
+) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(num_hashes);
+    blocked_hash_into(item, num_hashes, capacity, &mut indices);
+    indices
+}
+
+/// `blocked_hash_function` appending into an existing buffer
+pub fn blocked_hash_into(
+    item: &[u8],
+    num_hashes: usize,
+    capacity: usize,
+    out: &mut Vec<usize>,
+) {
+    let h1 = hash_murmur32(item);
+    let h2 = hash_fnv32(item);
+    let h3 = h1.rotate_left(16) | 1;
+    let num_blocks = (capacity / CACHE_LINE_BITS).max(1);
+    let block_bits = capacity.min(CACHE_LINE_BITS);
+    // Only the block choice needs more than 32 bits on huge filters
+    let block = if fits_u32(capacity) {
+        (h1 % num_blocks as u32) as usize
+    } else {
+        (hash_murmur64(item) % num_blocks as u64) as usize
+    };
+    let block_start = block * block_bits;
+    out.extend((0..num_hashes).map(|i| {
+        block_start
+            + (h2.wrapping_add((i as u32).wrapping_mul(h3)) % block_bits as u32)
+                as usize
+    }));
 }
 
 /// Calculates the optimal bit vector size for a Bloom filter.
@@ -133,6 +238,42 @@ pub fn optimal_num_hashes(n: usize, m: usize) -> usize {
     ((m as f64 / n as f64) * std::f64::consts::LN_2).round() as usize
 }
 
+/// Largest bit vector a filter can be configured with: 2^48 bits (32 TiB),
+/// or what the address space allows on smaller targets
+pub const MAX_BIT_VECTOR_SIZE: usize = if usize::BITS >= 64 {
+    (1u64 << 48) as usize
+} else {
+    usize::MAX >> 3
+};
+
+/// Bit vector size and hash count for `n` items at `fpr`, or why they
+/// would not make a working filter
+///
+/// `optimal_bit_vector_size` saturates and `optimal_num_hashes` rounds to
+/// 0 for FPRs above about 0.7, and both give a filter that cannot work;
+/// config validation uses this to reject such combinations up front.
+pub fn checked_filter_params(
+    n: usize,
+    fpr: f64,
+) -> Result<(usize, usize), String> {
+    let ln2 = std::f64::consts::LN_2;
+    let bits = (-(n as f64) * fpr.ln()) / (ln2 * ln2);
+    if !bits.is_finite() || bits.ceil() > MAX_BIT_VECTOR_SIZE as f64 {
+        return Err(format!(
+            "{n} items at FPR {fpr} need {bits:.0} bits, more than the maximum of {MAX_BIT_VECTOR_SIZE}"
+        ));
+    }
+    // Ideal hash count is -log2(fpr); tiny capacities round the bit
+    // vector up and can hide a 0 here, so check it before rounding
+    if (-fpr.log2()).round() < 1.0 {
+        return Err(format!(
+            "{n} items at FPR {fpr} give 0 hash functions; use an FPR below 0.7"
+        ));
+    }
+    let m = optimal_bit_vector_size(n, fpr);
+    Ok((m, optimal_num_hashes(n, m)))
+}
+
 /// Calculates the per-level false positive rate needed to achieve the target
 /// overall false positive rate in a multi-level Bloom filter.
 ///
@@ -248,6 +389,20 @@ mod tests {
         );
     }
 
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_indices_reach_past_u32() {
+        let capacity = u32::MAX as usize * 4;
+        let max_index = (0..1000)
+            .flat_map(|i| {
+                default_hash_function(format!("item_{i}").as_bytes(), 4, capacity)
+            })
+            .max()
+            .unwrap();
+        assert!(max_index > u32::MAX as usize);
+        assert!(max_index < capacity);
+    }
+
     #[test]
     fn test_hash_functions_distribution() {
         let capacity = 10000;
@@ -263,7 +418,7 @@ mod tests {
         for data in test_data {
             let hashes = default_hash_function(&data, 1, capacity);
             for hash in hashes {
-                distribution[hash as usize] += 1;
+                distribution[hash] += 1;
             }
         }
 
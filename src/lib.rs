@@ -25,16 +25,34 @@
 //!       the probability of false positives can increase.
 //!     * Synchronization: In concurrent environments, care must be taken to synchronize
 //!       access during sub-filter rotation.
-//!     * Since 32 bit hashes used, max capacity would be 2**32-1 (Not sure)
+
+// Persistence awaits run on data copied out of the filter, never under a lock
+#![deny(clippy::await_holding_lock)]
+
+pub use bitvec;
 
 pub mod bloom;
 pub mod common;
 pub mod ebloom;
+pub mod fpr;
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 mod hash;
+#[cfg(feature = "resp")]
+pub mod resp;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 pub use bloom::error::{BloomError, BloomResult};
-pub use ebloom::error::{EbloomError, EbloomResult};
+pub use common::Membership;
+pub use ebloom::error::{EbloomError, EbloomResult, SaturationError};
+pub use fpr::{FprReport, MembershipQuery, measure_fpr};
 pub use hash::{
-    HashFunction, default_hash_function, optimal_bit_vector_size,
+    CACHE_LINE_BITS, HashFunction, HashIntoFunction, MAX_BIT_VECTOR_SIZE,
+    blocked_hash_function, blocked_hash_into, checked_filter_params,
+    default_hash_function, default_hash_into, optimal_bit_vector_size,
     optimal_num_hashes,
 };
@@ -0,0 +1,394 @@
+//! Redis protocol (RESP2) server speaking the RedisBloom `BF.*` commands
+//! (`resp` feature)
+//!
+//! Handles `BF.RESERVE`, `BF.ADD`, `BF.MADD`, `BF.EXISTS`, `BF.MEXISTS` and
+//! `BF.INFO`, plus `PING` and `QUIT`, so RedisBloom clients work unchanged.
+//! Each key holds an in-memory [`BloomFilter`]. Filters do not scale:
+//! `EXPANSION` and `NONSCALING` are accepted for compatibility, and past its
+//! capacity a filter answers with a growing false positive rate.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::bloom::{
+    config::BloomFilterConfigBuilder, filter::BloomFilter, traits::BloomFilterOps,
+};
+
+/// Capacity of filters created implicitly by `BF.ADD`/`BF.MADD`, as in
+/// RedisBloom
+pub const DEFAULT_CAPACITY: usize = 100;
+/// Error rate of filters created implicitly
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+/// Expansion reported by `BF.INFO` when `BF.RESERVE` gave none
+const DEFAULT_EXPANSION: i64 = 2;
+/// Longest bulk string accepted from a client
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Reply sent back to the client
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(msg: &str) -> Self {
+        Reply::Error(format!("ERR {msg}"))
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(s) => {
+                out.extend_from_slice(format!("+{s}\r\n").as_bytes())
+            }
+            Reply::Error(e) => {
+                out.extend_from_slice(format!("-{e}\r\n").as_bytes())
+            }
+            Reply::Integer(n) => {
+                out.extend_from_slice(format!(":{n}\r\n").as_bytes())
+            }
+            Reply::Bulk(bytes) => {
+                out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Nil => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+}
+
+struct Entry {
+    filter: BloomFilter,
+    expansion: i64,
+}
+
+/// Named filters shared by every connection
+#[derive(Clone, Default)]
+pub struct RespServer {
+    filters: Arc<RwLock<HashMap<Vec<u8>, Arc<Entry>>>>,
+}
+
+impl RespServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    tracing::debug!(%peer, "RESP connection closed: {e}");
+                }
+            });
+        }
+    }
+
+    /// Serve one client until it disconnects or sends `QUIT`
+    pub async fn handle_connection(
+        &self,
+        stream: TcpStream,
+    ) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut out = Vec::new();
+        while let Some(args) = read_command(&mut reader).await? {
+            let quit = args
+                .first()
+                .is_some_and(|name| name.eq_ignore_ascii_case(b"QUIT"));
+            let reply = if quit {
+                Reply::Simple("OK")
+            } else {
+                self.execute(&args).await
+            };
+            out.clear();
+            reply.encode(&mut out);
+            writer.write_all(&out).await?;
+            if quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one command, given as its name followed by its arguments
+    pub async fn execute(&self, args: &[Vec<u8>]) -> Reply {
+        let Some((name, args)) = args.split_first() else {
+            return Reply::error("empty command");
+        };
+        let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+        let result = match name.as_str() {
+            "PING" => Ok(match args.first() {
+                Some(message) => Reply::Bulk(message.clone()),
+                None => Reply::Simple("PONG"),
+            }),
+            "BF.RESERVE" => self.reserve(args).await,
+            "BF.ADD" => self.add(args).await,
+            "BF.MADD" => self.madd(args).await,
+            "BF.EXISTS" => self.exists(args),
+            "BF.MEXISTS" => self.mexists(args),
+            "BF.INFO" => self.info(args),
+            _ => Err(format!("unknown command '{name}'")),
+        };
+        result.unwrap_or_else(|msg| Reply::error(&msg))
+    }
+
+    async fn reserve(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key, error_rate, capacity, options @ ..] = args else {
+            return Err(wrong_arity("BF.RESERVE"));
+        };
+        let error_rate: f64 = parse(error_rate).ok_or("bad error rate")?;
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err("(0 < error rate range < 1)".to_string());
+        }
+        let capacity: usize = parse(capacity)
+            .filter(|&c| c > 0)
+            .ok_or("(capacity should be larger than 0)")?;
+
+        let mut expansion = DEFAULT_EXPANSION;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            if option.eq_ignore_ascii_case(b"EXPANSION") {
+                expansion = options
+                    .next()
+                    .and_then(|value| parse(value))
+                    .filter(|&e| e > 0)
+                    .ok_or("bad expansion")?;
+            } else if !option.eq_ignore_ascii_case(b"NONSCALING") {
+                return Err("syntax error".to_string());
+            }
+        }
+
+        if self.get(key)?.is_some() {
+            return Err("item exists".to_string());
+        }
+        let entry = create_entry(capacity, error_rate, expansion).await?;
+        let mut filters = self.filters.write().map_err(|_| lock_error())?;
+        if filters.contains_key(key) {
+            return Err("item exists".to_string());
+        }
+        filters.insert(key.clone(), Arc::new(entry));
+        Ok(Reply::Simple("OK"))
+    }
+
+    async fn add(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key, item] = args else {
+            return Err(wrong_arity("BF.ADD"));
+        };
+        let entry = self.get_or_create(key).await?;
+        add_item(&entry.filter, item)
+    }
+
+    async fn madd(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key, items @ ..] = args else {
+            return Err(wrong_arity("BF.MADD"));
+        };
+        if items.is_empty() {
+            return Err(wrong_arity("BF.MADD"));
+        }
+        let entry = self.get_or_create(key).await?;
+        Ok(Reply::Array(
+            items
+                .iter()
+                .map(|item| {
+                    add_item(&entry.filter, item)
+                        .unwrap_or_else(|msg| Reply::error(&msg))
+                })
+                .collect(),
+        ))
+    }
+
+    fn exists(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key, item] = args else {
+            return Err(wrong_arity("BF.EXISTS"));
+        };
+        let Some(entry) = self.get(key)? else {
+            return Ok(Reply::Integer(0));
+        };
+        contains_item(&entry.filter, item)
+    }
+
+    fn mexists(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key, items @ ..] = args else {
+            return Err(wrong_arity("BF.MEXISTS"));
+        };
+        if items.is_empty() {
+            return Err(wrong_arity("BF.MEXISTS"));
+        }
+        let entry = self.get(key)?;
+        Ok(Reply::Array(
+            items
+                .iter()
+                .map(|item| match entry {
+                    Some(ref entry) => contains_item(&entry.filter, item)
+                        .unwrap_or_else(|msg| Reply::error(&msg)),
+                    None => Reply::Integer(0),
+                })
+                .collect(),
+        ))
+    }
+
+    fn info(&self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let [key] = args else {
+            return Err(wrong_arity("BF.INFO"));
+        };
+        let entry = self.get(key)?.ok_or("not found")?;
+        let stats = entry.filter.stats();
+        let field = |name: &str, value: i64| {
+            [Reply::Bulk(name.as_bytes().to_vec()), Reply::Integer(value)]
+        };
+        Ok(Reply::Array(
+            [
+                field("Capacity", stats.capacity as i64),
+                field("Size", stats.memory_bytes as i64),
+                field("Number of filters", 1),
+                field("Number of items inserted", stats.insert_count as i64),
+                field("Expansion rate", entry.expansion),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        ))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Arc<Entry>>, String> {
+        let filters = self.filters.read().map_err(|_| lock_error())?;
+        Ok(filters.get(key).cloned())
+    }
+
+    async fn get_or_create(&self, key: &[u8]) -> Result<Arc<Entry>, String> {
+        if let Some(entry) = self.get(key)? {
+            return Ok(entry);
+        }
+        let entry =
+            create_entry(DEFAULT_CAPACITY, DEFAULT_ERROR_RATE, DEFAULT_EXPANSION)
+                .await?;
+        let mut filters = self.filters.write().map_err(|_| lock_error())?;
+        // Another connection may have created it meanwhile
+        Ok(Arc::clone(
+            filters
+                .entry(key.to_vec())
+                .or_insert_with(|| Arc::new(entry)),
+        ))
+    }
+}
+
+async fn create_entry(
+    capacity: usize,
+    error_rate: f64,
+    expansion: i64,
+) -> Result<Entry, String> {
+    let config = BloomFilterConfigBuilder::default()
+        .capacity(capacity)
+        .false_positive_rate(error_rate)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let filter = BloomFilter::create(config)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Entry { filter, expansion })
+}
+
+/// `1` when the item was added, `0` when it may have existed already
+fn add_item(filter: &BloomFilter, item: &[u8]) -> Result<Reply, String> {
+    if filter.contains(item).map_err(|e| e.to_string())? {
+        return Ok(Reply::Integer(0));
+    }
+    filter.insert(item).map_err(|e| e.to_string())?;
+    Ok(Reply::Integer(1))
+}
+
+fn contains_item(filter: &BloomFilter, item: &[u8]) -> Result<Reply, String> {
+    let found = filter.contains(item).map_err(|e| e.to_string())?;
+    Ok(Reply::Integer(found as i64))
+}
+
+fn parse<T: std::str::FromStr>(arg: &[u8]) -> Option<T> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+fn wrong_arity(command: &str) -> String {
+    format!("wrong number of arguments for '{command}' command")
+}
+
+fn lock_error() -> String {
+    "filter registry lock poisoned".to_string()
+}
+
+/// Read one command: a RESP array of bulk strings, or an inline command.
+/// Returns `None` on a clean disconnect.
+async fn read_command<R>(reader: &mut R) -> std::io::Result<Option<Vec<Vec<u8>>>>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    loop {
+        let Some(line) = read_line(reader).await? else {
+            return Ok(None);
+        };
+        let Some(count) = line.strip_prefix(b"*") else {
+            // Inline command, as typed into telnet
+            let args: Vec<Vec<u8>> = line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+            if args.is_empty() {
+                continue;
+            }
+            return Ok(Some(args));
+        };
+
+        let count: usize =
+            parse(count).ok_or_else(|| protocol_error("bad array"))?;
+        let mut args = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let header = read_line(reader)
+                .await?
+                .ok_or_else(|| protocol_error("truncated command"))?;
+            let len: usize = header
+                .strip_prefix(b"$")
+                .and_then(parse)
+                .filter(|&len| len <= MAX_BULK_LEN)
+                .ok_or_else(|| protocol_error("expected bulk string"))?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await?;
+            arg.truncate(len);
+            args.push(arg);
+        }
+        return Ok(Some(args));
+    }
+}
+
+/// Next line without its `\r\n`
+async fn read_line<R>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    while line.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn protocol_error(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
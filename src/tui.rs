@@ -0,0 +1,300 @@
+//! Terminal viewer for an `ExpiringBloomFilter` (`tui` feature)
+//!
+//! [`App`] holds a snapshot of the filter taken by [`App::refresh`] and
+//! [`ui`] renders it with ratatui: a summary, per-level density, age and
+//! inserts, a bit density strip per level and the latest rotations. The
+//! event loop stays with the caller, see `examples/tui_viewer.rs`.
+
+use std::time::Duration;
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Table},
+};
+
+use crate::common::bytes2hr;
+use crate::ebloom::{
+    error::Result, events::RotationRecord, filter::ExpiringBloomFilter,
+    stats::ExpiringStats,
+};
+
+/// Buckets sampled per level for the density strips
+pub const DEFAULT_BUCKETS: usize = 256;
+
+/// Rotations listed at the bottom of the view
+const SHOWN_ROTATIONS: usize = 5;
+
+/// Density shades, from empty to saturated
+const SHADES: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// State rendered by [`ui`]
+#[derive(Debug, Clone)]
+pub struct App {
+    pub stats: Option<ExpiringStats>,
+    /// Per-level density histograms, `buckets` entries each
+    pub histograms: Vec<Vec<f64>>,
+    /// Most recent rotations, newest last
+    pub rotations: Vec<RotationRecord>,
+    /// Level highlighted in the density view
+    pub selected_level: usize,
+    pub buckets: usize,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS)
+    }
+
+    pub fn with_buckets(buckets: usize) -> Self {
+        Self {
+            stats: None,
+            histograms: Vec::new(),
+            rotations: Vec::new(),
+            selected_level: 0,
+            buckets,
+        }
+    }
+
+    /// Take a fresh snapshot of the filter
+    pub fn refresh(&mut self, filter: &ExpiringBloomFilter) -> Result<()> {
+        let stats = filter.stats()?;
+        self.histograms = (0..stats.num_levels)
+            .map(|level| filter.level_density_histogram(level, self.buckets))
+            .collect::<Result<_>>()?;
+        self.rotations = filter.rotation_history(SHOWN_ROTATIONS)?;
+        self.selected_level = self.selected_level.min(stats.num_levels - 1);
+        self.stats = Some(stats);
+        Ok(())
+    }
+
+    pub fn select_next_level(&mut self) {
+        if let Some(ref stats) = self.stats {
+            self.selected_level = (self.selected_level + 1) % stats.num_levels;
+        }
+    }
+
+    pub fn select_previous_level(&mut self) {
+        if let Some(ref stats) = self.stats {
+            self.selected_level =
+                (self.selected_level + stats.num_levels - 1) % stats.num_levels;
+        }
+    }
+}
+
+/// Render the filter snapshot held by `app`
+pub fn ui(frame: &mut Frame, app: &App) {
+    let Some(ref stats) = app.stats else {
+        frame.render_widget(
+            Paragraph::new("Waiting for the first refresh...")
+                .block(Block::bordered().title(" Expiring Bloom Filter ")),
+            frame.area(),
+        );
+        return;
+    };
+
+    let [summary, levels, density, rotations, help] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(stats.num_levels as u16 + 3),
+        Constraint::Min(stats.num_levels as u16 + 2),
+        Constraint::Length(SHOWN_ROTATIONS as u16 + 2),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    render_summary(frame, summary, stats);
+    render_levels(frame, levels, stats, app.selected_level);
+    render_density(frame, density, app, stats.current_level);
+    render_rotations(frame, rotations, &app.rotations);
+    frame.render_widget(
+        Paragraph::new("←/→ select level   q quit")
+            .style(Style::default().fg(Color::DarkGray)),
+        help,
+    );
+}
+
+fn render_summary(frame: &mut Frame, area: Rect, stats: &ExpiringStats) {
+    let fpr_style = if stats.estimated_fpr > stats.target_fpr {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::Green)
+    };
+    let lines = vec![
+        Line::from(format!(
+            "capacity/level {}   levels {}   bits/level {}   hashes {}   memory {}",
+            stats.capacity_per_level,
+            stats.num_levels,
+            stats.bit_vector_size,
+            stats.num_hashes,
+            bytes2hr(stats.memory_bytes),
+        )),
+        Line::from(vec![
+            Span::raw(format!(
+                "current level {}   inserts {}   fpr target {:.4}   estimated ",
+                stats.current_level, stats.total_insert_count, stats.target_fpr,
+            )),
+            Span::styled(format!("{:.4}", stats.estimated_fpr), fpr_style),
+            Span::raw(match stats.snapshot_lag_ms {
+                Some(lag) => format!("   snapshot lag {}", format_ms(lag)),
+                None => String::new(),
+            }),
+        ]),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(Block::bordered().title(" Expiring Bloom Filter ")),
+        area,
+    );
+}
+
+fn render_levels(
+    frame: &mut Frame,
+    area: Rect,
+    stats: &ExpiringStats,
+    selected_level: usize,
+) {
+    let rows = stats.levels.iter().map(|level| {
+        let marker = if level.level == stats.current_level {
+            "▶"
+        } else {
+            " "
+        };
+        let mut style = Style::default().fg(density_color(level.fill_ratio));
+        if level.level == selected_level {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        Row::new(vec![
+            format!("{marker} {}", level.level),
+            format!("{:>6.2}%", level.fill_ratio * 100.0),
+            level.insert_count.to_string(),
+            level.age_ms.map_or_else(|| "-".to_string(), format_ms),
+            if level.expired { "expired" } else { "" }.to_string(),
+        ])
+        .style(style)
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Min(8),
+        ],
+    )
+    .header(
+        Row::new(vec!["level", "fill", "inserts", "age", "state"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(" Levels "));
+    frame.render_widget(table, area);
+}
+
+fn render_density(
+    frame: &mut Frame,
+    area: Rect,
+    app: &App,
+    current_level: usize,
+) {
+    // Label column plus borders
+    let width = area.width.saturating_sub(7) as usize;
+    let lines: Vec<Line> = app
+        .histograms
+        .iter()
+        .enumerate()
+        .map(|(level, histogram)| {
+            let label_style = if level == app.selected_level {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let marker = if level == current_level { '▶' } else { ' ' };
+            let mut spans =
+                vec![Span::styled(format!("{marker}L{level:<2} "), label_style)];
+            spans.extend(resample(histogram, width).into_iter().map(|density| {
+                Span::styled(
+                    shade(density).to_string(),
+                    Style::default().fg(density_color(density)),
+                )
+            }));
+            Line::from(spans)
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Bit density ")),
+        area,
+    );
+}
+
+fn render_rotations(frame: &mut Frame, area: Rect, rotations: &[RotationRecord]) {
+    let lines: Vec<Line> = if rotations.is_empty() {
+        vec![Line::from("no rotations yet")]
+    } else {
+        rotations
+            .iter()
+            .rev()
+            .map(|record| {
+                Line::from(format!(
+                    "at {}  level {}  {:?}  inserts {}  fill {:.2}%",
+                    record.rotated_at,
+                    record.level,
+                    record.reason,
+                    record.insert_count,
+                    record.fill_ratio * 100.0,
+                ))
+            })
+            .collect()
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Rotations ")),
+        area,
+    );
+}
+
+/// Stretch or shrink a histogram to `width` cells, averaging merged buckets
+fn resample(histogram: &[f64], width: usize) -> Vec<f64> {
+    if histogram.is_empty() || width == 0 {
+        return Vec::new();
+    }
+    (0..width)
+        .map(|cell| {
+            let start = cell * histogram.len() / width;
+            let end = ((cell + 1) * histogram.len() / width).max(start + 1);
+            let slice = &histogram[start..end.min(histogram.len())];
+            slice.iter().sum::<f64>() / slice.len() as f64
+        })
+        .collect()
+}
+
+fn shade(density: f64) -> char {
+    let idx = (density.clamp(0.0, 1.0) * (SHADES.len() - 1) as f64).round();
+    SHADES[idx as usize]
+}
+
+/// Green while sparse, yellow past the optimal half-full point, red when
+/// the level is close to saturation
+fn density_color(density: f64) -> Color {
+    match density {
+        d if d < 0.5 => Color::Green,
+        d if d < 0.75 => Color::Yellow,
+        _ => Color::Red,
+    }
+}
+
+fn format_ms(ms: u64) -> String {
+    let duration = Duration::from_millis(ms);
+    match duration.as_secs() {
+        0 => format!("{ms}ms"),
+        secs if secs < 120 => format!("{secs}s"),
+        secs if secs < 7200 => format!("{}m", secs / 60),
+        secs => format!("{}h", secs / 3600),
+    }
+}
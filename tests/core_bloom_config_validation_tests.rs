@@ -75,16 +75,20 @@ mod capacity_validation_tests {
     }
 
     #[test]
-    fn test_very_large_capacity_succeeds() {
-        // Test with usize::MAX / 2 to avoid potential overflow issues
-        let large_capacity = usize::MAX / 2;
+    fn test_very_large_capacity_fails() {
+        // The bit vector size would overflow usize
         let config = BloomFilterConfigBuilder::default()
-            .capacity(large_capacity)
+            .capacity(usize::MAX / 2)
             .false_positive_rate(0.01)
             .build()
             .unwrap();
 
-        assert!(config.validate().is_ok());
+        match config.validate() {
+            Err(BloomError::InvalidConfig(msg)) => {
+                assert!(msg.contains("maximum"), "{msg}");
+            }
+            other => panic!("Expected InvalidConfig, got {other:?}"),
+        }
     }
 }
 
@@ -174,13 +178,18 @@ mod false_positive_rate_validation_tests {
             .unwrap();
         assert!(config1.validate().is_ok());
 
-        // Test close to 1 but not 1
+        // Close to 1 but not 1 gives 0 hash functions
         let config2 = BloomFilterConfigBuilder::default()
             .capacity(1000)
             .false_positive_rate(0.999999)
             .build()
             .unwrap();
-        assert!(config2.validate().is_ok());
+        match config2.validate() {
+            Err(BloomError::InvalidConfig(msg)) => {
+                assert!(msg.contains("0 hash functions"), "{msg}");
+            }
+            other => panic!("Expected InvalidConfig, got {other:?}"),
+        }
     }
 
     #[test]
@@ -230,14 +239,39 @@ mod configuration_combinations_tests {
             .unwrap();
         assert!(config1.validate().is_ok());
 
-        // Small capacity with large FPR
+        // Small capacity with the largest FPR that still gives 1 hash
         let config2 = BloomFilterConfigBuilder::default()
             .capacity(10)
-            .false_positive_rate(0.9)
+            .false_positive_rate(0.7)
             .build()
             .unwrap();
         assert!(config2.validate().is_ok());
     }
+
+    #[test]
+    fn test_pathological_combinations_fail() {
+        let test_cases = vec![
+            (1, 0.999999),
+            (10, 0.9),
+            (usize::MAX, 0.01),
+            (1 << 50, 0.000001),
+        ];
+
+        for (capacity, fpr) in test_cases {
+            let config = BloomFilterConfigBuilder::default()
+                .capacity(capacity)
+                .false_positive_rate(fpr)
+                .build()
+                .unwrap();
+
+            assert!(
+                matches!(config.validate(), Err(BloomError::InvalidConfig(_))),
+                "Pathological combination should fail: capacity={}, fpr={}",
+                capacity,
+                fpr
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -566,7 +600,8 @@ mod serialization_tests {
             deserialized.false_positive_rate,
             original.false_positive_rate
         );
-        assert!(deserialized.validate().is_ok());
+        // Round-trips fine but is far too large to build
+        assert!(deserialized.validate().is_err());
     }
 
     #[test]
@@ -676,15 +711,19 @@ mod error_recovery_tests {
 
     #[test]
     fn test_graceful_handling_of_extreme_values() {
-        // Test config with extreme but technically valid values
+        // Valid FPR, but it would build a filter with no hash functions
         let config = BloomFilterConfig {
             capacity: 1,
             false_positive_rate: 0.99999,
             persistence: None,
         };
 
-        // Should validate successfully despite being impractical
-        assert!(config.validate().is_ok());
+        match config.validate() {
+            Err(BloomError::InvalidConfig(msg)) => {
+                assert!(msg.contains("FPR below"), "{msg}");
+            }
+            other => panic!("Expected InvalidConfig, got {other:?}"),
+        }
     }
 
     #[test]
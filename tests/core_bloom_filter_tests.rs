@@ -1,6 +1,7 @@
 use probabilistic_rs::bloom::{
     BloomFilter, BloomFilterConfigBuilder, BloomFilterOps, BloomFilterStats,
 };
+use probabilistic_rs::{FprReport, Membership, measure_fpr};
 use std::{
     collections::HashSet,
     sync::{Arc, Mutex},
@@ -54,6 +55,21 @@ mod basic_operations_tests {
         // Note: result might be true due to false positive, but that's acceptable
     }
 
+    #[test]
+    fn test_contains_checked() {
+        let filter = create_test_filter(1000, 0.01);
+        filter.insert(b"hello_world").unwrap();
+
+        let found = filter.contains_checked(b"hello_world").unwrap();
+        assert_eq!(found, Membership::ProbablyPresent);
+        assert!(found.is_probably_present());
+        assert_eq!(
+            filter.contains_checked(b"goodbye_world").unwrap(),
+            Membership::from(filter.contains(b"goodbye_world").unwrap())
+        );
+        assert_eq!(Membership::from(false), Membership::DefinitelyAbsent);
+    }
+
     #[test]
     fn test_multiple_insertions() {
         let filter = create_test_filter(1000, 0.01);
@@ -268,6 +284,54 @@ mod configuration_and_stats_tests {
         );
     }
 
+    #[test]
+    fn test_fill_ratio() {
+        let filter = create_test_filter(1000, 0.01);
+        assert_eq!(filter.fill_ratio(), 0.0);
+
+        for item in generate_test_items(1000) {
+            filter.insert(&item).unwrap();
+        }
+        // A filter at capacity has roughly half its bits set
+        let ratio = filter.fill_ratio();
+        assert!((0.3..0.7).contains(&ratio), "unexpected fill ratio {ratio}");
+    }
+
+    #[test]
+    fn test_stats_bit_counts() {
+        let filter = create_test_filter(1000, 0.01);
+        assert_eq!(filter.bit_vector_size(), filter.bit_vector_size);
+        assert_eq!(filter.num_hashes(), filter.num_hashes);
+        assert_eq!(filter.bit_count(), 0);
+
+        filter.insert(b"item").unwrap();
+        let set = filter.bit_count();
+        assert!(set >= 1 && set <= filter.num_hashes());
+
+        for item in generate_test_items(500) {
+            filter.insert(&item).unwrap();
+        }
+        let expected = filter.fill_ratio() * filter.bit_vector_size() as f64;
+        assert_eq!(filter.bit_count(), expected.round() as usize);
+    }
+
+    #[test]
+    fn test_stats_snapshot() {
+        let filter = create_test_filter(1000, 0.01);
+        for item in generate_test_items(500) {
+            filter.insert(&item).unwrap();
+        }
+
+        let stats = filter.stats();
+        assert_eq!(stats.capacity, 1000);
+        assert_eq!(stats.target_fpr, 0.01);
+        assert_eq!(stats.insert_count, 500);
+        assert_eq!(stats.fill_ratio, filter.fill_ratio());
+        assert_eq!(stats.memory_bytes, filter.approx_memory_bits());
+        // Half full, so well under the target rate
+        assert!(stats.estimated_fpr > 0.0 && stats.estimated_fpr < 0.01);
+    }
+
     #[test]
     fn test_different_config_combinations() {
         let configs = [
@@ -467,6 +531,38 @@ mod behavioral_guarantees_tests {
         );
     }
 
+    #[test]
+    fn test_measure_fpr_report() {
+        let filter = create_test_filter(1000, 0.01);
+        for item in generate_test_items(1000) {
+            filter.insert(&item).unwrap();
+        }
+
+        let negatives =
+            (0..20_000).map(|i| format!("absent_item_{:06}", i).into_bytes());
+        let report = measure_fpr(&filter, negatives).unwrap();
+        assert_eq!(report.queries, 20_000);
+        assert_eq!(
+            report.rate,
+            report.false_positives as f64 / report.queries as f64
+        );
+        assert!(report.ci_low <= report.rate && report.rate <= report.ci_high);
+        assert!(!report.exceeds(0.01));
+        assert!(report.exceeds(0.0001));
+
+        // Inserted keys are all hits, through the closure impl
+        let hits = measure_fpr(
+            &|item: &[u8]| filter.contains(item),
+            generate_test_items(100),
+        )
+        .unwrap();
+        assert_eq!(hits.false_positives, 100);
+        assert_eq!(hits.rate, 1.0);
+
+        let empty = FprReport::from_counts(0, 0);
+        assert_eq!((empty.rate, empty.ci_low, empty.ci_high), (0.0, 0.0, 1.0));
+    }
+
     #[test]
     fn test_deterministic_behavior() {
         let capacity = 1000;
@@ -803,6 +899,27 @@ mod bulk_operations_tests {
         );
     }
 
+    #[test]
+    fn test_bulk_contains_bitset() {
+        let filter = create_test_filter(10_000, 0.01);
+        let items = generate_test_items(10_000);
+        for item in items.iter().step_by(2) {
+            filter.insert(item).unwrap();
+        }
+
+        let refs: Vec<&[u8]> = items.iter().map(|v| v.as_slice()).collect();
+        let bulk = filter.contains_bulk(&refs).unwrap();
+        let bitset = filter.contains_bulk_bitset(&refs).unwrap();
+        assert_eq!(bitset.len(), refs.len());
+        assert!(bitset.iter().by_vals().eq(bulk.iter().copied()));
+        assert!(bitset.iter_ones().filter(|i| i % 2 == 0).count() == 5_000);
+
+        // Streaming in batches gives the same bits
+        let streamed = filter.contains_iter_bitset(items.iter()).unwrap();
+        assert_eq!(streamed, bitset);
+        assert!(filter.contains_bulk_bitset(&[]).unwrap().is_empty());
+    }
+
     #[test]
     fn test_bulk_operations_consistency() {
         let filter = create_test_filter(1000, 0.01);
@@ -1027,3 +1144,557 @@ mod bulk_operations_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod redisbloom_dump_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{
+        RedisBloomFilter,
+        redisbloom::{DEFAULT_EXPANSION, OPT_FORCE64},
+    };
+
+    #[test]
+    fn test_scan_dump_round_trip() {
+        let mut filter =
+            RedisBloomFilter::new(100, 0.01, DEFAULT_EXPANSION, false).unwrap();
+        let items = generate_test_items(250);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        // Past capacity the chain grows: 100, then 200 entries
+        assert_eq!(filter.num_links(), 2);
+        assert_eq!(filter.capacity(), 300);
+
+        let chunks = filter.scan_dump();
+        assert_eq!(chunks[0].0, 1);
+        // One data chunk per link, iterators counting bytes from 1
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.last().unwrap().0 as usize, 1 + filter.memory_bytes());
+
+        let loaded = RedisBloomFilter::load_chunks(chunks).unwrap();
+        assert_eq!(loaded, filter);
+        assert!(items.iter().all(|item| loaded.contains(item)));
+    }
+
+    #[test]
+    fn test_insert_reports_duplicates() {
+        let mut filter = RedisBloomFilter::new(100, 0.01, 2, false).unwrap();
+        assert!(filter.insert(b"a").unwrap());
+        assert!(!filter.insert(b"a").unwrap());
+        assert_eq!(filter.len(), 1);
+        assert!(filter.contains(b"a"));
+        assert!(!filter.contains(b"b"));
+    }
+
+    #[test]
+    fn test_nonscaling_filter_fills_up() {
+        let mut filter = RedisBloomFilter::new(10, 0.01, 2, true).unwrap();
+        for item in generate_test_items(10) {
+            filter.insert(&item).unwrap();
+        }
+        assert!(filter.insert(b"one too many").is_err());
+        assert_eq!(filter.num_links(), 1);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_dumps() {
+        let filter = RedisBloomFilter::new(100, 0.01, 2, false).unwrap();
+        let chunks = filter.scan_dump();
+
+        // Truncated header
+        let mut bad = chunks.clone();
+        bad[0].1.pop();
+        assert!(RedisBloomFilter::load_chunks(bad).is_err());
+
+        // 32-bit hashing from RedisBloom 1.x
+        let mut bad = chunks.clone();
+        bad[0].1[12..16].copy_from_slice(&0u32.to_le_bytes());
+        assert!(RedisBloomFilter::load_chunks(bad).is_err());
+        let options = u32::from_le_bytes(chunks[0].1[12..16].try_into().unwrap());
+        assert_ne!(options & OPT_FORCE64, 0);
+
+        // Chunk past the end of the filter
+        let mut bad = chunks.clone();
+        bad[1].0 += 8;
+        assert!(RedisBloomFilter::load_chunks(bad).is_err());
+    }
+}
+
+mod gobloom_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{
+        GoBloomFilter,
+        gobloom::{estimate_parameters, locations},
+    };
+
+    #[test]
+    fn test_hashing_matches_go() {
+        // MurmurHash3 x64 128 of "hello": 0xcbd8a7b341bd9b02, 0x5b1e906a48ae1d19
+        let locs = locations(b"hello", 2);
+        assert_eq!(locs, vec![0xcbd8a7b341bd9b02, 0xb9a2276dfbf693b5]);
+    }
+
+    #[test]
+    fn test_estimates_match_go() {
+        assert_eq!(estimate_parameters(1000, 0.01), (9586, 7));
+        let filter = GoBloomFilter::with_estimates(1000, 0.01).unwrap();
+        assert_eq!((filter.m(), filter.k()), (9586, 7));
+        assert!(GoBloomFilter::with_estimates(0, 0.01).is_err());
+        assert!(GoBloomFilter::with_estimates(1000, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut filter = GoBloomFilter::with_estimates(500, 0.01).unwrap();
+        let items = generate_test_items(500);
+        for item in &items {
+            filter.insert(item);
+        }
+        assert!(filter.test_and_insert(&items[0]));
+
+        let bytes = filter.to_bytes();
+        assert_eq!(bytes.len(), 24 + filter.m().div_ceil(64) as usize * 8);
+        let loaded = GoBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, filter);
+        assert!(loaded.verify_members(&items).is_ok());
+    }
+
+    #[test]
+    fn test_decodes_go_encoding() {
+        // m = 70, k = 3, bitset of 70 bits with bits 0 and 65 set
+        let mut bytes = Vec::new();
+        for field in [70u64, 3, 70, 1, 2] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        let filter = GoBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!((filter.m(), filter.k()), (70, 3));
+        assert_eq!(filter.count_ones(), 2);
+        assert_eq!(filter.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_rejects_bad_encodings() {
+        let bytes = GoBloomFilter::new(100, 3).to_bytes();
+
+        assert!(GoBloomFilter::from_bytes(&bytes[..20]).is_err());
+        assert!(GoBloomFilter::from_bytes(&bytes[..bytes.len() - 8]).is_err());
+
+        // Bitset length disagrees with m
+        let mut bad = bytes.clone();
+        bad[16..24].copy_from_slice(&64u64.to_be_bytes());
+        assert!(GoBloomFilter::from_bytes(&bad).is_err());
+
+        // Bit 127 set, past m = 100
+        let mut bad = bytes.clone();
+        let last = bad.len() - 8;
+        bad[last] = 0x80;
+        assert!(GoBloomFilter::from_bytes(&bad).is_err());
+    }
+
+    #[test]
+    fn test_verify_members_catches_foreign_hashing() {
+        // The producer claims "member" was added, but its bits are not set
+        let mut producer = GoBloomFilter::new(1000, 4);
+        producer.insert(b"something else");
+        let filter = GoBloomFilter::from_bytes(&producer.to_bytes()).unwrap();
+        assert!(filter.verify_members([b"something else"]).is_ok());
+        assert!(filter.verify_members([b"member"]).is_err());
+    }
+}
+
+#[cfg(feature = "pybloom")]
+mod pybloom_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{
+        PyBloomFilter, PyHashScheme, PyScalableBloomFilter,
+    };
+
+    /// `BloomFilter(10, 0.1)` after adding "a", "b" and "c", as written by
+    /// `tofile` (4 slices of 12 bits, MD5 offsets)
+    const PYBLOOM_FILE: &str = "9a9999999999b93f04000000000000000c00000000000000\
+                                0a000000000000000300000000000000081609083001";
+
+    fn decode_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_loads_python_file() {
+        let bytes = decode_hex(PYBLOOM_FILE);
+        let filter =
+            PyBloomFilter::from_bytes(&bytes, PyHashScheme::Md5).unwrap();
+        assert_eq!(filter.num_slices(), 4);
+        assert_eq!(filter.bits_per_slice(), 12);
+        assert_eq!(filter.capacity(), 10);
+        assert_eq!(filter.len(), 3);
+        for key in [b"a", b"b", b"c"] {
+            assert!(filter.contains(key));
+        }
+    }
+
+    #[test]
+    fn test_builds_same_bits_as_python() {
+        let mut filter = PyBloomFilter::new(10, 0.1, PyHashScheme::Md5).unwrap();
+        for key in [b"a", b"b", b"c"] {
+            assert!(filter.insert(key).unwrap());
+        }
+        assert!(!filter.insert(b"a").unwrap());
+        assert_eq!(filter.to_bytes(), decode_hex(PYBLOOM_FILE));
+    }
+
+    #[test]
+    fn test_round_trip_large_filter() {
+        // 10 slices of 1438 bits: offsets come from SHA-1
+        let mut filter =
+            PyBloomFilter::new(1000, 0.001, PyHashScheme::default()).unwrap();
+        assert_eq!((filter.num_slices(), filter.bits_per_slice()), (10, 1438));
+        let items = generate_test_items(1000);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        let loaded =
+            PyBloomFilter::from_bytes(&filter.to_bytes(), PyHashScheme::Md5)
+                .unwrap();
+        assert_eq!(loaded, filter);
+        assert!(items.iter().all(|item| loaded.contains(item)));
+    }
+
+    #[test]
+    fn test_filter_refuses_keys_past_capacity() {
+        let mut filter = PyBloomFilter::new(2, 0.1, PyHashScheme::Md5).unwrap();
+        let results: Vec<_> = generate_test_items(100)
+            .iter()
+            .map(|item| filter.insert(item))
+            .collect();
+        assert!(results.iter().any(Result::is_err));
+        assert!(filter.len() <= 3);
+    }
+
+    #[test]
+    fn test_scalable_round_trip() {
+        let mut filter = PyScalableBloomFilter::new(
+            100,
+            0.01,
+            PyScalableBloomFilter::SMALL_SET_GROWTH,
+            PyHashScheme::Xxh128,
+        )
+        .unwrap();
+        let items = generate_test_items(500);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        assert!(filter.filters().len() > 1);
+
+        let loaded = PyScalableBloomFilter::from_bytes(
+            &filter.to_bytes(),
+            PyHashScheme::Xxh128,
+        )
+        .unwrap();
+        assert_eq!(loaded, filter);
+        assert!(items.iter().all(|item| loaded.contains(item)));
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        let bytes = decode_hex(PYBLOOM_FILE);
+        assert!(
+            PyBloomFilter::from_bytes(&bytes[..30], PyHashScheme::Md5).is_err()
+        );
+        assert!(
+            PyBloomFilter::from_bytes(
+                &bytes[..bytes.len() - 1],
+                PyHashScheme::Md5
+            )
+            .is_err()
+        );
+
+        let filter =
+            PyScalableBloomFilter::new(10, 0.1, 2, PyHashScheme::Md5).unwrap();
+        let mut bad = filter.to_bytes();
+        bad.push(0);
+        assert!(
+            PyScalableBloomFilter::from_bytes(&bad, PyHashScheme::Md5).is_err()
+        );
+    }
+}
+
+mod static_denylist_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{
+        DenylistFormat, GoBloomFilter, StaticDenylist,
+    };
+
+    #[test]
+    fn test_save_and_load_from_file() {
+        let entries = generate_test_items(1000);
+        let denylist =
+            StaticDenylist::from_entries(&entries, 1000, 0.001).unwrap();
+        let path = std::env::temp_dir().join("static_denylist_test.bin");
+        denylist.save(&path).unwrap();
+
+        let loaded =
+            StaticDenylist::from_file(&path, DenylistFormat::GoBloom).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(entries.iter().all(|entry| loaded.maybe_listed(entry)));
+        let misses = (0..1000)
+            .filter(|i| loaded.maybe_listed(format!("absent_{i}").as_bytes()))
+            .count();
+        assert!(misses < 10, "too many false positives: {misses}");
+    }
+
+    #[test]
+    fn test_streaming_reader_matches_bytes() {
+        let mut filter = GoBloomFilter::with_estimates(100_000, 0.01).unwrap();
+        filter.insert(b"bad.example");
+        let bytes = filter.to_bytes();
+
+        let denylist = StaticDenylist::from_reader(
+            bytes.as_slice(),
+            DenylistFormat::GoBloom,
+        )
+        .unwrap();
+        assert_eq!(
+            denylist.maybe_listed_bulk(&[b"bad.example", b"good.example"]),
+            vec![true, false]
+        );
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(
+            StaticDenylist::from_reader(truncated, DenylistFormat::GoBloom)
+                .is_err()
+        );
+        assert!(
+            StaticDenylist::from_file(
+                "/nonexistent/denylist.bin",
+                DenylistFormat::GoBloom
+            )
+            .is_err()
+        );
+    }
+}
+
+#[cfg(test)]
+mod auto_bloom_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{
+        AutoBloomConfigBuilder, AutoBloomFilter, BulkBloomFilterOps,
+        GrowthTrigger,
+    };
+
+    fn create_auto_filter(
+        initial_capacity: usize,
+        growth_trigger: GrowthTrigger,
+    ) -> AutoBloomFilter {
+        AutoBloomFilter::new(
+            AutoBloomConfigBuilder::default()
+                .initial_capacity(initial_capacity)
+                .false_positive_rate(0.01)
+                .growth_trigger(growth_trigger)
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_auto_bloom_grows_and_keeps_fpr() {
+        let filter = create_auto_filter(1000, GrowthTrigger::Capacity);
+        let items = generate_test_items(20_000);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+
+        // 1000 + 2000 + 4000 + 8000 < 20_000 <= 31_000
+        assert_eq!(filter.num_slices(), 5);
+        assert_eq!(filter.capacity(), 31_000);
+        let stats = filter.stats();
+        assert_eq!(
+            filter.bit_vector_size(),
+            stats
+                .slices
+                .iter()
+                .map(|s| s.bit_vector_size)
+                .sum::<usize>()
+        );
+        assert_eq!(filter.num_hashes(), stats.slices[4].num_hashes);
+        assert!(filter.bit_count() > 0);
+        assert!(filter.bit_count() < filter.bit_vector_size());
+        // Items that hit a false positive count as repeats
+        let inserted = filter.insert_count();
+        assert!(inserted <= 20_000 && inserted > 19_500, "{inserted}");
+        for item in &items {
+            assert!(filter.contains(item).unwrap());
+        }
+
+        let stats = filter.stats();
+        assert_eq!(stats.slices.len(), 5);
+        assert_eq!(stats.slices[1].capacity, 2000);
+        assert!(stats.slices[1].target_fpr < stats.slices[0].target_fpr);
+        assert!(stats.estimated_fpr < 0.01);
+
+        let negatives =
+            (0..20_000).map(|i| format!("absent_{i:06}").into_bytes());
+        let report = measure_fpr(&filter, negatives).unwrap();
+        assert!(!report.exceeds(0.01), "{report:?}");
+    }
+
+    #[test]
+    fn test_auto_bloom_skips_repeats_and_clears() {
+        let filter = create_auto_filter(100, GrowthTrigger::Capacity);
+        for _ in 0..1000 {
+            filter.insert(b"same").unwrap();
+        }
+        assert_eq!(filter.insert_count(), 1);
+        assert_eq!(filter.num_slices(), 1);
+        assert!(!filter.insert_new(b"same").unwrap());
+        assert!(filter.insert_new(b"other").unwrap());
+
+        let items = generate_test_items(500);
+        let refs: Vec<&[u8]> = items.iter().map(|i| i.as_slice()).collect();
+        filter.insert_bulk(&refs).unwrap();
+        assert!(filter.num_slices() > 1);
+        assert!(filter.contains_bulk(&refs).unwrap().iter().all(|&hit| hit));
+
+        filter.clear().unwrap();
+        assert_eq!(filter.num_slices(), 1);
+        assert_eq!(filter.insert_count(), 0);
+        assert!(!filter.contains(b"same").unwrap());
+    }
+
+    #[test]
+    fn test_auto_bloom_fill_ratio_trigger() {
+        let filter = create_auto_filter(100_000, GrowthTrigger::FillRatio(0.05));
+        for item in generate_test_items(10_000) {
+            filter.insert(&item).unwrap();
+        }
+        // Far below capacity, but the first slice passed 5% fill
+        assert!(filter.num_slices() > 1);
+        assert!(filter.stats().slices[0].fill_ratio >= 0.05);
+    }
+
+    #[test]
+    fn test_auto_bloom_rejects_invalid_config() {
+        for config in [
+            AutoBloomConfigBuilder::default().initial_capacity(0),
+            AutoBloomConfigBuilder::default().growth_factor(0),
+            AutoBloomConfigBuilder::default().tightening_ratio(1.0),
+            AutoBloomConfigBuilder::default()
+                .growth_trigger(GrowthTrigger::FillRatio(0.0)),
+        ] {
+            assert!(AutoBloomFilter::new(config.build().unwrap()).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod admission_tests {
+    use super::*;
+    use probabilistic_rs::bloom::{TinyLfu, TinyLfuConfigBuilder};
+
+    fn create_admission(min_frequency: u8, sample_size: usize) -> TinyLfu {
+        TinyLfu::new(
+            TinyLfuConfigBuilder::default()
+                .expected_items(1000)
+                .min_frequency(min_frequency)
+                .sample_size(sample_size)
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_admits_after_min_frequency() {
+        let admission = create_admission(3, 0);
+        assert!(!admission.admit(b"key").unwrap());
+        admission.record(b"key").unwrap();
+        admission.record(b"key").unwrap();
+        assert!(!admission.admit(b"key").unwrap());
+        admission.record(b"key").unwrap();
+        assert!(admission.admit(b"key").unwrap());
+        assert_eq!(admission.frequency(b"key").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_one_hit_wonders_are_rejected() {
+        let admission = create_admission(2, 0);
+        let items = generate_test_items(500);
+        for item in &items {
+            admission.record(item).unwrap();
+        }
+        let admitted =
+            items.iter().filter(|i| admission.admit(i).unwrap()).count();
+        // Only doorkeeper false positives get through
+        assert!(admitted < 25, "{admitted}");
+    }
+
+    #[test]
+    fn test_frequent_key_wins_over_rare_one() {
+        let admission = create_admission(2, 0);
+        for _ in 0..5 {
+            admission.record(b"hot").unwrap();
+        }
+        admission.record(b"cold").unwrap();
+        assert!(admission.admit_over(b"hot", b"cold").unwrap());
+        assert!(!admission.admit_over(b"cold", b"hot").unwrap());
+    }
+
+    #[test]
+    fn test_counts_age_after_sample() {
+        let admission = create_admission(2, 100);
+        for _ in 0..8 {
+            admission.record(b"key").unwrap();
+        }
+        assert_eq!(admission.frequency(b"key").unwrap(), 8);
+        for item in generate_test_items(92) {
+            admission.record(&item).unwrap();
+        }
+        // Sketch count 7 halved, doorkeeper cleared
+        assert_eq!(admission.frequency(b"key").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_frequency_saturates() {
+        let admission = create_admission(16, 0);
+        for _ in 0..100 {
+            admission.record(b"key").unwrap();
+        }
+        assert_eq!(admission.frequency(b"key").unwrap(), 16);
+        assert!(admission.admit(b"key").unwrap());
+    }
+
+    #[test]
+    fn test_invalid_admission_config_rejected() {
+        for config in [
+            TinyLfuConfigBuilder::default().expected_items(0),
+            TinyLfuConfigBuilder::default().min_frequency(0),
+            TinyLfuConfigBuilder::default().min_frequency(17),
+        ] {
+            assert!(TinyLfu::new(config.build().unwrap()).is_err());
+        }
+    }
+
+    #[cfg(feature = "moka")]
+    #[test]
+    fn test_admitting_cache_skips_one_hit_wonders() {
+        let cache = create_admission(2, 0).wrap(moka::sync::Cache::new(100));
+
+        assert_eq!(cache.get_with(1u32, || "one").unwrap(), "one");
+        assert!(!cache.inner().contains_key(&1));
+
+        assert_eq!(cache.get_with(1u32, || "one").unwrap(), "one");
+        assert!(cache.inner().contains_key(&1));
+        assert_eq!(cache.get(&1).unwrap(), Some("one"));
+
+        // Updates of cached keys are always stored
+        assert!(cache.insert(1, "uno").unwrap());
+        assert_eq!(cache.get(&1).unwrap(), Some("uno"));
+        assert!(!cache.insert(2, "two").unwrap());
+
+        cache.invalidate(&1);
+        assert_eq!(cache.get(&1).unwrap(), None);
+    }
+}
@@ -168,6 +168,38 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_unaligned_chunk_size_round_trip() {
+        let test_db = TestDb::new("unaligned_chunks");
+        let persistence = PersistenceConfigBuilder::default()
+            .db_path(test_db.path.clone())
+            .chunk_size_bytes(100) // Not a multiple of the word size
+            .auto_snapshot(false)
+            .build()
+            .unwrap();
+        let config = BloomFilterConfigBuilder::default()
+            .capacity(10_000)
+            .false_positive_rate(0.01)
+            .persistence(Some(persistence))
+            .build()
+            .unwrap();
+
+        let fill_ratio = {
+            let filter = BloomFilter::create(config).await.unwrap();
+            for i in 0..2000 {
+                filter.insert(format!("item_{i}").as_bytes()).unwrap();
+            }
+            filter.save_snapshot().await.unwrap();
+            filter.fill_ratio()
+        };
+
+        let filter = BloomFilter::load(test_db.path.clone()).await.unwrap();
+        assert_eq!(filter.fill_ratio(), fill_ratio);
+        for i in 0..2000 {
+            assert!(filter.contains(format!("item_{i}").as_bytes()).unwrap());
+        }
+    }
+
     #[tokio::test]
     async fn test_multiple_save_load_cycles() {
         let test_db = TestDb::new("multiple_cycles");
@@ -336,6 +368,33 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_error_handling_out_of_range_chunk() {
+        use probabilistic_rs::bloom::{StorageBackend, storage::FjallBackend};
+
+        let test_db = TestDb::new("out_of_range_chunk");
+        let config = create_test_config(test_db.path.clone());
+
+        {
+            let _filter = BloomFilter::create(config).await.unwrap();
+        }
+
+        // A chunk id this large would overflow the bit offset
+        {
+            let backend = FjallBackend::new(test_db.path.clone()).await.unwrap();
+            backend
+                .save_snapshot(&[(usize::MAX / 8, vec![0xFF])])
+                .await
+                .unwrap();
+        }
+
+        let result = BloomFilter::load(test_db.path.clone()).await;
+        match result {
+            Err(probabilistic_rs::bloom::BloomError::StorageError(_)) => {}
+            _ => panic!("Expected StorageError"),
+        }
+    }
+
     #[tokio::test]
     async fn test_concurrent_access_during_persistence() {
         let test_db = TestDb::new("concurrent_access");
@@ -0,0 +1,995 @@
+#[cfg(feature = "fjall")]
+mod tests {
+    use probabilistic_rs::EbloomError;
+    use probabilistic_rs::ebloom::{
+        config::LevelMetadata,
+        config::{
+            ExpiringFilterConfig, ExpiringFilterConfigBuilder,
+            ExpiringPersistenceConfigBuilder,
+        },
+        events::{FilterObserver, SnapshotEvent},
+        filter::ExpiringBloomFilter,
+        storage::{ExpiringStorageBackend, FjallExpiringBackend, SnapshotBatch},
+        traits::{ExpiringBloomFilterOps, ExpiringBloomFilterStats},
+    };
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    struct TestDb {
+        path: PathBuf,
+    }
+
+    impl TestDb {
+        fn new(test_name: &str) -> Self {
+            let path = PathBuf::from(format!("test_ebloom_{}.fjall", test_name));
+            Self { path }
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            if self.path.exists() {
+                let _ = fs::remove_dir_all(&self.path);
+            }
+        }
+    }
+
+    fn create_test_config(
+        db_path: PathBuf,
+        level_duration: Duration,
+    ) -> ExpiringFilterConfig {
+        let persistence = ExpiringPersistenceConfigBuilder::default()
+            .db_path(db_path)
+            .chunk_size_bytes(1024_usize)
+            .build()
+            .unwrap();
+
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(level_duration)
+            .persistence(Some(persistence))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_load_restores_items() {
+        let test_db = TestDb::new("load_restores_items");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            filter.insert(b"persisted").unwrap();
+            filter.save_snapshot().await.unwrap();
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert!(loaded.contains(b"persisted").unwrap());
+        assert_eq!(loaded.get_active_level(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_log_survives_restart() {
+        let test_db = TestDb::new("rotation_log");
+        let mut config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        if let Some(ref mut pers) = config.persistence {
+            pers.persist_rotation_log = true;
+        }
+
+        let history = {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            filter.insert(b"item").unwrap();
+            filter.rotate_levels().await.unwrap();
+            filter.rotate_levels().await.unwrap();
+            filter.rotation_history(10).unwrap()
+        };
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].insert_count, 0);
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert_eq!(loaded.rotation_history(10).unwrap(), history);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_log_is_in_memory_by_default() {
+        let test_db = TestDb::new("rotation_log_default");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            filter.rotate_levels().await.unwrap();
+            assert_eq!(filter.rotation_history(10).unwrap().len(), 1);
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert!(loaded.rotation_history(10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_restores_every_level() {
+        let test_db = TestDb::new("load_restores_every_level");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            for level in 0..3 {
+                filter.insert(format!("level_{level}").as_bytes()).unwrap();
+                filter.save_snapshot().await.unwrap();
+                if level < 2 {
+                    filter.rotate_levels().await.unwrap();
+                }
+            }
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert_eq!(loaded.get_active_level(), 2);
+        for level in 0..3 {
+            assert!(
+                loaded
+                    .contains(format!("level_{level}").as_bytes())
+                    .unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_rotates_levels_that_expired_while_offline() {
+        let test_db = TestDb::new("warm_start_rotation");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_millis(100));
+
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            filter.insert(b"stale").unwrap();
+            filter.save_snapshot().await.unwrap();
+        }
+
+        // Offline for longer than all three windows together
+        thread::sleep(Duration::from_millis(350));
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert!(!loaded.contains(b"stale").unwrap());
+        let current = loaded.get_active_level();
+        assert!(!loaded.is_level_expired(current).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_commit_snapshot_writes_everything() {
+        let test_db = TestDb::new("commit_snapshot");
+        let backend = FjallExpiringBackend::new(test_db.path.clone(), 2)
+            .await
+            .unwrap();
+
+        let metadata = vec![
+            LevelMetadata {
+                created_at: 10,
+                insert_count: 3,
+                last_snapshot_at: 20,
+                rotation_reason: Default::default(),
+            };
+            2
+        ];
+        backend
+            .commit_snapshot(SnapshotBatch {
+                level_chunks: vec![(0, vec![(0, vec![1, 2]), (1, vec![3])])],
+                dirty_chunks: vec![(1, vec![(4, vec![9])])],
+                metadata: Some(metadata),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            backend.load_level_chunks(0).await.unwrap(),
+            vec![(0, vec![1, 2]), (1, vec![3])]
+        );
+        assert_eq!(
+            backend.load_dirty_chunks(1).await.unwrap(),
+            vec![(4, vec![9])]
+        );
+        let loaded = backend.load_level_metadata().await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].insert_count, 3);
+
+        // Unknown levels are rejected before anything is written
+        let result = backend
+            .commit_snapshot(SnapshotBatch {
+                dirty_chunks: vec![(5, vec![(0, vec![1])])],
+                ..Default::default()
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_committed_batch_survives_restart() {
+        let test_db = TestDb::new("batch_commit");
+        let mut config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        if let Some(ref mut pers) = config.persistence {
+            pers.write_behind_capacity = Some(64);
+        }
+
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            let mut batch = filter.batch();
+            assert!(batch.is_empty());
+            for i in 0..100 {
+                batch.insert(format!("committed_{i}").as_bytes());
+            }
+            // Buffered items stay invisible until the commit
+            assert!(!filter.contains(b"committed_0").unwrap());
+            assert_eq!(batch.len(), 100);
+            assert_eq!(batch.commit().await.unwrap(), 100);
+            assert!(filter.contains(b"committed_0").unwrap());
+
+            let mut dropped = filter.batch();
+            dropped.insert(b"dropped");
+            drop(dropped);
+            assert!(!filter.contains(b"dropped").unwrap());
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        for i in 0..100 {
+            assert!(
+                loaded
+                    .contains(format!("committed_{i}").as_bytes())
+                    .unwrap()
+            );
+        }
+        assert_eq!(loaded.total_insert_count(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_stream_level_matches_read_level() {
+        let test_db = TestDb::new("stream_level");
+        let backend = FjallExpiringBackend::new(test_db.path.clone(), 2)
+            .await
+            .unwrap();
+        backend
+            .save_level_chunks(0, &[(0, vec![1, 2]), (1, vec![3])])
+            .await
+            .unwrap();
+
+        let mut streamed = Vec::new();
+        let count = backend
+            .stream_level(0, |chunk_id, bytes| {
+                streamed.push((chunk_id, bytes.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        streamed.sort();
+        assert_eq!(count, 2);
+        assert_eq!(streamed, backend.read_level(0).unwrap());
+
+        // Dirty chunks take precedence, as in read_level
+        backend.save_dirty_chunks(0, &[(4, vec![9])]).await.unwrap();
+        let mut streamed = Vec::new();
+        backend
+            .stream_level(0, |chunk_id, bytes| {
+                streamed.push((chunk_id, bytes.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(streamed, vec![(4, vec![9])]);
+
+        // The first error from the callback stops the scan
+        assert_eq!(backend.stream_level(1, |_, _| Ok(())).unwrap(), 0);
+        let mut seen = 0;
+        let result = backend.stream_level(0, |_, _| {
+            seen += 1;
+            Err(EbloomError::StorageError("stop".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(seen, 1);
+        assert!(backend.stream_level(2, |_, _| Ok(())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_group_commit_defers_sync_until_flush() {
+        let test_db = TestDb::new("group_commit");
+        let backend = FjallExpiringBackend::new(test_db.path.clone(), 2)
+            .await
+            .unwrap()
+            .with_group_commit(Some(Duration::from_secs(60)));
+
+        // First write syncs immediately, the next one inside the window waits
+        backend.save_current_level(1).await.unwrap();
+        assert!(!backend.has_pending_sync());
+        backend.save_dirty_chunks(0, &[(0, vec![7])]).await.unwrap();
+        assert!(backend.has_pending_sync());
+
+        // Deferred writes are still visible to readers
+        assert_eq!(
+            backend.load_dirty_chunks(0).await.unwrap(),
+            vec![(0, vec![7])]
+        );
+
+        backend.flush().unwrap();
+        assert!(!backend.has_pending_sync());
+    }
+
+    #[tokio::test]
+    async fn test_group_commit_syncs_when_window_ends() {
+        let test_db = TestDb::new("group_commit_timer");
+        let backend = FjallExpiringBackend::new(test_db.path.clone(), 2)
+            .await
+            .unwrap()
+            .with_group_commit(Some(Duration::from_millis(50)));
+
+        backend.save_current_level(1).await.unwrap();
+        backend.save_dirty_chunks(0, &[(0, vec![7])]).await.unwrap();
+        assert!(backend.has_pending_sync());
+
+        // No further write or flush: the timer syncs once the window ends
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while backend.has_pending_sync() {
+            assert!(std::time::Instant::now() < deadline, "sync never fired");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_run_alongside_inserts() {
+        let test_db = TestDb::new("snapshot_alongside_inserts");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+
+        {
+            let filter = std::sync::Arc::new(
+                ExpiringBloomFilter::create(config).await.unwrap(),
+            );
+            let writer = {
+                let filter = std::sync::Arc::clone(&filter);
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        filter.insert(format!("item_{i}").as_bytes()).unwrap();
+                    }
+                })
+            };
+            for _ in 0..5 {
+                filter.save_snapshot().await.unwrap();
+            }
+            writer.join().unwrap();
+
+            // Only chunks touched since the previous snapshot are written
+            filter.insert(b"late").unwrap();
+            filter.save_snapshot().await.unwrap();
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            assert!(loaded.contains(format!("item_{i}").as_bytes()).unwrap());
+        }
+        assert!(loaded.contains(b"late").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_write_behind_persists_without_snapshot() {
+        let test_db = TestDb::new("write_behind");
+        let mut config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        if let Some(ref mut pers) = config.persistence {
+            // Tiny queue so inserts hit backpressure
+            pers.write_behind_capacity = Some(2);
+        }
+
+        {
+            let filter = ExpiringBloomFilter::create(config).await.unwrap();
+            for i in 0..500 {
+                filter.insert(format!("item_{i}").as_bytes()).unwrap();
+            }
+            filter.flush().await.unwrap();
+        }
+
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            assert!(loaded.contains(format!("item_{i}").as_bytes()).unwrap());
+        }
+    }
+
+    #[derive(Default)]
+    struct SnapshotRecorder(Mutex<Vec<SnapshotEvent>>);
+
+    impl FilterObserver for SnapshotRecorder {
+        fn on_snapshot(&self, event: &SnapshotEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_sees_snapshots() {
+        let test_db = TestDb::new("observer_snapshots");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        let filter = ExpiringBloomFilter::create(config).await.unwrap();
+        let recorder = Arc::new(SnapshotRecorder::default());
+        filter.add_observer(recorder.clone()).unwrap();
+
+        filter.insert(b"item").unwrap();
+        filter.save_snapshot().await.unwrap();
+        filter.rotate_levels().await.unwrap();
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].full);
+        assert_eq!(events[0].level, 0);
+        assert!(events[0].chunks > 0 && events[0].bytes > 0);
+        // Rotation writes the full outgoing level
+        assert!(events[1].full);
+        assert!(events[1].chunks >= events[0].chunks);
+
+        // The same numbers are summed up in stats
+        let stats = filter.stats().unwrap();
+        assert_eq!(stats.incremental_snapshots.count, 1);
+        assert_eq!(stats.incremental_snapshots.last_bytes, events[0].bytes);
+        assert_eq!(stats.full_snapshots.count, 1);
+        assert_eq!(stats.full_snapshots.total_chunks, events[1].chunks as u64);
+        assert!(stats.full_snapshots.mean_bytes() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_snapshot_lag_and_dirty_chunks() {
+        let test_db = TestDb::new("health");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        let filter = ExpiringBloomFilter::create(config).await.unwrap();
+
+        filter.insert(b"item").unwrap();
+        let health = filter.health().unwrap();
+        assert!(health.persistent && health.writable);
+        assert_eq!(health.error, None);
+        assert_eq!(filter.backend_kind(), "fjall");
+        assert_eq!(health.since_last_snapshot_ms, None);
+        assert!(health.pending_dirty_chunks > 0);
+        assert!(!health.is_healthy(60_000));
+
+        filter.save_snapshot().await.unwrap();
+        let health = filter.health().unwrap();
+        assert!(health.since_last_snapshot_ms.is_some());
+        assert_eq!(health.pending_dirty_chunks, 0);
+        assert!(health.is_healthy(60_000));
+    }
+
+    #[tokio::test]
+    async fn test_sharded_filter_persists_each_shard() {
+        use probabilistic_rs::ebloom::sharded::ShardedExpiringFilter;
+
+        let test_db = TestDb::new("sharded_persists_each_shard");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+
+        {
+            let filter = ShardedExpiringFilter::create(config.clone(), 3)
+                .await
+                .unwrap();
+            for i in 0..30 {
+                filter.insert(format!("key-{i}").as_bytes()).unwrap();
+            }
+            filter.save_snapshot().await.unwrap();
+        }
+        for shard_idx in 0..3 {
+            assert!(test_db.path.join(format!("shard-{shard_idx}")).exists());
+        }
+
+        let loaded = ShardedExpiringFilter::create_or_load(config.clone(), 3)
+            .await
+            .unwrap();
+        for i in 0..30 {
+            assert!(loaded.contains(format!("key-{i}").as_bytes()).unwrap());
+        }
+        drop(loaded);
+        // Fewer shards than on disk would lose keys
+        assert!(
+            ShardedExpiringFilter::create_or_load(config, 2)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delta_sync_between_persisted_filters() {
+        let db_a = TestDb::new("delta_sync_a");
+        let db_b = TestDb::new("delta_sync_b");
+        let a = ExpiringBloomFilter::create(create_test_config(
+            db_a.path.clone(),
+            Duration::from_secs(60),
+        ))
+        .await
+        .unwrap();
+        let b = ExpiringBloomFilter::create(create_test_config(
+            db_b.path.clone(),
+            Duration::from_secs(60),
+        ))
+        .await
+        .unwrap();
+        a.insert(b"from-a").unwrap();
+        b.insert(b"from-b").unwrap();
+
+        let to_b = a.compute_delta(&b).unwrap();
+        let to_a = b.compute_delta(&a).unwrap();
+        // Only the chunks holding the new bits travel
+        assert_eq!(to_b.chunk_bytes, 1024);
+        assert!(to_b.chunk_count() > 0 && to_b.chunk_count() <= 7);
+        b.apply_delta(&to_b).unwrap();
+        a.apply_delta(&to_a).unwrap();
+        for filter in [&a, &b] {
+            assert!(filter.contains(b"from-a").unwrap());
+            assert!(filter.contains(b"from-b").unwrap());
+        }
+        assert!(a.compute_delta(&b).unwrap().is_empty());
+        assert!(b.compute_delta(&a).unwrap().is_empty());
+
+        // Merged chunks are persisted with the next snapshot
+        b.save_snapshot().await.unwrap();
+        drop(b);
+        let loaded = ExpiringBloomFilter::load(db_b.path.clone()).await.unwrap();
+        assert!(loaded.contains(b"from-a").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_merge_persisted_filters() {
+        let inputs = [
+            TestDb::new("merge_worker_0"),
+            TestDb::new("merge_worker_1"),
+            TestDb::new("merge_worker_2"),
+        ];
+        let output = TestDb::new("merge_output");
+        for (worker, db) in inputs.iter().enumerate() {
+            let filter = ExpiringBloomFilter::create(create_test_config(
+                db.path.clone(),
+                Duration::from_secs(60),
+            ))
+            .await
+            .unwrap();
+            filter.insert(format!("old-{worker}").as_bytes()).unwrap();
+            filter.rotate_levels().await.unwrap();
+            for i in 0..10 {
+                filter
+                    .insert(format!("new-{worker}-{i}").as_bytes())
+                    .unwrap();
+            }
+            filter.save_snapshot().await.unwrap();
+        }
+
+        let paths: Vec<PathBuf> =
+            inputs.iter().map(|db| db.path.clone()).collect();
+        let merged =
+            ExpiringBloomFilter::merge_persisted(&paths, output.path.clone())
+                .await
+                .unwrap();
+        assert_eq!(merged.get_active_level(), 1);
+        assert_eq!(merged.total_insert_count(), 33);
+        drop(merged);
+
+        let loaded = ExpiringBloomFilter::load(output.path.clone())
+            .await
+            .unwrap();
+        assert_eq!(loaded.get_active_level(), 1);
+        for worker in 0..3 {
+            assert!(loaded.contains(format!("old-{worker}").as_bytes()).unwrap());
+            for i in 0..10 {
+                assert!(
+                    loaded
+                        .contains(format!("new-{worker}-{i}").as_bytes())
+                        .unwrap()
+                );
+            }
+        }
+        let stats = loaded.stats().unwrap();
+        assert_eq!(stats.levels[1].insert_count, 30);
+        assert!(loaded.validate_invariants().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_merge_persisted_rejects_mismatched_inputs() {
+        let a = TestDb::new("merge_mismatch_a");
+        let b = TestDb::new("merge_mismatch_b");
+        let output = TestDb::new("merge_mismatch_output");
+        ExpiringBloomFilter::create(create_test_config(
+            a.path.clone(),
+            Duration::from_secs(60),
+        ))
+        .await
+        .unwrap();
+        ExpiringBloomFilter::create(create_test_config(
+            b.path.clone(),
+            Duration::from_secs(30),
+        ))
+        .await
+        .unwrap();
+
+        let paths = [a.path.clone(), b.path.clone()];
+        assert!(
+            ExpiringBloomFilter::merge_persisted(&paths, output.path.clone())
+                .await
+                .is_err()
+        );
+        assert!(
+            ExpiringBloomFilter::merge_persisted(&paths, a.path.clone())
+                .await
+                .is_err()
+        );
+        assert!(
+            ExpiringBloomFilter::merge_persisted(&[], output.path.clone())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_import_persists_whole_level() {
+        let test_db = TestDb::new("bulk_import");
+        let filter = ExpiringBloomFilter::create(create_test_config(
+            test_db.path.clone(),
+            Duration::from_secs(60),
+        ))
+        .await
+        .unwrap();
+        let keys: Vec<String> = (0..900).map(|i| format!("seed-{i}")).collect();
+        assert_eq!(filter.bulk_import(&keys, 3).unwrap(), 900);
+
+        filter.save_snapshot().await.unwrap();
+        drop(filter);
+        let loaded = ExpiringBloomFilter::load(test_db.path.clone())
+            .await
+            .unwrap();
+        assert!(
+            keys.iter()
+                .all(|key| loaded.contains(key.as_bytes()).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_named_filters_share_keyspace() {
+        use probabilistic_rs::ebloom::keyspace::FilterKeyspace;
+
+        let test_db = TestDb::new("named_filters");
+        let sessions_config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        let tokens_config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(500_usize)
+            .target_fpr(0.01)
+            .num_levels(2_usize)
+            .level_duration(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        {
+            let db = FilterKeyspace::open(&test_db.path).unwrap();
+            let sessions = db
+                .create("sessions", sessions_config.clone())
+                .await
+                .unwrap();
+            let tokens = db.create("tokens", tokens_config).await.unwrap();
+            sessions.insert(b"session-1").unwrap();
+            tokens.insert(b"token-1").unwrap();
+            sessions.rotate_levels().await.unwrap();
+            sessions.insert(b"session-2").unwrap();
+            db.save_snapshots().await.unwrap();
+            assert_eq!(db.stored_names(), ["sessions", "tokens"]);
+        }
+
+        let db = FilterKeyspace::open(&test_db.path).unwrap();
+        let sessions = db
+            .create_or_load("sessions", sessions_config)
+            .await
+            .unwrap();
+        let tokens = db.load("tokens").await.unwrap();
+        assert!(Arc::ptr_eq(&tokens, &db.load("tokens").await.unwrap()));
+        assert_eq!(sessions.num_levels(), 3);
+        assert_eq!(tokens.num_levels(), 2);
+        assert_eq!(sessions.get_active_level(), 1);
+        assert_eq!(tokens.get_active_level(), 0);
+        assert!(sessions.contains(b"session-1").unwrap());
+        assert!(sessions.contains(b"session-2").unwrap());
+        assert!(!sessions.contains(b"token-1").unwrap());
+        assert!(tokens.contains(b"token-1").unwrap());
+        assert!(!tokens.contains(b"session-1").unwrap());
+
+        // Removing needs the last handle, and leaves the other filter alone
+        assert!(db.remove("tokens").is_err());
+        drop(tokens);
+        db.remove("tokens").unwrap();
+        assert_eq!(db.stored_names(), ["sessions"]);
+        assert!(db.load("tokens").await.is_err());
+        assert!(sessions.contains(b"session-1").unwrap());
+
+        // Replacing an open filter needs the last handle too
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        assert!(db.create("sessions", config.clone()).await.is_err());
+        assert!(sessions.contains(b"session-1").unwrap());
+        drop(sessions);
+        let sessions = db.create("sessions", config).await.unwrap();
+        assert!(!sessions.contains(b"session-1").unwrap());
+
+        assert!(db.load("no#such").await.is_err());
+        assert!(
+            db.create(
+                "",
+                ExpiringFilterConfigBuilder::default().build().unwrap()
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recreated_named_filter_drops_extra_levels() {
+        use probabilistic_rs::ebloom::keyspace::FilterKeyspace;
+
+        let test_db = TestDb::new("named_filter_recreate");
+        let config = |num_levels: usize| {
+            let mut config =
+                create_test_config(test_db.path.clone(), Duration::from_secs(60));
+            config.num_levels = num_levels;
+            config
+        };
+        {
+            let db = FilterKeyspace::open(&test_db.path).unwrap();
+            let filter = db.create("events", config(3)).await.unwrap();
+            for _ in 0..2 {
+                filter.insert(b"item").unwrap();
+                filter.rotate_levels().await.unwrap();
+            }
+            filter.save_snapshot().await.unwrap();
+            drop(filter);
+            db.create("events", config(2)).await.unwrap();
+        }
+
+        let keyspace = fjall::Config::new(&test_db.path).open().unwrap();
+        let partitions = keyspace.list_partitions();
+        let level_two: Vec<_> = partitions
+            .iter()
+            .filter(|p| p.starts_with("f#events#level_2_"))
+            .collect();
+        assert!(level_two.is_empty(), "{level_two:?}");
+        assert!(partitions.iter().any(|p| p.starts_with("f#events#")));
+    }
+
+    /// Std lock guards are not `Send`, so any of these futures holding one
+    /// across a storage await stops compiling here
+    #[test]
+    fn test_persistence_futures_are_send() {
+        use probabilistic_rs::bloom::{
+            BloomFilter, BloomFilterConfigBuilder, PersistenceConfigBuilder,
+        };
+        use probabilistic_rs::ebloom::keyspace::FilterKeyspace;
+        use probabilistic_rs::ebloom::reshape::ReshapingFilter;
+        use probabilistic_rs::ebloom::sharded::ShardedExpiringFilter;
+
+        fn assert_send<F: std::future::Future + Send>(_: F) {}
+
+        let test_db = TestDb::new("futures_send");
+        let config =
+            create_test_config(test_db.path.clone(), Duration::from_secs(60));
+        assert_send(ExpiringBloomFilter::create(config.clone()));
+        assert_send(ExpiringBloomFilter::load(test_db.path.clone()));
+        assert_send(ExpiringBloomFilter::create_or_load(config.clone()));
+        assert_send(ExpiringBloomFilter::merge_persisted(
+            &[],
+            test_db.path.clone(),
+        ));
+        assert_send(ShardedExpiringFilter::create(config.clone(), 2));
+
+        let filter = ExpiringBloomFilter::new(
+            ExpiringFilterConfigBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+        assert_send(filter.rotate_levels());
+        assert_send(filter.save_snapshot());
+        assert_send(filter.flush());
+        assert_send(filter.clear_by_rotation());
+        assert_send(filter.batch().commit());
+        assert_send(filter.cleanup_expired_levels());
+
+        let sharded = ShardedExpiringFilter::new(
+            ExpiringFilterConfigBuilder::default().build().unwrap(),
+            2,
+        )
+        .unwrap();
+        assert_send(sharded.save_snapshot());
+        assert_send(sharded.flush());
+        assert_send(ExpiringBloomFilterOps::cleanup_expired_levels(&sharded));
+
+        let reshaping = ReshapingFilter::new(
+            ExpiringBloomFilter::new(
+                ExpiringFilterConfigBuilder::default().build().unwrap(),
+            )
+            .unwrap(),
+        );
+        assert_send(reshaping.begin_reshape(config.clone()));
+        assert_send(reshaping.save_snapshot());
+        assert_send(ExpiringBloomFilterOps::cleanup_expired_levels(&reshaping));
+
+        let keyspace = FilterKeyspace::open(&test_db.path).unwrap();
+        assert_send(keyspace.create("filter", config.clone()));
+        assert_send(keyspace.load("filter"));
+        assert_send(keyspace.cleanup_expired_levels());
+        assert_send(keyspace.save_snapshots());
+        assert_send(keyspace.flush());
+
+        let bloom_config = BloomFilterConfigBuilder::default()
+            .persistence(Some(
+                PersistenceConfigBuilder::default()
+                    .db_path(test_db.path.clone())
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        assert_send(BloomFilter::create(bloom_config.clone()));
+        assert_send(BloomFilter::load(test_db.path.clone()));
+        assert_send(BloomFilter::create_or_load(bloom_config));
+        let bloom = BloomFilter::new(
+            BloomFilterConfigBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+        assert_send(bloom.save_snapshot());
+    }
+}
+
+#[cfg(all(feature = "fjall", feature = "test_support"))]
+mod crash_tests {
+    use probabilistic_rs::EbloomError;
+    use probabilistic_rs::ebloom::{
+        clock::SystemClock,
+        config::{
+            ExpiringFilterConfig, ExpiringFilterConfigBuilder,
+            ExpiringPersistenceConfigBuilder,
+        },
+        filter::ExpiringBloomFilter,
+        storage::FjallExpiringBackend,
+        test_support::{
+            crash::{CrashMode, CrashStorage},
+            seeded_keys,
+        },
+        traits::ExpiringBloomFilterOps,
+    };
+    use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+    /// Upper bound on the writes of `run_until_crash`, so a regression
+    /// that stops the sweep from ending fails instead of hanging
+    const MAX_WRITES: usize = 64;
+
+    fn config(db_path: PathBuf) -> ExpiringFilterConfig {
+        let persistence = ExpiringPersistenceConfigBuilder::default()
+            .db_path(db_path)
+            .chunk_size_bytes(256_usize)
+            .build()
+            .unwrap();
+
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(3600))
+            .persistence(Some(persistence))
+            .build()
+            .unwrap()
+    }
+
+    /// Create, fill, snapshot and rotate until the storage crashes.
+    /// Returns the keys whose snapshot committed.
+    async fn run_until_crash(
+        storage: Arc<CrashStorage<FjallExpiringBackend>>,
+        config: ExpiringFilterConfig,
+    ) -> Vec<Vec<u8>> {
+        let mut durable = Vec::new();
+        let Ok(filter) = ExpiringBloomFilter::create_with_storage(
+            config,
+            Arc::new(SystemClock),
+            storage,
+        )
+        .await
+        else {
+            return durable;
+        };
+        for batch in 0..3 {
+            let keys = seeded_keys(batch, 50);
+            for key in &keys {
+                filter.insert(key).unwrap();
+            }
+            if filter.save_snapshot().await.is_err() {
+                return durable;
+            }
+            durable.extend(keys);
+            // Two rotations keep the first level within the window
+            if batch < 2 && filter.rotate_levels().await.is_err() {
+                return durable;
+            }
+        }
+        durable
+    }
+
+    async fn sweep_crash_points(name: &str, mode: CrashMode) {
+        let path = PathBuf::from(format!("test_ebloom_crash_{name}.fjall"));
+        for crash_after in 0..MAX_WRITES {
+            if path.exists() {
+                fs::remove_dir_all(&path).unwrap();
+            }
+            let backend =
+                FjallExpiringBackend::new(path.clone(), 3).await.unwrap();
+            let storage = Arc::new(CrashStorage::new(backend, crash_after, mode));
+            let durable =
+                run_until_crash(storage.clone(), config(path.clone())).await;
+            let crashed = storage.crashed();
+            drop(storage);
+
+            match ExpiringBloomFilter::load(path.clone()).await {
+                Ok(loaded) => {
+                    for key in &durable {
+                        assert!(
+                            loaded.contains(key).unwrap(),
+                            "{mode:?} after {crash_after} writes lost a key"
+                        );
+                    }
+                    assert_eq!(
+                        loaded.validate_invariants().unwrap(),
+                        vec![],
+                        "{mode:?} after {crash_after} writes"
+                    );
+                    let stats = loaded.stats().unwrap();
+                    assert!(stats.current_level < 3);
+                    loaded.insert(b"after-recovery").unwrap();
+                    assert!(loaded.contains(b"after-recovery").unwrap());
+                }
+                // Torn chunks are detected and refused; otherwise only a
+                // crash before anything durable may leave nothing to load
+                Err(EbloomError::StorageError(_))
+                    if mode == CrashMode::TornChunks => {}
+                Err(e) => assert!(
+                    durable.is_empty(),
+                    "{mode:?} after {crash_after} writes: {e}"
+                ),
+            }
+
+            if !crashed {
+                let _ = fs::remove_dir_all(&path);
+                return;
+            }
+        }
+        let _ = fs::remove_dir_all(&path);
+        panic!("Scenario did not finish within {MAX_WRITES} writes");
+    }
+
+    #[tokio::test]
+    async fn test_crash_lost_write_recovers() {
+        sweep_crash_points("lost", CrashMode::Lost).await;
+    }
+
+    #[tokio::test]
+    async fn test_crash_torn_chunks_recovers() {
+        sweep_crash_points("torn", CrashMode::TornChunks).await;
+    }
+
+    #[tokio::test]
+    async fn test_crash_missing_metadata_recovers() {
+        sweep_crash_points("metadata", CrashMode::MissingMetadata).await;
+    }
+}
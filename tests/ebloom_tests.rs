@@ -1,7 +1,20 @@
 use probabilistic_rs::ebloom::{
-    config::ExpiringFilterConfigBuilder,
+    bits::AtomicBitVec,
+    bulk::BulkContext,
+    clock::{Clock, ClockMode, ManualClock, MonotonicClock},
+    config::{
+        ExpiringFilterConfigBuilder, InsertMode, RotationPolicy, RotationReason,
+    },
+    events::ROTATION_HISTORY_LEN,
     filter::ExpiringBloomFilter,
-    traits::{ExpiringBloomFilterOps, ExpiringBloomFilterStats},
+    stats::InvariantViolation,
+    traits::{
+        BulkExpiringBloomFilterOps, ExpiringBloomFilterOps,
+        ExpiringBloomFilterStats,
+    },
+};
+use probabilistic_rs::{
+    CACHE_LINE_BITS, EbloomError, Membership, blocked_hash_function,
 };
 use std::{
     collections::HashSet,
@@ -44,6 +57,26 @@ fn create_short_expiry_filter(
     ExpiringBloomFilter::new(config).expect("Failed to create test filter")
 }
 
+// Helper function to create filter driven by a manual clock
+fn create_manual_clock_filter(
+    capacity_per_level: usize,
+    num_levels: usize,
+    duration_ms: u64,
+) -> (ExpiringBloomFilter, Arc<ManualClock>) {
+    let config = ExpiringFilterConfigBuilder::default()
+        .capacity_per_level(capacity_per_level)
+        .target_fpr(0.01)
+        .num_levels(num_levels)
+        .level_duration(Duration::from_millis(duration_ms))
+        .build()
+        .expect("Failed to build test config");
+
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let filter = ExpiringBloomFilter::with_clock(config, clock.clone())
+        .expect("Failed to create test filter");
+    (filter, clock)
+}
+
 // Helper function to generate consistent test data
 fn generate_test_items(count: usize) -> Vec<Vec<u8>> {
     (0..count)
@@ -55,6 +88,30 @@ fn generate_test_items(count: usize) -> Vec<Vec<u8>> {
 mod basic_operations_tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_contains_checked() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+        filter.insert(b"key").unwrap();
+        assert_eq!(
+            filter.contains_checked(b"key").unwrap(),
+            Membership::ProbablyPresent
+        );
+        assert!(
+            filter
+                .contains_checked(b"never-inserted")
+                .unwrap()
+                .is_definitely_absent()
+        );
+
+        // Expired items are definitely absent again
+        clock.advance(Duration::from_millis(301));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(
+            filter.contains_checked(b"key").unwrap(),
+            Membership::DefinitelyAbsent
+        );
+    }
+
     #[test]
     fn test_insert_and_contains() {
         let filter = create_test_filter(1000, 3, 0.01);
@@ -117,6 +174,36 @@ mod basic_operations_tests {
         assert_eq!(filter.total_insert_count(), 0u64);
     }
 
+    #[tokio::test]
+    async fn test_clear_by_rotation() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        filter.insert(b"hello").unwrap();
+        filter.rotate_levels().await.unwrap();
+        filter.insert(b"world").unwrap();
+
+        filter.clear_by_rotation().await.unwrap();
+
+        assert!(!filter.contains(b"hello").unwrap());
+        assert!(!filter.contains(b"world").unwrap());
+        assert_eq!(filter.total_insert_count(), 0);
+
+        // Every dropped window shows up in the rotation history
+        let history = filter.rotation_history(ROTATION_HISTORY_LEN).unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(
+            history[1..]
+                .iter()
+                .all(|record| record.reason == RotationReason::Clear)
+        );
+        assert_eq!(
+            history
+                .iter()
+                .map(|record| record.insert_count)
+                .sum::<u64>(),
+            2
+        );
+    }
+
     #[test]
     fn test_duplicate_insertions() {
         let filter = create_test_filter(1000, 3, 0.01);
@@ -148,11 +235,107 @@ mod basic_operations_tests {
             .expect("Clear should succeed on empty filter");
         assert_eq!(filter.total_insert_count(), 0u64);
     }
+
+    #[tokio::test]
+    async fn test_contains_bulk_matches_contains() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let items = generate_test_items(100);
+
+        // Spread inserts over two levels and leave every third item out
+        for (i, item) in items.iter().enumerate() {
+            if i == 50 {
+                filter.rotate_levels().await.unwrap();
+            }
+            if i % 3 != 0 {
+                filter.insert(item).unwrap();
+            }
+        }
+
+        // Odd length exercises a partial final batch
+        let refs: Vec<&[u8]> = items[..77].iter().map(|v| v.as_slice()).collect();
+        let bulk = filter.contains_bulk(&refs).unwrap();
+        assert_eq!(bulk.len(), refs.len());
+        for (item, found) in refs.iter().zip(bulk) {
+            assert_eq!(found, filter.contains(item).unwrap());
+        }
+        assert!(filter.contains_bulk(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_contains_bulk_bitset() {
+        let filter = create_test_filter(10_000, 3, 0.01);
+        let items = generate_test_items(10_000);
+        for item in items.iter().step_by(2) {
+            filter.insert(item).unwrap();
+        }
+
+        // More than one internal batch
+        let refs: Vec<&[u8]> = items.iter().map(|v| v.as_slice()).collect();
+        let bulk = filter.contains_bulk(&refs).unwrap();
+        let bitset = filter.contains_bulk_bitset(&refs).unwrap();
+        assert_eq!(bitset.len(), refs.len());
+        assert!(bitset.iter().by_vals().eq(bulk.iter().copied()));
+        assert!(bitset.iter_ones().filter(|i| i % 2 == 0).count() == 5_000);
+
+        let streamed = filter.contains_iter_bitset(items.iter()).unwrap();
+        assert_eq!(streamed, bitset);
+        assert!(filter.contains_bulk_bitset(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_contains_bulk_parallel_matches_serial() {
+        let filter = create_test_filter(10_000, 3, 0.01);
+        let items = generate_test_items(10_001);
+        for item in items.iter().step_by(2) {
+            filter.insert(item).unwrap();
+        }
+
+        let refs: Vec<&[u8]> = items.iter().map(|v| v.as_slice()).collect();
+        let serial = filter.contains_bulk(&refs).unwrap();
+        // Uneven shares, more threads than items, and 0 treated as 1
+        for parallelism in [0, 1, 3, 8] {
+            assert_eq!(
+                filter.contains_bulk_parallel(&refs, parallelism).unwrap(),
+                serial
+            );
+        }
+        assert_eq!(
+            filter.contains_bulk_parallel(&refs[..2], 8).unwrap(),
+            [true, false]
+        );
+        assert!(filter.contains_bulk_parallel(&[], 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bulk_context_reuse() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let items = generate_test_items(60);
+        let refs: Vec<&[u8]> = items.iter().map(|v| v.as_slice()).collect();
+        let mut ctx = BulkContext::with_capacity(30, 7);
+
+        // Insert in two batches through the same context
+        for batch in refs[..40].chunks(20) {
+            filter.insert_bulk_with(&mut ctx, batch).unwrap();
+        }
+        assert_eq!(filter.total_insert_count(), 40);
+
+        let expected = filter.contains_bulk(&refs).unwrap();
+        assert_eq!(
+            filter.contains_bulk_with(&mut ctx, &refs).unwrap(),
+            expected
+        );
+        assert!(expected[..40].iter().all(|&found| found));
+
+        // A smaller batch replaces, not appends to, the previous results
+        let found = filter.contains_bulk_with(&mut ctx, &refs[..5]).unwrap();
+        assert_eq!(found, &[true; 5]);
+    }
 }
 
 #[cfg(test)]
 mod multi_level_behavior_tests {
     use super::*;
+    use probabilistic_rs::ebloom::events::FilterObserver;
 
     #[test]
     fn test_current_level_tracking() {
@@ -219,382 +402,3197 @@ mod multi_level_behavior_tests {
 
     #[test]
     fn test_level_expiration_check() {
-        let filter = create_short_expiry_filter(1000, 3, 100);
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
 
         // Level should not be expired immediately
         assert!(!filter.is_level_expired(0).unwrap());
 
+        // Exactly at the duration boundary it is still alive
+        clock.advance(Duration::from_millis(100));
+        assert!(!filter.is_level_expired(0).unwrap());
+
         // After duration, should be expired
-        thread::sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(1));
         assert!(filter.is_level_expired(0).unwrap());
     }
-}
-
-#[cfg(test)]
-mod configuration_and_stats_tests {
-    use super::*;
 
     #[test]
-    fn test_config_validation_valid() {
+    fn test_backwards_clock_does_not_expire_or_panic() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+
+        // Wall clock jumps back before the level was created
+        clock.set(0);
+        assert!(!filter.is_level_expired(0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_monotonic_clock_mode() {
         let config = ExpiringFilterConfigBuilder::default()
             .capacity_per_level(1000_usize)
-            .target_fpr(0.01)
             .num_levels(3_usize)
-            .level_duration(Duration::from_secs(60))
-            .build();
+            .level_duration(Duration::from_millis(50))
+            .clock_mode(ClockMode::Monotonic)
+            .build()
+            .unwrap();
+        let filter = ExpiringBloomFilter::new(config).unwrap();
+
+        filter.insert(b"item").unwrap();
+        thread::sleep(Duration::from_millis(60));
+        filter.cleanup_expired_levels().await.unwrap();
 
-        assert!(config.is_ok(), "Valid config should build successfully");
+        assert_eq!(filter.get_active_level(), 1);
+        assert!(filter.contains(b"item").unwrap());
     }
 
     #[test]
-    fn test_stats_accuracy() {
-        let filter = create_test_filter(1000, 3, 0.01);
+    fn test_monotonic_clock_never_below_epoch() {
+        let clock = MonotonicClock::with_epoch(5_000);
+        let first = clock.now_ms().unwrap();
+        let second = clock.now_ms().unwrap();
+        assert!(first >= 5_000);
+        assert!(second >= first);
+    }
 
-        assert_eq!(filter.capacity_per_level(), 1000);
-        assert_eq!(filter.target_fpr(), 0.01);
-        assert_eq!(filter.num_levels(), 3);
-        assert_eq!(filter.active_levels(), 3);
-        assert_eq!(filter.total_insert_count(), 0u64);
+    fn create_aged_filter(
+        durations_ms: [u64; 3],
+    ) -> (ExpiringBloomFilter, Arc<ManualClock>) {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .num_levels(3_usize)
+            .level_durations(durations_ms.map(Duration::from_millis).to_vec())
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+        (filter, clock)
+    }
 
-        // Insert some items
-        for i in 0..10 {
-            filter.insert(format!("item{}", i).as_bytes()).unwrap();
+    #[tokio::test]
+    async fn test_per_level_durations() {
+        let (filter, clock) = create_aged_filter([100, 50, 400]);
+
+        // Every slot is current for the first window, whichever it is
+        for expected_level in [1, 2, 0] {
+            clock.advance(Duration::from_millis(101));
+            filter.cleanup_expired_levels().await.unwrap();
+            assert_eq!(filter.get_active_level(), expected_level);
         }
+    }
 
-        assert_eq!(filter.total_insert_count(), 10);
+    #[tokio::test]
+    async fn test_level_durations_follow_age() {
+        let (filter, clock) = create_aged_filter([100, 50, 400]);
+        assert_eq!(filter.config().level_lifetime(), Duration::from_millis(150));
+
+        for slot in [0, 2, 1] {
+            let start = clock.now_ms().unwrap();
+            filter.insert(b"item").unwrap();
+            assert_eq!(filter.get_active_level(), slot);
+            assert_eq!(
+                filter.ttl_estimate(b"item").unwrap(),
+                Some(Duration::from_millis(150))
+            );
+
+            // One rotation later the slot is 1 old and kept 50ms longer
+            clock.advance(Duration::from_millis(101));
+            filter.cleanup_expired_levels().await.unwrap();
+            assert_eq!(filter.level_age(slot), 1);
+            assert!(filter.contains(b"item").unwrap());
+            clock.advance(Duration::from_millis(49));
+            filter.cleanup_expired_levels().await.unwrap();
+            assert!(filter.contains(b"item").unwrap());
+
+            clock.advance(Duration::from_millis(1));
+            filter.cleanup_expired_levels().await.unwrap();
+            assert!(!filter.contains(b"item").unwrap());
+            assert!(!filter.is_level_expired(slot).unwrap());
+            assert_eq!(clock.now_ms().unwrap() - start, 151);
+            assert!(filter.stats().unwrap().levels[slot].age_ms.is_none());
+
+            // Start a fresh window in the slot after the current one
+            filter.rotate_levels().await.unwrap();
+        }
     }
 
-    #[test]
-    fn test_different_config_combinations() {
-        // Small capacity, few levels
-        let filter1 = create_test_filter(100, 2, 0.01);
-        assert_eq!(filter1.num_levels(), 2);
+    #[tokio::test]
+    async fn test_contains_at_historical_timestamp() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+        let start = clock.now_ms().unwrap();
 
-        // Large capacity, many levels
-        let filter2 = create_test_filter(10000, 5, 0.001);
-        assert_eq!(filter2.num_levels(), 5);
-        assert_eq!(filter2.target_fpr(), 0.001);
+        filter.insert(b"early").unwrap();
+        clock.advance(Duration::from_millis(150));
+        filter.cleanup_expired_levels().await.unwrap();
+        filter.insert(b"late").unwrap();
+
+        // Before the second level existed only the first one is consulted
+        assert!(filter.contains_at(b"early", start + 50).unwrap());
+        assert!(!filter.contains_at(b"late", start + 50).unwrap());
+
+        // After rotation both levels are visible
+        assert!(filter.contains_at(b"late", start + 150).unwrap());
+        assert!(filter.contains_at(b"early", start + 150).unwrap());
+
+        // Nothing existed before the filter was created
+        assert!(!filter.contains_at(b"early", start - 1).unwrap());
     }
-}
 
-#[cfg(test)]
-mod edge_cases_and_error_conditions {
-    use super::*;
+    #[tokio::test]
+    async fn test_insert_at_routes_to_historical_level() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+        let start = clock.now_ms().unwrap();
 
-    #[test]
-    fn test_empty_item_insertion() {
-        let filter = create_test_filter(1000, 3, 0.01);
+        clock.advance(Duration::from_millis(150));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
 
-        // Empty item should be insertable
-        filter
-            .insert(b"")
-            .expect("Empty item insertion should succeed");
-        assert!(
-            filter
-                .contains(b"")
-                .expect("Contains should work for empty item")
-        );
+        // Event from the first window lands in level 0
+        assert!(filter.insert_at(b"replayed", start + 50).unwrap());
+        assert!(filter.contains_at(b"replayed", start + 50).unwrap());
+
+        // Event from the current window lands in the current level
+        assert!(filter.insert_at(b"recent", start + 160).unwrap());
+        assert!(!filter.contains_at(b"recent", start + 50).unwrap());
+        assert!(filter.contains(b"recent").unwrap());
+        assert_eq!(filter.total_insert_count(), 2);
+
+        // Too old for any retained level
+        assert!(!filter.insert_at(b"ancient", start - 1).unwrap());
+        assert!(!filter.contains(b"ancient").unwrap());
     }
 
-    #[test]
-    fn test_binary_data_handling() {
-        let filter = create_test_filter(1000, 3, 0.01);
+    #[tokio::test]
+    async fn test_catch_up_rotation_after_long_gap() {
+        let (filter, clock) = create_manual_clock_filter(1000, 4, 100);
+        let start = clock.now_ms().unwrap();
 
-        // Test with binary data (null bytes, etc.)
-        let binary_data: Vec<u8> = vec![0, 1, 255, 128, 0, 0, 42];
-        filter
-            .insert(&binary_data)
-            .expect("Binary data insertion should succeed");
-        assert!(
-            filter
-                .contains(&binary_data)
-                .expect("Binary data lookup should succeed")
-        );
+        filter.insert(b"old").unwrap();
+
+        // Sleep through two and a half windows
+        clock.advance(Duration::from_millis(250));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 2);
+        assert!(!filter.is_level_expired(2).unwrap());
+        assert!(filter.contains(b"old").unwrap());
+        // Windows stay on the original schedule
+        assert!(filter.contains_at(b"old", start + 199).unwrap());
+        assert!(!filter.contains_at(b"missing", start + 199).unwrap());
     }
 
-    #[test]
-    fn test_large_item_insertion() {
-        let filter = create_test_filter(1000, 3, 0.01);
+    #[tokio::test]
+    async fn test_catch_up_rotation_clears_everything() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
 
-        // Test with large item (1MB)
-        let large_item = vec![b'x'; 1024 * 1024];
-        filter
-            .insert(&large_item)
-            .expect("Large item insertion should succeed");
-        assert!(
-            filter
-                .contains(&large_item)
-                .expect("Large item lookup should succeed")
-        );
+        filter.insert(b"old").unwrap();
+
+        // Far more windows than levels elapsed
+        clock.advance(Duration::from_millis(10_000));
+        filter.cleanup_expired_levels().await.unwrap();
+
+        assert!(!filter.contains(b"old").unwrap());
+        assert!(!filter.is_level_expired(filter.get_active_level()).unwrap());
+        assert_eq!(filter.total_insert_count(), 0);
     }
 
-    #[test]
-    fn test_capacity_stress_test() {
-        let capacity = 100;
-        let filter = create_test_filter(capacity, 3, 0.01);
-        let test_items = generate_test_items(capacity * 2); // Insert 2x capacity
+    #[tokio::test]
+    async fn test_saturation_triggers_early_rotation() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(3600))
+            .max_fill_ratio(Some(0.5))
+            .build()
+            .unwrap();
+        let filter = ExpiringBloomFilter::new(config).unwrap();
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let reasons_clone = Arc::clone(&reasons);
+        filter
+            .on_rotation(move |event| {
+                reasons_clone.lock().unwrap().push(event.reason);
+            })
+            .unwrap();
 
-        // Should handle over-capacity gracefully (bloom filters don't have hard limits)
-        for item in &test_items {
-            filter
-                .insert(item)
-                .expect("Insert should succeed even over capacity");
+        for item in generate_test_items(10) {
+            filter.insert(&item).unwrap();
         }
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 0);
 
-        // No false negatives (all inserted items should be found)
-        for item in &test_items {
-            assert!(
-                filter.contains(item).expect("Contains should succeed"),
-                "No false negatives even when over capacity"
-            );
+        for item in generate_test_items(300) {
+            filter.insert(&item).unwrap();
         }
+        assert!(filter.level_fill_ratio(0).unwrap() > 0.5);
+        filter.cleanup_expired_levels().await.unwrap();
+
+        assert_eq!(filter.get_active_level(), 1);
+        assert_eq!(filter.level_fill_ratio(1).unwrap(), 0.0);
+        assert_eq!(*reasons.lock().unwrap(), vec![RotationReason::Saturation]);
     }
-}
 
-#[cfg(test)]
-mod behavioral_guarantees_tests {
-    use super::*;
+    fn create_policy_filter(
+        policy: RotationPolicy,
+    ) -> (ExpiringBloomFilter, Arc<ManualClock>) {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_millis(100))
+            .rotation_policy(policy)
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+        (filter, clock)
+    }
 
-    #[test]
-    fn test_no_false_negatives_guarantee() {
-        let filter = create_test_filter(1000, 3, 0.01);
-        let test_items = generate_test_items(100);
+    #[tokio::test]
+    async fn test_insert_count_policy_ignores_time() {
+        let (filter, clock) =
+            create_policy_filter(RotationPolicy::InsertCount(5));
 
-        // Insert all items
-        for item in &test_items {
-            filter.insert(item).unwrap();
-        }
+        clock.advance(Duration::from_millis(1000));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 0);
 
-        // Verify no false negatives
-        for item in &test_items {
-            assert!(
-                filter.contains(item).unwrap(),
-                "Bloom filters must never have false negatives"
-            );
+        for item in generate_test_items(5) {
+            filter.insert(&item).unwrap();
         }
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
     }
 
-    #[test]
-    fn test_deterministic_behavior() {
-        let filter1 = create_test_filter(1000, 3, 0.01);
-        let filter2 = create_test_filter(1000, 3, 0.01);
+    #[derive(Default)]
+    struct RotationDueRecorder(Mutex<Vec<(usize, u64)>>);
 
-        let test_item = b"deterministic_test";
+    impl FilterObserver for RotationDueRecorder {
+        fn on_rotation_due(&self, level: usize, insert_count: u64) {
+            self.0.lock().unwrap().push((level, insert_count));
+        }
+    }
 
-        // Same operations on both filters
-        filter1.insert(test_item).unwrap();
-        filter2.insert(test_item).unwrap();
+    #[tokio::test]
+    async fn test_insert_count_policy_signals_on_insert() {
+        let (filter, _clock) =
+            create_policy_filter(RotationPolicy::InsertCount(5));
+        let recorder = Arc::new(RotationDueRecorder::default());
+        filter.add_observer(recorder.clone()).unwrap();
+
+        for item in generate_test_items(4) {
+            filter.insert(&item).unwrap();
+        }
+        assert!(!filter.is_rotation_due());
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        // The insert reaching the count signals, later ones in the burst
+        // do not
+        let burst = generate_test_items(10);
+        let refs: Vec<&[u8]> = burst[4..].iter().map(Vec::as_slice).collect();
+        filter.insert_bulk(&refs).unwrap();
+        filter.insert(b"more").unwrap();
+        assert!(filter.is_rotation_due());
+        assert_eq!(*recorder.0.lock().unwrap(), vec![(0, 10)]);
 
-        // Both should give same results
-        assert_eq!(
-            filter1.contains(test_item).unwrap(),
-            filter2.contains(test_item).unwrap()
-        );
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+        assert!(!filter.is_rotation_due());
+
+        for item in generate_test_items(5) {
+            filter.insert(&item).unwrap();
+        }
+        assert_eq!(*recorder.0.lock().unwrap(), vec![(0, 10), (1, 5)]);
     }
 
-    #[test]
-    fn test_false_positive_rate_reasonable() {
+    #[tokio::test]
+    async fn test_either_policy_rotates_on_first_trigger() {
+        let (filter, clock) = create_policy_filter(RotationPolicy::Either(5));
+
+        // Count triggers first
+        for item in generate_test_items(5) {
+            filter.insert(&item).unwrap();
+        }
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+
+        // Time triggers first
+        filter.insert(b"single").unwrap();
+        clock.advance(Duration::from_millis(150));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_contains_scored_decays_with_age() {
         let filter = create_test_filter(1000, 3, 0.01);
-        let inserted_items = generate_test_items(500);
-        let test_items = generate_test_items(1000);
 
-        // Insert first half
-        for item in &inserted_items {
+        assert_eq!(filter.contains_scored(b"missing").unwrap(), None);
+
+        filter.insert(b"item").unwrap();
+        let fresh = filter.contains_scored(b"item").unwrap().unwrap();
+        assert!(fresh > 0.99 && fresh <= 1.0, "fresh score {fresh}");
+
+        filter.rotate_levels().await.unwrap();
+        filter.rotate_levels().await.unwrap();
+        let old = filter.contains_scored(b"item").unwrap().unwrap();
+        assert!(old < fresh, "old score {old} >= fresh score {fresh}");
+        assert!(old > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_grace_overlap_carries_late_inserts() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .num_levels(2_usize)
+            .level_duration(Duration::from_millis(100))
+            .grace_overlap(Some(Duration::from_millis(20)))
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+
+        filter.insert(b"early").unwrap();
+        clock.advance(Duration::from_millis(85));
+        filter.insert(b"late").unwrap();
+
+        clock.advance(Duration::from_millis(65));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+
+        // Level 0 is cleared, but the late insert was carried into level 1
+        clock.advance(Duration::from_millis(100));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 0);
+        assert!(!filter.contains(b"early").unwrap());
+        assert!(filter.contains(b"late").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ttl_estimate_follows_schedule() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+
+        assert_eq!(filter.ttl_estimate(b"missing").unwrap(), None);
+
+        filter.insert(b"item").unwrap();
+        clock.advance(Duration::from_millis(40));
+        // 60ms left in the current window plus two more full windows
+        assert_eq!(
+            filter.ttl_estimate(b"item").unwrap(),
+            Some(Duration::from_millis(260))
+        );
+
+        clock.advance(Duration::from_millis(70));
+        filter.cleanup_expired_levels().await.unwrap();
+        // Now one window older: 90ms left in the current window plus one more
+        assert_eq!(
+            filter.ttl_estimate(b"item").unwrap(),
+            Some(Duration::from_millis(190))
+        );
+
+        // Refreshing moves the item into the current level, but the oldest
+        // match still drives the estimate
+        filter.insert(b"item").unwrap();
+        assert_eq!(
+            filter.ttl_estimate(b"item").unwrap(),
+            Some(Duration::from_millis(190))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_history_records_windows() {
+        let (filter, clock) = create_manual_clock_filter(1000, 2, 100);
+        assert!(filter.rotation_history(10).unwrap().is_empty());
+
+        for item in generate_test_items(20) {
+            filter.insert(&item).unwrap();
+        }
+        clock.advance(Duration::from_millis(101));
+        filter.cleanup_expired_levels().await.unwrap();
+        clock.advance(Duration::from_millis(100));
+        filter.cleanup_expired_levels().await.unwrap();
+
+        let history = filter.rotation_history(10).unwrap();
+        assert_eq!(history.len(), 2);
+        // First rotation reuses the untouched level 1
+        assert_eq!(history[0].level, 1);
+        assert_eq!(history[0].insert_count, 0);
+        assert_eq!(history[0].fill_ratio, 0.0);
+        assert_eq!(history[0].reason, RotationReason::Time);
+        // Second rotation drops the window carrying the 20 items
+        assert_eq!(history[1].level, 0);
+        assert_eq!(history[1].insert_count, 20);
+        assert!(history[1].fill_ratio > 0.0);
+        assert!(history[1].rotated_at > history[0].rotated_at);
+
+        // A limit keeps the most recent rotations
+        assert_eq!(filter.rotation_history(1).unwrap(), history[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_history_is_bounded() {
+        let filter = create_test_filter(100, 2, 0.01);
+        for _ in 0..ROTATION_HISTORY_LEN + 5 {
+            filter.rotate_levels().await.unwrap();
+        }
+        assert_eq!(
+            filter.rotation_history(usize::MAX).unwrap().len(),
+            ROTATION_HISTORY_LEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aligned_windows_start_on_boundaries() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_millis(100))
+            .align_windows(true)
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_050));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+
+        // Process started mid-window, level 0 still starts on the boundary
+        assert_eq!(filter.level_metadata(0).unwrap().created_at, 1_000_000);
+
+        clock.advance(Duration::from_millis(60));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+        assert_eq!(filter.level_metadata(1).unwrap().created_at, 1_000_100);
+
+        // Manual rotation mid-window is aligned as well
+        clock.advance(Duration::from_millis(30));
+        filter.rotate_levels().await.unwrap();
+        assert_eq!(filter.level_metadata(2).unwrap().created_at, 1_000_100);
+    }
+
+    #[tokio::test]
+    async fn test_smooth_decay_outlives_current_level() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_millis(100))
+            .insert_mode(InsertMode::CurrentAndNext)
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+
+        filter.insert(b"item").unwrap();
+        filter.insert_bulk(&[b"bulk".as_slice()]).unwrap();
+        // Only the current level is written until it rotates
+        assert!(filter.level_fill_ratio(0).unwrap() > 0.0);
+        assert_eq!(filter.level_fill_ratio(1).unwrap(), 0.0);
+        // Insert counts are not doubled
+        assert_eq!(filter.total_insert_count(), 2);
+
+        clock.advance(Duration::from_millis(101));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(
+            filter.level_fill_ratio(0).unwrap(),
+            filter.level_fill_ratio(1).unwrap()
+        );
+
+        // Level 0 alone would drop the items when the ring comes round at
+        // 300ms; the copy in level 1 keeps them until 400ms
+        clock.advance(Duration::from_millis(200));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 0);
+        assert!(filter.contains(b"item").unwrap());
+        assert!(filter.contains(b"bulk").unwrap());
+
+        clock.advance(Duration::from_millis(99));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert!(filter.contains(b"item").unwrap());
+
+        clock.advance(Duration::from_millis(1));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+        assert_eq!(clock.now_ms().unwrap(), 1_000_401);
+        assert!(!filter.contains(b"item").unwrap());
+        assert!(!filter.contains(b"bulk").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_manual_clock_rotation() {
+        let (filter, clock) = create_manual_clock_filter(100, 2, 100);
+
+        filter.insert(b"expire_me").unwrap();
+
+        clock.advance(Duration::from_millis(150));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+        assert!(filter.contains(b"expire_me").unwrap());
+
+        // Nothing happens until the new level expires as well
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 1);
+
+        clock.advance(Duration::from_millis(150));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.get_active_level(), 0);
+        assert!(!filter.contains(b"expire_me").unwrap());
+    }
+}
+
+#[cfg(test)]
+mod configuration_and_stats_tests {
+    use super::*;
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_memory_mapped_levels() {
+        use probabilistic_rs::ebloom::config::LevelBacking;
+
+        let dir = std::path::PathBuf::from("test_ebloom_mmap_levels");
+        for backing in [
+            LevelBacking::AnonymousMmap,
+            LevelBacking::FileMmap(dir.clone()),
+        ] {
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(1000_usize)
+                .num_levels(2_usize)
+                .level_backing(backing)
+                .build()
+                .unwrap();
+            let filter = ExpiringBloomFilter::new(config).unwrap();
+            let items = generate_test_items(200);
+            for item in &items {
+                filter.insert(item).unwrap();
+            }
+            for item in &items {
+                assert!(filter.contains(item).unwrap());
+            }
+            // Large enough for file-mapped levels to be queried in parallel
+            let queried = generate_test_items(5000);
+            let refs: Vec<&[u8]> = queried.iter().map(|v| v.as_slice()).collect();
+            let found = filter.contains_bulk(&refs).unwrap();
+            for (item, found) in refs.iter().zip(found) {
+                assert_eq!(found, filter.contains(item).unwrap());
+            }
+            let thawed = filter.freeze().unwrap().thaw().unwrap();
+            assert!(thawed.contains(&items[0]).unwrap());
+        }
+        assert!(dir.join("level_1.bits").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prefault_and_huge_pages() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_000_usize)
+            .num_levels(2_usize)
+            .prefault_levels(true)
+            .huge_pages(true)
+            .build()
+            .unwrap();
+        if !cfg!(feature = "mmap") {
+            assert!(config.validate().is_err());
+            return;
+        }
+
+        let filter = ExpiringBloomFilter::new(config).unwrap();
+        // Pre-faulting must not set any bits
+        assert_eq!(filter.fill_ratio(0).unwrap(), 0.0);
+        let items = generate_test_items(200);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        for item in &items {
+            assert!(filter.contains(item).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_snapshot() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        for item in generate_test_items(300) {
+            filter.insert(&item).unwrap();
+        }
+        filter.rotate_levels().await.unwrap();
+        filter.insert(b"fresh").unwrap();
+
+        let stats = filter.stats().unwrap();
+        assert_eq!(stats.capacity_per_level, 1000);
+        assert_eq!(stats.num_levels, 3);
+        assert_eq!(stats.current_level, 1);
+        assert_eq!(stats.total_insert_count, 301);
+        assert_eq!(stats.snapshot_lag_ms, None);
+        assert!(stats.memory_bytes >= 3 * stats.bit_vector_size / 8);
+        assert!(stats.estimated_fpr > 0.0 && stats.estimated_fpr < 0.01);
+
+        assert_eq!(stats.levels.len(), 3);
+        assert_eq!(stats.levels[0].insert_count, 300);
+        assert_eq!(stats.levels[0].fill_ratio, filter.fill_ratio(0).unwrap());
+        assert_eq!(stats.levels[1].insert_count, 1);
+        assert!(stats.levels[1].age_ms.is_some());
+        // Never used, so no age and no contribution to the estimate
+        assert_eq!(stats.levels[2].age_ms, None);
+        assert!(!stats.levels[2].expired);
+        #[cfg(not(feature = "latency"))]
+        assert!(stats.latency.is_empty());
+    }
+
+    #[cfg(feature = "latency")]
+    #[tokio::test]
+    async fn test_latency_histograms() {
+        use probabilistic_rs::ebloom::stats::LatencyOperation;
+
+        let filter = create_test_filter(1000, 3, 0.01);
+        let items = generate_test_items(100);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        filter.contains(&items[0]).unwrap();
+        let refs: Vec<&[u8]> = items.iter().map(|i| i.as_slice()).collect();
+        filter.contains_bulk(&refs).unwrap();
+        filter.rotate_levels().await.unwrap();
+
+        let latency = filter.stats().unwrap().latency;
+        let of = |operation| latency.iter().find(|l| l.operation == operation);
+        let inserts = of(LatencyOperation::Insert).unwrap();
+        assert_eq!(inserts.count, 100);
+        assert!(inserts.p50_ns <= inserts.p99_ns);
+        assert!(inserts.p99_ns <= inserts.max_ns);
+        assert_eq!(of(LatencyOperation::Contains).unwrap().count, 1);
+        assert_eq!(of(LatencyOperation::ContainsBulk).unwrap().count, 1);
+        assert_eq!(of(LatencyOperation::Rotation).unwrap().count, 1);
+        // Never called
+        assert!(of(LatencyOperation::InsertBulk).is_none());
+
+        filter.reset_latency();
+        assert!(filter.stats().unwrap().latency.is_empty());
+    }
+
+    #[test]
+    fn test_memory_usage_accounts_every_level() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let bit_vector_size = filter.stats().unwrap().bit_vector_size;
+
+        let usage = filter.memory_usage().unwrap();
+        assert_eq!(usage.levels, 3 * bit_vector_size.div_ceil(64) * 8);
+        // No persistence or grace overlap configured
+        assert_eq!(usage.dirty_tracking, 0);
+        assert_eq!(usage.grace_bits, 0);
+        assert_eq!(usage.write_behind, 0);
+        assert!(usage.metadata > 0);
+        assert_eq!(filter.approx_memory_bytes().unwrap(), usage.total());
+        assert_eq!(filter.bits_per_item(), bit_vector_size as f64 / 1000.0);
+    }
+
+    #[test]
+    fn test_health_without_persistence() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        filter.insert(b"item").unwrap();
+
+        let health = filter.health().unwrap();
+        assert!(!health.persistent);
+        assert_eq!(filter.backend_kind(), "memory");
+        assert!(health.writable);
+        assert_eq!(health.since_last_snapshot_ms, None);
+        assert_eq!(health.pending_dirty_chunks, 0);
+        assert!(health.is_healthy(0));
+    }
+
+    #[tokio::test]
+    async fn test_dump_state() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        for item in generate_test_items(100) {
+            filter.insert(&item).unwrap();
+        }
+        filter.rotate_levels().await.unwrap();
+
+        let dump = filter.dump_state().unwrap();
+        assert_eq!(dump.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(dump.backend, "memory");
+        assert_eq!(dump.config.capacity_per_level, 1000);
+        assert_eq!(dump.stats.current_level, 1);
+        assert_eq!(dump.levels.len(), 3);
+        assert_eq!(dump.levels[0].metadata.insert_count, 100);
+        assert!(dump.levels[0].fill_ratio > 0.0);
+        assert_eq!(dump.levels[0].density_histogram.len(), 64);
+        assert!(dump.levels[0].density_histogram.iter().all(|&d| d > 0.0));
+        assert_eq!(dump.levels[1].fill_ratio, 0.0);
+        assert_eq!(dump.rotations.len(), 1);
+        let json = serde_json::to_string(&dump).unwrap();
+        assert!(json.contains("density_histogram"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_invariants() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        assert_eq!(filter.validate_invariants().unwrap(), vec![]);
+
+        for item in generate_test_items(100) {
+            filter.insert(&item).unwrap();
+        }
+        for _ in 0..4 {
+            filter.rotate_levels().await.unwrap();
+            assert_eq!(filter.validate_invariants().unwrap(), vec![]);
+        }
+
+        let violation = InvariantViolation::LevelBitLength {
+            level: 1,
+            found: 10,
+            expected: 12,
+        };
+        assert_eq!(violation.to_string(), "level 1 has 10 bits, expected 12");
+    }
+
+    #[test]
+    fn test_fill_ratio_counts_set_bits() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        assert_eq!(filter.fill_ratio(0).unwrap(), 0.0);
+
+        for item in generate_test_items(500) {
+            filter.insert(&item).unwrap();
+        }
+        let ratio = filter.fill_ratio(0).unwrap();
+        assert!(ratio > 0.0 && ratio < 1.0);
+        assert_eq!(ratio, filter.level_fill_ratio(0).unwrap());
+        assert_eq!(filter.fill_ratio(1).unwrap(), 0.0);
+        assert!(matches!(
+            filter.fill_ratio(3),
+            Err(EbloomError::InvalidLevel { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stats_level_bit_counts() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let stats = filter.stats().unwrap();
+        assert_eq!(filter.bit_vector_size(), stats.bit_vector_size);
+        assert_eq!(filter.num_hashes(), stats.num_hashes);
+        assert_eq!(filter.level_bit_counts().unwrap(), vec![0, 0, 0]);
+
+        for item in generate_test_items(500) {
+            filter.insert(&item).unwrap();
+        }
+        let counts = filter.level_bit_counts().unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(
+            counts[0] as f64 / filter.bit_vector_size() as f64,
+            filter.fill_ratio(0).unwrap()
+        );
+        assert_eq!(&counts[1..], &[0, 0]);
+    }
+
+    #[test]
+    fn test_blocked_layout() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(2_usize)
+            .blocked_layout(true)
+            .build()
+            .unwrap();
+        assert_eq!(config.level_bit_size() % CACHE_LINE_BITS, 0);
+
+        // Every probe of an item lands in the same cache line
+        for item in generate_test_items(50) {
+            let indices =
+                blocked_hash_function(&item, 7, config.level_bit_size());
+            let block = indices[0] / CACHE_LINE_BITS;
+            assert!(indices.iter().all(|&idx| idx / CACHE_LINE_BITS == block));
+        }
+
+        let filter = ExpiringBloomFilter::new(config).unwrap();
+        let items = generate_test_items(500);
+        for item in &items {
             filter.insert(item).unwrap();
         }
+        for item in &items {
+            assert!(filter.contains(item).unwrap());
+        }
+        assert!(filter.freeze().unwrap().contains(&items[0]).unwrap());
+    }
+
+    #[test]
+    fn test_config_validation_valid() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .build();
+
+        assert!(config.is_ok(), "Valid config should build successfully");
+    }
+
+    #[test]
+    fn test_config_validation_level_durations() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .num_levels(3_usize)
+            .level_durations(vec![Duration::from_secs(1); 2])
+            .build()
+            .unwrap();
+        assert!(config.validate().is_err());
+
+        let config = ExpiringFilterConfigBuilder::default()
+            .num_levels(2_usize)
+            .level_durations(vec![Duration::from_secs(1), Duration::ZERO])
+            .build()
+            .unwrap();
+        assert!(config.validate().is_err());
+
+        let config = ExpiringFilterConfigBuilder::default()
+            .num_levels(2_usize)
+            .level_duration(Duration::from_secs(7))
+            .level_durations(vec![
+                Duration::from_secs(60),
+                Duration::from_secs(300),
+            ])
+            .build()
+            .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.duration_for_age(1), Duration::from_secs(300));
+        assert_eq!(config.retention_for_age(1), Duration::from_secs(360));
+        // The ring comes round after two 60s windows
+        assert_eq!(config.level_lifetime(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_config_validation_max_fill_ratio() {
+        for ratio in [0.0, -0.1, 1.5] {
+            let config = ExpiringFilterConfigBuilder::default()
+                .max_fill_ratio(Some(ratio))
+                .build()
+                .unwrap();
+            assert!(config.validate().is_err(), "ratio {ratio} accepted");
+        }
+    }
+
+    #[test]
+    fn test_config_validation_pathological_params() {
+        for (capacity, fpr) in
+            [(1_usize, 0.999999), (100, 0.9), (usize::MAX, 0.01)]
+        {
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(capacity)
+                .target_fpr(fpr)
+                .build()
+                .unwrap();
+            assert!(
+                matches!(config.validate(), Err(EbloomError::InvalidConfig(_))),
+                "capacity {capacity} at FPR {fpr} accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_validation_rotation_policy() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .rotation_policy(RotationPolicy::InsertCount(0))
+            .build()
+            .unwrap();
+        assert!(config.validate().is_err());
+
+        let config = ExpiringFilterConfigBuilder::default()
+            .rotation_policy(RotationPolicy::Either(1000))
+            .build()
+            .unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_level_metadata_accessors() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+        let start = clock.now_ms().unwrap();
+
+        filter.insert(b"a").unwrap();
+        filter.insert(b"b").unwrap();
+
+        let meta = filter.level_metadata(0).unwrap();
+        assert_eq!(meta.created_at, start);
+        assert_eq!(meta.insert_count, 2);
+        assert_eq!(meta.last_snapshot_at, 0);
+        assert_eq!(meta.rotation_reason, RotationReason::Created);
+
+        // Uninitialized levels report created_at = 0
+        assert_eq!(filter.level_metadata(1).unwrap().created_at, 0);
+
+        filter.rotate_levels().await.unwrap();
+        let all = filter.all_level_metadata().unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[1].rotation_reason, RotationReason::Manual);
+        assert_eq!(all[0].insert_count, 2);
+
+        assert!(matches!(
+            filter.level_metadata(3),
+            Err(EbloomError::InvalidLevel { level: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_stats_accuracy() {
+        let filter = create_test_filter(1000, 3, 0.01);
+
+        assert_eq!(filter.capacity_per_level(), 1000);
+        assert_eq!(filter.target_fpr(), 0.01);
+        assert_eq!(filter.num_levels(), 3);
+        assert_eq!(filter.active_levels(), 3);
+        assert_eq!(filter.total_insert_count(), 0u64);
+
+        // Insert some items
+        for i in 0..10 {
+            filter.insert(format!("item{}", i).as_bytes()).unwrap();
+        }
+
+        assert_eq!(filter.total_insert_count(), 10);
+    }
+
+    #[test]
+    fn test_different_config_combinations() {
+        // Small capacity, few levels
+        let filter1 = create_test_filter(100, 2, 0.01);
+        assert_eq!(filter1.num_levels(), 2);
+
+        // Large capacity, many levels
+        let filter2 = create_test_filter(10000, 5, 0.001);
+        assert_eq!(filter2.num_levels(), 5);
+        assert_eq!(filter2.target_fpr(), 0.001);
+    }
+}
+
+#[cfg(test)]
+mod edge_cases_and_error_conditions {
+    use super::*;
+
+    #[test]
+    fn test_empty_item_insertion() {
+        let filter = create_test_filter(1000, 3, 0.01);
+
+        // Empty item should be insertable
+        filter
+            .insert(b"")
+            .expect("Empty item insertion should succeed");
+        assert!(
+            filter
+                .contains(b"")
+                .expect("Contains should work for empty item")
+        );
+    }
+
+    #[test]
+    fn test_binary_data_handling() {
+        let filter = create_test_filter(1000, 3, 0.01);
+
+        // Test with binary data (null bytes, etc.)
+        let binary_data: Vec<u8> = vec![0, 1, 255, 128, 0, 0, 42];
+        filter
+            .insert(&binary_data)
+            .expect("Binary data insertion should succeed");
+        assert!(
+            filter
+                .contains(&binary_data)
+                .expect("Binary data lookup should succeed")
+        );
+    }
+
+    #[test]
+    fn test_large_item_insertion() {
+        let filter = create_test_filter(1000, 3, 0.01);
+
+        // Test with large item (1MB)
+        let large_item = vec![b'x'; 1024 * 1024];
+        filter
+            .insert(&large_item)
+            .expect("Large item insertion should succeed");
+        assert!(
+            filter
+                .contains(&large_item)
+                .expect("Large item lookup should succeed")
+        );
+    }
+
+    #[test]
+    fn test_capacity_stress_test() {
+        let capacity = 100;
+        let filter = create_test_filter(capacity, 3, 0.01);
+        let test_items = generate_test_items(capacity * 2); // Insert 2x capacity
+
+        // Should handle over-capacity gracefully (bloom filters don't have hard limits)
+        for item in &test_items {
+            filter
+                .insert(item)
+                .expect("Insert should succeed even over capacity");
+        }
+
+        // No false negatives (all inserted items should be found)
+        for item in &test_items {
+            assert!(
+                filter.contains(item).expect("Contains should succeed"),
+                "No false negatives even when over capacity"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod behavioral_guarantees_tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives_guarantee() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let test_items = generate_test_items(100);
+
+        // Insert all items
+        for item in &test_items {
+            filter.insert(item).unwrap();
+        }
+
+        // Verify no false negatives
+        for item in &test_items {
+            assert!(
+                filter.contains(item).unwrap(),
+                "Bloom filters must never have false negatives"
+            );
+        }
+    }
+
+    #[test]
+    fn test_deterministic_behavior() {
+        let filter1 = create_test_filter(1000, 3, 0.01);
+        let filter2 = create_test_filter(1000, 3, 0.01);
+
+        let test_item = b"deterministic_test";
+
+        // Same operations on both filters
+        filter1.insert(test_item).unwrap();
+        filter2.insert(test_item).unwrap();
+
+        // Both should give same results
+        assert_eq!(
+            filter1.contains(test_item).unwrap(),
+            filter2.contains(test_item).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_false_positive_rate_reasonable() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        let inserted_items = generate_test_items(500);
+        let test_items = generate_test_items(1000);
+
+        // Insert first half
+        for item in &inserted_items {
+            filter.insert(item).unwrap();
+        }
+
+        // Check false positives on items NOT inserted
+        let mut false_positives = 0;
+        for item in test_items.iter().skip(500) {
+            if filter.contains(item).unwrap() {
+                false_positives += 1;
+            }
+        }
+
+        let false_positive_rate = false_positives as f64 / 500.0;
+
+        // FPR should be somewhat close to target (allowing for multi-level increase)
+        // Multi-level can increase FPR, so we allow up to 5x the target FPR
+        assert!(
+            false_positive_rate < 0.05,
+            "False positive rate too high: {}",
+            false_positive_rate
+        );
+    }
+}
+
+#[cfg(test)]
+mod thread_safety_tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_reads() {
+        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
+        let test_items = generate_test_items(100);
+
+        // Insert items first
+        for item in &test_items {
+            filter.insert(item).unwrap();
+        }
+
+        // Spawn multiple reader threads
+        let mut handles = vec![];
+        for _ in 0..10 {
+            let filter_clone = Arc::clone(&filter);
+            let items_clone = test_items.clone();
+
+            let handle = thread::spawn(move || {
+                for item in &items_clone {
+                    assert!(filter_clone.contains(item).unwrap());
+                }
+            });
+            handles.push(handle);
+        }
+
+        // Wait for all threads
+        for handle in handles {
+            handle.join().expect("Thread should complete successfully");
+        }
+    }
+
+    #[test]
+    fn test_single_writer_multiple_readers() {
+        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
+        let test_items = generate_test_items(100);
+        let inserted_items = Arc::new(Mutex::new(HashSet::new()));
+
+        // Writer thread
+        let filter_clone = Arc::clone(&filter);
+        let items_clone = test_items.clone();
+        let inserted_clone = Arc::clone(&inserted_items);
+
+        let writer = thread::spawn(move || {
+            for item in &items_clone {
+                filter_clone.insert(item).unwrap();
+                inserted_clone.lock().unwrap().insert(item.clone());
+                thread::sleep(Duration::from_micros(100));
+            }
+        });
+
+        // Reader threads
+        let mut readers = vec![];
+        for _ in 0..5 {
+            let filter_clone = Arc::clone(&filter);
+            let inserted_clone = Arc::clone(&inserted_items);
+
+            let handle = thread::spawn(move || {
+                for _ in 0..50 {
+                    let items = inserted_clone.lock().unwrap().clone();
+                    for item in items.iter() {
+                        // No false negatives for inserted items
+                        assert!(filter_clone.contains(item).unwrap());
+                    }
+                    thread::sleep(Duration::from_micros(200));
+                }
+            });
+            readers.push(handle);
+        }
+
+        writer.join().expect("Writer should complete");
+        for reader in readers {
+            reader.join().expect("Reader should complete");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_run_alongside_rotation() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
+        let test_items = generate_test_items(100);
+        for item in &test_items {
+            filter.insert(item).unwrap();
+        }
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let filter = Arc::clone(&filter);
+                let items = test_items.clone();
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        for item in &items {
+                            assert!(filter.contains(item).unwrap());
+                            assert!(filter.contains_at(item, u64::MAX).unwrap());
+                        }
+                        let _ = filter.is_level_expired(0).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        // Rotating twice clears the two levels that never held the items
+        filter.rotate_levels().await.unwrap();
+        filter.rotate_levels().await.unwrap();
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().expect("Reader should complete");
+        }
+        assert_eq!(filter.get_active_level(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let filter_clone = Arc::clone(&filter);
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        let item = format!("writer_{t}_{i}");
+                        filter_clone.insert(item.as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("Writer should complete");
+        }
+
+        assert_eq!(filter.total_insert_count(), 8 * 500);
+        for t in 0..8 {
+            for i in 0..500 {
+                let item = format!("writer_{t}_{i}");
+                assert!(filter.contains(item.as_bytes()).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_bulk_writers_count_exactly() {
+        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let filter_clone = Arc::clone(&filter);
+                thread::spawn(move || {
+                    for batch in 0..50 {
+                        let items: Vec<Vec<u8>> = (0..10)
+                            .map(|i| format!("bulk_{t}_{batch}_{i}").into_bytes())
+                            .collect();
+                        let refs: Vec<&[u8]> =
+                            items.iter().map(Vec::as_slice).collect();
+                        filter_clone.insert_bulk(&refs).unwrap();
+                        filter_clone.insert(b"single").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("Writer should complete");
+        }
+
+        // Counters are atomics; no increment is lost between writers
+        assert_eq!(filter.total_insert_count(), 8 * 50 * 11);
+        assert_eq!(filter.level_metadata(0).unwrap().insert_count, 8 * 50 * 11);
+    }
+
+    #[test]
+    fn test_atomic_bit_vec_density_histogram() {
+        let bits = AtomicBitVec::new(256 + 32);
+        // Fill the first word, leave the rest empty
+        for idx in 0..64 {
+            bits.set(idx);
+        }
+        bits.set(256);
+
+        let histogram = bits.density_histogram(5);
+        assert_eq!(histogram.len(), 5);
+        assert_eq!(histogram[0], 1.0);
+        assert_eq!(histogram[1], 0.0);
+        // Last bucket only covers the 32 bits past the last full word
+        assert_eq!(histogram[4], 1.0 / 32.0);
+        assert!(bits.density_histogram(0).is_empty());
+    }
+
+    #[test]
+    fn test_atomic_bit_vec_density_histogram_uneven_words() {
+        // 10 words into 3 buckets: slices of 3, 3 and 4 words
+        let bits = AtomicBitVec::new(640);
+        for idx in (0..64).chain(576..640) {
+            bits.set(idx);
+        }
+
+        let histogram = bits.density_histogram(3);
+        assert_eq!(histogram.len(), 3);
+        assert_eq!(histogram, [64.0 / 192.0, 0.0, 64.0 / 256.0]);
+        let ones: f64 = histogram
+            .iter()
+            .zip([192.0, 192.0, 256.0])
+            .map(|(density, bits)| density * bits)
+            .sum();
+        assert_eq!(ones as usize, bits.count_ones());
+        // More buckets than words: one per word
+        assert_eq!(bits.density_histogram(16).len(), 10);
+    }
+
+    #[test]
+    fn test_atomic_bit_vec_all_set_each_matches_all_set() {
+        let len = 64 * 40 + 17;
+        let bits = AtomicBitVec::new(len);
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        for _ in 0..len / 2 {
+            bits.set(next() % len);
+        }
+
+        // Probe counts around the vector widths, including short tails
+        for num_hashes in 1..=9 {
+            let items = 50;
+            let indices: Vec<usize> =
+                (0..items * num_hashes).map(|_| next() % len).collect();
+            let mut found = vec![false; items];
+            found[0] = true;
+            bits.all_set_each(&indices, num_hashes, &mut found);
+
+            let expected: Vec<bool> = indices
+                .chunks(num_hashes)
+                .enumerate()
+                .map(|(item, probes)| item == 0 || bits.all_set(probes))
+                .collect();
+            assert_eq!(found, expected, "num_hashes {num_hashes}");
+            assert!(
+                found.iter().any(|&hit| hit) && found.iter().any(|&hit| !hit)
+            );
+        }
+    }
+
+    #[test]
+    fn test_atomic_bit_vec_byte_round_trip() {
+        let bits = AtomicBitVec::new(100);
+        for idx in [0, 7, 8, 63, 64, 99] {
+            bits.set(idx);
+        }
+        assert_eq!(bits.count_ones(), 6);
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), [0, 7, 8, 63, 64, 99]);
+
+        let bytes = bits.read_bytes(0, 100);
+        assert_eq!(bytes.len(), 13);
+        assert_eq!(bytes[0], 0b1000_0001);
+
+        // Ranges may start inside a word
+        assert_eq!(bits.read_bytes(8, 72), bytes[1..9]);
+        assert_eq!(bits.read_bytes(96, 200), [0b1000]);
+
+        let copy = AtomicBitVec::new(100);
+        copy.write_bytes(0, &bytes);
+        assert_eq!(copy.iter_ones().collect::<Vec<_>>(), [0, 7, 8, 63, 64, 99]);
+
+        assert_eq!(bits.take_ones().len(), 6);
+        assert!(!bits.any());
+    }
+}
+
+#[cfg(test)]
+mod integration_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_realistic_usage_scenario() {
+        // Simulate a cache invalidation scenario
+        let filter = create_short_expiry_filter(1000, 3, 200);
+
+        // Phase 1: Add some cache keys
+        for i in 0..50 {
+            let key = format!("cache_key_{}", i);
+            filter.insert(key.as_bytes()).unwrap();
+        }
+
+        // Verify all are present
+        for i in 0..50 {
+            let key = format!("cache_key_{}", i);
+            assert!(filter.contains(key.as_bytes()).unwrap());
+        }
+
+        // Phase 2: Rotate levels
+        thread::sleep(Duration::from_millis(250));
+        filter.cleanup_expired_levels().await.unwrap();
+
+        // Old keys should still be findable (in rotated level)
+        for i in 0..50 {
+            let key = format!("cache_key_{}", i);
+            assert!(filter.contains(key.as_bytes()).unwrap());
+        }
+
+        // Phase 3: Add new keys
+        for i in 50..100 {
+            let key = format!("cache_key_{}", i);
+            filter.insert(key.as_bytes()).unwrap();
+        }
+
+        // All keys should be present
+        for i in 0..100 {
+            let key = format!("cache_key_{}", i);
+            assert!(filter.contains(key.as_bytes()).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_time_based_expiration_workflow() {
+        let filter = create_short_expiry_filter(100, 3, 100); // 3 levels for better testing
+
+        // Insert items at different times
+        filter.insert(b"early_item").unwrap();
+
+        thread::sleep(Duration::from_millis(110)); // Wait for rotation
+        filter.cleanup_expired_levels().await.unwrap();
+
+        // early_item should still be present (now in previous level)
+        assert!(filter.contains(b"early_item").unwrap());
+
+        // Insert new item in new current level
+        filter.insert(b"mid_item").unwrap();
+
+        thread::sleep(Duration::from_millis(110)); // Wait for another rotation
+        filter.cleanup_expired_levels().await.unwrap();
+
+        // Both should still be present (in different historical levels)
+        assert!(filter.contains(b"early_item").unwrap());
+        assert!(filter.contains(b"mid_item").unwrap());
+
+        thread::sleep(Duration::from_millis(110)); // Third rotation
+        filter.cleanup_expired_levels().await.unwrap();
+
+        // early_item should be expired now (was in oldest level)
+        // mid_item still present
+        assert!(filter.contains(b"mid_item").unwrap());
+    }
+
+    #[test]
+    fn test_mixed_operations() {
+        let filter = create_test_filter(1000, 3, 0.01);
+
+        // Mix of operations
+        filter.insert(b"item1").unwrap();
+        assert!(filter.contains(b"item1").unwrap());
+
+        filter.insert(b"item2").unwrap();
+        filter.insert(b"item3").unwrap();
+
+        assert_eq!(filter.total_insert_count(), 3);
+
+        filter.clear().unwrap();
+        assert_eq!(filter.total_insert_count(), 0);
+
+        filter.insert(b"new_item").unwrap();
+        assert!(filter.contains(b"new_item").unwrap());
+        assert_eq!(filter.total_insert_count(), 1);
+    }
+}
+
+#[cfg(test)]
+mod rotation_events_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::drift::FprSample;
+    use probabilistic_rs::ebloom::events::{
+        FilterObserver, RotationEvent, SaturationWarning,
+    };
+
+    #[tokio::test]
+    async fn test_on_rotation_fires_with_rotated_out_level() {
+        let filter = create_short_expiry_filter(1000, 2, 100);
+        let events: Arc<Mutex<Vec<RotationEvent>>> =
+            Arc::new(Mutex::new(Vec::new()));
+
+        let events_clone = Arc::clone(&events);
+        filter
+            .on_rotation(move |event| {
+                events_clone.lock().unwrap().push(event.clone());
+            })
+            .unwrap();
+
+        filter.insert(b"item1").unwrap();
+        filter.insert(b"item2").unwrap();
+
+        // First rotation reuses the never-initialized level 1
+        filter.rotate_levels().await.unwrap();
+        // Second rotation rotates out level 0 holding both items
+        filter.rotate_levels().await.unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].level, 1);
+        assert_eq!(events[0].insert_count, 0);
+        assert_eq!(events[1].level, 0);
+        assert_eq!(events[1].insert_count, 2);
+        assert_eq!(events[1].metadata.insert_count, 2);
+        assert!(events[1].metadata.created_at > 0);
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        inserts: Mutex<Vec<(Vec<u8>, usize)>>,
+        rotations: Mutex<Vec<RotationReason>>,
+        saturations: Mutex<Vec<usize>>,
+    }
+
+    impl FilterObserver for RecordingObserver {
+        fn on_insert(&self, item: &[u8], level: usize) {
+            self.inserts.lock().unwrap().push((item.to_vec(), level));
+        }
+
+        fn on_rotate(&self, event: &RotationEvent) {
+            self.rotations.lock().unwrap().push(event.reason);
+        }
+
+        fn on_saturation(&self, level: usize, fill_ratio: f64) {
+            assert!(fill_ratio > 0.5);
+            self.saturations.lock().unwrap().push(level);
+        }
+    }
+
+    #[derive(Default)]
+    struct WarningRecorder(Mutex<Vec<SaturationWarning>>);
+
+    impl FilterObserver for WarningRecorder {
+        fn on_saturation_warning(&self, warning: &SaturationWarning) {
+            self.0.lock().unwrap().push(warning.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_saturation_warning_fires_once_per_window() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_millis(1000))
+            .saturation_warning(Some(0.3))
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+        let recorder = Arc::new(WarningRecorder::default());
+        filter.add_observer(recorder.clone()).unwrap();
+
+        for item in generate_test_items(60) {
+            filter.insert(&item).unwrap();
+        }
+        clock.advance(Duration::from_millis(500));
+        filter.cleanup_expired_levels().await.unwrap();
+        filter.cleanup_expired_levels().await.unwrap();
+        {
+            let warnings = recorder.0.lock().unwrap();
+            assert_eq!(warnings.len(), 1);
+            let warning = &warnings[0];
+            assert_eq!(warning.level, 0);
+            assert!(warning.fill_ratio >= 0.3);
+            // Half the window is left, so the level keeps filling
+            assert!(warning.projected_fpr > warning.level_fpr);
+        }
+
+        // A new window may warn again
+        filter.rotate_levels().await.unwrap();
+        for i in 0..60 {
+            filter.insert(format!("next_{i}").as_bytes()).unwrap();
+        }
+        filter.cleanup_expired_levels().await.unwrap();
+        let warnings = recorder.0.lock().unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[1].level, 1);
+    }
+
+    #[derive(Default)]
+    struct DriftRecorder(Mutex<Vec<FprSample>>);
+
+    impl FilterObserver for DriftRecorder {
+        fn on_fpr_drift(&self, sample: &FprSample, _target_fpr: f64) {
+            self.0.lock().unwrap().push(sample.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fpr_drift_tracks_duplicates_and_alerts() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_usize)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(3600))
+            .fpr_sample_interval(Some(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+        let recorder = Arc::new(DriftRecorder::default());
+        filter.add_observer(recorder.clone()).unwrap();
+
+        let items = generate_test_items(50);
+        for item in &items {
+            filter.insert(item).unwrap();
+        }
+        // Every repeat is a probable duplicate
+        for item in &items[..10] {
+            filter.insert(item).unwrap();
+        }
+        let sample = filter.sample_fpr().unwrap();
+        assert_eq!(sample.inserts, 60);
+        assert!(sample.probable_duplicates >= 10);
+        assert!(sample.duplicate_rate >= 10.0 / 60.0);
+        assert!(sample.estimated_fpr < 0.01);
+        assert!(!filter.fpr_drift().unwrap().diverging);
+
+        // Overfill the level, sampled from cleanup once the interval passed
+        for i in 0..1000 {
+            filter.insert(format!("over_{i}").as_bytes()).unwrap();
+        }
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(filter.fpr_drift().unwrap().samples.len(), 1);
+        clock.advance(Duration::from_secs(60));
+        filter.cleanup_expired_levels().await.unwrap();
+        filter.cleanup_expired_levels().await.unwrap();
+
+        let drift = filter.fpr_drift().unwrap();
+        assert_eq!(drift.samples.len(), 2);
+        assert_eq!(drift.samples[1].inserts, 1000);
+        assert!(drift.diverging);
+        assert!(drift.trend_per_hour > 0.0);
+        // One alert per excursion
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_filter_observer_hooks() {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_usize)
+            .num_levels(3_usize)
+            .max_fill_ratio(Some(0.5))
+            .build()
+            .unwrap();
+        let filter = ExpiringBloomFilter::new(config).unwrap();
+        let observer = Arc::new(RecordingObserver::default());
+        filter.add_observer(observer.clone()).unwrap();
+
+        filter.insert(b"single").unwrap();
+        filter.insert_bulk(&[b"a".as_slice(), b"b"]).unwrap();
+        filter.rotate_levels().await.unwrap();
+        filter.insert(b"next").unwrap();
+        assert_eq!(
+            *observer.inserts.lock().unwrap(),
+            vec![
+                (b"single".to_vec(), 0),
+                (b"a".to_vec(), 0),
+                (b"b".to_vec(), 0),
+                (b"next".to_vec(), 1),
+            ]
+        );
+
+        for item in generate_test_items(300) {
+            filter.insert(&item).unwrap();
+        }
+        filter.cleanup_expired_levels().await.unwrap();
+        assert_eq!(*observer.saturations.lock().unwrap(), vec![1]);
+        assert_eq!(
+            *observer.rotations.lock().unwrap(),
+            vec![RotationReason::Manual, RotationReason::Saturation]
+        );
+    }
+}
+
+#[cfg(test)]
+mod freeze_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frozen_view_is_stable() {
+        let filter = create_test_filter(1000, 2, 0.01);
+        filter.insert(b"before").unwrap();
+
+        let frozen = filter.freeze().unwrap();
+        let shared = frozen.clone();
+
+        filter.insert(b"after").unwrap();
+        filter.rotate_levels().await.unwrap();
+        filter.rotate_levels().await.unwrap();
+
+        // Live filter moved on, the frozen view did not
+        assert!(!filter.contains(b"before").unwrap());
+        assert!(frozen.contains(b"before").unwrap());
+        assert!(!frozen.contains(b"after").unwrap());
+        assert_eq!(frozen.active_level(), 0);
+        assert_eq!(frozen.level_metadata(0).unwrap().insert_count, 1);
+        assert_eq!(
+            shared
+                .contains_bulk(&[b"before".as_slice(), b"after".as_slice()])
+                .unwrap(),
+            vec![true, false]
+        );
+    }
+
+    #[test]
+    fn test_thaw_returns_writable_copy() {
+        let filter = create_test_filter(1000, 3, 0.01);
+        filter.insert(b"item").unwrap();
+
+        let frozen = filter.freeze().unwrap();
+        let thawed = frozen.thaw().unwrap();
+
+        assert!(thawed.contains(b"item").unwrap());
+        assert_eq!(thawed.total_insert_count(), 1);
+
+        thawed.insert(b"new").unwrap();
+        assert!(thawed.contains(b"new").unwrap());
+        assert!(!frozen.contains(b"new").unwrap());
+        assert!(!filter.contains(b"new").unwrap());
+    }
+}
+
+#[cfg(all(test, feature = "url"))]
+mod url_dedup_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::url_dedup::UrlDeduper;
+
+    fn create_deduper() -> UrlDeduper {
+        UrlDeduper::new(create_test_filter(1000, 3, 0.01))
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let deduper = create_deduper();
+        assert_eq!(
+            deduper
+                .canonicalize(
+                    "HTTPS://Example.COM:443/Path?b=2&utm_source=x&a=1#top"
+                )
+                .unwrap(),
+            "https://example.com/Path?a=1&b=2"
+        );
+        // Only tracking parameters: the query goes away entirely
+        assert_eq!(
+            deduper
+                .canonicalize("http://example.com?fbclid=abc&utm_medium=y")
+                .unwrap(),
+            "http://example.com/"
+        );
+        assert!(matches!(
+            deduper.canonicalize("not a url"),
+            Err(EbloomError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_equivalent_urls_are_duplicates() {
+        let deduper = create_deduper();
+        assert!(
+            deduper
+                .check_and_insert("https://example.com/a?x=1&y=2")
+                .unwrap()
+        );
+        assert!(
+            !deduper
+                .check_and_insert("https://EXAMPLE.com/a?y=2&x=1&gclid=9#frag")
+                .unwrap()
+        );
+        assert!(
+            deduper
+                .is_seen("https://example.com:443/a?x=1&y=2")
+                .unwrap()
+        );
+        assert!(!deduper.is_seen("https://example.com/b").unwrap());
+    }
+
+    #[test]
+    fn test_custom_tracking_params() {
+        let deduper = create_deduper().with_tracking_params(["sid", "ref_*"]);
+        assert_eq!(
+            deduper
+                .canonicalize("https://example.com/?sid=1&ref_src=t&utm_source=x")
+                .unwrap(),
+            "https://example.com/?utm_source=x"
+        );
+    }
+}
+
+mod shared_filter_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::shared::SharedFilter;
+
+    #[test]
+    fn test_clones_share_one_filter() {
+        let shared = SharedFilter::new(create_test_filter(1000, 3, 0.01));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    shared.insert(format!("item_{i}").as_bytes()).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for i in 0..4 {
+            assert!(shared.contains(format!("item_{i}").as_bytes()).unwrap());
+        }
+        assert_eq!(Arc::strong_count(&shared.arc()), 2);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_axum_extractor_from_composite_state() {
+        use axum::{
+            Router,
+            body::Body,
+            extract::FromRef,
+            http::{Request, StatusCode},
+            routing::post,
+        };
+        use tower::ServiceExt;
+
+        #[derive(Clone)]
+        struct AppState {
+            filter: SharedFilter,
+        }
+
+        impl FromRef<AppState> for SharedFilter {
+            fn from_ref(state: &AppState) -> Self {
+                state.filter.clone()
+            }
+        }
+
+        async fn insert(filter: SharedFilter, body: String) -> StatusCode {
+            filter.insert(body.as_bytes()).unwrap();
+            StatusCode::NO_CONTENT
+        }
+
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let shared = SharedFilter::from_config(config).unwrap();
+        let app =
+            Router::new()
+                .route("/items", post(insert))
+                .with_state(AppState {
+                    filter: shared.clone(),
+                });
+
+        let response = app
+            .oneshot(Request::post("/items").body(Body::from("key")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(shared.contains(b"key").unwrap());
+    }
+}
+
+mod statsd_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::config::StatsdConfigBuilder;
+    use std::net::UdpSocket;
+
+    fn recv_lines(socket: &UdpSocket) -> Vec<String> {
+        let mut buf = [0u8; 2048];
+        let len = socket.recv(&mut buf).expect("No StatsD datagram");
+        String::from_utf8_lossy(&buf[..len])
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_statsd_emits_counters_rotations_and_density() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        daemon
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let statsd = StatsdConfigBuilder::default()
+            .host(daemon.local_addr().unwrap().to_string())
+            .prefix("test.filter")
+            .flush_interval(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .statsd(statsd)
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap();
+
+        for item in generate_test_items(10) {
+            filter.insert(&item).unwrap();
+        }
+        filter.insert(b"test_item_000000").unwrap();
+
+        // First cleanup flushes right away
+        filter.cleanup_expired_levels().await.unwrap();
+        let lines = recv_lines(&daemon);
+        assert!(lines.contains(&"test.filter.inserts:11|c".to_string()));
+        assert!(
+            lines.contains(&"test.filter.probable_duplicates:1|c".to_string())
+        );
+        assert!(lines.contains(&"test.filter.current_level:0|g".to_string()));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("test.filter.duplicate_rate:0.09"))
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("test.filter.level.0.fill_ratio:"))
+        );
+
+        // Counters restart after a flush; the next one waits for the interval
+        filter.insert(b"another").unwrap();
+        filter.cleanup_expired_levels().await.unwrap();
+        filter.rotate_levels().await.unwrap();
+        assert_eq!(
+            recv_lines(&daemon),
+            vec!["test.filter.rotations.manual:1|c"]
+        );
+
+        filter.flush_statsd().unwrap();
+        let lines = recv_lines(&daemon);
+        assert!(lines.contains(&"test.filter.inserts:1|c".to_string()));
+        assert!(lines.contains(&"test.filter.current_level:1|g".to_string()));
+    }
+
+    #[test]
+    fn test_statsd_config_is_validated() {
+        let statsd = StatsdConfigBuilder::default().prefix("").build().unwrap();
+        let config = ExpiringFilterConfigBuilder::default()
+            .statsd(statsd)
+            .build()
+            .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
+
+mod greylist_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::greylist::{
+        GreylistConfigBuilder, SeenRecently, Verdict,
+    };
+
+    fn create_greylist() -> (SeenRecently, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter = |duration_ms| {
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(1000_usize)
+                .target_fpr(0.01)
+                .num_levels(2_usize)
+                .level_duration(Duration::from_millis(duration_ms))
+                .build()
+                .unwrap();
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap()
+        };
+        (SeenRecently::new(filter(100), filter(10_000)), clock)
+    }
+
+    #[tokio::test]
+    async fn test_retry_within_window_passes() {
+        let (greylist, clock) = create_greylist();
+
+        assert_eq!(greylist.check(b"sender-a").unwrap(), Verdict::Defer);
+        assert_eq!(greylist.check(b"sender-a").unwrap(), Verdict::Accept);
+        assert_eq!(greylist.check(b"sender-b").unwrap(), Verdict::Defer);
+
+        // Past the retry window "b" starts over; "a" passed and stays accepted
+        clock.advance(Duration::from_millis(250));
+        greylist.cleanup_expired_levels().await.unwrap();
+        assert_eq!(greylist.check(b"sender-b").unwrap(), Verdict::Defer);
+        assert_eq!(greylist.check(b"sender-a").unwrap(), Verdict::Accept);
+    }
+
+    #[tokio::test]
+    async fn test_first_seen() {
+        let (greylist, clock) = create_greylist();
+        assert!(greylist.first_seen(b"key").unwrap());
+        assert!(!greylist.first_seen(b"key").unwrap());
+        assert!(!greylist.passed().contains(b"key").unwrap());
+
+        clock.advance(Duration::from_millis(250));
+        greylist.cleanup_expired_levels().await.unwrap();
+        assert!(greylist.first_seen(b"key").unwrap());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = GreylistConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .retry_window(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let greylist = SeenRecently::from_config(&config).unwrap();
+        assert_eq!(greylist.check(b"key").unwrap(), Verdict::Defer);
+        assert_eq!(greylist.check(b"key").unwrap(), Verdict::Accept);
+
+        let config = GreylistConfigBuilder::default()
+            .num_levels(0_usize)
+            .build()
+            .unwrap();
+        assert!(SeenRecently::from_config(&config).is_err());
+    }
+}
+
+mod rate_limit_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::rate_limit::ApproxRateLimiter;
+
+    #[tokio::test]
+    async fn test_budget_per_key_refills_as_levels_expire() {
+        let (filter, clock) = create_manual_clock_filter(1000, 2, 100);
+        let limiter = ApproxRateLimiter::new(filter, 3).unwrap();
+
+        let remaining: Vec<u32> = (0..3)
+            .map(|_| {
+                let decision = limiter.check(b"client-a").unwrap();
+                assert!(decision.allowed);
+                decision.remaining
+            })
+            .collect();
+        assert_eq!(remaining, vec![2, 1, 0]);
+        assert!(!limiter.check(b"client-a").unwrap().allowed);
+        // Other keys keep their own budget
+        assert!(limiter.check(b"client-b").unwrap().allowed);
+        assert_eq!(limiter.remaining(b"client-b").unwrap(), 2);
+
+        // Once every level holding the hits expired the budget is back
+        clock.advance(Duration::from_millis(250));
+        limiter.filter().cleanup_expired_levels().await.unwrap();
+        assert_eq!(limiter.remaining(b"client-a").unwrap(), 3);
+        assert!(limiter.check(b"client-a").unwrap().allowed);
+    }
+
+    #[test]
+    fn test_zero_limit_is_rejected() {
+        let filter = create_test_filter(1000, 2, 0.01);
+        assert!(ApproxRateLimiter::new(filter, 0).is_err());
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn test_tower_layer_rejects_over_budget() {
+        use http::{Request, Response, StatusCode};
+        use probabilistic_rs::ebloom::rate_limit::{
+            REMAINING_HEADER, RateLimitLayer,
+        };
+        use std::convert::Infallible;
+        use tower::{Layer, ServiceExt, service_fn};
+
+        let limiter = Arc::new(
+            ApproxRateLimiter::new(create_test_filter(1000, 2, 0.01), 2).unwrap(),
+        );
+        let layer = RateLimitLayer::new(limiter, |request: &Request<()>| {
+            request
+                .headers()
+                .get("x-client")
+                .map(|value| value.as_bytes().to_vec())
+        });
+        let service = layer.layer(service_fn(|_: Request<()>| async {
+            Ok::<_, Infallible>(Response::new(String::from("ok")))
+        }));
+
+        let request =
+            || Request::builder().header("x-client", "a").body(()).unwrap();
+        let mut statuses = Vec::new();
+        for _ in 0..3 {
+            let response = service.clone().oneshot(request()).await.unwrap();
+            statuses.push((
+                response.status(),
+                response.headers()[REMAINING_HEADER]
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ));
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                (StatusCode::OK, "1".to_string()),
+                (StatusCode::OK, "0".to_string()),
+                (StatusCode::TOO_MANY_REQUESTS, "0".to_string()),
+            ]
+        );
+
+        // Requests without a key are not limited
+        let unkeyed = Request::builder().body(()).unwrap();
+        let response = service.clone().oneshot(unkeyed).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(REMAINING_HEADER).is_none());
+    }
+}
+
+mod log_dedup_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::log_dedup::{
+        LogDeduper, strip_leading_timestamp,
+    };
+    use std::io::Write;
+
+    fn create_deduper(interval: Duration) -> Arc<LogDeduper> {
+        let filter = create_test_filter(1000, 2, 0.01);
+        Arc::new(LogDeduper::new(filter).with_summary_interval(interval))
+    }
+
+    #[test]
+    fn test_duplicates_suppressed_with_summary() {
+        let deduper = create_deduper(Duration::ZERO);
+        let mut output = Vec::new();
+        {
+            let mut writer = deduper.writer(&mut output);
+            writer
+                .write_all(b"2024-01-01T00:00:00Z  WARN upstream down\n")
+                .unwrap();
+            writer
+                .write_all(b"2024-01-01T00:00:01Z  WARN upstream down\n")
+                .unwrap();
+            // Lines may arrive in pieces
+            writer.write_all(b"2024-01-01T00:00:02Z  INFO ").unwrap();
+            writer.write_all(b"recovered\n").unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2024-01-01T00:00:00Z  WARN upstream down\n\
+             suppressed 1 duplicate log lines\n\
+             2024-01-01T00:00:02Z  INFO recovered\n"
+        );
+        assert_eq!(deduper.suppressed(), 0);
+    }
+
+    #[test]
+    fn test_summary_waits_for_interval() {
+        let deduper = create_deduper(Duration::from_secs(3600));
+        let mut output = Vec::new();
+        {
+            let mut writer = deduper.writer(&mut output);
+            for _ in 0..3 {
+                writer.write_all(b"same line\n").unwrap();
+            }
+            // Partial line is written on drop
+            writer.write_all(b"tail").unwrap();
+        }
+        assert_eq!(output, b"same line\ntail");
+        assert_eq!(deduper.suppressed(), 2);
+    }
+
+    #[test]
+    fn test_custom_fingerprint() {
+        let filter = create_test_filter(1000, 2, 0.01);
+        let deduper = LogDeduper::new(filter).with_fingerprint(|line| {
+            line.split(|&b| b == b':').next().unwrap_or(line).to_vec()
+        });
+        assert!(deduper.admit(b"timeout: host-a"));
+        assert!(!deduper.admit(b"timeout: host-b"));
+        assert!(deduper.admit(b"refused: host-a"));
+    }
+
+    #[test]
+    fn test_strip_leading_timestamp() {
+        assert_eq!(
+            strip_leading_timestamp(
+                b"\x1b[2m2024-01-01T00:00:00.123Z\x1b[0m \x1b[32m INFO\x1b[0m hi"
+            ),
+            b"INFO\x1b[0m hi"
+        );
+        assert_eq!(strip_leading_timestamp(b"INFO hi"), b"INFO hi");
+    }
+}
+
+mod union_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::union::UnionPayload;
+
+    #[tokio::test]
+    async fn test_union_keeps_level_timing() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter = || {
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(1000_usize)
+                .target_fpr(0.01)
+                .num_levels(2_usize)
+                .level_duration(Duration::from_millis(100))
+                .build()
+                .unwrap();
+            ExpiringBloomFilter::with_clock(config, clock.clone()).unwrap()
+        };
+        let (worker, central) = (filter(), filter());
+
+        worker.insert(b"old").unwrap();
+        clock.advance(Duration::from_millis(150));
+        worker.cleanup_expired_levels().await.unwrap();
+        central.cleanup_expired_levels().await.unwrap();
+        worker.insert(b"new").unwrap();
+        central.insert(b"local").unwrap();
+
+        let payload = worker.export_union().unwrap();
+        assert_eq!(payload.levels.len(), 2);
+        let payload =
+            UnionPayload::from_bytes(&payload.to_bytes().unwrap()).unwrap();
+        assert_eq!(central.apply_union(&payload).unwrap(), 2);
+        // OR is idempotent
+        assert_eq!(central.apply_union(&payload).unwrap(), 2);
+        for item in [&b"old"[..], b"new", b"local"] {
+            assert!(central.contains(item).unwrap());
+        }
+
+        // "old" expires with the central level matching its window
+        clock.advance(Duration::from_millis(150));
+        central.cleanup_expired_levels().await.unwrap();
+        assert!(!central.contains(b"old").unwrap());
+        assert!(central.contains(b"new").unwrap());
+    }
+
+    #[test]
+    fn test_union_rejects_other_layout() {
+        let worker = create_test_filter(1000, 2, 0.01);
+        let central = create_test_filter(2000, 2, 0.01);
+        worker.insert(b"item").unwrap();
+        let payload = worker.export_union().unwrap();
+        assert!(central.apply_union(&payload).is_err());
+
+        let mut payload =
+            create_test_filter(1000, 2, 0.01).export_union().unwrap();
+        assert!(payload.levels.is_empty());
+        payload.levels = worker.export_union().unwrap().levels;
+        payload.levels[0].bits.pop();
+        assert!(
+            create_test_filter(1000, 2, 0.01)
+                .apply_union(&payload)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seed_from_warms_oldest_level() {
+        let (old, old_clock) = create_manual_clock_filter(1000, 2, 100);
+        old.insert(b"expired").unwrap();
+        old_clock.advance(Duration::from_millis(150));
+        old.cleanup_expired_levels().await.unwrap();
+        old.insert(b"recent").unwrap();
+        let frozen = old.freeze().unwrap();
+        old_clock.advance(Duration::from_millis(100));
+        old.cleanup_expired_levels().await.unwrap();
+        assert!(!old.contains(b"expired").unwrap());
+
+        // Same capacity and FPR keep the bit layout across a ring change
+        let (fresh, clock) = create_manual_clock_filter(1000, 3, 100);
+        assert_eq!(fresh.seed_from(&frozen).unwrap(), 2);
+        assert_eq!(fresh.seed_from(&old).unwrap(), 1);
+        assert_eq!(fresh.get_active_level(), 0);
+        for item in [&b"expired"[..], b"recent"] {
+            assert!(fresh.contains(item).unwrap());
+        }
+        let current = fresh.level_metadata(0).unwrap().created_at;
+        let oldest = fresh.level_metadata(1).unwrap().created_at;
+        assert_eq!(oldest, current - 200);
+        assert_eq!(fresh.level_metadata(2).unwrap().created_at, 0);
+
+        // Seeded keys age out with the oldest level, fresh inserts stay
+        fresh.insert(b"live").unwrap();
+        clock.advance(Duration::from_millis(150));
+        fresh.cleanup_expired_levels().await.unwrap();
+        assert_eq!(fresh.get_active_level(), 1);
+        assert!(!fresh.contains(b"recent").unwrap());
+        assert!(fresh.contains(b"live").unwrap());
+    }
+
+    #[test]
+    fn test_seed_from_payload_and_layout_check() {
+        let old = create_test_filter(1000, 2, 0.01);
+        old.insert(b"item").unwrap();
+        let payload = UnionPayload::from_bytes(
+            &old.export_union().unwrap().to_bytes().unwrap(),
+        )
+        .unwrap();
+
+        let fresh = create_test_filter(1000, 1, 0.01);
+        assert_eq!(fresh.seed_from(&payload).unwrap(), 1);
+        assert!(fresh.contains(b"item").unwrap());
+
+        let other_layout = create_test_filter(2000, 2, 0.01);
+        assert!(other_layout.seed_from(&payload).is_err());
+        assert!(!other_layout.contains(b"item").unwrap());
+    }
+}
+
+#[cfg(feature = "gossip")]
+mod gossip_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::gossip::{
+        AntiEntropy, ChunkDelta, GossipConfigBuilder, SyncDigest,
+    };
+
+    fn create_node(chunk_bytes: usize) -> AntiEntropy {
+        let filter = Arc::new(create_test_filter(10_000, 2, 0.01));
+        let config = GossipConfigBuilder::default()
+            .chunk_bytes(chunk_bytes)
+            .build()
+            .unwrap();
+        AntiEntropy::new(filter, config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_nodes_converge_shipping_only_differences() {
+        let (a, b) = (create_node(256), create_node(256));
+        a.filter().insert(b"seen-by-a").unwrap();
+        b.filter().insert(b"seen-by-b").unwrap();
+
+        let stats = a.sync_with(&b).await.unwrap();
+        assert!(stats.chunks_sent > 0 && stats.chunks_received > 0);
+        // Each key touches a few chunks, far from the whole level
+        let total_chunks = a.digest().unwrap().levels[0].chunk_hashes.len();
+        assert!(stats.chunks_sent < total_chunks);
+
+        for node in [&a, &b] {
+            assert!(node.filter().contains(b"seen-by-a").unwrap());
+            assert!(node.filter().contains(b"seen-by-b").unwrap());
+        }
+        let hashes = |node: &AntiEntropy| {
+            node.digest().unwrap().levels[0].chunk_hashes.clone()
+        };
+        assert_eq!(hashes(&a), hashes(&b));
+        assert_eq!(a.sync_with(&b).await.unwrap().chunks_sent, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_round_uses_peers() {
+        let mut a = create_node(256);
+        assert!(a.sync_round().await.unwrap().is_none());
+        let b = Arc::new(create_node(256));
+        b.filter().insert(b"key").unwrap();
+        a.add_peer(b.clone());
+        assert!(a.sync_round().await.unwrap().is_some());
+        assert!(a.filter().contains(b"key").unwrap());
+    }
+
+    #[test]
+    fn test_payloads_round_trip_and_mismatch_rejected() {
+        let (a, b) = (create_node(256), create_node(512));
+        a.filter().insert(b"key").unwrap();
+        let digest = a.digest().unwrap();
+        assert_eq!(
+            SyncDigest::from_bytes(&digest.to_bytes().unwrap()).unwrap(),
+            digest
+        );
+        assert!(b.delta_for(&digest).is_err());
+
+        let delta = a.delta_for(&create_node(256).digest().unwrap()).unwrap();
+        assert_eq!(
+            ChunkDelta::from_bytes(&delta.to_bytes().unwrap()).unwrap(),
+            delta
+        );
+        assert!(b.apply(&delta).is_err());
+    }
+}
+
+mod crdt_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::crdt::BloomCrdt;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    fn create_replica(clock: Arc<ManualClock>) -> ExpiringBloomFilter {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(100_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_millis(100))
+            .align_windows(true)
+            .build()
+            .unwrap();
+        ExpiringBloomFilter::with_clock(config, clock).unwrap()
+    }
+
+    /// State of a replica with random inserts and rotations
+    async fn random_state(rng: &mut StdRng) -> BloomCrdt {
+        let start = 1_000_000 + rng.random_range(0..500);
+        let clock = Arc::new(ManualClock::new(start));
+        let replica = create_replica(clock.clone());
+        for _ in 0..rng.random_range(1..4) {
+            for _ in 0..rng.random_range(0..20) {
+                replica.insert(&rng.random::<u16>().to_le_bytes()).unwrap();
+            }
+            clock.advance(Duration::from_millis(rng.random_range(0..250)));
+            replica.cleanup_expired_levels().await.unwrap();
+        }
+        replica.crdt_state().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_merge_is_a_join() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..50 {
+            let a = random_state(&mut rng).await;
+            let b = random_state(&mut rng).await;
+            let c = random_state(&mut rng).await;
+
+            let ab = a.join(&b).unwrap();
+            assert_eq!(ab, b.join(&a).unwrap(), "commutative");
+            assert_eq!(a.join(&a).unwrap(), a, "idempotent");
+            assert_eq!(ab.join(&b).unwrap(), ab, "absorbs");
+            assert_eq!(
+                ab.join(&c).unwrap(),
+                a.join(&b.join(&c).unwrap()).unwrap(),
+                "associative"
+            );
+            assert!(ab.windows().count() <= 3);
+        }
+    }
 
-        // Check false positives on items NOT inserted
-        let mut false_positives = 0;
-        for item in test_items.iter().skip(500) {
-            if filter.contains(item).unwrap() {
-                false_positives += 1;
-            }
+    #[tokio::test]
+    async fn test_replicas_converge_and_expire() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let (a, b) =
+            (create_replica(clock.clone()), create_replica(clock.clone()));
+        a.insert(b"from-a").unwrap();
+        b.insert(b"from-b").unwrap();
+
+        let state = a
+            .crdt_state()
+            .unwrap()
+            .join(&b.crdt_state().unwrap())
+            .unwrap();
+        let state = BloomCrdt::from_bytes(&state.to_bytes().unwrap()).unwrap();
+        for replica in [&a, &b] {
+            replica.merge_crdt(&state).unwrap();
+            assert!(replica.contains(b"from-a").unwrap());
+            assert!(replica.contains(b"from-b").unwrap());
         }
+        assert_eq!(a.crdt_state().unwrap(), b.crdt_state().unwrap());
 
-        let false_positive_rate = false_positives as f64 / 500.0;
+        // Newer windows push the merged one out
+        for _ in 0..4 {
+            clock.advance(Duration::from_millis(100));
+            a.cleanup_expired_levels().await.unwrap();
+        }
+        assert!(!a.contains(b"from-b").unwrap());
+        let mut newer = a.crdt_state().unwrap();
+        newer.merge(&state).unwrap();
+        assert!(!newer.windows().any(|start| start == 1_000_000));
+    }
 
-        // FPR should be somewhat close to target (allowing for multi-level increase)
-        // Multi-level can increase FPR, so we allow up to 5x the target FPR
+    #[test]
+    fn test_merge_rejects_other_layout() {
+        let mut state = create_test_filter(1000, 3, 0.01).crdt_state().unwrap();
+        let other = create_test_filter(2000, 3, 0.01).crdt_state().unwrap();
+        assert!(state.merge(&other).is_err());
+        let other = create_test_filter(1000, 2, 0.01).crdt_state().unwrap();
+        assert!(state.merge(&other).is_err());
         assert!(
-            false_positive_rate < 0.05,
-            "False positive rate too high: {}",
-            false_positive_rate
+            create_test_filter(2000, 3, 0.01)
+                .merge_crdt(&state)
+                .is_err()
         );
     }
 }
 
-#[cfg(test)]
-mod thread_safety_tests {
+mod sharded_tests {
     use super::*;
+    use probabilistic_rs::ebloom::sharded::ShardedExpiringFilter;
 
-    #[test]
-    fn test_concurrent_reads() {
-        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
-        let test_items = generate_test_items(100);
-
-        // Insert items first
-        for item in &test_items {
-            filter.insert(item).unwrap();
-        }
-
-        // Spawn multiple reader threads
-        let mut handles = vec![];
-        for _ in 0..10 {
-            let filter_clone = Arc::clone(&filter);
-            let items_clone = test_items.clone();
+    fn create_sharded(
+        num_shards: usize,
+    ) -> (ShardedExpiringFilter, Arc<ManualClock>) {
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(4000_usize)
+            .target_fpr(0.01)
+            .num_levels(2_usize)
+            .level_duration(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let filter =
+            ShardedExpiringFilter::with_clock(config, num_shards, clock.clone())
+                .unwrap();
+        (filter, clock)
+    }
 
-            let handle = thread::spawn(move || {
-                for item in &items_clone {
-                    assert!(filter_clone.contains(item).unwrap());
-                }
-            });
-            handles.push(handle);
+    #[tokio::test]
+    async fn test_keys_route_to_one_shard_and_expire() {
+        let (filter, clock) = create_sharded(4);
+        assert_eq!(filter.num_shards(), 4);
+        assert_eq!(filter.capacity_per_level(), 4000);
+
+        let items: Vec<Vec<u8>> =
+            (0..400).map(|i| format!("key-{i}").into_bytes()).collect();
+        let refs: Vec<&[u8]> = items.iter().map(Vec::as_slice).collect();
+        filter.insert_bulk(&refs).unwrap();
+        filter.insert(b"single").unwrap();
+
+        assert!(filter.contains_bulk(&refs).unwrap().iter().all(|&hit| hit));
+        assert!(filter.contains(b"single").unwrap());
+        let owner = filter.shard_for(b"single");
+        assert!(filter.shard(owner).unwrap().contains(b"single").unwrap());
+        // Every shard takes a share of the keys
+        for shard in filter.shards() {
+            assert!(shard.total_insert_count() > 50);
         }
+        assert_eq!(filter.total_insert_count(), 401);
+        let shard_bits = filter.shards()[0].bit_vector_size();
+        assert_eq!(filter.bit_vector_size(), 4 * shard_bits);
+        assert_eq!(filter.num_hashes(), filter.shards()[0].num_hashes());
+        let per_shard: Vec<usize> = filter
+            .shards()
+            .iter()
+            .map(|shard| shard.level_bit_counts().unwrap()[0])
+            .collect();
+        assert_eq!(
+            filter.level_bit_counts().unwrap(),
+            vec![per_shard.iter().sum::<usize>(), 0]
+        );
 
-        // Wait for all threads
-        for handle in handles {
-            handle.join().expect("Thread should complete successfully");
-        }
+        clock.advance(Duration::from_millis(250));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert!(!filter.contains(b"single").unwrap());
     }
 
     #[test]
-    fn test_single_writer_multiple_readers() {
-        let filter = Arc::new(create_test_filter(10000, 3, 0.01));
-        let test_items = generate_test_items(100);
-        let inserted_items = Arc::new(Mutex::new(HashSet::new()));
+    fn test_zero_shards_rejected() {
+        let config = ExpiringFilterConfigBuilder::default().build().unwrap();
+        assert!(ShardedExpiringFilter::new(config, 0).is_err());
+    }
+}
 
-        // Writer thread
-        let filter_clone = Arc::clone(&filter);
-        let items_clone = test_items.clone();
-        let inserted_clone = Arc::clone(&inserted_items);
+mod fpr_guardrail_tests {
+    use super::*;
+    use probabilistic_rs::SaturationError;
+    use probabilistic_rs::ebloom::events::GuardrailAction;
 
-        let writer = thread::spawn(move || {
-            for item in &items_clone {
-                filter_clone.insert(item).unwrap();
-                inserted_clone.lock().unwrap().insert(item.clone());
-                thread::sleep(Duration::from_micros(100));
+    #[test]
+    fn test_guardrail_rejects_inserts_past_limit() {
+        let filter = create_test_filter(100, 3, 0.01);
+        filter.set_fpr_guardrail(Some(0.02)).unwrap();
+        assert_eq!(filter.fpr_guardrail(), Some(0.02));
+
+        let mut accepted = 0;
+        let error = loop {
+            match filter.insert(format!("key-{accepted}").as_bytes()) {
+                Ok(()) => accepted += 1,
+                Err(EbloomError::Saturated(error)) => break error,
+                Err(other) => panic!("unexpected error {other}"),
             }
-        });
+        };
+        // Sized for 100 items at 1%, so the 2% limit trips a little later
+        assert!((100..200).contains(&accepted), "accepted {accepted}");
+        assert_eq!(error.level, 0);
+        assert_eq!(error.insert_count, accepted + 1);
+        assert!(error.estimated_fpr > 0.02);
+        assert_eq!(error.limit, 0.02);
+        assert_eq!(filter.total_insert_count(), accepted);
+        assert!(
+            !filter
+                .contains(format!("key-{accepted}").as_bytes())
+                .unwrap()
+        );
 
-        // Reader threads
-        let mut readers = vec![];
-        for _ in 0..5 {
-            let filter_clone = Arc::clone(&filter);
-            let inserted_clone = Arc::clone(&inserted_items);
+        // A batch that would cross the limit is refused as a whole
+        let batch: [&[u8]; 2] = [b"a", b"b"];
+        assert!(matches!(
+            filter.insert_bulk(&batch),
+            Err(EbloomError::Saturated(_))
+        ));
 
-            let handle = thread::spawn(move || {
-                for _ in 0..50 {
-                    let items = inserted_clone.lock().unwrap().clone();
-                    for item in items.iter() {
-                        // No false negatives for inserted items
-                        assert!(filter_clone.contains(item).unwrap());
-                    }
-                    thread::sleep(Duration::from_micros(200));
-                }
-            });
-            readers.push(handle);
-        }
+        filter.set_fpr_guardrail(None).unwrap();
+        filter.insert(b"unguarded").unwrap();
+        assert!(filter.set_fpr_guardrail(Some(1.5)).is_err());
+    }
 
-        writer.join().expect("Writer should complete");
-        for reader in readers {
-            reader.join().expect("Reader should complete");
+    #[tokio::test]
+    async fn test_guardrail_policy_and_rotation() {
+        let filter = Arc::new(create_test_filter(100, 3, 0.01));
+        filter.set_fpr_guardrail(Some(0.02)).unwrap();
+        let tripped: Arc<Mutex<Vec<SaturationError>>> = Arc::default();
+        {
+            let tripped = Arc::clone(&tripped);
+            filter
+                .on_fpr_guardrail(move |error| {
+                    tripped.lock().unwrap().push(error.clone());
+                    GuardrailAction::Allow
+                })
+                .unwrap();
         }
+        for i in 0..300 {
+            filter.insert(format!("key-{i}").as_bytes()).unwrap();
+        }
+        let tripped = tripped.lock().unwrap().len();
+        assert!(tripped > 100 && tripped < 200, "tripped {tripped}");
+
+        // A fresh level is back under the limit
+        filter.rotate_levels().await.unwrap();
+        filter
+            .on_fpr_guardrail(|_| GuardrailAction::Reject)
+            .unwrap();
+        filter.insert(b"fresh").unwrap();
     }
 }
 
-#[cfg(test)]
-mod integration_tests {
+mod reshape_tests {
     use super::*;
+    use probabilistic_rs::ebloom::reshape::ReshapingFilter;
 
     #[tokio::test]
-    async fn test_realistic_usage_scenario() {
-        // Simulate a cache invalidation scenario
-        let filter = create_short_expiry_filter(1000, 3, 200);
+    async fn test_old_filter_serves_until_its_windows_pass() {
+        let (filter, clock) = create_manual_clock_filter(1000, 3, 100);
+        let filter = ReshapingFilter::new(filter);
+        filter.insert(b"before").unwrap();
+        assert!(!filter.is_reshaping().unwrap());
 
-        // Phase 1: Add some cache keys
-        for i in 0..50 {
-            let key = format!("cache_key_{}", i);
-            filter.insert(key.as_bytes()).unwrap();
-        }
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(5000_usize)
+            .target_fpr(0.001)
+            .num_levels(2_usize)
+            .level_duration(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        filter.begin_reshape(config).await.unwrap();
+        filter.insert(b"after").unwrap();
+
+        let current = filter.current().unwrap();
+        assert_eq!(current.config().capacity_per_level, 5000);
+        assert!(current.contains(b"after").unwrap());
+        assert!(!current.contains(b"before").unwrap());
+        assert!(filter.is_reshaping().unwrap());
+        assert_eq!(filter.reshape_done_at().unwrap(), Some(1_000_300));
+        let items: [&[u8]; 3] = [b"before", b"after", b"never"];
+        assert_eq!(
+            filter.contains_bulk(&items).unwrap(),
+            vec![true, true, false]
+        );
 
-        // Verify all are present
-        for i in 0..50 {
-            let key = format!("cache_key_{}", i);
-            assert!(filter.contains(key.as_bytes()).unwrap());
-        }
+        // The old filter keeps answering until all three of its windows
+        // have passed
+        clock.advance(Duration::from_millis(250));
+        filter.cleanup_expired_levels().await.unwrap();
+        assert!(filter.is_reshaping().unwrap());
+        assert!(filter.contains(b"before").unwrap());
 
-        // Phase 2: Rotate levels
-        thread::sleep(Duration::from_millis(250));
+        clock.advance(Duration::from_millis(100));
         filter.cleanup_expired_levels().await.unwrap();
+        assert!(!filter.is_reshaping().unwrap());
+        assert!(!filter.contains(b"before").unwrap());
+        assert!(filter.contains(b"after").unwrap());
+    }
 
-        // Old keys should still be findable (in rotated level)
-        for i in 0..50 {
-            let key = format!("cache_key_{}", i);
-            assert!(filter.contains(key.as_bytes()).unwrap());
+    #[tokio::test]
+    async fn test_invalid_reshape_keeps_current_filter() {
+        let (filter, _clock) = create_manual_clock_filter(1000, 3, 100);
+        let filter = ReshapingFilter::new(filter);
+        let mut config = filter.current().unwrap().config().clone();
+        config.capacity_per_level = 0;
+        assert!(filter.begin_reshape(config).await.is_err());
+        assert!(!filter.is_reshaping().unwrap());
+        assert_eq!(filter.current().unwrap().config().capacity_per_level, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_reshape_rejects_insert_count_rotation() {
+        for policy in
+            [RotationPolicy::InsertCount(10), RotationPolicy::Either(10)]
+        {
+            let config = ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(1000_usize)
+                .num_levels(3_usize)
+                .level_duration(Duration::from_millis(100))
+                .rotation_policy(policy)
+                .build()
+                .unwrap();
+            let filter =
+                ReshapingFilter::new(ExpiringBloomFilter::new(config).unwrap());
+            filter.insert(b"before").unwrap();
+
+            let mut config = filter.current().unwrap().config().clone();
+            config.capacity_per_level = 5000;
+            config.rotation_policy = RotationPolicy::Time;
+            assert!(filter.begin_reshape(config).await.is_err());
+            assert!(!filter.is_reshaping().unwrap());
+            assert_eq!(
+                filter.current().unwrap().config().capacity_per_level,
+                1000
+            );
+            assert!(filter.contains(b"before").unwrap());
         }
+    }
+}
 
-        // Phase 3: Add new keys
-        for i in 50..100 {
-            let key = format!("cache_key_{}", i);
-            filter.insert(key.as_bytes()).unwrap();
+mod ring_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::ring::{FilterRing, RingConfigBuilder};
+
+    fn create_ring(replication_factor: usize) -> FilterRing {
+        let config = RingConfigBuilder::default()
+            .replication_factor(replication_factor)
+            .build()
+            .unwrap();
+        let mut ring = FilterRing::new(config).unwrap();
+        for name in ["store-a", "store-b", "store-c"] {
+            ring.add(name, Arc::new(create_test_filter(10_000, 2, 0.01)));
         }
+        ring
+    }
 
-        // All keys should be present
-        for i in 0..100 {
-            let key = format!("cache_key_{}", i);
-            assert!(filter.contains(key.as_bytes()).unwrap());
+    fn keys() -> Vec<Vec<u8>> {
+        (0..1000).map(|i| format!("key-{i}").into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_keys_stored_on_replicas() {
+        let ring = create_ring(2);
+        for key in keys() {
+            ring.insert(&key).unwrap();
+            let replicas = ring.replicas_for(&key);
+            assert_eq!(replicas.len(), 2);
+            assert_ne!(replicas[0], replicas[1]);
+            for name in ring.names() {
+                let stored = ring.get(name).unwrap().contains(&key).unwrap();
+                assert!(!replicas.contains(&name) || stored);
+            }
+            assert!(ring.contains(&key).unwrap());
+        }
+        // Every store owns a share of the primaries
+        for name in ring.names() {
+            let primaries = keys()
+                .iter()
+                .filter(|key| ring.replicas_for(key)[0] == name)
+                .count();
+            assert!(primaries > 150, "{name} owns {primaries} keys");
+        }
+    }
+
+    #[test]
+    fn test_removing_a_store_only_moves_its_keys() {
+        let mut ring = create_ring(1);
+        let before: Vec<String> = keys()
+            .iter()
+            .map(|key| ring.replicas_for(key)[0].to_string())
+            .collect();
+        assert!(ring.remove("store-b").is_some());
+        for (key, owner) in keys().iter().zip(before) {
+            let now = ring.replicas_for(key)[0];
+            if owner != "store-b" {
+                assert_eq!(now, owner);
+            } else {
+                assert_ne!(now, "store-b");
+            }
         }
     }
 
+    #[test]
+    fn test_empty_ring_and_invalid_config() {
+        let ring: FilterRing =
+            FilterRing::new(RingConfigBuilder::default().build().unwrap())
+                .unwrap();
+        assert!(ring.replicas_for(b"key").is_empty());
+        assert!(ring.insert(b"key").is_err());
+
+        let config = RingConfigBuilder::default()
+            .virtual_nodes(0_usize)
+            .build()
+            .unwrap();
+        assert!(FilterRing::<ExpiringBloomFilter>::new(config).is_err());
+    }
+}
+
+mod oplog_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::oplog::OpLogBatch;
+
     #[tokio::test]
-    async fn test_time_based_expiration_workflow() {
-        let filter = create_short_expiry_filter(100, 3, 100); // 3 levels for better testing
+    async fn test_follower_replays_leader_inserts() {
+        let (leader, clock) = create_manual_clock_filter(1000, 2, 100);
+        let log = leader.replication_log(1000).unwrap();
+        let follower = ExpiringBloomFilter::with_clock(
+            ExpiringFilterConfigBuilder::default()
+                .capacity_per_level(1000_usize)
+                .target_fpr(0.01)
+                .num_levels(2_usize)
+                .level_duration(Duration::from_millis(100))
+                .build()
+                .unwrap(),
+            clock.clone(),
+        )
+        .unwrap();
+
+        leader.insert(b"first").unwrap();
+        leader.insert_bulk(&[&b"second"[..], b"third"]).unwrap();
+
+        let mut cursor = 0;
+        let batch = log.read_from(cursor, 2).unwrap();
+        assert_eq!(batch.entries.len(), 2);
+        cursor = batch.next_seq;
+        let shipped = OpLogBatch::from_bytes(&batch.to_bytes().unwrap()).unwrap();
+        assert_eq!(follower.apply_oplog(&shipped).unwrap(), 2);
+        let batch = log.read_from(cursor, 100).unwrap();
+        assert_eq!(follower.apply_oplog(&batch).unwrap(), 1);
+        assert_eq!(batch.next_seq, 3);
+        assert!(
+            log.read_from(batch.next_seq, 100)
+                .unwrap()
+                .entries
+                .is_empty()
+        );
 
-        // Insert items at different times
-        filter.insert(b"early_item").unwrap();
+        for item in [&b"first"[..], b"second", b"third"] {
+            assert!(follower.contains(item).unwrap());
+        }
+        assert!(!follower.contains(b"never-inserted").unwrap());
 
-        thread::sleep(Duration::from_millis(110)); // Wait for rotation
-        filter.cleanup_expired_levels().await.unwrap();
+        // Replayed entries expire on the follower's own schedule
+        clock.advance(Duration::from_millis(250));
+        follower.cleanup_expired_levels().await.unwrap();
+        assert!(!follower.contains(b"first").unwrap());
+    }
 
-        // early_item should still be present (now in previous level)
-        assert!(filter.contains(b"early_item").unwrap());
+    #[test]
+    fn test_trimmed_log_and_layout_mismatch() {
+        let leader = create_test_filter(1000, 2, 0.01);
+        let log = leader.replication_log(2).unwrap();
+        for i in 0..5 {
+            leader.insert(format!("key-{i}").as_bytes()).unwrap();
+        }
+        assert_eq!(log.oldest_seq().unwrap(), 3);
+        assert_eq!(log.next_seq().unwrap(), 5);
+        assert!(log.read_from(0, 10).is_err());
+
+        let batch = log.read_from(3, 10).unwrap();
+        let other = create_test_filter(2000, 2, 0.01);
+        assert!(other.apply_oplog(&batch).is_err());
+        assert!(leader.replication_log(0).is_err());
+    }
+}
 
-        // Insert new item in new current level
-        filter.insert(b"mid_item").unwrap();
+mod delta_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::union::{ChunkDelta, DELTA_CHUNK_BYTES};
 
-        thread::sleep(Duration::from_millis(110)); // Wait for another rotation
-        filter.cleanup_expired_levels().await.unwrap();
+    #[test]
+    fn test_in_memory_delta_and_bounds() {
+        let (a, b) = (
+            create_test_filter(10_000, 2, 0.01),
+            create_test_filter(10_000, 2, 0.01),
+        );
+        a.insert(b"key").unwrap();
+        let delta = a.compute_delta(&b).unwrap();
+        assert_eq!(delta.chunk_bytes, DELTA_CHUNK_BYTES);
+        let delta = ChunkDelta::from_bytes(&delta.to_bytes().unwrap()).unwrap();
+        assert_eq!(b.apply_delta(&delta).unwrap(), 1);
+        assert!(b.contains(b"key").unwrap());
+
+        let mut corrupt = delta.clone();
+        corrupt.levels[0].chunks[0].0 = usize::MAX;
+        assert!(b.apply_delta(&corrupt).is_err());
+        assert!(
+            a.compute_delta(&create_test_filter(20_000, 2, 0.01))
+                .is_err()
+        );
+    }
+}
 
-        // Both should still be present (in different historical levels)
-        assert!(filter.contains(b"early_item").unwrap());
-        assert!(filter.contains(b"mid_item").unwrap());
+mod shipping_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::shipping::{
+        DirectorySink, ReadReplica, ShippedSnapshot, SnapshotPublisher,
+    };
+    use std::sync::mpsc;
 
-        thread::sleep(Duration::from_millis(110)); // Third rotation
-        filter.cleanup_expired_levels().await.unwrap();
+    #[tokio::test]
+    async fn test_replica_follows_full_and_incremental_snapshots() {
+        let (leader, clock) = create_manual_clock_filter(1000, 2, 100);
+        let leader = Arc::new(leader);
+        let (tx, rx) = mpsc::channel();
+        let publisher =
+            SnapshotPublisher::new(leader.clone(), tx, 256, 10).unwrap();
+        let replica = ReadReplica::new();
+        assert!(replica.contains(b"old").is_err());
+
+        leader.insert(b"old").unwrap();
+        let full = publisher.publish().unwrap();
+        assert!(full.is_full());
+        replica.apply(&rx.recv().unwrap()).unwrap();
+        assert!(replica.contains(b"old").unwrap());
+        assert!(!replica.contains(b"new").unwrap());
+
+        leader.insert(b"new").unwrap();
+        let incremental = publisher.publish().unwrap();
+        assert_eq!(incremental.base_seq, Some(full.seq));
+        let shipped =
+            ShippedSnapshot::from_bytes(&rx.recv().unwrap().to_bytes().unwrap())
+                .unwrap();
+        replica.apply(&shipped).unwrap();
+        assert!(replica.contains(b"new").unwrap());
+        assert_eq!(replica.seq().unwrap(), Some(incremental.seq));
+
+        // Rotating "old" out clears chunks, which incrementals carry too
+        for _ in 0..4 {
+            clock.advance(Duration::from_millis(101));
+            leader.cleanup_expired_levels().await.unwrap();
+        }
+        assert!(!leader.contains(b"old").unwrap());
+        publisher.publish().unwrap();
+        replica.apply(&rx.recv().unwrap()).unwrap();
+        assert!(!replica.contains(b"old").unwrap());
+
+        // A gap is rejected and the replica keeps serving its last state
+        leader.insert(b"later").unwrap();
+        publisher.publish().unwrap();
+        rx.recv().unwrap();
+        leader.insert(b"latest").unwrap();
+        publisher.publish().unwrap();
+        let after_gap = rx.recv().unwrap();
+        assert!(replica.apply(&after_gap).is_err());
+        assert!(!replica.contains(b"latest").unwrap());
+    }
 
-        // early_item should be expired now (was in oldest level)
-        // mid_item still present
-        assert!(filter.contains(b"mid_item").unwrap());
+    #[test]
+    fn test_replica_rejects_out_of_range_chunks() {
+        let leader = Arc::new(create_test_filter(1000, 2, 0.01));
+        let (tx, rx) = mpsc::channel();
+        let publisher =
+            SnapshotPublisher::new(leader.clone(), tx, 256, 10).unwrap();
+        leader.insert(b"key").unwrap();
+        publisher.publish().unwrap();
+        let full = rx.recv().unwrap();
+        let chunk_count =
+            full.config.level_bit_size().div_ceil(full.chunk_bytes * 8);
+
+        let replica = ReadReplica::new();
+        for (chunk_idx, len) in [
+            // Overflows the bit offset
+            (usize::MAX / 8, 1),
+            // One past the last chunk
+            (chunk_count, 1),
+            // Spills into the next chunk
+            (0, full.chunk_bytes + 1),
+        ] {
+            let mut corrupt = full.clone();
+            corrupt.chunks.push((0, chunk_idx, vec![0xff; len]));
+            assert!(replica.apply(&corrupt).is_err());
+        }
+        assert_eq!(replica.seq().unwrap(), None);
+
+        replica.apply(&full).unwrap();
+        assert!(replica.contains(b"key").unwrap());
     }
 
     #[test]
-    fn test_mixed_operations() {
-        let filter = create_test_filter(1000, 3, 0.01);
+    fn test_directory_catch_up_and_leader_restart() {
+        let dir = std::env::temp_dir()
+            .join(format!("ebloom_shipping_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let leader = Arc::new(create_test_filter(1000, 2, 0.01));
+        let replica = ReadReplica::new();
+
+        let publisher = SnapshotPublisher::new(
+            leader.clone(),
+            DirectorySink::new(&dir).unwrap(),
+            256,
+            3,
+        )
+        .unwrap();
+        for i in 0..5 {
+            leader.insert(format!("key-{i}").as_bytes()).unwrap();
+            publisher.publish().unwrap();
+        }
+        // The full snapshot at seq 3 pruned everything before it
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(replica.catch_up(&dir).unwrap(), 2);
+        assert_eq!(replica.seq().unwrap(), Some(4));
+        assert_eq!(replica.catch_up(&dir).unwrap(), 0);
+        for i in 0..5 {
+            assert!(replica.contains(format!("key-{i}").as_bytes()).unwrap());
+        }
 
-        // Mix of operations
-        filter.insert(b"item1").unwrap();
-        assert!(filter.contains(b"item1").unwrap());
+        // A restarted leader continues the sequence from the directory
+        let publisher = SnapshotPublisher::new(
+            leader.clone(),
+            DirectorySink::new(&dir).unwrap(),
+            256,
+            3,
+        )
+        .unwrap();
+        leader.insert(b"after-restart").unwrap();
+        let snapshot = publisher.publish().unwrap();
+        assert_eq!(snapshot.seq, 5);
+        assert!(snapshot.is_full());
+        assert_eq!(replica.catch_up(&dir).unwrap(), 1);
+        assert!(replica.contains(b"after-restart").unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
 
-        filter.insert(b"item2").unwrap();
-        filter.insert(b"item3").unwrap();
+mod bulk_import_tests {
+    use super::*;
 
-        assert_eq!(filter.total_insert_count(), 3);
+    #[test]
+    fn test_parallel_import_matches_insert_bulk() {
+        let keys = generate_test_items(200_000);
+        let imported = create_test_filter(200_000, 2, 0.01);
+        assert_eq!(imported.bulk_import(&keys, 4).unwrap(), 200_000);
+        let inserted = create_test_filter(200_000, 2, 0.01);
+        let refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+        inserted.insert_bulk(&refs).unwrap();
+
+        assert_eq!(imported.total_insert_count(), 200_000);
+        assert_eq!(
+            imported.fill_ratio(0).unwrap(),
+            inserted.fill_ratio(0).unwrap()
+        );
+        assert!(
+            imported
+                .contains_bulk(&refs)
+                .unwrap()
+                .into_iter()
+                .all(|found| found)
+        );
+    }
 
-        filter.clear().unwrap();
-        assert_eq!(filter.total_insert_count(), 0);
+    #[test]
+    fn test_import_from_iterator_with_zero_parallelism() {
+        let filter = create_test_filter(1000, 2, 0.01);
+        let keys = (0..500).map(|i| format!("key-{i}").into_bytes());
+        assert_eq!(filter.bulk_import(keys, 0).unwrap(), 500);
+        assert!(filter.contains(b"key-499").unwrap());
+        assert_eq!(filter.bulk_import(Vec::<Vec<u8>>::new(), 8).unwrap(), 0);
+    }
+}
 
-        filter.insert(b"new_item").unwrap();
-        assert!(filter.contains(b"new_item").unwrap());
-        assert_eq!(filter.total_insert_count(), 1);
+#[cfg(feature = "test_support")]
+mod test_support_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::storage::ExpiringStorageBackend;
+    use probabilistic_rs::ebloom::test_support::{
+        MOCK_EPOCH_MS, MockStorage, StorageCall, StorageOp, expire_all,
+        filter_with_mock_clock, filter_with_mock_storage, seeded_keys,
+    };
+
+    fn config() -> probabilistic_rs::ebloom::config::ExpiringFilterConfig {
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_expiry_without_sleeping() {
+        let keys = seeded_keys(42, 100);
+        assert_eq!(keys, seeded_keys(42, 100));
+        assert_ne!(keys, seeded_keys(43, 100));
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), 100);
+
+        let (filter, clock) = filter_with_mock_clock(config()).unwrap();
+        assert_eq!(clock.now_ms().unwrap(), MOCK_EPOCH_MS);
+        for key in &keys {
+            filter.insert(key).unwrap();
+        }
+        assert!(keys.iter().all(|key| filter.contains(key).unwrap()));
+
+        expire_all(&filter, &clock).await.unwrap();
+        assert!(keys.iter().all(|key| !filter.contains(key).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_mock_storage_faults_and_reload() {
+        let (filter, clock, storage) =
+            filter_with_mock_storage(config()).await.unwrap();
+        assert_eq!(filter.backend_kind(), "custom");
+        filter.insert(b"persisted").unwrap();
+
+        storage.fail_next(StorageOp::CommitSnapshot, 1);
+        assert!(filter.save_snapshot().await.is_err());
+        assert!(storage.dirty_chunks(0).is_empty());
+        // The failed snapshot's chunks are retried by the next one
+        filter.save_snapshot().await.unwrap();
+        assert!(!storage.dirty_chunks(0).is_empty());
+        assert_eq!(storage.calls(StorageOp::CommitSnapshot), 2);
+
+        storage.fail_all(true);
+        assert!(
+            ExpiringBloomFilter::load_with_storage(
+                clock.clone(),
+                storage.clone()
+            )
+            .await
+            .is_err()
+        );
+        storage.fail_all(false);
+        let loaded = ExpiringBloomFilter::load_with_storage(
+            clock.clone(),
+            storage.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(loaded.contains(b"persisted").unwrap());
+        assert!(!loaded.contains(b"never-inserted").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mock_storage_call_log_errors_and_latency() {
+        let storage = MockStorage::new();
+        storage
+            .save_level_chunks(1, &[(0, vec![0xFF])])
+            .await
+            .unwrap();
+
+        storage.fail_next_with(
+            StorageOp::LoadLevelChunks,
+            EbloomError::LockError("busy".to_string()),
+        );
+        assert_eq!(
+            storage.load_level_chunks(1).await,
+            Err(EbloomError::LockError("busy".to_string()))
+        );
+        assert_eq!(
+            storage.load_level_chunks(1).await.unwrap(),
+            vec![(0, vec![0xFF])]
+        );
+        assert_eq!(
+            storage.call_log(),
+            vec![
+                StorageCall {
+                    op: StorageOp::SaveLevelChunks,
+                    level: Some(1),
+                    failed: false,
+                },
+                StorageCall {
+                    op: StorageOp::LoadLevelChunks,
+                    level: Some(1),
+                    failed: true,
+                },
+                StorageCall {
+                    op: StorageOp::LoadLevelChunks,
+                    level: Some(1),
+                    failed: false,
+                },
+            ]
+        );
+
+        storage.clear_calls();
+        storage
+            .set_latency(StorageOp::LoadCurrentLevel, Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        storage.load_current_level().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        storage.set_latency(StorageOp::LoadCurrentLevel, Duration::ZERO);
+        storage.load_current_level().await.unwrap();
+        assert_eq!(storage.calls(StorageOp::LoadCurrentLevel), 2);
+        assert_eq!(storage.calls(StorageOp::LoadLevelChunks), 0);
+    }
+
+    #[tokio::test]
+    async fn test_retention_simulation_band() {
+        use probabilistic_rs::ebloom::test_support::retention::{
+            RetentionWorkload, simulate_retention,
+        };
+
+        let workload = RetentionWorkload {
+            inserts: 300,
+            insert_every: Duration::from_secs(1),
+            step: Duration::from_secs(1),
+            seed: 7,
+        };
+        let report = simulate_retention(config(), workload).await.unwrap();
+
+        assert_eq!(report.configured_ms, 180_000);
+        assert_eq!(report.expired + report.censored, 300);
+        // Keys live between two and three levels' worth of time
+        let min = report.min_ms.unwrap();
+        let max = report.max_ms.unwrap();
+        assert!(min >= 120_000, "min {min}");
+        assert!(max <= 180_000 + report.resolution_ms, "max {max}");
+        assert!(report.early_by_max_ms() <= 60_000);
+        assert!(report.late_by_max_ms() <= report.resolution_ms);
     }
 }
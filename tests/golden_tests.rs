@@ -0,0 +1,272 @@
+//! Golden-file tests for the persisted format
+//!
+//! `tests/golden/` holds canonical encodings of configs, level metadata and
+//! snapshot chunks. Each test checks that current code writes the same bytes
+//! and still loads the committed ones. A failure means databases written by
+//! an earlier release will not load; if the format change is intended,
+//! regenerate the files with
+//! `UPDATE_GOLDEN=1 cargo test --test golden_tests` and call it out in the
+//! release notes.
+#[cfg(all(feature = "fjall", feature = "test_support"))]
+mod golden_tests {
+    use probabilistic_rs::bloom::{
+        BloomFilter, BloomFilterConfig, BloomFilterConfigBuilder, BloomFilterOps,
+        BloomFilterStats, PersistenceConfigBuilder, StorageBackend,
+        storage::FjallBackend,
+    };
+    use probabilistic_rs::ebloom::{
+        config::{
+            ExpiringFilterConfig, ExpiringFilterConfigBuilder,
+            ExpiringPersistenceConfigBuilder, LevelMetadata, RotationReason,
+        },
+        filter::ExpiringBloomFilter,
+        storage::{ExpiringStorageBackend, SnapshotBatch},
+        test_support::{
+            MOCK_EPOCH_MS, MockStorage, filter_with_mock_storage, mock_clock,
+            seeded_keys,
+        },
+        traits::ExpiringBloomFilterOps,
+    };
+    use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+    const KEY_SEED: u64 = 215;
+    const KEY_COUNT: usize = 200;
+
+    struct TestDb {
+        path: PathBuf,
+    }
+
+    impl TestDb {
+        fn new(test_name: &str) -> Self {
+            let path = PathBuf::from(format!("test_golden_{}.fjall", test_name));
+            if path.exists() {
+                let _ = fs::remove_dir_all(&path);
+            }
+            Self { path }
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            if self.path.exists() {
+                let _ = fs::remove_dir_all(&self.path);
+            }
+        }
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name)
+    }
+
+    /// Compare `bytes` with the golden file, or rewrite it under
+    /// `UPDATE_GOLDEN`. Returns the golden bytes.
+    fn check_golden(name: &str, bytes: &[u8]) -> Vec<u8> {
+        let path = golden_path(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, bytes).unwrap();
+        }
+        let golden = fs::read(&path).unwrap_or_else(|e| {
+            panic!("Failed to read golden file {path:?}: {e}")
+        });
+        assert_eq!(
+            bytes,
+            golden.as_slice(),
+            "{name} no longer matches the committed encoding"
+        );
+        golden
+    }
+
+    fn encode<T: bincode::Encode>(value: &T) -> Vec<u8> {
+        bincode::encode_to_vec(value, bincode::config::standard()).unwrap()
+    }
+
+    fn decode<T: bincode::Decode<()>>(bytes: &[u8]) -> T {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .unwrap()
+            .0
+    }
+
+    fn bloom_config(db_path: PathBuf) -> BloomFilterConfig {
+        let persistence = PersistenceConfigBuilder::default()
+            .db_path(db_path)
+            .chunk_size_bytes(256)
+            .snapshot_interval(Duration::from_secs(60))
+            .auto_snapshot(false)
+            .build()
+            .unwrap();
+
+        BloomFilterConfigBuilder::default()
+            .capacity(1000)
+            .false_positive_rate(0.01)
+            .persistence(Some(persistence))
+            .build()
+            .unwrap()
+    }
+
+    fn expiring_config() -> ExpiringFilterConfig {
+        let persistence = ExpiringPersistenceConfigBuilder::default()
+            .db_path(PathBuf::from("golden.fjall"))
+            .chunk_size_bytes(256_usize)
+            .build()
+            .unwrap();
+
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .persistence(Some(persistence))
+            .build()
+            .unwrap()
+    }
+
+    fn level_metadata() -> Vec<LevelMetadata> {
+        vec![
+            LevelMetadata {
+                created_at: MOCK_EPOCH_MS,
+                insert_count: 200,
+                last_snapshot_at: MOCK_EPOCH_MS + 1_000,
+                rotation_reason: RotationReason::Created,
+            },
+            LevelMetadata {
+                created_at: MOCK_EPOCH_MS + 60_000,
+                insert_count: 0,
+                last_snapshot_at: 0,
+                rotation_reason: RotationReason::Time,
+            },
+            LevelMetadata {
+                created_at: 0,
+                insert_count: u64::MAX,
+                last_snapshot_at: u64::MAX,
+                rotation_reason: RotationReason::Saturation,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_bloom_config_format() {
+        let config = bloom_config(PathBuf::from("golden.fjall"));
+        let golden =
+            check_golden("bloom_config.bin", &config.to_bytes().unwrap());
+
+        let loaded = BloomFilterConfig::from_bytes(&golden).unwrap();
+        loaded.validate().unwrap();
+        assert_eq!(loaded.capacity, 1000);
+        assert_eq!(loaded.false_positive_rate, 0.01);
+        let persistence = loaded.persistence.unwrap();
+        assert_eq!(persistence.db_path, PathBuf::from("golden.fjall"));
+        assert_eq!(persistence.chunk_size_bytes, 256);
+        assert_eq!(persistence.snapshot_interval, Duration::from_secs(60));
+        assert!(!persistence.auto_snapshot);
+    }
+
+    #[test]
+    fn test_expiring_config_format() {
+        let config = expiring_config();
+        let golden =
+            check_golden("expiring_config.bin", &config.to_bytes().unwrap());
+
+        let loaded = ExpiringFilterConfig::from_bytes(&golden).unwrap();
+        loaded.validate().unwrap();
+        assert_eq!(loaded.to_bytes().unwrap(), golden);
+        assert_eq!(loaded.capacity_per_level, 1000);
+        assert_eq!(loaded.num_levels, 3);
+        assert_eq!(loaded.level_duration, Duration::from_secs(60));
+        assert_eq!(loaded.persistence.unwrap().chunk_size_bytes, 256);
+    }
+
+    #[test]
+    fn test_level_metadata_format() {
+        let metadata = level_metadata();
+        let golden = check_golden("level_metadata.bin", &encode(&metadata));
+
+        let loaded: Vec<LevelMetadata> = decode(&golden);
+        assert_eq!(loaded.len(), metadata.len());
+        for (loaded, expected) in loaded.iter().zip(&metadata) {
+            assert_eq!(loaded.created_at, expected.created_at);
+            assert_eq!(loaded.insert_count, expected.insert_count);
+            assert_eq!(loaded.last_snapshot_at, expected.last_snapshot_at);
+            assert_eq!(loaded.rotation_reason, expected.rotation_reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bloom_chunks_format() {
+        let keys = seeded_keys(KEY_SEED, KEY_COUNT);
+
+        let written = TestDb::new("bloom_written");
+        let filter = BloomFilter::create(bloom_config(written.path.clone()))
+            .await
+            .unwrap();
+        for key in &keys {
+            filter.insert(key).unwrap();
+        }
+        let chunks = filter.extract_dirty_chunks();
+        let golden = check_golden("bloom_chunks.bin", &encode(&chunks));
+        let fill_ratio = filter.fill_ratio();
+        drop(filter);
+
+        // Load a database holding only the golden chunks
+        let stored = TestDb::new("bloom_stored");
+        {
+            let backend = FjallBackend::new(stored.path.clone()).await.unwrap();
+            backend
+                .save_config(&bloom_config(stored.path.clone()))
+                .await
+                .unwrap();
+            let golden_chunks: Vec<(usize, Vec<u8>)> = decode(&golden);
+            backend.save_snapshot(&golden_chunks).await.unwrap();
+        }
+
+        let loaded = BloomFilter::load(stored.path.clone()).await.unwrap();
+        for key in &keys {
+            assert!(loaded.contains(key).unwrap());
+        }
+        assert_eq!(loaded.fill_ratio(), fill_ratio);
+        assert_eq!(loaded.insert_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_expiring_chunks_format() {
+        let keys = seeded_keys(KEY_SEED, KEY_COUNT);
+
+        let (filter, _clock, storage) =
+            filter_with_mock_storage(expiring_config()).await.unwrap();
+        for key in &keys {
+            filter.insert(key).unwrap();
+        }
+        filter.save_snapshot().await.unwrap();
+        // The current level is snapshotted as dirty chunks
+        let chunks = storage.dirty_chunks(0);
+        let golden = check_golden("expiring_chunks.bin", &encode(&chunks));
+
+        // Load a backend holding only the golden chunks and metadata
+        let stored = Arc::new(MockStorage::new());
+        stored.save_config(&expiring_config()).await.unwrap();
+        stored.save_current_level(0).await.unwrap();
+        let metadata =
+            check_golden("level_metadata.bin", &encode(&level_metadata()));
+        stored
+            .commit_snapshot(SnapshotBatch {
+                dirty_chunks: vec![(0, decode(&golden))],
+                metadata: Some(decode(&metadata)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let loaded = ExpiringBloomFilter::load_with_storage(mock_clock(), stored)
+            .await
+            .unwrap();
+        for key in &keys {
+            assert!(loaded.contains(key).unwrap());
+        }
+        assert_eq!(
+            loaded.level_fill_ratio(0).unwrap(),
+            filter.level_fill_ratio(0).unwrap()
+        );
+    }
+}
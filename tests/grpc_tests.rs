@@ -0,0 +1,186 @@
+#![cfg(feature = "grpc")]
+
+use probabilistic_rs::ebloom::{
+    config::ExpiringFilterConfigBuilder, filter::ExpiringBloomFilter,
+};
+use probabilistic_rs::grpc::{
+    ExpiringBloomService,
+    proto::{
+        BulkInsertRequest, ContainsRequest, InsertRequest, RotateRequest,
+        StatsRequest, expiring_bloom_server::ExpiringBloom,
+    },
+};
+use std::{sync::Arc, time::Duration};
+use tonic::Request;
+
+fn create_service() -> ExpiringBloomService {
+    let config = ExpiringFilterConfigBuilder::default()
+        .capacity_per_level(1000_usize)
+        .target_fpr(0.01)
+        .num_levels(3_usize)
+        .level_duration(Duration::from_secs(60))
+        .build()
+        .unwrap();
+    ExpiringBloomService::new(Arc::new(ExpiringBloomFilter::new(config).unwrap()))
+}
+
+async fn contains(service: &ExpiringBloomService, item: &[u8]) -> bool {
+    service
+        .contains(Request::new(ContainsRequest {
+            item: item.to_vec(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .present
+}
+
+#[cfg(test)]
+mod grpc_service_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_insert_and_contains() {
+        let service = create_service();
+        service
+            .insert(Request::new(InsertRequest {
+                item: b"hello".to_vec(),
+            }))
+            .await
+            .unwrap();
+
+        assert!(contains(&service, b"hello").await);
+        assert!(!contains(&service, b"world").await);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_insert_and_stats() {
+        let service = create_service();
+        let items: Vec<Vec<u8>> =
+            (0..50).map(|i| format!("item_{i}").into_bytes()).collect();
+        let response = service
+            .bulk_insert(Request::new(BulkInsertRequest {
+                items: items.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.inserted, 50);
+        for item in &items {
+            assert!(contains(&service, item).await);
+        }
+
+        let stats = service
+            .stats(Request::new(StatsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.capacity_per_level, 1000);
+        assert_eq!(stats.total_insert_count, 50);
+        assert_eq!(stats.levels.len(), 3);
+        assert_eq!(stats.levels[0].insert_count, 50);
+        assert_eq!(stats.snapshot_lag_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_expires_oldest_window() {
+        let service = create_service();
+        service
+            .insert(Request::new(InsertRequest {
+                item: b"old".to_vec(),
+            }))
+            .await
+            .unwrap();
+
+        for expected in [1, 2, 0] {
+            let response = service
+                .rotate(Request::new(RotateRequest {}))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(response.current_level, expected);
+        }
+        assert!(!contains(&service, b"old").await);
+    }
+}
+
+#[cfg(all(test, feature = "fjall"))]
+mod remote_storage_tests {
+    use super::*;
+    use probabilistic_rs::ebloom::{
+        config::ExpiringPersistenceConfigBuilder, storage::FjallExpiringBackend,
+        traits::ExpiringBloomFilterOps,
+    };
+    use probabilistic_rs::grpc::storage::{
+        ExpiringStorageService, GrpcStorageBackend,
+    };
+
+    async fn serve_storage(db_path: &str) -> String {
+        let _ = std::fs::remove_dir_all(db_path);
+        let backend = FjallExpiringBackend::new(db_path.into(), 8).await.unwrap();
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(
+                    ExpiringStorageService::new(Arc::new(backend)).into_server(),
+                )
+                .serve(addr),
+        );
+        format!("http://{addr}")
+    }
+
+    async fn connect(endpoint: &str) -> GrpcStorageBackend {
+        for _ in 0..50 {
+            if let Ok(backend) = GrpcStorageBackend::connect(endpoint).await {
+                return backend;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("storage server at {endpoint} did not come up");
+    }
+
+    #[tokio::test]
+    async fn test_filter_persists_through_remote_storage() {
+        let db_path = "test_grpc_remote_storage.fjall";
+        let endpoint = serve_storage(db_path).await;
+        let config = ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(1000_usize)
+            .target_fpr(0.01)
+            .num_levels(3_usize)
+            .level_duration(Duration::from_secs(60))
+            .persistence(Some(
+                ExpiringPersistenceConfigBuilder::default()
+                    .db_path(std::path::PathBuf::from("unused"))
+                    .chunk_size_bytes(1024_usize)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+
+        let filter =
+            ExpiringBloomFilter::create_remote(config, connect(&endpoint).await)
+                .await
+                .unwrap();
+        assert_eq!(filter.backend_kind(), "grpc");
+        filter.insert(b"before-rotation").unwrap();
+        filter.rotate_levels().await.unwrap();
+        filter.insert(b"after-rotation").unwrap();
+        filter.save_snapshot().await.unwrap();
+        assert!(filter.health().unwrap().writable);
+        drop(filter);
+
+        let loaded = ExpiringBloomFilter::load_remote(connect(&endpoint).await)
+            .await
+            .unwrap();
+        assert_eq!(loaded.get_active_level(), 1);
+        assert!(loaded.contains(b"before-rotation").unwrap());
+        assert!(loaded.contains(b"after-rotation").unwrap());
+        assert!(!loaded.contains(b"never-inserted").unwrap());
+
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+}
@@ -0,0 +1,128 @@
+//! loom models of the filter core
+//!
+//! Run with:
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg ebloom_loom" cargo test --release --test loom_tests
+//! ```
+#[cfg(ebloom_loom)]
+mod loom_tests {
+    use loom::future::block_on;
+    use loom::thread;
+    use probabilistic_rs::ebloom::{
+        config::{ExpiringFilterConfig, ExpiringFilterConfigBuilder},
+        filter::ExpiringBloomFilter,
+        test_support::{filter_with_mock_clock, filter_with_mock_storage},
+        traits::{ExpiringBloomFilterOps, ExpiringBloomFilterStats},
+    };
+    use std::{sync::Arc, time::Duration};
+
+    /// One word per level and one snapshot chunk keep the state space small
+    fn config() -> ExpiringFilterConfig {
+        ExpiringFilterConfigBuilder::default()
+            .capacity_per_level(4usize)
+            .target_fpr(0.1)
+            .num_levels(2usize)
+            .level_duration(Duration::from_secs(60))
+            .build()
+            .unwrap()
+    }
+
+    /// Explore interleavings with at most three preemptions, unless
+    /// `LOOM_MAX_PREEMPTIONS` says otherwise
+    fn model<F>(f: F)
+    where
+        F: Fn() + Sync + Send + 'static,
+    {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound.get_or_insert(3);
+        builder.check(f);
+    }
+
+    #[test]
+    fn insert_racing_rotation_stays_visible() {
+        model(|| {
+            let (filter, _clock) = filter_with_mock_clock(config()).unwrap();
+            let filter = Arc::new(filter);
+
+            let inserter = {
+                let filter = filter.clone();
+                thread::spawn(move || filter.insert(b"racing").unwrap())
+            };
+            block_on(filter.rotate_levels()).unwrap();
+            inserter.join().unwrap();
+
+            // Whichever level the insert read, it is not the one cleared
+            assert!(filter.contains(b"racing").unwrap());
+            assert_eq!(filter.total_insert_count(), 1);
+        });
+    }
+
+    #[test]
+    fn contains_during_rotation_sees_earlier_inserts() {
+        model(|| {
+            let (filter, _clock) = filter_with_mock_clock(config()).unwrap();
+            filter.insert(b"earlier").unwrap();
+            let filter = Arc::new(filter);
+
+            let reader = {
+                let filter = filter.clone();
+                thread::spawn(move || filter.contains(b"earlier").unwrap())
+            };
+            block_on(filter.rotate_levels()).unwrap();
+
+            assert!(reader.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn insert_into_new_level_reaches_next_snapshot() {
+        model(|| {
+            let (filter, clock, storage) =
+                block_on(filter_with_mock_storage(config())).unwrap();
+            let filter = Arc::new(filter);
+
+            let rotator = {
+                let filter = filter.clone();
+                thread::spawn(move || block_on(filter.rotate_levels()).unwrap())
+            };
+            // An insert that reads the new level must not have its dirty
+            // mark dropped by the rest of the rotation
+            let saw_new_level = filter.get_active_level() == 1;
+            filter.insert(b"racing").unwrap();
+            rotator.join().unwrap();
+            block_on(filter.save_snapshot()).unwrap();
+
+            let loaded =
+                block_on(ExpiringBloomFilter::load_with_storage(clock, storage))
+                    .unwrap();
+            if saw_new_level {
+                assert!(loaded.contains(b"racing").unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn insert_racing_snapshot_reaches_a_snapshot() {
+        model(|| {
+            let (filter, clock, storage) =
+                block_on(filter_with_mock_storage(config())).unwrap();
+            let filter = Arc::new(filter);
+
+            let snapshotter = {
+                let filter = filter.clone();
+                thread::spawn(move || block_on(filter.save_snapshot()).unwrap())
+            };
+            filter.insert(b"racing").unwrap();
+            snapshotter.join().unwrap();
+            block_on(filter.save_snapshot()).unwrap();
+
+            // Bits and count land in the racing snapshot or the next one
+            let loaded =
+                block_on(ExpiringBloomFilter::load_with_storage(clock, storage))
+                    .unwrap();
+            assert!(loaded.contains(b"racing").unwrap());
+            assert_eq!(loaded.total_insert_count(), 1);
+        });
+    }
+}
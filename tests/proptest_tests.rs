@@ -0,0 +1,126 @@
+#[cfg(feature = "proptest")]
+mod proptest_tests {
+    use probabilistic_rs::ebloom::{
+        clock::Clock,
+        filter::ExpiringBloomFilter,
+        test_support::{
+            filter_with_mock_clock, filter_with_mock_storage,
+            strategies::{Model, apply_op, scenario_strategy},
+        },
+        traits::{ExpiringBloomFilterOps, ExpiringBloomFilterStats},
+    };
+    use proptest::prelude::*;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    /// Level ages from the current level backwards, in rotation order
+    fn ages_newest_first(filter: &ExpiringBloomFilter) -> Vec<Option<u64>> {
+        let stats = filter.stats().unwrap();
+        let num_levels = stats.levels.len();
+        (0..num_levels)
+            .map(|age| {
+                let level = (stats.current_level + num_levels - age) % num_levels;
+                stats.levels[level].age_ms
+            })
+            .collect()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn no_false_negatives_within_window(
+            (config, ops) in scenario_strategy(48)
+        ) {
+            let runtime = runtime();
+            let (filter, clock) = filter_with_mock_clock(config).unwrap();
+            let mut model = Model::new(filter.config());
+
+            for op in &ops {
+                runtime
+                    .block_on(apply_op(&filter, &clock, &mut model, op))
+                    .unwrap();
+                let now_ms = clock.now_ms().unwrap();
+                for key in model.must_contain(now_ms) {
+                    prop_assert!(
+                        filter.contains(key).unwrap(),
+                        "lost {:?} after {:?}",
+                        key,
+                        op
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn level_timestamps_are_monotone(
+            (config, ops) in scenario_strategy(48)
+        ) {
+            let runtime = runtime();
+            let (filter, clock) = filter_with_mock_clock(config).unwrap();
+            let mut model = Model::new(filter.config());
+
+            for op in &ops {
+                runtime
+                    .block_on(apply_op(&filter, &clock, &mut model, op))
+                    .unwrap();
+                let ages = ages_newest_first(&filter);
+                prop_assert!(ages[0].is_some(), "current level unused");
+                // Older levels are older, and unused levels only follow
+                // used ones
+                for pair in ages.windows(2) {
+                    let ordered = match (pair[0], pair[1]) {
+                        (Some(newer), Some(older)) => newer <= older,
+                        (None, Some(_)) => false,
+                        _ => true,
+                    };
+                    prop_assert!(ordered, "ages out of order: {:?}", ages);
+                }
+            }
+        }
+
+        #[test]
+        fn snapshot_load_round_trip((config, ops) in scenario_strategy(48)) {
+            runtime().block_on(async {
+                let (filter, clock, storage) =
+                    filter_with_mock_storage(config).await.unwrap();
+                let mut model = Model::new(filter.config());
+                for op in &ops {
+                    apply_op(&filter, &clock, &mut model, op).await.unwrap();
+                }
+                filter.save_snapshot().await.unwrap();
+
+                let loaded = ExpiringBloomFilter::load_with_storage(
+                    clock.clone(),
+                    storage.clone(),
+                )
+                .await
+                .unwrap();
+                prop_assert_eq!(
+                    loaded.get_active_level(),
+                    filter.get_active_level()
+                );
+                prop_assert_eq!(
+                    loaded.total_insert_count(),
+                    filter.total_insert_count()
+                );
+                prop_assert_eq!(
+                    loaded.export_union().unwrap(),
+                    filter.export_union().unwrap()
+                );
+                for key in model.inserted() {
+                    prop_assert_eq!(
+                        loaded.contains(key).unwrap(),
+                        filter.contains(key).unwrap()
+                    );
+                }
+                Ok(())
+            })?;
+        }
+    }
+}
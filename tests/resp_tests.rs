@@ -0,0 +1,123 @@
+#![cfg(feature = "resp")]
+
+use probabilistic_rs::resp::{Reply, RespServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn command(parts: &[&str]) -> Vec<Vec<u8>> {
+    parts.iter().map(|part| part.as_bytes().to_vec()).collect()
+}
+
+#[cfg(test)]
+mod resp_command_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_exists() {
+        let server = RespServer::new();
+        let add = |item| command(&["BF.ADD", "seen", item]);
+
+        assert_eq!(server.execute(&add("a")).await, Reply::Integer(1));
+        assert_eq!(server.execute(&add("a")).await, Reply::Integer(0));
+        assert_eq!(
+            server.execute(&command(&["BF.EXISTS", "seen", "a"])).await,
+            Reply::Integer(1)
+        );
+        assert_eq!(
+            server.execute(&command(&["bf.exists", "seen", "b"])).await,
+            Reply::Integer(0)
+        );
+        // Missing keys hold nothing
+        assert_eq!(
+            server.execute(&command(&["BF.EXISTS", "other", "a"])).await,
+            Reply::Integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_madd_and_mexists() {
+        let server = RespServer::new();
+        assert_eq!(
+            server
+                .execute(&command(&["BF.MADD", "seen", "a", "b", "a"]))
+                .await,
+            Reply::Array(vec![
+                Reply::Integer(1),
+                Reply::Integer(1),
+                Reply::Integer(0)
+            ])
+        );
+        assert_eq!(
+            server
+                .execute(&command(&["BF.MEXISTS", "seen", "a", "c"]))
+                .await,
+            Reply::Array(vec![Reply::Integer(1), Reply::Integer(0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reserve_and_info() {
+        let server = RespServer::new();
+        let reserve =
+            command(&["BF.RESERVE", "r", "0.001", "5000", "EXPANSION", "4"]);
+        assert_eq!(server.execute(&reserve).await, Reply::Simple("OK"));
+        assert!(matches!(
+            server.execute(&reserve).await,
+            Reply::Error(e) if e == "ERR item exists"
+        ));
+        server.execute(&command(&["BF.ADD", "r", "x"])).await;
+
+        let Reply::Array(info) =
+            server.execute(&command(&["BF.INFO", "r"])).await
+        else {
+            panic!("BF.INFO should reply with an array");
+        };
+        assert_eq!(info.len(), 10);
+        assert_eq!(info[0], Reply::Bulk(b"Capacity".to_vec()));
+        assert_eq!(info[1], Reply::Integer(5000));
+        assert_eq!(info[7], Reply::Integer(1));
+        assert_eq!(info[9], Reply::Integer(4));
+
+        assert!(matches!(
+            server.execute(&command(&["BF.INFO", "missing"])).await,
+            Reply::Error(e) if e == "ERR not found"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_arguments() {
+        let server = RespServer::new();
+        for bad in [
+            command(&["BF.RESERVE", "k", "1.5", "100"]),
+            command(&["BF.RESERVE", "k", "0.01", "0"]),
+            command(&["BF.RESERVE", "k", "0.01", "100", "BOGUS"]),
+            command(&["BF.ADD", "k"]),
+            command(&["BF.MEXISTS", "k"]),
+            command(&["FLUSHALL"]),
+        ] {
+            assert!(matches!(server.execute(&bad).await, Reply::Error(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_speaks_resp_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(RespServer::new().serve(listener));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(
+                b"*3\r\n$6\r\nBF.ADD\r\n$4\r\nseen\r\n$5\r\nhello\r\n\
+                  PING\r\n\
+                  *3\r\n$9\r\nBF.EXISTS\r\n$4\r\nseen\r\n$5\r\nhello\r\n\
+                  QUIT\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b":1\r\n+PONG\r\n:1\r\n+OK\r\n");
+    }
+}